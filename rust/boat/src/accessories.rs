@@ -0,0 +1,110 @@
+use anyhow::Result;
+use chrono::{Local, Timelike};
+use rust_pigpio::{set_mode, write, OUTPUT, ON, OFF};
+use tracing::{error, info};
+
+use crate::config::{AccessorySettings, Settings};
+
+/// Whether the hour is in the dark range [from, until), which wraps around midnight when from is after until
+pub fn is_dark(hour: u32, from: u32, until: u32) -> bool {
+    if from <= until {
+        (from..until).contains(&hour)
+    } else {
+        !(until..from).contains(&hour)
+    }
+}
+
+/// A plain on/off GPIO output (lights, relays...)
+pub struct AccessoryOutput {
+    name: String,
+    pin_number: u32,
+    on_when_dark: bool,     // Forced on when it is dark, whatever the remote says
+    claimed: bool,          // Pin set as output at start-up, the accessory being left alone otherwise
+    requested: bool,        // Last state sent by the remote, kept while it is left out of the commands
+    state: bool,
+}
+
+impl AccessoryOutput {
+    pub fn new(name: &str, pin_number: u32, on_when_dark: bool) -> Result<Self> {
+        set_mode(pin_number, OUTPUT)
+          .map_err(|e| anyhow::anyhow!("Accessory {} error: {}", name, e))?;
+        write(pin_number, OFF)
+          .map_err(|e| anyhow::anyhow!("Accessory {} error: {}", name, e))?;
+
+        info!("Init accessory {} to pin {}", name, pin_number);
+
+        Ok(Self { name: name.to_string(), pin_number, on_when_dark, claimed: true, requested: false, state: false })
+    }
+
+    /// Output whose pin could not be claimed, reported by the self-test
+    fn unclaimed(name: &str, pin_number: u32, on_when_dark: bool) -> Self {
+        Self { name: name.to_string(), pin_number, on_when_dark, claimed: false, requested: false, state: false }
+    }
+
+    pub fn set(&mut self, on: Option<bool>, dark: bool) -> Result<()> {
+        if let Some(on) = on {
+            self.requested = on;
+        }
+        let on = self.requested || (dark && self.on_when_dark);
+        if on == self.state || !self.claimed {
+            return Ok(());
+        }
+
+        write(self.pin_number, if on { ON } else { OFF })
+          .map_err(|e| anyhow::anyhow!("Accessory {} error: {}", self.name, e))?;
+        self.state = on;
+
+        Ok(())
+    }
+}
+
+pub struct Accessories {
+    nav_lights: AccessoryOutput,
+    spotlight: AccessoryOutput,
+    deck_light: AccessoryOutput,
+    dark_from_hour: u32,
+    dark_until_hour: u32,
+}
+
+impl Accessories {
+    /// Names of the outputs which could not be claimed are added to unclaimed
+    pub fn new(settings: &Settings, unclaimed: &mut Vec<String>) -> Self {
+        let mut output = |name, config: AccessorySettings| {
+            AccessoryOutput::new(name, config.pin, config.on_when_dark).unwrap_or_else(|e| {
+                error!("{}", e);
+                unclaimed.push(name.to_string());
+                AccessoryOutput::unclaimed(name, config.pin, config.on_when_dark)
+            })
+        };
+        Self {
+            nav_lights: output("nav_lights", settings.nav_lights),
+            spotlight: output("spotlight", settings.spotlight),
+            deck_light: output("deck_light", settings.deck_light),
+            dark_from_hour: settings.dark_from_hour,
+            dark_until_hour: settings.dark_until_hour,
+        }
+    }
+
+    pub fn apply(&mut self, nav_lights: Option<bool>, spotlight: Option<bool>, deck_light: Option<bool>) -> Result<()> {
+        let dark = is_dark(Local::now().hour(), self.dark_from_hour, self.dark_until_hour);
+        self.nav_lights.set(nav_lights, dark)?;
+        self.spotlight.set(spotlight, dark)?;
+        self.deck_light.set(deck_light, dark)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dark_hours() {
+        assert!(is_dark(22, 21, 7));
+        assert!(is_dark(3, 21, 7));
+        assert!(!is_dark(7, 21, 7));
+        assert!(!is_dark(12, 21, 7));
+        assert!(is_dark(1, 0, 6));
+        assert!(!is_dark(23, 0, 6));
+    }
+}
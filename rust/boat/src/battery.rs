@@ -0,0 +1,127 @@
+#[cfg(feature = "i2c")]
+use rppal::i2c::I2c;
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::get_timestamp_ms;
+use tracing::{error, info};
+
+// ADS1115 with its ADDR pin to ground
+#[cfg(feature = "i2c")]
+const ADS1115_ADDRESS: u16 = 0x48;
+const REG_CONVERSION: u8 = 0x00;
+const REG_CONFIG: u8 = 0x01;
+// AIN0 against ground, 4.096 V full scale, continuous conversions at 128 per second, comparator off
+const CONFIG_AIN0_CONTINUOUS: u16 = 0x4283;
+const VOLTS_PER_COUNT: f32 = 4.096 / 32768.;
+
+const SAMPLE_PERIOD: Duration = Duration::from_millis(500);
+// Share of each sample in the voltage, which sags with the motor current
+const SMOOTHING: f32 = 0.2;
+// A voltage older than this is considered lost
+const BATTERY_TIMEOUT_MS: u64 = 3000;
+
+#[derive(Debug, Clone, Copy)]
+pub struct BatteryVoltage {
+    pub volts: f32,
+    pub timestamp: u64,     // Local time of the last sample in ms
+}
+
+impl BatteryVoltage {
+    pub fn is_fresh(&self) -> bool {
+        get_timestamp_ms().saturating_sub(self.timestamp) < BATTERY_TIMEOUT_MS
+    }
+}
+
+/// 16 bit registers of the ADC, high byte first
+trait Adc: Send {
+    fn write_register(&mut self, register: u8, value: u16) -> Result<(), Box<dyn std::error::Error>>;
+    fn read_register(&mut self, register: u8) -> Result<u16, Box<dyn std::error::Error>>;
+}
+
+#[cfg(feature = "i2c")]
+impl Adc for I2c {
+    fn write_register(&mut self, register: u8, value: u16) -> Result<(), Box<dyn std::error::Error>> {
+        let [high, low] = value.to_be_bytes();
+        I2c::write(self, &[register, high, low])?;
+        Ok(())
+    }
+
+    fn read_register(&mut self, register: u8) -> Result<u16, Box<dyn std::error::Error>> {
+        let mut value = [0u8; 2];
+        self.write_read(&[register], &mut value)?;
+        Ok(u16::from_be_bytes(value))
+    }
+}
+
+/// Reads the boat battery through a divider on input 0 of an ADS1115 on I2C bus 1, returning the first voltage.
+///
+/// `scale` is the ratio of the divider, the battery voltage over that of the input.
+pub fn start(battery_mutex: Arc<Mutex<Option<BatteryVoltage>>>, scale: f32) -> Result<f32, Box<dyn std::error::Error>> {
+    let mut adc = open_adc()?;
+    adc.write_register(REG_CONFIG, CONFIG_AIN0_CONTINUOUS)?;
+    // First conversion done after 8 ms
+    thread::sleep(Duration::from_millis(10));
+    let volts = battery_volts(adc.read_register(REG_CONVERSION)?, scale);
+    info!("Boat battery {:.2}V", volts);
+    *battery_mutex.lock().unwrap() = Some(BatteryVoltage { volts, timestamp: get_timestamp_ms() });
+    thread::spawn(move || battery_thread(adc, scale, battery_mutex));
+    Ok(volts)
+}
+
+#[cfg(feature = "i2c")]
+fn open_adc() -> Result<Box<dyn Adc>, Box<dyn std::error::Error>> {
+    let mut i2c = I2c::with_bus(crate::selftest::I2C_BUS)?;
+    i2c.set_slave_address(ADS1115_ADDRESS)?;
+    Ok(Box::new(i2c))
+}
+
+#[cfg(not(feature = "i2c"))]
+fn open_adc() -> Result<Box<dyn Adc>, Box<dyn std::error::Error>> {
+    Err("built without the i2c feature".into())
+}
+
+/// Battery voltage of a conversion, a signed count which is only negative from noise around 0 V
+fn battery_volts(raw: u16, scale: f32) -> f32 {
+    (raw as i16).max(0) as f32 * VOLTS_PER_COUNT * scale
+}
+
+fn battery_thread(mut adc: Box<dyn Adc>, scale: f32, battery_mutex: Arc<Mutex<Option<BatteryVoltage>>>) {
+    let mut failed = false;
+    loop {
+        thread::sleep(SAMPLE_PERIOD);
+        match adc.read_register(REG_CONVERSION) {
+            Ok(raw) => {
+                failed = false;
+                let volts = battery_volts(raw, scale);
+                let mut battery = battery_mutex.lock().unwrap();
+                let volts = match *battery {
+                    Some(previous) if previous.is_fresh() => previous.volts + (volts - previous.volts) * SMOOTHING,
+                    _ => volts,
+                };
+                *battery = Some(BatteryVoltage { volts, timestamp: get_timestamp_ms() });
+            }
+            // Once per failure, the voltage going stale
+            Err(e) if !failed => {
+                error!("Battery ADC error: {}", e);
+                failed = true;
+            }
+            Err(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_battery_volts() {
+        assert_eq!(battery_volts(0, 4.), 0.);
+        assert_eq!(battery_volts(0xFFF0, 4.), 0.);
+        // 3 V on the input of a divider by 4, a full 3S LiPo
+        assert!((battery_volts(24000, 4.) - 12.).abs() < 0.01);
+    }
+}
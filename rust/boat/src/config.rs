@@ -0,0 +1,123 @@
+use serde::{Serialize, Deserialize};
+use std::fs;
+use std::io;
+
+use crate::trim::{TRIM_TABLE, TrimPoint};
+use tracing::{error, info};
+
+/// Settings of the boat tuned without a rebuild, those missing from the file taking their default
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub boat_id: u8,                    // Commands addressed to other boats are ignored, the remote selecting the boat it drives by this id, 1 to 4
+    pub bow_thruster_max_speed: f32,    // Speed over ground (m/s) above which the bow thruster is held at neutral, for docking only
+    pub nav_lights: AccessorySettings,
+    pub spotlight: AccessorySettings,
+    pub deck_light: AccessorySettings,
+    pub dark_from_hour: u32,            // Local time range during which it is considered dark, across midnight if from is after until
+    pub dark_until_hour: u32,
+    pub return_home_delay_ms: u64,      // Link lost for that long before heading back to the launch point
+    pub battery_scale: f32,             // Ratio of the divider on the battery ADC input, battery voltage over input voltage
+    pub tension_limit_g: f32,           // Sheet load above which the sail is eased
+    pub tension_hold_ms: u64,           // How long the load stays above the limit before easing, riding out short snatches
+    pub tension_ease_us: i32,           // Sheet servo offset while eased, negative letting the sheets out on this boat
+    pub trim_table: Vec<TrimPoint>,     // Sail positions of the auto trim by apparent wind angle, in any order
+    pub gps_device: String,             // Serial port of the GPS module, its baud rate configured by the system
+    pub sbus_device: String,            // PL011 UART of the SBUS output, the mini UART having no parity
+    pub sonar_device: String,           // USB serial adapter of the sonar, the one UART on the header of a Pi Zero 2 W going to the GPS
+}
+
+/// GPIO output of an accessory
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AccessorySettings {
+    pub pin: u32,
+    pub on_when_dark: bool,             // Forced on when it is dark, whatever the remote says
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            boat_id: 1,
+            bow_thruster_max_speed: 0.5,
+            nav_lights: AccessorySettings { pin: 16, on_when_dark: true },
+            spotlight: AccessorySettings { pin: 20, on_when_dark: false },
+            deck_light: AccessorySettings { pin: 21, on_when_dark: false },
+            dark_from_hour: 21,
+            dark_until_hour: 7,
+            return_home_delay_ms: 30_000,
+            battery_scale: 4.,
+            tension_limit_g: 4000.,
+            tension_hold_ms: 300,
+            tension_ease_us: -200,
+            trim_table: TRIM_TABLE.to_vec(),
+            gps_device: String::from("/dev/serial0"),
+            sbus_device: String::from("/dev/ttyAMA0"),
+            sonar_device: String::from("/dev/ttyUSB0"),
+        }
+    }
+}
+
+impl Settings {
+    /// Settings of the file, written back with the defaults of the missing ones for them to be edited.
+    /// A file which does not parse is left alone, the defaults applying.
+    pub fn load_or_create(path: &str) -> Self {
+        let settings = match fs::read_to_string(path) {
+            Ok(content) => match serde_json::from_str(&content) {
+                Ok(settings) => settings,
+                Err(e) => {
+                    error!("Default settings, {} ignored: {}", path, e);
+                    return Settings::default();
+                }
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                info!("No {}, created with the default settings", path);
+                Settings::default()
+            }
+            Err(e) => {
+                error!("Default settings, {} not read: {}", path, e);
+                return Settings::default();
+            }
+        };
+        if let Err(e) = settings.save(path) {
+            error!("Could not save {}: {}", path, e);
+        }
+        settings
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self).map_err(io::Error::other)?)
+    }
+}
+
+/// Whether two device paths lead to the same device, through links such as /dev/serial0
+pub fn same_device(a: &str, b: &str) -> bool {
+    let canonical = |path: &str| fs::canonicalize(path).unwrap_or_else(|_| path.into());
+    canonical(a) == canonical(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_settings() {
+        let settings: Settings = serde_json::from_str(r#"{ "bow_thruster_max_speed": 0.8 }"#).unwrap();
+        assert_eq!(settings.bow_thruster_max_speed, 0.8);
+        let settings: Settings = serde_json::from_str("{}").unwrap();
+        assert_eq!(settings, Settings::default());
+    }
+
+    #[test]
+    fn test_same_device() {
+        let dir = std::env::temp_dir().join(format!("pizboat-devices-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let uart = dir.join("ttyAMA0");
+        let serial0 = dir.join("serial0");
+        fs::write(&uart, "").unwrap();
+        let _ = fs::remove_file(&serial0);
+        std::os::unix::fs::symlink(&uart, &serial0).unwrap();
+        assert!(same_device(serial0.to_str().unwrap(), uart.to_str().unwrap()));
+        assert!(!same_device(serial0.to_str().unwrap(), "/dev/ttyUSB0"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,138 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::get_timestamp_ms;
+use tracing::{error, info};
+
+// A fix older than this is considered lost
+const GPS_TIMEOUT_MS: u64 = 3000;
+
+const KNOTS_TO_MS: f32 = 0.514444;
+const EARTH_RADIUS_M: f64 = 6_371_000.;
+
+#[derive(Debug, Clone, Copy)]
+pub struct GpsFix {
+    pub latitude: f64,      // Decimal degrees, north positive
+    pub longitude: f64,     // Decimal degrees, east positive
+    pub speed: f32,         // Speed over ground in m/s
+    pub course: f32,        // Course over ground in degrees
+    pub timestamp: u64,     // Local reception time in ms
+}
+
+impl GpsFix {
+    pub fn is_fresh(&self) -> bool {
+        get_timestamp_ms().saturating_sub(self.timestamp) < GPS_TIMEOUT_MS
+    }
+    
+    /// Distance (m) and bearing (degrees) to a point, flat earth approximation good enough on a pond
+    pub fn distance_and_bearing_to(&self, latitude: f64, longitude: f64) -> (f64, f32) {
+        let mean_latitude = ((self.latitude + latitude) / 2.).to_radians();
+        let north = (latitude - self.latitude).to_radians() * EARTH_RADIUS_M;
+        let east = (longitude - self.longitude).to_radians() * mean_latitude.cos() * EARTH_RADIUS_M;
+        let bearing = east.atan2(north).to_degrees().rem_euclid(360.);
+        ((north * north + east * east).sqrt(), bearing as f32)
+    }
+}
+
+fn checksum_ok(sentence: &str) -> bool {
+    let Some(body) = sentence.strip_prefix('$') else { return false };
+    let Some((data, checksum)) = body.split_once('*') else { return false };
+    let Ok(expected) = u8::from_str_radix(checksum.trim(), 16) else { return false };
+    data.bytes().fold(0u8, |acc, b| acc ^ b) == expected
+}
+
+/// Convert NMEA "dddmm.mmmm" plus hemisphere to decimal degrees
+fn parse_coordinate(value: &str, hemisphere: &str) -> Option<f64> {
+    let raw: f64 = value.parse().ok()?;
+    let degrees = (raw / 100.).trunc();
+    let decimal = degrees + (raw - degrees * 100.) / 60.;
+    match hemisphere {
+        "N" | "E" => Some(decimal),
+        "S" | "W" => Some(-decimal),
+        _ => None,
+    }
+}
+
+/// Parse a RMC sentence ($GPRMC, $GNRMC...), None if invalid or without fix
+pub fn parse_rmc(sentence: &str) -> Option<GpsFix> {
+    if !checksum_ok(sentence) {
+        return None;
+    }
+    let data = sentence[1..].split('*').next()?;
+    let fields: Vec<&str> = data.split(',').collect();
+    if fields.len() < 9 || !fields[0].ends_with("RMC") || fields[2] != "A" {
+        return None;
+    }
+    Some(GpsFix {
+        latitude: parse_coordinate(fields[3], fields[4])?,
+        longitude: parse_coordinate(fields[5], fields[6])?,
+        speed: fields[7].parse::<f32>().unwrap_or(0.) * KNOTS_TO_MS,
+        course: fields[8].parse::<f32>().unwrap_or(0.),
+        timestamp: get_timestamp_ms(),
+    })
+}
+
+/// Reads the NMEA sentences of the GPS on `device`, at the baud rate configured by the system (9600 by default)
+pub fn gps_thread(device: String, gps_mutex: Arc<Mutex<Option<GpsFix>>>) {
+    loop {
+        let file = match File::open(&device) {
+            Ok(f) => f,
+            Err(e) => {
+                error!("Could not open GPS {}: {}", device, e);
+                thread::sleep(Duration::from_secs(5));
+                continue;
+            }
+        };
+        info!("GPS reading from {}", device);
+
+        for line in BufReader::new(file).lines() {
+            let line = match line {
+                Ok(l) => l,
+                Err(e) => {
+                    error!("GPS read error: {}", e);
+                    break;
+                }
+            };
+            if let Some(fix) = parse_rmc(line.trim()) {
+                let mut locked = gps_mutex.lock().unwrap();
+                *locked = Some(fix);
+            }
+        }
+        thread::sleep(Duration::from_secs(1));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rmc() {
+        let fix = parse_rmc("$GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W*6A").unwrap();
+        assert!((fix.latitude - 48.1173).abs() < 0.0001);
+        assert!((fix.longitude - 11.5166).abs() < 0.0001);
+        assert!((fix.speed - 11.52).abs() < 0.01);
+        assert_eq!(fix.course, 84.4);
+    }
+
+    #[test]
+    fn test_parse_rmc_rejects_invalid() {
+        // Void status
+        assert!(parse_rmc("$GPRMC,123519,V,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W*7D").is_none());
+        // Bad checksum
+        assert!(parse_rmc("$GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W*00").is_none());
+    }
+
+    #[test]
+    fn test_distance_and_bearing() {
+        let fix = GpsFix { latitude: 48.0, longitude: 11.0, speed: 0., course: 0., timestamp: 0 };
+        let (distance, bearing) = fix.distance_and_bearing_to(48.001, 11.0);
+        assert!((distance - 111.2).abs() < 0.5);
+        assert!(bearing.abs() < 0.1);
+        let (_, bearing) = fix.distance_and_bearing_to(48.0, 10.999);
+        assert!((bearing - 270.).abs() < 0.1);
+    }
+}
@@ -1,11 +1,40 @@
+#[cfg(not(feature = "rppal"))]
 use rust_pigpio::{initialize, set_mode, read, write, INPUT, OUTPUT, ON, OFF};
+#[cfg(feature = "rppal")]
+use rppal::gpio::{Gpio, InputPin, OutputPin, Trigger};
+use tracing::{debug, error, info};
 
-use std::time::{Duration, SystemTime};
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use std::thread;
 
+mod stats;
+
+pub use stats::Statistics;
+use stats::RollingStats;
+
+// PD_SCK high and low time, 0.2 to 50 us on the datasheet
+const CLOCK_DELAY_US: u32 = 1;
+// Spin loop iterations timed to calibrate the clock delay
+const CALIBRATION_SPINS: u32 = 100_000;
+// Longest wait for a conversion, which comes at 10 or 80 per second
+const READY_TIMEOUT: Duration = Duration::from_secs(1);
+// Pause of the sampler between readings, each one waiting for its conversion anyway
+const SAMPLER_PAUSE: Duration = Duration::from_millis(20);
+// Latest raw readings each sampled value is computed from, the corrupted ones rejected
+const SAMPLER_WINDOW: usize = 5;
+const SAMPLER_SIGMAS: f32 = 3.;
+// Span of the rolling statistics of each cell
+const STATS_WINDOW: Duration = Duration::from_secs(10);
+// DOUT polling period without interrupts, a conversion waiting until read
+#[cfg(not(feature = "rppal"))]
+const READY_POLL: Duration = Duration::from_millis(1);
+
 
 /// HX711 gain settings which also select the channel
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Gain {
     /// Channel A with gain of 128 (default)
     ChAGain128 = 1,
@@ -15,10 +44,111 @@ pub enum Gain {
     ChAGain64 = 3,
 }
 
-pub struct HX711 {
+/// Failure of an HX711 read
+#[derive(Clone, Debug, PartialEq)]
+pub enum Hx711Error {
+    /// No conversion within READY_TIMEOUT, the HX711 being unpowered or disconnected
+    Timeout,
+    /// No conversion read yet
+    NotReady,
+    /// Pin access failure, with the message of the GPIO library
+    GpioError(String),
+}
+
+impl fmt::Display for Hx711Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Hx711Error::Timeout => write!(f, "HX711 timeout, no conversion within {:?}", READY_TIMEOUT),
+            Hx711Error::NotReady => write!(f, "HX711 not ready, no conversion read yet"),
+            Hx711Error::GpioError(e) => write!(f, "HX711 GPIO error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Hx711Error {}
+
+/// DOUT and PD_SCK through the pigpio daemon
+#[cfg(not(feature = "rppal"))]
+struct Pins {
     pd_sck_pin: u32,
     dout_pin: u32,
+}
+
+#[cfg(not(feature = "rppal"))]
+impl Pins {
+    fn new(dout_pin: u32, pd_sck_pin: u32) -> Result<Self, Box<dyn std::error::Error>> {
+        initialize()?;
+
+        set_mode(pd_sck_pin, OUTPUT)?;
+        set_mode(dout_pin, INPUT)?;
+        write(pd_sck_pin, OFF)?;
+        Ok(Pins { pd_sck_pin, dout_pin })
+    }
+
+    fn set_clock(&mut self, high: bool) -> Result<(), Hx711Error> {
+        write(self.pd_sck_pin, if high { ON } else { OFF }).map_err(Hx711Error::GpioError)
+    }
+
+    fn is_data_low(&self) -> Result<bool, Hx711Error> {
+        Ok(read(self.dout_pin).map_err(Hx711Error::GpioError)? == 0)
+    }
+
+    // rust_pigpio has no GPIO callbacks: poll, sleeping in between
+    fn wait_data_ready(&mut self, timeout: Duration) -> Result<(), Hx711Error> {
+        let start = Instant::now();
+        while !self.is_data_low()? {
+            if start.elapsed() > timeout {
+                return Err(Hx711Error::Timeout);
+            }
+            thread::sleep(READY_POLL);
+        }
+        Ok(())
+    }
+}
+
+/// DOUT and PD_SCK through rppal, without the pigpio daemon
+#[cfg(feature = "rppal")]
+struct Pins {
+    pd_sck: OutputPin,
+    dout: InputPin,
+}
+
+#[cfg(feature = "rppal")]
+impl Pins {
+    fn new(dout_pin: u32, pd_sck_pin: u32) -> Result<Self, Box<dyn std::error::Error>> {
+        let gpio = Gpio::new()?;
+        let pd_sck = gpio.get(pd_sck_pin as u8)?.into_output_low();
+        let mut dout = gpio.get(dout_pin as u8)?.into_input();
+        // DOUT going low signals a conversion ready
+        dout.set_interrupt(Trigger::FallingEdge)?;
+        Ok(Pins { pd_sck, dout })
+    }
+
+    fn set_clock(&mut self, high: bool) -> Result<(), Hx711Error> {
+        if high { self.pd_sck.set_high() } else { self.pd_sck.set_low() }
+        Ok(())
+    }
+
+    fn is_data_low(&self) -> Result<bool, Hx711Error> {
+        Ok(self.dout.is_low())
+    }
+
+    // Sleeps until the falling edge of DOUT
+    fn wait_data_ready(&mut self, timeout: Duration) -> Result<(), Hx711Error> {
+        let start = Instant::now();
+        while !self.is_data_low()? {
+            let left = timeout.checked_sub(start.elapsed()).ok_or(Hx711Error::Timeout)?;
+            // Edges of the previous read may be pending, the level is checked again on each wake up
+            self.dout.poll_interrupt(false, Some(left)).map_err(|e| Hx711Error::GpioError(e.to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+pub struct HX711 {
+    pins: Pins,
     gain: Gain,
+    clock_spins: u32,   // Spin loop iterations lasting CLOCK_DELAY_US
 }
 
 impl HX711 {
@@ -29,90 +159,62 @@ impl HX711 {
     /// * `pd_sck_pin` - GPIO pin number for power down and serial clock (PD_SCK)
     /// * `gain` - Initial gain setting (default: ChAGain128)
     pub fn new(dout_pin: u32, pd_sck_pin: u32, gain: Gain) -> Result<Self, Box<dyn std::error::Error>> {
-        initialize().expect("Could not init pigpio !");
-
-        set_mode(pd_sck_pin, OUTPUT)?;
-        set_mode(dout_pin, INPUT)?;
-        
-        write(pd_sck_pin, OFF).unwrap();
-        // let mut pd_sck = gpio.get(pd_sck_pin)?.into_output();
-        // let dout = gpio.get(dout_pin)?.into_input();
-        
-        // pd_sck.set_low();
-        
         let mut hx711 = HX711 {
-            pd_sck_pin,
-            dout_pin,
+            pins: Pins::new(dout_pin, pd_sck_pin)?,
             gain,
+            clock_spins: calibrate_clock_spins(),
         };
         
         // Initial setup delay
         thread::sleep(Duration::from_micros(100));
         
         // Reset to ensure proper state
-        hx711.reset();
+        hx711.reset()?;
         
-        hx711.init();
+        hx711.init()?;
         
         Ok(hx711)
     }
     
-    pub fn init(&self) {
+    pub fn init(&mut self) -> Result<(), Hx711Error> {
         for _n in 0..10 {
-            write(self.pd_sck_pin, ON).unwrap();
-            self.do_sleep();
-            write(self.pd_sck_pin, OFF).unwrap();
-            self.do_sleep();
+            self.clock_pulse()?;
         }
+        Ok(())
+    }
+    
+    fn clock_pulse(&mut self) -> Result<(), Hx711Error> {
+        self.pins.set_clock(true)?;
+        self.do_sleep();
+        self.pins.set_clock(false)?;
+        self.do_sleep();
+        Ok(())
     }
     
+    /// Busy wait of CLOCK_DELAY_US, too short to sleep
     pub fn do_sleep(&self) {
-        let start = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_nanos();
-
-        loop {
-            let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_nanos();
-            let diff = now - start;
-            // println!("diff {}", diff);
-            
-            if diff > 5000
-            {
-                break;
-            }
-        }
+        spin(self.clock_spins);
     }
 
     /// Check if the HX711 is ready to send data
-    pub fn is_ready(&self) -> bool {
-        // self.dout.is_low()
-        let value = read(self.dout_pin).unwrap();
-        return value == 0;
+    pub fn is_ready(&self) -> Result<bool, Hx711Error> {
+        self.pins.is_data_low()
     }
     
     /// Read raw 24-bit value from the HX711
-    fn read_raw_bytes(&mut self) -> i32 {
-        // Wait until HX711 is ready (with a simple timeout)
-        
-        let mut timeout = 0;
-        while !self.is_ready() {
-            timeout += 1;
-            if timeout > 1000000 {
-                return -1; // Timeout after ~1 second
-            }
-            thread::sleep(Duration::from_micros(1));
+    fn read_raw_bytes(&mut self) -> Result<i32, Hx711Error> {
+        // Wait until HX711 is ready, without using the CPU
+        if !self.is_ready()? {
+            self.pins.wait_data_ready(READY_TIMEOUT)?;
         }
         let mut count: i32 = 0;
         
         // Read three bytes of data
         for _bit in 0..24 {
-            write(self.pd_sck_pin, ON).unwrap();
-            self.do_sleep();
-
-            write(self.pd_sck_pin, OFF).unwrap();
-            self.do_sleep();            
+            self.clock_pulse()?;
             
             // Read bit based on bit format
-            // let bit_value = if self.dout.is_high() { 1 } else { 0 };
-            let bit_value = read(self.dout_pin).unwrap() as u8;
+            let bit_value = if self.pins.is_data_low()? { 0 } else { 1 };
             
             count <<= 1;
             count += bit_value as i32;
@@ -121,41 +223,50 @@ impl HX711 {
         
         // Set gain for next reading by sending additional clock pulses
         for _ in 0..(self.gain as u8) {
-            // self.pd_sck.set_high();
-            write(self.pd_sck_pin, ON).unwrap();
-            self.do_sleep();
-            
-            //self.pd_sck.set_low();
-            write(self.pd_sck_pin, OFF).unwrap();
-            self.do_sleep();
+            self.clock_pulse()?;
         }
         
         count = count ^ 0x800000;
-        count
+        Ok(count)
     }
     
     /// Get a single reading
-    pub fn get_value(&mut self) -> Option<i32> {
-        let value = self.read_raw_bytes();
-        if value == -1 { None } else { Some(value) }
+    pub fn get_value(&mut self) -> Result<i32, Hx711Error> {
+        self.read_raw_bytes()
+    }
+    
+    /// Select the channel and gain of the following readings.
+    /// The gain is clocked in after a read and applies to the next conversion, so one reading is discarded.
+    pub fn set_gain(&mut self, gain: Gain) -> Result<(), Hx711Error> {
+        if gain != self.gain {
+            self.gain = gain;
+            self.read_raw_bytes()?;
+        }
+        Ok(())
+    }
+    
+    /// Get a single reading from the channel selected by the gain
+    pub fn get_channel_value(&mut self, gain: Gain) -> Result<i32, Hx711Error> {
+        self.set_gain(gain)?;
+        self.get_value()
     }
         
     /// Power down the HX711
-    pub fn power_down(&mut self) {
-        println!("power_down()");
-        write(self.pd_sck_pin, OFF).unwrap();
+    pub fn power_down(&mut self) -> Result<(), Hx711Error> {
+        debug!("power_down()");
+        self.pins.set_clock(false)?;
         thread::sleep(Duration::from_micros(100));
-        write(self.pd_sck_pin, ON).unwrap();
+        self.pins.set_clock(true)?;
         
         // Wait 100 microseconds (HX711 powers down after 60us)
         thread::sleep(Duration::from_micros(100));
+        Ok(())
     }
     
     /// Power up the HX711
-    pub fn power_up(&mut self) {
-        println!("power_up()");
-        // self.pd_sck.set_low();
-        write(self.pd_sck_pin, OFF).unwrap();
+    pub fn power_up(&mut self) -> Result<(), Hx711Error> {
+        debug!("power_up()");
+        self.pins.set_clock(false)?;
         
         // Wait 100 microseconds for HX711 to power back up
         thread::sleep(Duration::from_micros(100));
@@ -165,15 +276,127 @@ impl HX711 {
         // if !matches!(self.gain, Gain::ChAGain128) {
         //            self.read_raw_bytes();
         //}
+        Ok(())
     }
     
     /// Reset the HX711 (power cycle)
-    pub fn reset(&mut self) {
-        self.power_down();
-        self.power_up();
+    pub fn reset(&mut self) -> Result<(), Hx711Error> {
+        self.power_down()?;
+        self.power_up()
+    }
+}
+
+/// Load cell wired to one of the HX711 channels
+#[derive(Clone, Copy, Debug)]
+pub struct LoadCell {
+    pub gain: Gain,     // Selects channel A or B
+    pub offset: i32,    // Raw value without load
+    pub scale: f32,     // Raw units per gram
+}
+
+impl LoadCell {
+    pub fn weight(&self, raw: i32) -> f32 {
+        (raw - self.offset) as f32 / self.scale
+    }
+}
+
+// Latest filtered value of a cell and the statistics of its weight
+struct CellState {
+    raw: Result<i32, Hx711Error>,
+    stats: RollingStats,
+}
+
+/// Reads the HX711 in its own thread, the latest values being available without waiting for a conversion.
+/// With cells on both channels, they are read alternately, each switch costing a discarded reading.
+#[derive(Clone)]
+pub struct HX711Sampler {
+    states: Arc<Mutex<Vec<CellState>>>,
+    cells: Vec<LoadCell>,
+}
+
+impl HX711Sampler {
+    pub fn start(hx711: HX711, cells: Vec<LoadCell>) -> Self {
+        let states = cells.iter()
+            .map(|_| CellState { raw: Err(Hx711Error::NotReady), stats: RollingStats::new(STATS_WINDOW) })
+            .collect();
+        let states = Arc::new(Mutex::new(states));
+        let states_clone = Arc::clone(&states);
+        let cells_clone = cells.clone();
+        thread::spawn(move || sampler_thread(hx711, cells_clone, states_clone));
+        HX711Sampler { states, cells }
+    }
+
+    /// Filtered raw value of the cell, by its index at start, NotReady before the first reading, or the error of the last one
+    pub fn latest_raw(&self, cell: usize) -> Result<i32, Hx711Error> {
+        self.states.lock().unwrap().get(cell).map_or(Err(Hx711Error::NotReady), |state| state.raw.clone())
+    }
+
+    /// Weight in grams
+    pub fn latest_weight(&self, cell: usize) -> Result<f32, Hx711Error> {
+        let raw = self.latest_raw(cell)?;
+        Ok(self.cells[cell].weight(raw))
+    }
+
+    /// Mean, standard deviation and rate of the filtered weights over STATS_WINDOW, and their peak since the last reset
+    pub fn statistics(&self, cell: usize) -> Option<Statistics> {
+        self.states.lock().unwrap().get(cell)?.stats.statistics()
+    }
+
+    pub fn reset_peaks(&self) {
+        for state in self.states.lock().unwrap().iter_mut() {
+            state.stats.reset_peak();
+        }
     }
 }
 
+fn sampler_thread(mut hx711: HX711, cells: Vec<LoadCell>, states: Arc<Mutex<Vec<CellState>>>) {
+    let mut windows = vec![VecDeque::with_capacity(SAMPLER_WINDOW + 1); cells.len()];
+    loop {
+        for (index, cell) in cells.iter().enumerate() {
+            let window = &mut windows[index];
+            let value = match hx711.get_channel_value(cell.gain) {
+                Ok(value) => {
+                    window.push_back(value);
+                    if window.len() > SAMPLER_WINDOW {
+                        window.pop_front();
+                    }
+                    // Never empty with a value just pushed
+                    Ok(pizboat_filter::sigma_clipped_mean(window.make_contiguous(), SAMPLER_SIGMAS).unwrap_or(value))
+                }
+                Err(e) => {
+                    error!("Failed to read from sensor {:?}: {}", cell.gain, e);
+                    Err(e)
+                }
+            };
+            {
+                let state = &mut states.lock().unwrap()[index];
+                if let Ok(raw) = value {
+                    state.stats.push(cell.weight(raw), Instant::now());
+                }
+                state.raw = value;
+            }
+            thread::sleep(SAMPLER_PAUSE);
+        }
+    }
+}
+
+fn spin(iterations: u32) {
+    for i in 0..iterations {
+        std::hint::black_box(i);
+        std::hint::spin_loop();
+    }
+}
+
+// Reading the clock at each iteration costs more than the delay itself
+fn calibrate_clock_spins() -> u32 {
+    let start = Instant::now();
+    spin(CALIBRATION_SPINS);
+    let elapsed_ns = start.elapsed().as_nanos().max(1);
+    let spins = (CALIBRATION_SPINS as u128 * CLOCK_DELAY_US as u128 * 1000 / elapsed_ns).max(1) as u32;
+    info!("HX711 clock delay: {} spins per {} us", spins, CLOCK_DELAY_US);
+    spins
+}
+
 // Example usage
 #[cfg(test)]
 mod tests {
@@ -182,17 +405,14 @@ mod tests {
     #[test]
     #[ignore] // Ignore by default as it requires actual hardware
     fn test_basic_reading() {
-        let mut hx711 = HX711::new(5, 6, Gain::ChAGain128).unwrap();
-        
-        // Set reading format if needed
-        hx711.set_reading_format(ByteFormat::MSB, BitFormat::MSB);
+        let hx711 = HX711::new(5, 6, Gain::ChAGain128).unwrap();
         
-        // Set calibration values
-        hx711.set_offset_a(8388608);
-        hx711.set_reference_unit_a(432.0);
+        // Calibration values of the cell
+        let sampler = HX711Sampler::start(hx711, vec![LoadCell { gain: Gain::ChAGain128, offset: 8388608, scale: 432.0 }]);
+        thread::sleep(Duration::from_millis(500));
         
         // Read weight
-        if let Some(weight) = hx711.get_weight(5) {
+        if let Ok(weight) = sampler.latest_weight(0) {
             println!("Weight: {:.2} g", weight);
         }
     }
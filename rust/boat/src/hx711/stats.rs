@@ -0,0 +1,75 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Statistics of the values over the rolling window, the peak being held until reset
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Statistics {
+    pub mean: f32,
+    pub std_dev: f32,
+    pub peak: f32,
+    pub sample_rate: f32,   // Values per second achieved over the window
+    pub count: usize,
+}
+
+pub struct RollingStats {
+    window: Duration,
+    values: VecDeque<(Instant, f32)>,
+    peak: Option<f32>,
+}
+
+impl RollingStats {
+    pub fn new(window: Duration) -> Self {
+        RollingStats { window, values: VecDeque::new(), peak: None }
+    }
+
+    pub fn push(&mut self, value: f32, at: Instant) {
+        self.values.push_back((at, value));
+        while self.values.front().is_some_and(|&(time, _)| at.duration_since(time) > self.window) {
+            self.values.pop_front();
+        }
+        self.peak = Some(self.peak.map_or(value, |peak| peak.max(value)));
+    }
+
+    pub fn reset_peak(&mut self) {
+        self.peak = None;
+    }
+
+    /// None before the first value
+    pub fn statistics(&self) -> Option<Statistics> {
+        let count = self.values.len();
+        let peak = self.peak?;
+        let (first, _) = *self.values.front()?;
+        let (last, _) = *self.values.back()?;
+        let mean = self.values.iter().map(|&(_, v)| v as f64).sum::<f64>() / count as f64;
+        let variance = self.values.iter().map(|&(_, v)| (v as f64 - mean).powi(2)).sum::<f64>() / count as f64;
+        let span = last.duration_since(first).as_secs_f32();
+        let sample_rate = if span > 0. { (count - 1) as f32 / span } else { 0. };
+        Some(Statistics { mean: mean as f32, std_dev: variance.sqrt() as f32, peak, sample_rate, count })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rolling_statistics() {
+        let mut stats = RollingStats::new(Duration::from_secs(1));
+        assert_eq!(stats.statistics(), None);
+        let start = Instant::now();
+        // A gust peak, then 4 values per second
+        stats.push(900., start);
+        for (i, value) in [100., 110., 90., 100., 100.].iter().enumerate() {
+            stats.push(*value, start + Duration::from_millis(500 + 250 * i as u64));
+        }
+        let statistics = stats.statistics().unwrap();
+        assert_eq!(statistics.count, 5);
+        assert_eq!(statistics.mean, 100.);
+        assert!((statistics.std_dev - 6.32).abs() < 0.01);
+        assert_eq!(statistics.peak, 900.);
+        assert_eq!(statistics.sample_rate, 4.);
+        stats.reset_peak();
+        stats.push(120., start + Duration::from_millis(1750));
+        assert_eq!(stats.statistics().unwrap().peak, 120.);
+    }
+}
@@ -0,0 +1,387 @@
+#[cfg(feature = "i2c")]
+use rppal::i2c::I2c;
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::get_timestamp_ms;
+use tracing::{error, info, warn};
+
+// LSM303AGR, its accelerometer and magnetometer answering at two addresses
+const ACCEL_ADDRESS: u16 = 0x19;
+const MAG_ADDRESS: u16 = 0x1E;
+const WHO_AM_I_A: u8 = 0x0F;
+const WHO_AM_I_M: u8 = 0x4F;
+const ACCEL_ID: u8 = 0x33;
+const MAG_ID: u8 = 0x40;
+// 100 Hz with the three axes, then block data update in high resolution
+const CTRL_REG1_A: u8 = 0x20;
+const CTRL_REG4_A: u8 = 0x23;
+const ACCEL_ODR_100HZ_XYZ: u8 = 0x57;
+const ACCEL_BDU_HR: u8 = 0x88;
+// Continuous 20 Hz with the temperature compensation, then block data update
+const CFG_REG_A_M: u8 = 0x60;
+const CFG_REG_C_M: u8 = 0x62;
+const MAG_CONTINUOUS_20HZ: u8 = 0x84;
+const MAG_BDU: u8 = 0x10;
+// X, Y and Z, low byte first, the accelerometer only reading several registers with the top bit of the first set
+const OUT_X_L_A: u8 = 0x28 | 0x80;
+const OUTX_L_REG_M: u8 = 0x68;
+
+const SAMPLE_PERIOD: Duration = Duration::from_millis(50);
+// Share of each sample in the vectors, the waves shaking the accelerometer far more than the heel changes
+const SMOOTHING: f32 = 0.2;
+// A heading older than this is considered lost
+const HEADING_TIMEOUT_MS: u64 = 1000;
+
+// Calibration of the magnetometer on board, in the working directory
+const CALIBRATION_FILE: &str = "compass_calibration.json";
+// Five minutes of circling at most are kept
+const MAX_CALIBRATION_SAMPLES: usize = 6000;
+// Compass rose cut in sectors, a calibration being rejected unless enough of them were sampled
+const ROSE_SECTORS: usize = 36;
+const MIN_COVERAGE: f32 = 90.;
+// Spread of the corrected field strength above which a calibration is rejected
+const MAX_ERROR: f32 = 10.;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Heading {
+    pub degrees: f32,       // Magnetic, tilt compensated, 0 to 360
+    pub raw_degrees: f32,   // Magnetic, as if the boat were level
+    pub heel: f32,          // Degrees, starboard side down positive
+    pub timestamp: u64,     // Local time of the last sample in ms
+}
+
+impl Heading {
+    pub fn is_fresh(&self) -> bool {
+        get_timestamp_ms().saturating_sub(self.timestamp) < HEADING_TIMEOUT_MS
+    }
+}
+
+/// How well a calibration fits the circles it comes from
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Quality {
+    pub coverage: f32,      // Percent of the compass rose sampled
+    pub error: f32,         // Spread of the corrected field strength, in percent of its mean
+}
+
+/// Hard and soft iron correction of the magnetometer, in the boat frame.
+/// Circling on flat water turns the field in the horizontal plane only, z keeping its previous correction.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MagCalibration {
+    pub offset: [f32; 3],   // Hard iron, the field of the boat itself, subtracted
+    pub scale: [f32; 3],    // Soft iron, making the circle of the field round again
+    pub quality: Quality,
+}
+
+impl MagCalibration {
+    fn correct(&self, field: [f32; 3]) -> [f32; 3] {
+        [0, 1, 2].map(|i| (field[i] - self.offset[i]) * self.scale[i])
+    }
+}
+
+/// Stage of the calibration commanded from the remote
+#[derive(Debug, Default)]
+enum CalibrationStage {
+    #[default]
+    Idle,
+    Circling(Vec<[f32; 3]>),        // Raw field of each sample
+    Rejected(Option<Quality>),      // None when the field did not turn at all
+}
+
+/// Heading and calibration of the compass, shared by its thread and the link
+#[derive(Debug, Default)]
+pub struct Compass {
+    pub heading: Option<Heading>,
+    calibration: Option<MagCalibration>,
+    stage: CalibrationStage,
+}
+
+impl Compass {
+    /// Records the field while true, then computes the calibration, saved and applied if good enough
+    pub fn set_calibrating(&mut self, calibrating: bool) {
+        match (&self.stage, calibrating) {
+            (CalibrationStage::Circling(_), false) => {
+                if let CalibrationStage::Circling(samples) = std::mem::take(&mut self.stage) {
+                    self.finish_calibration(&samples);
+                }
+            }
+            (CalibrationStage::Circling(_), true) | (_, false) => {}
+            (_, true) => {
+                info!("Compass calibration started, circling");
+                self.stage = CalibrationStage::Circling(Vec::new());
+            }
+        }
+    }
+
+    fn finish_calibration(&mut self, samples: &[[f32; 3]]) {
+        let calibration = fit(samples, self.calibration);
+        match calibration {
+            Some(calibration) if calibration.quality.coverage >= MIN_COVERAGE && calibration.quality.error <= MAX_ERROR => {
+                info!("Compass calibrated from {} samples: {:?}", samples.len(), calibration);
+                if let Err(e) = save_calibration(&calibration) {
+                    error!("Could not save the compass calibration: {}", e);
+                }
+                self.calibration = Some(calibration);
+            }
+            _ => {
+                warn!("Compass calibration rejected, {} samples giving {:?}", samples.len(), calibration.map(|c| c.quality));
+                self.stage = CalibrationStage::Rejected(calibration.map(|c| c.quality));
+            }
+        }
+    }
+
+    /// Stage of the calibration, and the quality of the one being recorded, rejected or applied
+    pub fn calibration_report(&self) -> (&'static str, Option<Quality>) {
+        match &self.stage {
+            CalibrationStage::Circling(samples) => ("circling", fit(samples, self.calibration).map(|c| c.quality)),
+            CalibrationStage::Rejected(quality) => ("rejected", *quality),
+            CalibrationStage::Idle => match self.calibration {
+                Some(calibration) => ("calibrated", Some(calibration.quality)),
+                None => ("uncalibrated", None),
+            },
+        }
+    }
+
+    fn record(&mut self, field: [f32; 3]) {
+        if let CalibrationStage::Circling(samples) = &mut self.stage && samples.len() < MAX_CALIBRATION_SAMPLES {
+            samples.push(field);
+        }
+    }
+
+    fn correct(&self, field: [f32; 3]) -> [f32; 3] {
+        self.calibration.map_or(field, |calibration| calibration.correct(field))
+    }
+}
+
+/// Calibration centering and rounding the horizontal field of the samples, None unless it turned
+fn fit(samples: &[[f32; 3]], previous: Option<MagCalibration>) -> Option<MagCalibration> {
+    let mut calibration = previous.unwrap_or(MagCalibration {
+        offset: [0.; 3],
+        scale: [1.; 3],
+        quality: Quality { coverage: 0., error: 0. },
+    });
+    let mut radius = [0.; 2];
+    for axis in 0..2 {
+        let min = samples.iter().map(|sample| sample[axis]).fold(f32::INFINITY, f32::min);
+        let max = samples.iter().map(|sample| sample[axis]).fold(f32::NEG_INFINITY, f32::max);
+        if max <= min {
+            return None;
+        }
+        calibration.offset[axis] = (min + max) / 2.;
+        radius[axis] = (max - min) / 2.;
+    }
+    let mean_radius = (radius[0] + radius[1]) / 2.;
+    calibration.scale[0] = mean_radius / radius[0];
+    calibration.scale[1] = mean_radius / radius[1];
+
+    let mut sectors = [false; ROSE_SECTORS];
+    let strengths: Vec<f32> = samples.iter().map(|&sample| {
+        let [x, y, _] = calibration.correct(sample);
+        let sector = (wrap(y.atan2(x).to_degrees()) / 360. * ROSE_SECTORS as f32) as usize;
+        sectors[sector.min(ROSE_SECTORS - 1)] = true;
+        x.hypot(y)
+    }).collect();
+    let mean = strengths.iter().sum::<f32>() / strengths.len() as f32;
+    let variance = strengths.iter().map(|strength| (strength - mean).powi(2)).sum::<f32>() / strengths.len() as f32;
+    calibration.quality = Quality {
+        coverage: sectors.iter().filter(|&&sampled| sampled).count() as f32 * 100. / ROSE_SECTORS as f32,
+        error: variance.sqrt() * 100. / mean,
+    };
+    Some(calibration)
+}
+
+fn save_calibration(calibration: &MagCalibration) -> Result<(), Box<dyn std::error::Error>> {
+    fs::write(CALIBRATION_FILE, serde_json::to_string_pretty(calibration)?)?;
+    Ok(())
+}
+
+/// The calibration saved last, None before the first one
+fn load_calibration() -> Option<MagCalibration> {
+    match fs::read_to_string(CALIBRATION_FILE) {
+        Ok(content) => serde_json::from_str(&content).map_err(|e| error!("Compass calibration ignored, {}", e)).ok(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            warn!("Compass not calibrated, {} missing", CALIBRATION_FILE);
+            None
+        }
+        Err(e) => {
+            error!("Could not read {}: {}", CALIBRATION_FILE, e);
+            None
+        }
+    }
+}
+
+/// I2C bus of the chip, each access addressing one of its two devices
+trait Bus: Send {
+    fn write(&mut self, address: u16, register: u8, value: u8) -> Result<(), Box<dyn std::error::Error>>;
+    /// Reads the registers starting at the given one
+    fn read(&mut self, address: u16, register: u8, buffer: &mut [u8]) -> Result<(), Box<dyn std::error::Error>>;
+}
+
+#[cfg(feature = "i2c")]
+impl Bus for I2c {
+    fn write(&mut self, address: u16, register: u8, value: u8) -> Result<(), Box<dyn std::error::Error>> {
+        self.set_slave_address(address)?;
+        I2c::write(self, &[register, value])?;
+        Ok(())
+    }
+
+    fn read(&mut self, address: u16, register: u8, buffer: &mut [u8]) -> Result<(), Box<dyn std::error::Error>> {
+        self.set_slave_address(address)?;
+        self.write_read(&[register], buffer)?;
+        Ok(())
+    }
+}
+
+/// Reads the compass of an LSM303AGR on I2C bus 1, its two devices being identified first and its saved calibration
+/// applied, returning the first heading
+pub fn start(compass_mutex: Arc<Mutex<Compass>>) -> Result<f32, Box<dyn std::error::Error>> {
+    let mut bus = open_bus()?;
+    configure(bus.as_mut())?;
+    let calibration = load_calibration();
+    compass_mutex.lock().unwrap().calibration = calibration;
+    let (gravity, field) = sample(bus.as_mut())?;
+    let field = compass_mutex.lock().unwrap().correct(field);
+    let (degrees, heel) = tilt_compensated(gravity, field);
+    info!("Compass heading {:.0} deg, {:.0} deg uncompensated, heel {:.0} deg, calibration {:?}", degrees, raw_heading(field), heel, calibration);
+    thread::spawn(move || imu_thread(bus, compass_mutex));
+    Ok(degrees)
+}
+
+#[cfg(feature = "i2c")]
+fn open_bus() -> Result<Box<dyn Bus>, Box<dyn std::error::Error>> {
+    Ok(Box::new(I2c::with_bus(crate::selftest::I2C_BUS)?))
+}
+
+#[cfg(not(feature = "i2c"))]
+fn open_bus() -> Result<Box<dyn Bus>, Box<dyn std::error::Error>> {
+    Err("built without the i2c feature".into())
+}
+
+fn configure(bus: &mut dyn Bus) -> Result<(), Box<dyn std::error::Error>> {
+    for (address, register, id, name) in [(ACCEL_ADDRESS, WHO_AM_I_A, ACCEL_ID, "accelerometer"), (MAG_ADDRESS, WHO_AM_I_M, MAG_ID, "magnetometer")] {
+        let mut who_am_i = [0u8; 1];
+        bus.read(address, register, &mut who_am_i)?;
+        if who_am_i[0] != id {
+            return Err(format!("unknown {} id {:#04x}", name, who_am_i[0]).into());
+        }
+    }
+    bus.write(ACCEL_ADDRESS, CTRL_REG1_A, ACCEL_ODR_100HZ_XYZ)?;
+    bus.write(ACCEL_ADDRESS, CTRL_REG4_A, ACCEL_BDU_HR)?;
+    bus.write(MAG_ADDRESS, CFG_REG_A_M, MAG_CONTINUOUS_20HZ)?;
+    bus.write(MAG_ADDRESS, CFG_REG_C_M, MAG_BDU)?;
+    Ok(())
+}
+
+fn read_axes(bus: &mut dyn Bus, address: u16, register: u8) -> Result<[f32; 3], Box<dyn std::error::Error>> {
+    let mut bytes = [0u8; 6];
+    bus.read(address, register, &mut bytes)?;
+    Ok([0, 2, 4].map(|i| i16::from_le_bytes([bytes[i], bytes[i + 1]]) as f32))
+}
+
+/// Gravity and magnetic field in the boat frame: x to the bow, y to starboard, z down.
+/// The board lies flat, its x arrow to the bow, so its y and z axes are reversed, and the
+/// accelerometer measures the reaction to gravity, reversed again.
+fn sample(bus: &mut dyn Bus) -> Result<([f32; 3], [f32; 3]), Box<dyn std::error::Error>> {
+    let [ax, ay, az] = read_axes(bus, ACCEL_ADDRESS, OUT_X_L_A)?;
+    let [mx, my, mz] = read_axes(bus, MAG_ADDRESS, OUTX_L_REG_M)?;
+    Ok(([-ax, ay, az], [mx, -my, -mz]))
+}
+
+/// Degrees between 0 and 360
+fn wrap(degrees: f32) -> f32 {
+    degrees.rem_euclid(360.)
+}
+
+/// Heading of the horizontal part of the field, wrong by up to the inclination of the field once heeled
+fn raw_heading(field: [f32; 3]) -> f32 {
+    wrap((-field[1]).atan2(field[0]).to_degrees())
+}
+
+/// Heading and heel, the field being rotated back to the horizontal by the heel and pitch of the gravity
+fn tilt_compensated(gravity: [f32; 3], field: [f32; 3]) -> (f32, f32) {
+    let [gx, gy, gz] = gravity;
+    let [bx, by, bz] = field;
+    let roll = gy.atan2(gz);
+    let pitch = (-gx).atan2(gy * roll.sin() + gz * roll.cos());
+    let north = bx * pitch.cos() + (by * roll.sin() + bz * roll.cos()) * pitch.sin();
+    let east = bz * roll.sin() - by * roll.cos();
+    (wrap(east.atan2(north).to_degrees()), roll.to_degrees())
+}
+
+fn smooth(previous: [f32; 3], sample: [f32; 3]) -> [f32; 3] {
+    [0, 1, 2].map(|i| previous[i] + (sample[i] - previous[i]) * SMOOTHING)
+}
+
+fn imu_thread(mut bus: Box<dyn Bus>, compass_mutex: Arc<Mutex<Compass>>) {
+    let mut failed = false;
+    // Smoothed vectors, started over once stale
+    let mut vectors: Option<([f32; 3], [f32; 3])> = None;
+    loop {
+        match sample(bus.as_mut()) {
+            Ok((gravity, field)) => {
+                failed = false;
+                let mut compass = compass_mutex.lock().unwrap();
+                compass.record(field);
+                let field = compass.correct(field);
+                let (gravity, field) = match vectors {
+                    Some((previous_gravity, previous_field)) => (smooth(previous_gravity, gravity), smooth(previous_field, field)),
+                    None => (gravity, field),
+                };
+                vectors = Some((gravity, field));
+                let (degrees, heel) = tilt_compensated(gravity, field);
+                compass.heading = Some(Heading { degrees, raw_degrees: raw_heading(field), heel, timestamp: get_timestamp_ms() });
+            }
+            // Once per failure, the heading going stale
+            Err(e) if !failed => {
+                error!("Compass error: {}", e);
+                failed = true;
+                vectors = None;
+            }
+            Err(_) => {}
+        }
+        thread::sleep(SAMPLE_PERIOD);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tilt_compensation() {
+        // Field inclined 60 deg down, the boat heading north then east, level
+        assert!((tilt_compensated([0., 0., 1.], [0.5, 0., 0.866]).0 - 0.).abs() < 0.1);
+        assert!((tilt_compensated([0., 0., 1.], [0., -0.5, 0.866]).0 - 90.).abs() < 0.1);
+        assert!((raw_heading([0., -0.5, 0.866]) - 90.).abs() < 0.1);
+
+        // Heading north, heeled 30 deg to starboard: the raw heading is 41 deg off
+        let gravity = [0., 0.5, 0.866];
+        let field = [0.5, 0.433, 0.75];
+        let (degrees, heel) = tilt_compensated(gravity, field);
+        assert!(degrees.min(360. - degrees) < 0.1);
+        assert!((heel - 30.).abs() < 0.1);
+        assert!((raw_heading(field) - 319.1).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_calibration_fit() {
+        // Field of the boat shifting the circle, iron nearby flattening it
+        let circle = |degrees: std::ops::Range<u16>| -> Vec<[f32; 3]> {
+            degrees.map(|degrees| (degrees as f32).to_radians()).map(|angle| [100. + 300. * angle.cos(), -50. + 200. * angle.sin(), 400.]).collect()
+        };
+        let calibration = fit(&circle(0..360), None).unwrap();
+        assert_eq!(calibration.offset, [100., -50., 0.]);
+        assert_eq!(calibration.scale, [250. / 300., 250. / 200., 1.]);
+        assert_eq!(calibration.quality.coverage, 100.);
+        assert!(calibration.quality.error < 0.1);
+
+        // Half a circle only, off center and short of the coverage, and none at all
+        assert!(fit(&circle(0..180), None).unwrap().quality.coverage < MIN_COVERAGE);
+        assert_eq!(fit(&circle(0..1), None), None);
+    }
+}
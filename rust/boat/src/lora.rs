@@ -0,0 +1,69 @@
+#[cfg(feature = "lora")]
+use rppal::spi::{Bus, Mode, SlaveSelect, Spi};
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use pizboat_hal::RadioBus;
+use pizboat_lora::{Frame, Sx127x};
+use tracing::{error, info};
+
+use crate::BoatController;
+
+// Same as lora_frequency_hz in the remote settings
+const LORA_FREQUENCY_HZ: u32 = 868_100_000;
+#[cfg(feature = "lora")]
+const LORA_SPI_CLOCK_HZ: u32 = 1_000_000;
+const RECEIVE_POLL: Duration = Duration::from_millis(10);
+
+type Radio = Sx127x<Box<dyn RadioBus + Send>>;
+
+/// Answers the heartbeats of the remote on the LoRa backup link, applying the orders they carry
+pub fn start(controller: Arc<Mutex<BoatController>>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut radio = Sx127x::new(open_spi()?, LORA_FREQUENCY_HZ)?;
+    radio.listen()?;
+    info!("LoRa backup link on {} Hz", LORA_FREQUENCY_HZ);
+    thread::spawn(move || lora_thread(radio, controller));
+    Ok(())
+}
+
+#[cfg(feature = "lora")]
+fn open_spi() -> Result<Box<dyn RadioBus + Send>, Box<dyn std::error::Error>> {
+    Ok(Box::new(Spi::new(Bus::Spi0, SlaveSelect::Ss0, LORA_SPI_CLOCK_HZ, Mode::Mode0)?))
+}
+
+#[cfg(not(feature = "lora"))]
+fn open_spi() -> Result<Box<dyn RadioBus + Send>, Box<dyn std::error::Error>> {
+    Err("built without the lora feature, SPI unavailable".into())
+}
+
+fn lora_thread(mut radio: Radio, controller: Arc<Mutex<BoatController>>) {
+    let mut failed = false;
+    loop {
+        match receive(&mut radio, &controller) {
+            Ok(()) => failed = false,
+            // Once per failure
+            Err(e) if !failed => {
+                error!("LoRa error: {}", e);
+                failed = true;
+            }
+            Err(_) => {}
+        }
+        thread::sleep(RECEIVE_POLL);
+    }
+}
+
+fn receive(radio: &mut Radio, controller: &Mutex<BoatController>) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(packet) = radio.receive()? else {
+        return Ok(());
+    };
+    let Some(Frame::Command(command)) = Frame::decode(&packet.payload) else {
+        return Ok(());
+    };
+    // Acked first, the remote only waits for a short while
+    radio.send(&Frame::Ack(command.seq).encode())?;
+    radio.listen()?;
+    controller.lock().unwrap().apply_lora(&command)?;
+    Ok(())
+}
@@ -1,62 +1,281 @@
 mod hx711;
+mod gps;
+mod accessories;
+mod return_home;
+mod tension;
+mod sbus;
+mod lora;
+mod nrf24;
+mod telemetry;
+mod selftest;
+mod waveform;
+mod wind_vane;
+mod trim;
+mod pulse_counter;
+mod sonar;
+mod imu;
+mod config;
+mod battery;
 
-use hx711::{HX711, Gain};
+use hx711::{HX711, HX711Sampler, Gain, LoadCell};
+use gps::{GpsFix, gps_thread};
+use accessories::Accessories;
+use return_home::return_home_thread;
+use tension::TensionGuard;
+use trim::TrimPoint;
+use sbus::{Mirrored, SbusChannel, SbusOutput};
+use selftest::SelfTest;
+use waveform::WaveformOutput;
+use wind_vane::WindAngle;
+use pulse_counter::PulseCounter;
+use sonar::Depth;
+use imu::{Compass, Heading};
+use battery::BatteryVoltage;
+use config::Settings;
 
 use anyhow::Result;
-use rust_pigpio::{initialize, pwm::servo};
-use serde::{Deserialize, Serialize};
+use pizboat_hal::PwmOut;
+use pizboat_link::Transport;
+use pizboat_link::wireless;
+use pizboat_lora::Command;
+use pizboat_protocol::{AppliedPulses, Failsafe, Message, Query, TelemetryValue};
+use rust_pigpio::{initialize, pwm::{pwm, servo, set_pwm_frequency, set_pwm_range}};
+use std::collections::BTreeMap;
 use std::thread;
 use std::sync::{Arc, Mutex};
+use std::net::TcpStream;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use tungstenite::{connect, Message};
-use std::fs;
+use tungstenite::{connect, WebSocket};
+use tungstenite::stream::MaybeTlsStream;
+use tracing::{error, info, trace, warn};
 
 const WS_URL: &str = "ws://10.250.1.1:10013";
+// Settings tuned on the boat, in the working directory
+const SETTINGS_FILE: &str = "boat.json";
+// Link to the remote, WebSocket over Wi-Fi or an nRF24L01 on SPI0 CE1 for range (built with the nrf24 feature)
+const LINK_TRANSPORT: LinkTransport = LinkTransport::WebSocket;
+// No message from the remote for that long means the link is lost
+const LINK_TIMEOUT_MS: u64 = 1000;
+// No command for that long applies the failsafe values, until the remote sends its own timeout, kept within the range
+const FAILSAFE_TIMEOUT_MS: u64 = 1000;
+const FAILSAFE_TIMEOUT_RANGE_MS: (u64, u64) = (200, 10_000);
+const FAILSAFE_CHECK_PERIOD: Duration = Duration::from_millis(50);
+// No LoRa heartbeat for that long means the backup link is lost too
+const LORA_TIMEOUT_MS: u64 = 2000;
 
-#[derive(Debug, Serialize)]
-struct QueryMessage {
-    #[serde(rename = "type")]
-    msg_type: String,
-    timestamp: u64,
-    wireless_quality: i16,
-    latency: u64,
-    weight: f32
+const MOTOR_NEUTRAL_US: u32 = 1500;
+const RUDDER_CENTER_US: u32 = 1500;
+
+const BOW_THRUSTER_NEUTRAL_US: u32 = 1500;
+
+const GIMBAL_CENTER_US: u32 = 1500;
+
+// Range of the pulse widths of the protocol, mapped onto that of each servo
+const SERVO_MIN_US: u32 = 1000;
+const SERVO_CENTER_US: u32 = 1500;
+const SERVO_MAX_US: u32 = 2000;
+// Rate of the pigpio servo pulses, faster servos going through its PWM
+const SERVO_REFRESH_HZ: u32 = 50;
+// Pulses of each servo timed by pigpio on its own, or all of them together in one waveform
+const SERVO_BACKEND: ServoBackend = ServoBackend::Pigpio;
+
+/// Boat side setup of one servo output
+struct ServoConfig {
+    name: &'static str,
+    pin_number: u32,
+    // Pulses per second: 50 for analog servos, more for the digital servos and ESCs which take it.
+    // pigpio PWM runs at 80, 100, 160, 200, 250, 320 and 400 Hz, the waveform at 50 Hz only,
+    // the servo is left out at other rates.
+    refresh_hz: u32,
+    range: PulseRange,
+    reverse: bool,      // Mirrored around the center, for a servo installed the other way round
+}
+
+/// Pulse widths in us a servo is driven within, the protocol range being mapped onto them on each side of the center
+#[derive(Clone, Copy)]
+struct PulseRange {
+    pulse_min: u32,
+    pulse_center: u32,
+    pulse_max: u32,
 }
 
-#[derive(Debug, Deserialize)]
-struct CommandResponse {
-    #[serde(rename = "type")]
-    msg_type: String,
-    timestamp: u64,
-    rudder_star: Option<u32>,
-    rudder_port: Option<u32>,
-    motor: Option<u32>,
-    boom: Option<u32>,
-    genoa: Option<u32>,
+const STANDARD_RANGE: PulseRange = PulseRange { pulse_min: SERVO_MIN_US, pulse_center: SERVO_CENTER_US, pulse_max: SERVO_MAX_US };
+
+// In the order of the remote channels without its misc channel, which is also that of the SBUS channels
+const SERVOS: [ServoConfig; 8] = [
+    ServoConfig { name: "rudder_star", pin_number: 23, refresh_hz: 50, range: STANDARD_RANGE, reverse: false },
+    ServoConfig { name: "rudder_port", pin_number: 24, refresh_hz: 50, range: STANDARD_RANGE, reverse: false },
+    ServoConfig { name: "motor", pin_number: 25, refresh_hz: 50, range: STANDARD_RANGE, reverse: false },
+    ServoConfig { name: "boom", pin_number: 22, refresh_hz: 50, range: STANDARD_RANGE, reverse: false },
+    ServoConfig { name: "genoa", pin_number: 27, refresh_hz: 50, range: STANDARD_RANGE, reverse: false },
+    ServoConfig { name: "bow_thruster", pin_number: 17, refresh_hz: 50, range: STANDARD_RANGE, reverse: false },
+    ServoConfig { name: "gimbal_pan", pin_number: 12, refresh_hz: 50, range: STANDARD_RANGE, reverse: false },
+    ServoConfig { name: "gimbal_tilt", pin_number: 13, refresh_hz: 50, range: STANDARD_RANGE, reverse: false },
+];
+
+// SX127x on SPI0 CE0 answering the remote when Wi-Fi fails (built with the lora feature)
+const LORA_ENABLED: bool = false;
+// AS5600 of the masthead wind vane on I2C bus 1 (built with the i2c feature)
+const WIND_VANE_ENABLED: bool = false;
+// Reed switch of a cup anemometer on GPIO 4, to ground (built with the pulse feature)
+const ANEMOMETER_ENABLED: bool = false;
+const ANEMOMETER_PIN: u8 = 4;
+// Wind speed of one pulse per second, 2.4 km/h for the common cups
+const ANEMOMETER_MS_PER_HZ: f32 = 0.667;
+const ANEMOMETER_DEBOUNCE: Duration = Duration::from_millis(5);
+// Wind speed averaged over the mean window, gusts being the highest over the gust window within it
+const WIND_MEAN_WINDOW: Duration = Duration::from_secs(10);
+const WIND_GUST_WINDOW: Duration = Duration::from_secs(3);
+// Hall sensor of a hull paddle wheel on GPIO 18, to ground (built with the pulse feature)
+const PADDLE_WHEEL_ENABLED: bool = false;
+const PADDLE_WHEEL_PIN: u8 = 18;
+// Water speed of one pulse per second, the travel of one paddle
+const PADDLE_WHEEL_MS_PER_HZ: f32 = 0.05;
+const PADDLE_WHEEL_DEBOUNCE: Duration = Duration::from_millis(2);
+const WATER_SPEED_WINDOW: Duration = Duration::from_secs(2);
+// Ping1D sonar on a serial port, measuring the depth under the hull (built with the sonar feature)
+const SONAR_ENABLED: bool = false;
+// LSM303AGR accelerometer and magnetometer on I2C bus 1, for a compass heading corrected for the heel (built with the i2c feature)
+const IMU_ENABLED: bool = false;
+// ADS1115 on I2C bus 1 reading the boat battery through a divider on its input 0 (built with the i2c feature)
+const BATTERY_ENABLED: bool = false;
+
+// Positions of the servo sweep of the self-test, each held for a step
+const SWEEP_PULSES_US: [u32; 3] = [1000, 2000, 1500];
+const SWEEP_STEP: Duration = Duration::from_millis(500);
+
+#[derive(PartialEq)]
+enum LinkTransport {
+    WebSocket,
+    Nrf24,
+}
+
+#[derive(PartialEq)]
+enum ServoBackend {
+    Pigpio,     // Servo pulses or PWM of pigpio, at the refresh rate of each servo
+    Waveform,   // One DMA waveform of pigpio for all the servos, starting their pulses together (built with the waveform feature)
+}
+
+/// Servo output of the pigpio daemon: its servo pulses at 50 Hz, its PWM with a range of one period in us above
+struct PigpioServo {
+    pin_number: u32,
+    pwm: bool,
+}
+
+impl PigpioServo {
+    fn new(pin_number: u32, refresh_hz: u32, pulse_max: u32) -> Result<Self> {
+        if refresh_hz == SERVO_REFRESH_HZ {
+            return Ok(Self { pin_number, pwm: false });
+        }
+        let period_us = pwm_period_us(refresh_hz, pulse_max)?;
+        // pigpio picks the closest rate it can do
+        let actual_hz = set_pwm_frequency(pin_number, refresh_hz).map_err(anyhow::Error::msg)?;
+        if actual_hz != refresh_hz {
+            anyhow::bail!("{} Hz not available with pigpio PWM, {} Hz being the closest", refresh_hz, actual_hz);
+        }
+        set_pwm_range(pin_number, period_us).map_err(anyhow::Error::msg)?;
+        Ok(Self { pin_number, pwm: true })
+    }
+}
+
+impl PwmOut for PigpioServo {
+    fn set_pulse_us(&mut self, pulse_width_us: u32) -> Result<(), Box<dyn std::error::Error>> {
+        if self.pwm {
+            pwm(self.pin_number, pulse_width_us)?;
+        } else {
+            servo(self.pin_number, pulse_width_us)?;
+        }
+        Ok(())
+    }
+}
+
+/// Output of a servo on the selected backend, provided its refresh rate is one the backend runs at
+fn servo_output(config: &ServoConfig, index: usize, waveform: Option<&WaveformOutput>) -> Result<Box<dyn PwmOut + Send>> {
+    match SERVO_BACKEND {
+        ServoBackend::Pigpio => Ok(Box::new(PigpioServo::new(config.pin_number, config.refresh_hz, config.range.pulse_max)?)),
+        ServoBackend::Waveform => {
+            if config.refresh_hz != waveform::FRAME_RATE_HZ {
+                anyhow::bail!("{} Hz with the waveform output, which runs at {} Hz", config.refresh_hz, waveform::FRAME_RATE_HZ);
+            }
+            let waveform = waveform.ok_or_else(|| anyhow::anyhow!("waveform output not started"))?;
+            Ok(Box::new(waveform.channel(index)))
+        }
+    }
+}
+
+/// Period of a refresh rate, which must leave room for the longest pulse
+fn pwm_period_us(refresh_hz: u32, pulse_max: u32) -> Result<u32> {
+    let period_us = 1_000_000 / refresh_hz.max(1);
+    if refresh_hz < SERVO_REFRESH_HZ || period_us <= pulse_max {
+        anyhow::bail!("{} Hz out of the rates of {} us pulses, {} to {} Hz", refresh_hz, pulse_max, SERVO_REFRESH_HZ, (1_000_000 - 1) / pulse_max);
+    }
+    Ok(period_us)
+}
+
+impl PulseRange {
+    /// Width of the pulse for a protocol value, within the protocol range
+    fn map(&self, pulse_width_us: u32) -> u32 {
+        let half = SERVO_MAX_US - SERVO_CENTER_US;
+        if pulse_width_us < SERVO_CENTER_US {
+            self.pulse_center - (SERVO_CENTER_US - pulse_width_us) * (self.pulse_center - self.pulse_min) / half
+        } else {
+            self.pulse_center + (pulse_width_us - SERVO_CENTER_US) * (self.pulse_max - self.pulse_center) / half
+        }
+    }
 }
 
 struct ServoController {
     name: String,
-    pin_number: u32,
+    range: PulseRange,
+    reverse: bool,
+    output: Option<Box<dyn PwmOut + Send>>,     // None when its pin could not be claimed, reported by the self-test
+    pulse_width_us: u32,    // Last applied, clamped to the protocol range, before its mapping onto that of the servo
 }
 
 impl ServoController {
-    fn new(name: &str, pin_number: u32) -> Result<Self> {
+    /// Copied to an SBUS channel when given, at the SBUS frame rate whatever its own
+    fn new(config: &ServoConfig, servo: Box<dyn PwmOut + Send>, sbus: Option<SbusChannel>) -> Result<Self> {
+        let PulseRange { pulse_min, pulse_center, pulse_max } = config.range;
+        if !(pulse_min < pulse_center && pulse_center < pulse_max) {
+            anyhow::bail!("Servo {} error: range {} {} {} us out of order", config.name, pulse_min, pulse_center, pulse_max);
+        }
+        let output: Box<dyn PwmOut + Send> = match sbus {
+            Some(copy) => Box::new(Mirrored { output: servo, copy }),
+            None => servo,
+        };
+        let controller = Self::with_output(config.name, config.range, config.reverse, output)?;
+
+        info!("Init servo {} to pin {} at {} Hz, {} to {} us", config.name, config.pin_number, config.refresh_hz,
+            config.range.pulse_min, config.range.pulse_max);
+
+        Ok(controller)
+    }
+
+    fn with_output(name: &str, range: PulseRange, reverse: bool, output: Box<dyn PwmOut + Send>) -> Result<Self> {
         let default_pulse_width_us = 1450;
         
-        servo(pin_number, default_pulse_width_us)
-          .map_err(|e| anyhow::anyhow!("Servo {} error: {}", name, e))?;
+        let mut controller = Self { name: name.to_string(), range, reverse, output: Some(output), pulse_width_us: default_pulse_width_us };
+        controller.set_servo_pulse(default_pulse_width_us)?;
 
-        println!("Init servo {} to pin {}", name.to_string(), pin_number);
+        Ok(controller)
+    }
 
-        Ok(Self { name: name.to_string(), pin_number})
+    /// Left out, its applied pulse staying at the default for the remote to see it
+    fn unclaimed(name: &str) -> Self {
+        Self { name: name.to_string(), range: STANDARD_RANGE, reverse: false, output: None, pulse_width_us: 1450 }
     }
 
+    /// Protocol value, reversed if needed then driving the servo within its own range
     fn set_servo_pulse(&mut self, pulse_width_us: u32) -> Result<()> {
-        let pulse_width_us = pulse_width_us.clamp(1000, 2000);
+        let pulse_width_us = pulse_width_us.clamp(SERVO_MIN_US, SERVO_MAX_US);
+        let driven_us = if self.reverse { 2 * SERVO_CENTER_US - pulse_width_us } else { pulse_width_us };
+        let Some(output) = self.output.as_mut() else {
+            return Ok(());
+        };
 
-        servo(self.pin_number, pulse_width_us)
+        output.set_pulse_us(self.range.map(driven_us))
           .map_err(|e| anyhow::anyhow!("Servo {} error: {}", self.name, e))?;
+        self.pulse_width_us = pulse_width_us;
 
         Ok(())
     }
@@ -68,130 +287,417 @@ struct BoatController {
     motor: ServoController,
     boom: ServoController,
     genoa: ServoController,
+    bow_thruster: ServoController,
+    gimbal_pan: ServoController,
+    gimbal_tilt: ServoController,
+    accessories: Accessories,
+    boat_id: u8,
+    // Every field the remote sent, its delta commands merged in
+    command: pizboat_protocol::Command,
+    last_command_ms: u64,
+    failsafe: Option<Failsafe>,
+    failsafe_timeout_ms: u64,
+    failsafe_applied: bool,     // Since the last command
+    // Last LoRa heartbeat of the remote
+    lora_command: Option<(Command, u64)>,
+    mainsheet_guard: TensionGuard,
+    jibsheet_guard: TensionGuard,
+    // Sails trimmed from the wind angle, and the bias of the remote on the boom and genoa
+    auto_trim: bool,
+    trim_bias_us: (i32, i32),
+    trim_table: Vec<TrimPoint>,
+    bow_thruster_max_speed: f32,
 }
 
 impl BoatController {
-    fn new() -> Result<Self, anyhow::Error> {
-        Ok(Self {
-            rudder_star: ServoController::new("rudder_star", 23)?,
-            rudder_port: ServoController::new("rudder_port", 24)?,
-            motor: ServoController::new("motor", 25)?,
-            boom: ServoController::new("boom", 22)?,
-            genoa: ServoController::new("genoa", 27)?,
-        })
+    /// SBUS channels follow the remote channels, without its misc channel.
+    /// Outputs whose pin cannot be claimed are left out, failing the GPIO check of the self-test.
+    fn new(settings: &Settings, sbus: Option<&SbusOutput>, self_test: &mut SelfTest) -> Self {
+        let waveform = if SERVO_BACKEND == ServoBackend::Waveform {
+            self_test.check("Waveform", WaveformOutput::start(&SERVOS.map(|config| config.pin_number)))
+        } else {
+            None
+        };
+        let mut unclaimed = Vec::new();
+        let servo = |index: usize| {
+            let config = &SERVOS[index];
+            servo_output(config, index, waveform.as_ref())
+              .map_err(|e| anyhow::anyhow!("Servo {} error: {}", config.name, e))
+              .and_then(|output| ServoController::new(config, output, sbus.map(|sbus| sbus.channel(index))))
+              .unwrap_or_else(|e| {
+                  error!("{}", e);
+                  unclaimed.push(config.name.to_string());
+                  ServoController::unclaimed(config.name)
+              })
+        };
+        let [rudder_star, rudder_port, motor, boom, genoa, bow_thruster, gimbal_pan, gimbal_tilt] = std::array::from_fn(servo);
+        let accessories = Accessories::new(settings, &mut unclaimed);
+        self_test.check("GPIO", if unclaimed.is_empty() { Ok(()) } else { Err(unclaimed.join(" ")) });
+
+        Self {
+            rudder_star,
+            rudder_port,
+            motor,
+            boom,
+            genoa,
+            bow_thruster,
+            gimbal_pan,
+            gimbal_tilt,
+            accessories,
+            boat_id: settings.boat_id,
+            command: Default::default(),
+            last_command_ms: get_timestamp_ms(),
+            failsafe: None,
+            failsafe_timeout_ms: FAILSAFE_TIMEOUT_MS,
+            failsafe_applied: false,
+            lora_command: None,
+            mainsheet_guard: TensionGuard::new(settings.tension_limit_g, settings.tension_hold_ms, settings.tension_ease_us),
+            jibsheet_guard: TensionGuard::new(settings.tension_limit_g, settings.tension_hold_ms, settings.tension_ease_us),
+            auto_trim: false,
+            trim_bias_us: (0, 0),
+            trim_table: trim::sorted_table(&settings.trim_table),
+            bow_thruster_max_speed: settings.bow_thruster_max_speed,
+        }
+    }
+
+    /// Move the servos which are not ESCs to both ends and back to center
+    fn sweep_servos(&mut self) -> Result<()> {
+        for pulse_width_us in SWEEP_PULSES_US {
+            for servo in [&mut self.rudder_star, &mut self.rudder_port, &mut self.boom, &mut self.genoa, &mut self.gimbal_pan, &mut self.gimbal_tilt] {
+                servo.set_servo_pulse(pulse_width_us)?;
+            }
+            thread::sleep(SWEEP_STEP);
+        }
+        Ok(())
     }
     
-    fn apply_commands(&mut self, cmd: &CommandResponse) -> Result<()> {
-        if let Some(val) = cmd.rudder_star {
+    /// All the channels are applied again, those left out by a delta with their last value, for the boat limits to act on them
+    fn apply_commands(&mut self, delta: &pizboat_protocol::Command, gps_fix: Option<GpsFix>, wind: Option<WindAngle>) -> Result<()> {
+        self.last_command_ms = get_timestamp_ms();
+        self.failsafe_applied = false;
+        self.command.merge(delta);
+        let cmd = self.command.clone();
+        
+        if let Some(val) = cmd.rudder_star.map(u32::from) {
             self.rudder_star.set_servo_pulse(val)?;
         }
-        if let Some(val) = cmd.rudder_port {
+        if let Some(val) = cmd.rudder_port.map(u32::from) {
             self.rudder_port.set_servo_pulse(val)?;
         }
-        if let Some(val) = cmd.motor {
-            self.motor.set_servo_pulse(val)?;
+        if let Some(val) = cmd.motor.map(u32::from) {
+            // A motor cut sent over LoRa wins over late Wi-Fi commands
+            self.motor.set_servo_pulse(if self.lora_motor_cut() { MOTOR_NEUTRAL_US } else { val })?;
+        }
+        if let Some(auto_trim) = cmd.auto_trim && auto_trim != self.auto_trim {
+            info!("Auto trim {}, wind angle {:?}", if auto_trim { "engaged" } else { "released" }, wind.map(|wind| wind.degrees));
+            self.auto_trim = auto_trim;
+        }
+        if self.auto_trim {
+            self.trim_sails(&cmd, wind)?;
+        } else {
+            if let Some(val) = cmd.boom.map(u32::from) {
+                self.boom.set_servo_pulse(self.mainsheet_guard.ease(val))?;
+            }
+            if let Some(val) = cmd.genoa.map(u32::from) {
+                self.genoa.set_servo_pulse(self.jibsheet_guard.ease(val))?;
+            }
+        }
+        if let Some(val) = cmd.bow_thruster.map(u32::from) {
+            // Without a GPS fix the speed is unknown, leave the thruster available
+            let too_fast = gps_fix.is_some_and(|fix| fix.speed > self.bow_thruster_max_speed);
+            self.bow_thruster.set_servo_pulse(if too_fast { BOW_THRUSTER_NEUTRAL_US } else { val })?;
         }
-        if let Some(val) = cmd.boom {
-            self.boom.set_servo_pulse(val)?;
+        if let Some(val) = cmd.gimbal_pan.map(u32::from) {
+            self.gimbal_pan.set_servo_pulse(val)?;
         }
-        if let Some(val) = cmd.genoa {
-            self.genoa.set_servo_pulse(val)?;
+        if let Some(val) = cmd.gimbal_tilt.map(u32::from) {
+            self.gimbal_tilt.set_servo_pulse(val)?;
         }
+        self.accessories.apply(cmd.nav_lights, cmd.spotlight, cmd.deck_light)?;
         Ok(())
-    }    
-}
-
-fn get_wireless_link_quality() -> i16 {
-    match read_link_quality() {
-        Ok(quality) => quality,
-        Err(err) => {
-            // Equivalent of logger.info in Python
-            eprintln!("Caught exception: {:?}", err);
-            -1
+    }
+    
+    /// Sails at the trim of the wind angle, offset by the sail values of the remote from their center.
+    /// They hold their position without a fresh wind angle.
+    fn trim_sails(&mut self, cmd: &pizboat_protocol::Command, wind: Option<WindAngle>) -> Result<()> {
+        let bias = |val: Option<u16>, kept: i32| val.map_or(kept, |val| val as i32 - SERVO_CENTER_US as i32);
+        self.trim_bias_us = (bias(cmd.boom, self.trim_bias_us.0), bias(cmd.genoa, self.trim_bias_us.1));
+        let Some(wind) = wind else {
+            return Ok(());
+        };
+        let (boom, genoa) = trim::sail_pulses(&self.trim_table, wind.degrees);
+        self.boom.set_servo_pulse(self.mainsheet_guard.ease(boom.saturating_add_signed(self.trim_bias_us.0)))?;
+        self.genoa.set_servo_pulse(self.jibsheet_guard.ease(genoa.saturating_add_signed(self.trim_bias_us.1)))?;
+        Ok(())
+    }
+    
+    /// Ease the sails whose sheet load stayed too high, applied with the next commands
+    fn update_tension(&mut self, mainsheet_g: Option<f32>, jibsheet_g: Option<f32>) {
+        let now = get_timestamp_ms();
+        let was_easing = self.is_easing();
+        self.mainsheet_guard.update(mainsheet_g, now);
+        self.jibsheet_guard.update(jibsheet_g, now);
+        if self.is_easing() != was_easing {
+            warn!("Sheet tension alarm {}: mainsheet {:?}g jibsheet {:?}g",
+                if was_easing { "cleared" } else { "raised, easing" }, mainsheet_g, jibsheet_g);
         }
     }
+    
+    fn applied(&self) -> AppliedPulses {
+        AppliedPulses {
+            rudder_star: self.rudder_star.pulse_width_us as u16,
+            rudder_port: self.rudder_port.pulse_width_us as u16,
+            motor: self.motor.pulse_width_us as u16,
+            boom: self.boom.pulse_width_us as u16,
+            genoa: self.genoa.pulse_width_us as u16,
+            bow_thruster: self.bow_thruster.pulse_width_us as u16,
+            gimbal_pan: self.gimbal_pan.pulse_width_us as u16,
+            gimbal_tilt: self.gimbal_tilt.pulse_width_us as u16,
+        }
+    }
+    
+    fn is_easing(&self) -> bool {
+        self.mainsheet_guard.is_easing() || self.jibsheet_guard.is_easing()
+    }
+    
+    fn link_lost_ms(&self) -> u64 {
+        get_timestamp_ms().saturating_sub(self.last_command_ms)
+    }
+    
+    /// Values and timeout of the remote
+    fn set_failsafe(&mut self, failsafe: Failsafe) {
+        let (min_ms, max_ms) = FAILSAFE_TIMEOUT_RANGE_MS;
+        self.failsafe_timeout_ms = failsafe.timeout_ms.map_or(FAILSAFE_TIMEOUT_MS, |timeout_ms| timeout_ms.clamp(min_ms, max_ms));
+        self.failsafe = Some(failsafe);
+    }
+    
+    /// Failsafe values applied once per link loss, the remote silent for the failsafe timeout
+    fn check_failsafe(&mut self) -> Result<()> {
+        if self.failsafe_applied || self.link_lost_ms() <= self.failsafe_timeout_ms {
+            return Ok(());
+        }
+        warn!("No command for {}ms, failsafe values applied", self.failsafe_timeout_ms);
+        self.on_link_lost()
+    }
+    
+    fn on_link_lost(&mut self) -> Result<()> {
+        self.failsafe_applied = true;
+        if let Some(failsafe) = &self.failsafe {
+            self.rudder_star.set_servo_pulse(failsafe.rudder_star.into())?;
+            self.rudder_port.set_servo_pulse(failsafe.rudder_port.into())?;
+            self.motor.set_servo_pulse(failsafe.motor.into())?;
+            self.boom.set_servo_pulse(failsafe.boom.into())?;
+            self.genoa.set_servo_pulse(failsafe.genoa.into())?;
+            self.bow_thruster.set_servo_pulse(failsafe.bow_thruster.into())?;
+            self.gimbal_pan.set_servo_pulse(failsafe.gimbal_pan.into())?;
+            self.gimbal_tilt.set_servo_pulse(failsafe.gimbal_tilt.into())?;
+            return Ok(());
+        }
+        
+        // Nothing received from the remote yet
+        self.motor.set_servo_pulse(MOTOR_NEUTRAL_US)?;
+        self.gimbal_pan.set_servo_pulse(GIMBAL_CENTER_US)?;
+        self.gimbal_tilt.set_servo_pulse(GIMBAL_CENTER_US)?;
+        Ok(())
+    }
+    
+    /// Orders of a LoRa heartbeat: the motor cut applies at once, the rudder is only centered once Wi-Fi is lost
+    fn apply_lora(&mut self, command: &Command) -> Result<()> {
+        self.lora_command = Some((*command, get_timestamp_ms()));
+        if command.motor_cut {
+            self.motor.set_servo_pulse(MOTOR_NEUTRAL_US)?;
+        }
+        if command.rudder_center && self.link_lost_ms() > LINK_TIMEOUT_MS {
+            self.rudder_star.set_servo_pulse(RUDDER_CENTER_US)?;
+            self.rudder_port.set_servo_pulse(RUDDER_CENTER_US)?;
+        }
+        Ok(())
+    }
+    
+    /// The remote still reaches the boat over LoRa
+    fn lora_alive(&self) -> bool {
+        self.lora_command.is_some_and(|(_, at)| get_timestamp_ms().saturating_sub(at) < LORA_TIMEOUT_MS)
+    }
+    
+    fn lora_motor_cut(&self) -> bool {
+        self.lora_alive() && self.lora_command.is_some_and(|(command, _)| command.motor_cut)
+    }
+    
+    /// Steer with a rudder offset from center, used by the return to home autopilot
+    fn limp_home(&mut self, rudder_offset_us: i32, motor_us: u32) -> Result<()> {
+        let rudder = RUDDER_CENTER_US.saturating_add_signed(rudder_offset_us);
+        self.rudder_star.set_servo_pulse(rudder)?;
+        self.rudder_port.set_servo_pulse(rudder)?;
+        self.motor.set_servo_pulse(motor_us)?;
+        Ok(())
+    }    
 }
 
-fn read_link_quality() -> Result<i16, Box<dyn std::error::Error>> {
-    let content = fs::read_to_string("/proc/net/wireless")?;
-    let lines: Vec<&str> = content.lines().collect();
-    if lines.len() < 3 { return Ok(-1); }
-    let data_line = lines[2].trim();
-    let fields: Vec<&str> = data_line.split_whitespace().collect();
-    if fields.len() <= 2 { return Ok(-1); }
-    let link_quality = fields[2];
-    let link_quality = link_quality.trim_end_matches('.');
-    let quality = link_quality.parse::<i16>()?;
-    Ok(quality)
-}
-
-fn get_timestamp_ms() -> u64 {
+pub fn get_timestamp_ms() -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap()
         .as_millis() as u64
 }
 
-fn handle_websocket(controller: &mut BoatController, weight_mutex: Arc<Mutex<Option<f32>>>) -> Result<()> {
-    let (mut socket, _response) = connect(WS_URL)?;
-    println!("WebSocket connected to {}", WS_URL);
+fn connect_websocket() -> Result<WebSocket<MaybeTlsStream<TcpStream>>> {
+    let (socket, _response) = connect(WS_URL)?;
+    info!("WebSocket connected to {}", WS_URL);
+    if let MaybeTlsStream::Plain(stream) = socket.get_ref() {
+        stream.set_read_timeout(Some(Duration::from_millis(LINK_TIMEOUT_MS)))?;
+    }
+    Ok(socket)
+}
+
+/// Query the remote and apply its commands until the link is lost
+fn handle_link(link: &mut impl Transport, controller: &Mutex<BoatController>, load: Option<&HX711Sampler>, gps_mutex: Arc<Mutex<Option<GpsFix>>>, wind_mutex: &Mutex<Option<WindAngle>>, sensors: &Sensors, self_test: &SelfTest) -> Result<()> {
+    // Peak loads of this session
+    if let Some(load) = load {
+        load.reset_peaks();
+    }
 
     let mut counter = 0;
     let max_counter = 1000 / 40;
     
     let mut latency = 0;
+    // remote_timestamp of the last command and when it was received
+    let mut echo: Option<(u64, u64)> = None;
+    // Commands addressed to another boat, reported once
+    let mut ignored = false;
+    // Sent with the first query only
+    let mut self_test = Some(self_test.checks());
+    let boat_id = controller.lock().unwrap().boat_id;
     
 
     loop {
         let timestamp = get_timestamp_ms();
-        let wireless_quality = get_wireless_link_quality();
+        let wireless = wireless::read();
+        let wireless_quality = wireless.map_or(-1, |w| w.quality);
         
-        let weight = match *(weight_mutex.lock().unwrap())
-        {
-        Some(d) => { d }
-        None => { -1 as f32 }
+        let weight = load.and_then(|load| load.latest_weight(MAINSHEET).ok());
+        let jib_weight = load.and_then(|load| load.latest_weight(JIBSHEET).ok());
+        let wind = (*wind_mutex.lock().unwrap()).filter(|wind| wind.is_fresh());
+        let gps_fix = (*gps_mutex.lock().unwrap()).filter(|fix| fix.is_fresh());
+        let (tension_alarm, applied) = {
+            let mut controller = controller.lock().unwrap();
+            controller.update_tension(weight, jib_weight);
+            (controller.is_easing(), controller.applied())
         };
         
-        let query = QueryMessage {
-            msg_type: "query".to_string(),
+        // Readings without a field of their own in the query
+        let mut telemetry = BTreeMap::new();
+        if let Some(temperature) = telemetry::cpu_temperature() {
+            telemetry.insert("cpu_temp".to_string(), TelemetryValue::number(temperature, "C"));
+        }
+        for (cell, name) in [(MAINSHEET, "mainsheet_peak"), (JIBSHEET, "jibsheet_peak")] {
+            if let Some(stats) = load.and_then(|load| load.statistics(cell)) {
+                telemetry.insert(name.to_string(), TelemetryValue::number(stats.peak.round() as f64, "g"));
+            }
+        }
+        if let Some(fix) = gps_fix {
+            telemetry.insert("course".to_string(), TelemetryValue::number(fix.course.round().into(), "deg"));
+        }
+        sensors.add_telemetry(&mut telemetry);
+        let transport = if LINK_TRANSPORT == LinkTransport::Nrf24 { "nrf24" } else { "wifi" };
+        telemetry.insert("link".to_string(), TelemetryValue::string(transport));
+        
+        let query = Message::Query(Query {
+            boat_id: Some(boat_id),
             timestamp,
-            wireless_quality,
-            latency,
-            weight
-        };
+            wireless_quality: Some(wireless_quality),
+            wireless_signal: wireless.map(|w| w.signal_dbm),
+            latency: Some(latency),
+            echo_timestamp: echo.map(|(remote_timestamp, _)| remote_timestamp),
+            echo_delay_ms: echo.map(|(_, received)| timestamp.saturating_sub(received)),
+            weight: Some(weight.unwrap_or(-1.)),
+            jib_weight: Some(jib_weight.unwrap_or(-1.)),
+            tension_alarm: Some(tension_alarm),
+            applied: Some(applied),
+            wind_angle: wind.map(|wind| wind.degrees),
+            latitude: gps_fix.map(|fix| fix.latitude),
+            longitude: gps_fix.map(|fix| fix.longitude),
+            speed: gps_fix.map(|fix| fix.speed),
+            heading: sensors.heading().map(|heading| heading.degrees),
+            battery_voltage: sensors.battery_voltage(),
+            telemetry,
+            self_test: self_test.take(),
+            ..Default::default()
+        });
         
         let query_json = serde_json::to_string(&query)?;
         
-        // println!("Update {query_json}");
-        socket.send(Message::Text(query_json))?;
+        trace!("Update {query_json}");
+        link.send(&query_json).map_err(|e| anyhow::anyhow!("Link error: {}", e))?;
         
-        match socket.read() {
-            Ok(Message::Text(text)) => {
-                // println!("Update {text}");
-                match serde_json::from_str::<CommandResponse>(&text) {
-                    Ok(response) => {
+        match link.receive() {
+            Ok(Some(text)) => {
+                trace!("Update {text}");
+                
+                // Failsafe values come before the command answering the query
+                let mut message = pizboat_protocol::parse(&text);
+                if let Ok(Message::Failsafe(failsafe)) = message {
+                    if failsafe.is_for(boat_id) {
+                        info!("Failsafe values received: {:?}", failsafe);
+                        controller.lock().unwrap().set_failsafe(failsafe);
+                    }
+                    message = match link.receive() {
+                        Ok(Some(text)) => pizboat_protocol::parse(&text),
+                        Ok(None) => {
+                            error!("No command from the remote for {}ms", LINK_TIMEOUT_MS);
+                            break;
+                        }
+                        Err(e) => {
+                            error!("Link error: {}", e);
+                            break;
+                        }
+                    };
+                }
+                
+                match message {
+                    // The remote drives another boat: hold the failsafe values and keep polling
+                    Ok(Message::Command(response)) if !response.is_for(boat_id) => {
+                        if !ignored {
+                            warn!("Commands for boat {:?} ignored, this is boat {}", response.boat_id, boat_id);
+                            controller.lock().unwrap().on_link_lost()?;
+                            ignored = true;
+                        }
+                    }
+                    Ok(Message::Command(response)) => {
+                        ignored = false;
                         let now = get_timestamp_ms();
                         latency = now.saturating_sub(response.timestamp);
+                        echo = response.remote_timestamp.map(|remote_timestamp| (remote_timestamp, now));
                         
-                        if let Err(e) = controller.apply_commands(&response) {
-                            eprintln!("Error applying command: {}", e);
+                        if let Some(calibrating) = response.calibrate_compass {
+                            sensors.calibrate_compass(calibrating);
+                        }
+                        if let Err(e) = controller.lock().unwrap().apply_commands(&response, gps_fix, wind) {
+                            error!("Error applying command: {}", e);
                         } else {
-                            // println!("Commands applied - lag: {}ms", lag_ms);
+                            trace!("Commands applied - lag: {}ms", latency);
                             counter += 1;
                             if counter % max_counter == 0
                             {
-                                println!("Counter {} wireless quality: {} lag: {}ms", counter, wireless_quality, latency);
+                                info!("Counter {} wireless quality: {} (remote {:?}) signal: {:?}dBm (remote {:?}dBm) lag: {}ms", counter,
+                                    wireless_quality, response.wireless_quality, wireless.map(|w| w.signal_dbm), response.wireless_signal, latency);
+                                if let Some(load) = load {
+                                    log_load_statistics(load);
+                                }
                             }
                         }
                     }
-                    Err(e) => eprintln!("JSON parse error: {}", e),
+                    Ok(other) => error!("Unexpected message from the remote: {:?}", other),
+                    Err(e) => error!("Remote message refused, {}", e),
                 }
             }
+            Ok(None) => {
+                error!("No command from the remote for {}ms", LINK_TIMEOUT_MS);
+                break;
+            }
             Err(e) => {
-                eprintln!("WebSocket error: {}", e);
+                error!("Link error: {}", e);
                 break;
             }
-            _ => {}
         }
         
         thread::sleep(Duration::from_millis(40));
@@ -200,57 +706,249 @@ fn handle_websocket(controller: &mut BoatController, weight_mutex: Arc<Mutex<Opt
     Ok(())
 }
 
-// Your calibration constants
-const OFFSET: i32 = 8661777;  // Zero offset value
-const SCALE: f32 = 960.33;     // Scale factor (raw units per gram)
+/// Watch the commands, whether the link is up, reconnecting or silent, the failsafe timeout being shorter or longer than LINK_TIMEOUT_MS
+fn failsafe_thread(controller: Arc<Mutex<BoatController>>) {
+    loop {
+        if let Err(e) = controller.lock().unwrap().check_failsafe() {
+            error!("Error on link lost: {}", e);
+        }
+        thread::sleep(FAILSAFE_CHECK_PERIOD);
+    }
+}
 
+/// Sensors reported to the remote without a part in the control, None when disabled or failed
+struct Sensors {
+    anemometer: Option<PulseCounter>,
+    paddle_wheel: Option<PulseCounter>,
+    depth: Option<Arc<Mutex<Option<Depth>>>>,
+    compass: Option<Arc<Mutex<Compass>>>,
+    battery: Option<Arc<Mutex<Option<BatteryVoltage>>>>,
+}
 
-fn hx711_thread(weight_mutex: Arc<Mutex<Option<f32>>>) {
-    let mut hx711 = HX711::new(5, 6, Gain::ChAGain128).expect("Could not init hx711 :");
+impl Sensors {
+    fn new(settings: &Settings, self_test: &mut SelfTest) -> Self {
+        let anemometer = ANEMOMETER_ENABLED.then(|| {
+            self_test.check("Anemometer", PulseCounter::new(ANEMOMETER_PIN, ANEMOMETER_DEBOUNCE, WIND_MEAN_WINDOW))
+        }).flatten();
+        let paddle_wheel = PADDLE_WHEEL_ENABLED.then(|| {
+            self_test.check("Paddle wheel", PulseCounter::new(PADDLE_WHEEL_PIN, PADDLE_WHEEL_DEBOUNCE, WATER_SPEED_WINDOW))
+        }).flatten();
+        let depth = SONAR_ENABLED.then(|| {
+            let depth = Arc::new(Mutex::new(None));
+            // The GPS keeps its device
+            let started = if config::same_device(&settings.sonar_device, &settings.gps_device) {
+                Err(format!("{} is also the GPS device", settings.sonar_device).into())
+            } else {
+                sonar::start(&settings.sonar_device, Arc::clone(&depth))
+            };
+            self_test.check_with("Sonar", started, |meters| format!("{:.2}m", meters)).map(|_| depth)
+        }).flatten();
+        let compass = IMU_ENABLED.then(|| {
+            let compass = Arc::new(Mutex::new(Compass::default()));
+            self_test.check_with("Compass", imu::start(Arc::clone(&compass)), |degrees| format!("{:.0} deg", degrees)).map(|_| compass)
+        }).flatten();
+        let battery = BATTERY_ENABLED.then(|| {
+            let battery = Arc::new(Mutex::new(None));
+            self_test.check_with("Battery", battery::start(Arc::clone(&battery), settings.battery_scale), |volts| format!("{:.2}V", volts)).map(|_| battery)
+        }).flatten();
+        Sensors { anemometer, paddle_wheel, depth, compass, battery }
+    }
 
-    loop {
-         match hx711.get_value() {
-            Some(raw_value) => {
-                // Calculate weight using calibration
-                let weight = (raw_value - OFFSET) as f32 / SCALE;
-                // println!("Raw: {:8} | Weight: {:8.2} g", raw_value, weight);
-                
-                {
-                    let mut locked = weight_mutex.lock().unwrap();
-                    *locked = Some(weight);
-                }
-            }
-            None => {
-                println!("Error: Failed to read from sensor");
-                {
-                    let mut locked = weight_mutex.lock().unwrap();
-                    *locked = None;
-                }
+    fn battery_voltage(&self) -> Option<f32> {
+        self.battery.as_ref().and_then(|battery| battery.lock().unwrap().filter(BatteryVoltage::is_fresh)).map(|battery| battery.volts)
+    }
+
+    fn heading(&self) -> Option<Heading> {
+        self.compass.as_ref().and_then(|compass| compass.lock().unwrap().heading.filter(Heading::is_fresh))
+    }
 
+    /// Calibration of the magnetometer commanded from the remote, while the boat is driven in circles
+    fn calibrate_compass(&self, calibrating: bool) {
+        if let Some(compass) = &self.compass {
+            compass.lock().unwrap().set_calibrating(calibrating);
+        }
+    }
+
+    /// Speeds in m/s to the decimeter per second, depth in m to the centimeter, angles to the degree.
+    /// The heading corrected for the heel goes in the query, the uncorrected one in the telemetry to compare,
+    /// along with the stage of the compass calibration and its quality in percent.
+    fn add_telemetry(&self, telemetry: &mut BTreeMap<String, TelemetryValue>) {
+        let mut insert = |name: &str, speed: f32| {
+            telemetry.insert(name.to_string(), TelemetryValue::number((speed as f64 * 10.).round() / 10., "m/s"));
+        };
+        if let Some(anemometer) = &self.anemometer {
+            insert("wind_speed", anemometer.rate_hz(WIND_MEAN_WINDOW) * ANEMOMETER_MS_PER_HZ);
+            insert("wind_gust", anemometer.peak_rate_hz(WIND_MEAN_WINDOW, WIND_GUST_WINDOW) * ANEMOMETER_MS_PER_HZ);
+        }
+        if let Some(paddle_wheel) = &self.paddle_wheel {
+            insert("water_speed", paddle_wheel.rate_hz(WATER_SPEED_WINDOW) * PADDLE_WHEEL_MS_PER_HZ);
+        }
+        if let Some(depth) = self.depth.as_ref().and_then(|depth| depth.lock().unwrap().filter(Depth::is_fresh)) {
+            telemetry.insert("depth".to_string(), TelemetryValue::number((depth.meters as f64 * 100.).round() / 100., "m"));
+            telemetry.insert("depth_confidence".to_string(), TelemetryValue::number(depth.confidence.into(), "%"));
+        }
+        if let Some(heading) = self.heading() {
+            telemetry.insert("heading_raw".to_string(), TelemetryValue::number(heading.raw_degrees.round().into(), "deg"));
+            telemetry.insert("heel".to_string(), TelemetryValue::number(heading.heel.round().into(), "deg"));
+        }
+        if let Some(compass) = &self.compass {
+            let (stage, quality) = compass.lock().unwrap().calibration_report();
+            telemetry.insert("compass_cal".to_string(), TelemetryValue::string(stage));
+            if let Some(quality) = quality {
+                telemetry.insert("compass_coverage".to_string(), TelemetryValue::number(quality.coverage.round().into(), "%"));
+                telemetry.insert("compass_error".to_string(), TelemetryValue::number((quality.error as f64 * 10.).round() / 10., "%"));
             }
         }
-        thread::sleep(Duration::from_micros(20_000));
     }
 }
 
+fn log_load_statistics(load: &HX711Sampler) {
+    for (cell, name) in [(MAINSHEET, "Mainsheet"), (JIBSHEET, "Jibsheet")] {
+        if let Some(stats) = load.statistics(cell) {
+            info!("{} load mean: {:.0}g std-dev: {:.1}g peak: {:.0}g rate: {:.1}/s", name,
+                stats.mean, stats.std_dev, stats.peak, stats.sample_rate);
+        }
+    }
+}
+
+// Load cells on the HX711, by their index in the sampler
+const MAINSHEET: usize = 0;
+const JIBSHEET: usize = 1;
+const LOAD_CELLS: [LoadCell; 2] = [
+    LoadCell { gain: Gain::ChAGain128, offset: 8661777, scale: 960.33 },
+    // Not calibrated yet: zero input offset, and a quarter of the channel A scale with the gain of 32
+    LoadCell { gain: Gain::ChBGain32, offset: 8388608, scale: 240.08 },
+];
+
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    initialize().expect("Could not init pigpio !");
-    
+    // RUST_LOG levels, and daily files when PIZBOAT_LOG_DIR is set
+    let _log_guard = pizboat_logging::init_from_env("boat");
+    let settings = Settings::load_or_create(SETTINGS_FILE);
+    // Failures are reported to the remote, the boat running with what works
+    let mut self_test = SelfTest::default();
+    self_test.check_with("pigpio", initialize(), |version| format!("v{}", version));
 
-    let weight_mutex: Arc<Mutex<Option<f32>>> = Arc::new(Mutex::new(None));
-    let weight_mutex_clone = Arc::clone(&weight_mutex);
-    
-    
-    thread::spawn(move || hx711_thread(weight_mutex_clone));
+    let load = match HX711::new(5, 6, Gain::ChAGain128) {
+        // Present once a first conversion comes, sampled anyway in case it is only late
+        Ok(mut hx711) => {
+            self_test.check_with("HX711", hx711.get_value(), |raw| format!("raw {}", raw));
+            Some(HX711Sampler::start(hx711, LOAD_CELLS.to_vec()))
+        }
+        Err(e) => {
+            self_test.check("HX711", Err::<(), _>(e));
+            None
+        }
+    };
+    if cfg!(feature = "i2c") {
+        self_test.check_with("I2C", selftest::scan_i2c(), |found| {
+            found.iter().map(|address| format!("{:02x}", address)).collect::<Vec<_>>().join(" ")
+        });
+    }
+
+    let gps_mutex: Arc<Mutex<Option<GpsFix>>> = Arc::new(Mutex::new(None));
+    let gps_mutex_clone = Arc::clone(&gps_mutex);
+    let gps_device = settings.gps_device.clone();
+    thread::spawn(move || gps_thread(gps_device, gps_mutex_clone));
 
-    let mut controller = BoatController::new()?;
+    let wind_mutex: Arc<Mutex<Option<WindAngle>>> = Arc::new(Mutex::new(None));
+    if WIND_VANE_ENABLED {
+        self_test.check_with("Wind vane", wind_vane::start(Arc::clone(&wind_mutex)), |degrees| format!("{:.0} deg", degrees));
+    }
+    let sensors = Sensors::new(&settings, &mut self_test);
+
+    // Servo outputs copied as SBUS frames, for controllers expecting a receiver, when built with the sbus feature.
+    // A Pi Zero 2 W has one UART on its header: the GPS keeps it, SBUS only starting on another device
+    let sbus = cfg!(feature = "sbus").then(|| {
+        let sbus = if config::same_device(&settings.sbus_device, &settings.gps_device) {
+            Err(format!("{} is also the GPS device", settings.sbus_device).into())
+        } else {
+            SbusOutput::start(&settings.sbus_device)
+        };
+        self_test.check("SBUS", sbus)
+    }).flatten();
+    let mut boat = BoatController::new(&settings, sbus.as_ref(), &mut self_test);
+    // Rudders, sails and gimbal moved to both ends and back, to check them by eye.
+    // The motor and bow thruster are left alone, their ESCs would run.
+    if std::env::args().any(|arg| arg == "--sweep") {
+        self_test.check("Sweep", boat.sweep_servos());
+    }
+    let controller = Arc::new(Mutex::new(boat));
+    if LORA_ENABLED && let Err(e) = lora::start(Arc::clone(&controller)) {
+        error!("Could not start LoRa link: {}", e);
+    }
+    let controller_clone = Arc::clone(&controller);
+    let gps_mutex_clone = Arc::clone(&gps_mutex);
+    let compass = sensors.compass.clone();
+    let return_home_delay_ms = settings.return_home_delay_ms;
+    thread::spawn(move || return_home_thread(controller_clone, gps_mutex_clone, compass, return_home_delay_ms));
+    let controller_clone = Arc::clone(&controller);
+    thread::spawn(move || failsafe_thread(controller_clone));
+
+    // Opened once, the radio has no connection to set up
+    let mut radio = if LINK_TRANSPORT == LinkTransport::Nrf24 {
+        Some(nrf24::open(Duration::from_millis(LINK_TIMEOUT_MS))?)
+    } else {
+        None
+    };
 
     loop {
-        println!("Connecting to {}", WS_URL);
-        if let Err(e) = handle_websocket(&mut controller, Arc::clone(&weight_mutex)) {
-            eprintln!("Connection error: {}", e);
+        let result = match radio.as_mut() {
+            Some(radio) => handle_link(radio, &controller, load.as_ref(), Arc::clone(&gps_mutex), &wind_mutex, &sensors, &self_test),
+            None => {
+                info!("Connecting to {}", WS_URL);
+                connect_websocket().and_then(|mut socket| handle_link(&mut socket, &controller, load.as_ref(), Arc::clone(&gps_mutex), &wind_mutex, &sensors, &self_test))
+            }
+        };
+        
+        if let Err(e) = result {
+            error!("Connection error: {}", e);
             thread::sleep(Duration::from_secs(1));
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pizboat_hal::mock::MockPwm;
+
+    #[test]
+    fn test_servo_pulse_clamped() {
+        let pwm = MockPwm::default();
+        let mut servo = ServoController::with_output("boom", STANDARD_RANGE, false, Box::new(pwm.clone())).unwrap();
+        servo.set_servo_pulse(2500).unwrap();
+        servo.set_servo_pulse(1200).unwrap();
+        assert_eq!(pwm.pulses(), [1450, 2000, 1200]);
+    }
+
+    #[test]
+    fn test_pulse_range() {
+        let pwm = MockPwm::default();
+        let winch = PulseRange { pulse_min: 800, pulse_center: 1500, pulse_max: 2200 };
+        let mut servo = ServoController::with_output("boom", winch, false, Box::new(pwm.clone())).unwrap();
+        for pulse_width_us in [1000, 1250, 2000, 2500] {
+            servo.set_servo_pulse(pulse_width_us).unwrap();
+        }
+        assert_eq!(pwm.pulses(), [1430, 800, 1150, 2200, 2200]);
+        // Echoed in the protocol range
+        assert_eq!(servo.pulse_width_us, 2000);
+
+        // Reversed before the mapping, the range being that of the servo itself
+        let pwm = MockPwm::default();
+        let mut servo = ServoController::with_output("boom", winch, true, Box::new(pwm.clone())).unwrap();
+        servo.set_servo_pulse(1250).unwrap();
+        assert_eq!(pwm.pulses(), [1570, 1850]);
+        assert_eq!(servo.pulse_width_us, 1250);
+    }
+
+    #[test]
+    fn test_refresh_rates() {
+        assert_eq!(pwm_period_us(200, 2000).unwrap(), 5000);
+        assert_eq!(pwm_period_us(400, 2000).unwrap(), 2500);
+        // No room left for the longest pulse
+        assert!(pwm_period_us(500, 2000).is_err());
+        assert!(pwm_period_us(400, 2600).is_err());
+        assert!(pwm_period_us(20, 2000).is_err());
+    }
+}
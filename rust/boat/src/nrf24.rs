@@ -0,0 +1,41 @@
+#[cfg(feature = "nrf24")]
+use rppal::gpio::Gpio;
+#[cfg(feature = "nrf24")]
+use rppal::spi::{Bus, Mode, SlaveSelect, Spi};
+
+use std::time::Duration;
+
+use pizboat_hal::{DigitalOut, RadioBus};
+use pizboat_link::{Nrf24, Nrf24Transport};
+use tracing::info;
+
+// Same as nrf24_channel in the remote settings
+const NRF24_CHANNEL: u8 = 76;
+#[cfg(feature = "nrf24")]
+const NRF24_SPI_CLOCK_HZ: u32 = 4_000_000;
+#[cfg(feature = "nrf24")]
+const NRF24_CE_PIN: u8 = 26;
+
+pub type RadioTransport = Nrf24Transport<Box<dyn RadioBus + Send>, Box<dyn DigitalOut + Send>>;
+// SPI link and CE pin
+type RadioWiring = (Box<dyn RadioBus + Send>, Box<dyn DigitalOut + Send>);
+
+/// Link to the remote over an nRF24L01 on SPI0 CE1, `timeout` without message meaning the link is lost
+pub fn open(timeout: Duration) -> Result<RadioTransport, Box<dyn std::error::Error>> {
+    let (spi, ce) = open_radio()?;
+    let radio = Nrf24::new(spi, ce, NRF24_CHANNEL)?;
+    info!("nRF24 link on channel {}", NRF24_CHANNEL);
+    Ok(Nrf24Transport::new(radio, timeout))
+}
+
+#[cfg(feature = "nrf24")]
+fn open_radio() -> Result<RadioWiring, Box<dyn std::error::Error>> {
+    let spi = Spi::new(Bus::Spi0, SlaveSelect::Ss1, NRF24_SPI_CLOCK_HZ, Mode::Mode0)?;
+    let ce = Gpio::new()?.get(NRF24_CE_PIN)?.into_output();
+    Ok((Box::new(spi), Box::new(ce)))
+}
+
+#[cfg(not(feature = "nrf24"))]
+fn open_radio() -> Result<RadioWiring, Box<dyn std::error::Error>> {
+    Err("built without the nrf24 feature, SPI unavailable".into())
+}
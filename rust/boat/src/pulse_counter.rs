@@ -0,0 +1,135 @@
+#[cfg(feature = "pulse")]
+use rppal::gpio::Gpio;
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use pizboat_hal::DigitalIn;
+use tracing::info;
+
+/// Rising edges kept for the rate, those closer than the debounce time to the last one being contact bounce
+struct PulseEdges {
+    times: VecDeque<Instant>,
+    debounce: Duration,
+    kept: Duration,
+}
+
+impl PulseEdges {
+    fn new(debounce: Duration, kept: Duration) -> Self {
+        PulseEdges { times: VecDeque::new(), debounce, kept }
+    }
+
+    fn edge(&mut self, now: Instant) {
+        if self.times.back().is_some_and(|&last| now.duration_since(last) < self.debounce) {
+            return;
+        }
+        self.times.push_back(now);
+        while self.times.front().is_some_and(|&first| now.duration_since(first) > self.kept) {
+            self.times.pop_front();
+        }
+    }
+
+    /// Pulses per second over the window ending now
+    fn rate_hz(&self, now: Instant, window: Duration) -> f32 {
+        let count = self.times.iter().filter(|&&time| now.duration_since(time) <= window).count();
+        count as f32 / window.as_secs_f32()
+    }
+
+    /// Highest rate over any `span` of the window ending now
+    fn peak_rate_hz(&self, now: Instant, window: Duration, span: Duration) -> f32 {
+        let times: Vec<Instant> = self.times.iter().copied().filter(|&time| now.duration_since(time) <= window).collect();
+        let mut peak = 0;
+        let mut end = 0;
+        for (start, &time) in times.iter().enumerate() {
+            while end < times.len() && times[end].duration_since(time) < span {
+                end += 1;
+            }
+            peak = peak.max(end - start);
+        }
+        peak as f32 / span.as_secs_f32()
+    }
+}
+
+/// Counts the pulses of a reed switch or Hall sensor, such as the cups of an anemometer, from the interrupts of its pin
+pub struct PulseCounter {
+    // Kept for its interrupt to stay registered
+    _pin: Box<dyn DigitalIn>,
+    edges: Arc<Mutex<PulseEdges>>,
+}
+
+impl PulseCounter {
+    /// Edges are kept for the longest window asked later
+    pub fn new(pin_number: u8, debounce: Duration, kept: Duration) -> Result<Self, Box<dyn std::error::Error>> {
+        let pin = open_pin(pin_number)?;
+        info!("Pulse counter on GPIO {}", pin_number);
+        Self::from_pin(pin, debounce, kept)
+    }
+
+    pub fn from_pin(mut pin: Box<dyn DigitalIn>, debounce: Duration, kept: Duration) -> Result<Self, Box<dyn std::error::Error>> {
+        let edges = Arc::new(Mutex::new(PulseEdges::new(debounce, kept)));
+        let edges_clone = Arc::clone(&edges);
+        pin.set_interrupt(Box::new(move |high| {
+            if high {
+                edges_clone.lock().unwrap().edge(Instant::now());
+            }
+        }))?;
+        Ok(PulseCounter { _pin: pin, edges })
+    }
+
+    /// Mean pulse rate over the last `window`
+    pub fn rate_hz(&self, window: Duration) -> f32 {
+        self.edges.lock().unwrap().rate_hz(Instant::now(), window)
+    }
+
+    /// Highest pulse rate over any `span` of the last `window`, the gusts of an anemometer
+    pub fn peak_rate_hz(&self, window: Duration, span: Duration) -> f32 {
+        self.edges.lock().unwrap().peak_rate_hz(Instant::now(), window, span)
+    }
+}
+
+#[cfg(feature = "pulse")]
+fn open_pin(pin_number: u8) -> Result<Box<dyn DigitalIn>, Box<dyn std::error::Error>> {
+    Ok(Box::new(Gpio::new()?.get(pin_number)?.into_input_pullup()))
+}
+
+#[cfg(not(feature = "pulse"))]
+fn open_pin(_pin_number: u8) -> Result<Box<dyn DigitalIn>, Box<dyn std::error::Error>> {
+    Err("built without the pulse feature".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pizboat_hal::mock::MockPin;
+
+    #[test]
+    fn test_pulse_rates() {
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let mut edges = PulseEdges::new(Duration::from_millis(5), Duration::from_secs(10));
+        // 1 Hz for 6 s, then a bouncing contact, then 5 Hz for 2 s
+        for ms in (0..6000).step_by(1000) {
+            edges.edge(at(ms));
+        }
+        edges.edge(at(6000));
+        edges.edge(at(6002));
+        for ms in (6200..8000).step_by(200) {
+            edges.edge(at(ms));
+        }
+        assert_eq!(edges.times.len(), 16);
+        assert_eq!(edges.rate_hz(at(8000), Duration::from_secs(2)), 5.);
+        assert_eq!(edges.rate_hz(at(8000), Duration::from_secs(10)), 1.6);
+        assert_eq!(edges.peak_rate_hz(at(8000), Duration::from_secs(10), Duration::from_secs(2)), 5.);
+
+        // Dropped once older than kept
+        edges.edge(at(16_500));
+        assert_eq!(edges.times.len(), 8);
+
+        let pin = MockPin::default();
+        let counter = PulseCounter::from_pin(Box::new(pin.clone()), Duration::from_millis(5), Duration::from_secs(10)).unwrap();
+        pin.set_level(true);
+        pin.set_level(false);
+        assert_eq!(counter.rate_hz(Duration::from_secs(1)), 1.);
+    }
+}
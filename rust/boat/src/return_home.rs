@@ -0,0 +1,87 @@
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::gps::GpsFix;
+use crate::imu::{Compass, Heading};
+use crate::{BoatController, MOTOR_NEUTRAL_US};
+use tracing::{error, info, warn};
+
+// Slow ahead
+const RETURN_HOME_MOTOR_US: u32 = 1600;
+// Close enough to the launch point to stop the motor
+const RETURN_HOME_ARRIVED_M: f64 = 10.;
+// Rudder correction per degree of heading error, and its maximum
+const RUDDER_GAIN_US_PER_DEG: f32 = 4.;
+const RUDDER_MAX_OFFSET_US: i32 = 400;
+
+const RETURN_HOME_PERIOD_MS: u64 = 200;
+
+/// Rudder offset (µs) steering from the current heading towards the bearing
+fn rudder_offset(heading: f32, bearing: f32) -> i32 {
+    let error = (bearing - heading + 540.).rem_euclid(360.) - 180.;
+    ((error * RUDDER_GAIN_US_PER_DEG) as i32).clamp(-RUDDER_MAX_OFFSET_US, RUDDER_MAX_OFFSET_US)
+}
+
+/// Motor the boat slowly back to its launch point when the link is lost for delay_ms.
+///
+/// The boat steers by the compass heading, the GPS course being meaningless at low speed:
+/// without a fresh fix and a fresh heading, or without a compass at all, it stays in failsafe.
+pub fn return_home_thread(controller: Arc<Mutex<BoatController>>, gps_mutex: Arc<Mutex<Option<GpsFix>>>,
+                          compass: Option<Arc<Mutex<Compass>>>, delay_ms: u64) {
+    let Some(compass) = compass else {
+        warn!("No compass, the boat will not return home on link loss");
+        return;
+    };
+    let mut home: Option<(f64, f64)> = None;
+    let mut returning = false;
+
+    loop {
+        thread::sleep(Duration::from_millis(RETURN_HOME_PERIOD_MS));
+
+        let fix = (*gps_mutex.lock().unwrap()).filter(GpsFix::is_fresh);
+        let heading = compass.lock().unwrap().heading.filter(Heading::is_fresh);
+        if let Some(fix) = fix {
+            home.get_or_insert_with(|| {
+                info!("Launch point recorded at {:.6} {:.6}", fix.latitude, fix.longitude);
+                (fix.latitude, fix.longitude)
+            });
+        }
+
+        let mut controller = controller.lock().unwrap();
+
+        // The remote still holds the boat through the LoRa backup link
+        if controller.link_lost_ms() < delay_ms || controller.lora_alive() {
+            if returning {
+                info!("Link is back, return to home stopped");
+                returning = false;
+            }
+            continue;
+        }
+
+        let (Some(fix), Some(heading), Some((home_latitude, home_longitude))) = (fix, heading, home) else {
+            if returning {
+                warn!("GPS fix or compass heading lost, return to home stopped");
+                returning = false;
+                if let Err(e) = controller.on_link_lost() {
+                    error!("Error applying failsafe: {}", e);
+                }
+            }
+            continue;
+        };
+        let (distance, bearing) = fix.distance_and_bearing_to(home_latitude, home_longitude);
+        if !returning {
+            warn!("Link lost, returning home: {:.0}m at {:.0}°", distance, bearing);
+            returning = true;
+        }
+
+        let result = if distance < RETURN_HOME_ARRIVED_M {
+            controller.limp_home(0, MOTOR_NEUTRAL_US)
+        } else {
+            controller.limp_home(rudder_offset(heading.degrees, bearing), RETURN_HOME_MOTOR_US)
+        };
+        if let Err(e) = result {
+            error!("Error returning home: {}", e);
+        }
+    }
+}
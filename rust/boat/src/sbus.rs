@@ -0,0 +1,144 @@
+#[cfg(feature = "sbus")]
+use rppal::uart::{Parity, Uart};
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use pizboat_hal::PwmOut;
+use tracing::{error, info};
+
+// 8E2 on the PL011 UART, the mini UART has no parity. SBUS is inverted: TX goes through a transistor or a 74HC14
+const SBUS_BAUD_RATE: u32 = 100_000;
+// Normal speed SBUS, fast receivers also take it
+const FRAME_PERIOD: Duration = Duration::from_millis(14);
+
+pub const SBUS_CHANNELS: usize = 16;
+const FRAME_LENGTH: usize = 25;
+const HEADER: u8 = 0x0F;
+const FOOTER: u8 = 0x00;
+// Pulse width at SBUS value 0, and 8 values per 5 us: 1000 us is 192, 1500 us 992 and 2000 us 1792
+const SBUS_ZERO_US: u32 = 880;
+const SBUS_MAX: u16 = 0x7FF;
+
+type FrameWriter = Box<dyn FnMut(&[u8]) -> Result<(), Box<dyn std::error::Error>> + Send>;
+
+pub fn pulse_to_sbus(pulse_width_us: u32) -> u16 {
+    (pulse_width_us.saturating_sub(SBUS_ZERO_US) * 8 / 5).min(SBUS_MAX as u32) as u16
+}
+
+/// 16 channels of 11 bits, least significant bit first, without the digital channels and flags
+pub fn encode_frame(channels: &[u16; SBUS_CHANNELS]) -> [u8; FRAME_LENGTH] {
+    let mut frame = [0u8; FRAME_LENGTH];
+    frame[0] = HEADER;
+    for (i, &value) in channels.iter().enumerate() {
+        for bit in 0..11 {
+            if value & (1 << bit) != 0 {
+                let position = i * 11 + bit;
+                frame[1 + position / 8] |= 1 << (position % 8);
+            }
+        }
+    }
+    frame[FRAME_LENGTH - 1] = FOOTER;
+    frame
+}
+
+/// SBUS frames sent continuously on the UART, with the last value given to each channel
+pub struct SbusOutput {
+    channels: Arc<Mutex<[u16; SBUS_CHANNELS]>>,
+}
+
+impl SbusOutput {
+    pub fn start(device: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let write = open_uart(device)?;
+        info!("SBUS output on {}", device);
+        let channels = Arc::new(Mutex::new([pulse_to_sbus(1500); SBUS_CHANNELS]));
+        let channels_clone = Arc::clone(&channels);
+        thread::spawn(move || sbus_thread(write, channels_clone));
+        Ok(SbusOutput { channels })
+    }
+
+    /// Output for one channel, 0 to 15
+    pub fn channel(&self, index: usize) -> SbusChannel {
+        SbusChannel { channels: Arc::clone(&self.channels), index }
+    }
+}
+
+/// One SBUS channel, set with a pulse width like a servo
+pub struct SbusChannel {
+    channels: Arc<Mutex<[u16; SBUS_CHANNELS]>>,
+    index: usize,
+}
+
+impl PwmOut for SbusChannel {
+    fn set_pulse_us(&mut self, pulse_width_us: u32) -> Result<(), Box<dyn std::error::Error>> {
+        self.channels.lock().unwrap()[self.index] = pulse_to_sbus(pulse_width_us);
+        Ok(())
+    }
+}
+
+/// Servo output copied to an SBUS channel once applied
+pub struct Mirrored {
+    pub output: Box<dyn PwmOut + Send>,
+    pub copy: SbusChannel,
+}
+
+impl PwmOut for Mirrored {
+    fn set_pulse_us(&mut self, pulse_width_us: u32) -> Result<(), Box<dyn std::error::Error>> {
+        self.output.set_pulse_us(pulse_width_us)?;
+        self.copy.set_pulse_us(pulse_width_us)
+    }
+}
+
+#[cfg(feature = "sbus")]
+fn open_uart(device: &str) -> Result<FrameWriter, Box<dyn std::error::Error>> {
+    let mut uart = Uart::with_path(device, SBUS_BAUD_RATE, Parity::Even, 8, 2)?;
+    Ok(Box::new(move |frame| {
+        uart.write(frame)?;
+        Ok(())
+    }))
+}
+
+#[cfg(not(feature = "sbus"))]
+fn open_uart(_device: &str) -> Result<FrameWriter, Box<dyn std::error::Error>> {
+    Err(format!("built without the sbus feature, {} baud UART unavailable", SBUS_BAUD_RATE).into())
+}
+
+fn sbus_thread(mut write: FrameWriter, channels: Arc<Mutex<[u16; SBUS_CHANNELS]>>) {
+    let mut failed = false;
+    loop {
+        let frame = encode_frame(&channels.lock().unwrap());
+        match write(&frame) {
+            Ok(()) => failed = false,
+            // Once per failure
+            Err(e) if !failed => {
+                error!("SBUS write error: {}", e);
+                failed = true;
+            }
+            Err(_) => {}
+        }
+        thread::sleep(FRAME_PERIOD);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sbus_frame() {
+        assert_eq!(pulse_to_sbus(1000), 192);
+        assert_eq!(pulse_to_sbus(1500), 992);
+        assert_eq!(pulse_to_sbus(2000), 1792);
+        assert_eq!(pulse_to_sbus(500), 0);
+
+        let mut channels = [0; SBUS_CHANNELS];
+        channels[0] = 0x7FF;
+        channels[1] = 992;
+        let frame = encode_frame(&channels);
+        assert_eq!(frame[0], 0x0F);
+        // Channel 0 on the 8 bits of byte 1 and the 3 low bits of byte 2, channel 1 after it
+        assert_eq!(frame[1..5], [0xFF, 0x07 | ((992 & 0x1F) << 3) as u8, (992 >> 5) as u8, 0]);
+        assert_eq!(frame[24], 0x00);
+    }
+}
@@ -0,0 +1,73 @@
+#[cfg(feature = "i2c")]
+use rppal::i2c::I2c;
+
+use pizboat_protocol::HardwareCheck;
+use std::fmt;
+use tracing::{error, info};
+
+// Bus of the sensors, scanned at start-up
+#[cfg(feature = "i2c")]
+pub const I2C_BUS: u8 = 1;
+// Addresses left to devices, the others being reserved
+#[cfg(feature = "i2c")]
+const I2C_ADDRESSES: std::ops::RangeInclusive<u16> = 0x08..=0x77;
+
+/// Hardware checks of the start-up, sent to the remote with the first query of each connection
+#[derive(Default)]
+pub struct SelfTest {
+    checks: Vec<HardwareCheck>,
+}
+
+impl SelfTest {
+    fn report(&mut self, name: &str, passed: bool, detail: String) {
+        if passed {
+            info!("[SELF-TEST] {} ok {}", name, detail);
+        } else {
+            error!("[SELF-TEST] {} FAILED: {}", name, detail);
+        }
+        self.checks.push(HardwareCheck { name: name.to_string(), passed, detail });
+    }
+
+    /// Hardware the boat runs without, None when it failed
+    pub fn check<T, E: fmt::Display>(&mut self, name: &str, result: Result<T, E>) -> Option<T> {
+        self.check_with(name, result, |_| String::new())
+    }
+
+    /// Same, with what was found when it passed
+    pub fn check_with<T, E: fmt::Display>(&mut self, name: &str, result: Result<T, E>, found: impl FnOnce(&T) -> String) -> Option<T> {
+        match result {
+            Ok(value) => {
+                self.report(name, true, found(&value));
+                Some(value)
+            }
+            Err(e) => {
+                self.report(name, false, e.to_string());
+                None
+            }
+        }
+    }
+
+    pub fn checks(&self) -> Vec<HardwareCheck> {
+        self.checks.clone()
+    }
+}
+
+/// Addresses of the devices answering on the I2C bus, as i2cdetect -r finds them
+#[cfg(feature = "i2c")]
+pub fn scan_i2c() -> Result<Vec<u16>, Box<dyn std::error::Error>> {
+    let mut i2c = I2c::with_bus(I2C_BUS)?;
+    let mut found = Vec::new();
+    for address in I2C_ADDRESSES {
+        i2c.set_slave_address(address)?;
+        // Without a device, nothing acknowledges the address
+        if i2c.read(&mut [0u8; 1]).is_ok() {
+            found.push(address);
+        }
+    }
+    Ok(found)
+}
+
+#[cfg(not(feature = "i2c"))]
+pub fn scan_i2c() -> Result<Vec<u16>, Box<dyn std::error::Error>> {
+    Err("built without the i2c feature".into())
+}
@@ -0,0 +1,207 @@
+#[cfg(feature = "sonar")]
+use rppal::uart::{Parity, Uart};
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::get_timestamp_ms;
+use tracing::{error, info};
+
+#[cfg(feature = "sonar")]
+const SONAR_BAUD_RATE: u32 = 115_200;
+// Reads return what came within this time, possibly nothing
+#[cfg(feature = "sonar")]
+const READ_TIMEOUT: Duration = Duration::from_millis(20);
+const ANSWER_TIMEOUT: Duration = Duration::from_millis(200);
+const PING_PERIOD: Duration = Duration::from_millis(100);
+// Readings below this confidence, in percent, are echoes of weed or noise
+const MIN_CONFIDENCE: u8 = 50;
+// A depth older than this is considered lost
+const DEPTH_TIMEOUT_MS: u64 = 2000;
+
+// Ping protocol of the Ping1D: "BR", payload length, message id, source and destination device ids, payload, checksum
+const FRAME_START: &[u8] = b"BR";
+const HEADER_LEN: usize = 8;
+const CHECKSUM_LEN: usize = 2;
+// Longer frames, such as the echo profiles, are not asked for
+const MAX_PAYLOAD: usize = 64;
+const GENERAL_REQUEST: u16 = 6;
+const DISTANCE_SIMPLE: u16 = 1211;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Depth {
+    pub meters: f32,        // Under the transducer
+    pub confidence: u8,     // Percent, as the sonar rates its echo
+    pub timestamp: u64,     // Local time of the reading in ms
+}
+
+impl Depth {
+    pub fn is_fresh(&self) -> bool {
+        get_timestamp_ms().saturating_sub(self.timestamp) < DEPTH_TIMEOUT_MS
+    }
+}
+
+/// Serial link of the sonar
+trait SerialPort: Send {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Box<dyn std::error::Error>>;
+    /// What came, nothing once the read timeout elapsed
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Box<dyn std::error::Error>>;
+}
+
+#[cfg(feature = "sonar")]
+impl SerialPort for Uart {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        Uart::write(self, bytes)?;
+        Ok(())
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Box<dyn std::error::Error>> {
+        Ok(Uart::read(self, buffer)?)
+    }
+}
+
+/// Asks a Ping1D sonar on the serial `device` for the depth several times per second, returning the first one
+pub fn start(device: &str, depth_mutex: Arc<Mutex<Option<Depth>>>) -> Result<f32, Box<dyn std::error::Error>> {
+    let mut port = open_port(device)?;
+    let mut parser = FrameParser::default();
+    let (meters, confidence) = ping(port.as_mut(), &mut parser)?;
+    info!("Sonar depth {:.2}m, confidence {}%", meters, confidence);
+    thread::spawn(move || sonar_thread(port, parser, depth_mutex));
+    Ok(meters)
+}
+
+#[cfg(feature = "sonar")]
+fn open_port(device: &str) -> Result<Box<dyn SerialPort>, Box<dyn std::error::Error>> {
+    let mut uart = Uart::with_path(device, SONAR_BAUD_RATE, Parity::None, 8, 1)?;
+    uart.set_read_mode(0, READ_TIMEOUT)?;
+    Ok(Box::new(uart))
+}
+
+#[cfg(not(feature = "sonar"))]
+fn open_port(_device: &str) -> Result<Box<dyn SerialPort>, Box<dyn std::error::Error>> {
+    Err("built without the sonar feature".into())
+}
+
+/// Frame of a message, its checksum being the sum of the bytes before it
+fn encode(message_id: u16, payload: &[u8]) -> Vec<u8> {
+    let mut frame = FRAME_START.to_vec();
+    frame.extend_from_slice(&(payload.len() as u16).to_le_bytes());
+    frame.extend_from_slice(&message_id.to_le_bytes());
+    // From the host, device id 0 to the sonar
+    frame.extend_from_slice(&[0, 0]);
+    frame.extend_from_slice(payload);
+    let checksum = frame.iter().fold(0u16, |sum, &byte| sum.wrapping_add(byte as u16));
+    frame.extend_from_slice(&checksum.to_le_bytes());
+    frame
+}
+
+/// Frames of the bytes received so far, cut anywhere by the reads
+#[derive(Default)]
+struct FrameParser {
+    buffer: Vec<u8>,
+}
+
+impl FrameParser {
+    /// Message id and payload of each complete frame, those with a wrong checksum being skipped
+    fn push(&mut self, bytes: &[u8]) -> Vec<(u16, Vec<u8>)> {
+        self.buffer.extend_from_slice(bytes);
+        let mut frames = Vec::new();
+        loop {
+            // Up to the next frame start, keeping a last B which may be one
+            let start = self.buffer.windows(2).position(|pair| pair == FRAME_START)
+                .unwrap_or(self.buffer.len().saturating_sub(1));
+            self.buffer.drain(..start);
+            if self.buffer.len() < HEADER_LEN {
+                return frames;
+            }
+            let payload_len = u16::from_le_bytes([self.buffer[2], self.buffer[3]]) as usize;
+            if payload_len > MAX_PAYLOAD {
+                self.buffer.drain(..1);
+                continue;
+            }
+            let frame_len = HEADER_LEN + payload_len + CHECKSUM_LEN;
+            if self.buffer.len() < frame_len {
+                return frames;
+            }
+            let body = &self.buffer[..HEADER_LEN + payload_len];
+            let checksum = body.iter().fold(0u16, |sum, &byte| sum.wrapping_add(byte as u16));
+            if checksum.to_le_bytes() == self.buffer[HEADER_LEN + payload_len..frame_len] {
+                let message_id = u16::from_le_bytes([self.buffer[4], self.buffer[5]]);
+                frames.push((message_id, body[HEADER_LEN..].to_vec()));
+                self.buffer.drain(..frame_len);
+            } else {
+                self.buffer.drain(..1);
+            }
+        }
+    }
+}
+
+/// Depth in meters and confidence of a distance_simple payload, the distance being in millimeters
+fn distance_simple(payload: &[u8]) -> Option<(f32, u8)> {
+    let distance_mm = u32::from_le_bytes(payload.get(..4)?.try_into().ok()?);
+    Some((distance_mm as f32 / 1000., *payload.get(4)?))
+}
+
+fn ping(port: &mut dyn SerialPort, parser: &mut FrameParser) -> Result<(f32, u8), Box<dyn std::error::Error>> {
+    port.write(&encode(GENERAL_REQUEST, &DISTANCE_SIMPLE.to_le_bytes()))?;
+    let start = Instant::now();
+    let mut buffer = [0u8; 64];
+    while start.elapsed() < ANSWER_TIMEOUT {
+        let count = port.read(&mut buffer)?;
+        let distance = parser.push(&buffer[..count]).into_iter()
+            .filter(|(message_id, _)| *message_id == DISTANCE_SIMPLE)
+            .find_map(|(_, payload)| distance_simple(&payload));
+        if let Some(distance) = distance {
+            return Ok(distance);
+        }
+    }
+    Err(format!("no answer within {}ms", ANSWER_TIMEOUT.as_millis()).into())
+}
+
+fn sonar_thread(mut port: Box<dyn SerialPort>, mut parser: FrameParser, depth_mutex: Arc<Mutex<Option<Depth>>>) {
+    let mut failed = false;
+    loop {
+        match ping(port.as_mut(), &mut parser) {
+            Ok((meters, confidence)) => {
+                failed = false;
+                // Kept out, the depth goes stale over weed
+                if confidence >= MIN_CONFIDENCE {
+                    *depth_mutex.lock().unwrap() = Some(Depth { meters, confidence, timestamp: get_timestamp_ms() });
+                }
+            }
+            // Once per failure
+            Err(e) if !failed => {
+                error!("Sonar error: {}", e);
+                failed = true;
+            }
+            Err(_) => {}
+        }
+        thread::sleep(PING_PERIOD);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ping_frames() {
+        let request = encode(GENERAL_REQUEST, &DISTANCE_SIMPLE.to_le_bytes());
+        assert_eq!(request, [0x42, 0x52, 2, 0, 6, 0, 0, 0, 0xbb, 0x04, 0x5b, 0x01]);
+
+        // 1.234 m at 87 %, after some noise, cut in two reads, then a corrupted copy
+        let mut answer = encode(DISTANCE_SIMPLE, &[0xd2, 0x04, 0, 0, 87]);
+        let mut parser = FrameParser::default();
+        let mut bytes = b"\x00B\x13".to_vec();
+        bytes.extend_from_slice(&answer);
+        assert!(parser.push(&bytes[..9]).is_empty());
+        let frames = parser.push(&bytes[9..]);
+        assert_eq!(frames, [(DISTANCE_SIMPLE, vec![0xd2, 0x04, 0, 0, 87])]);
+        assert_eq!(distance_simple(&frames[0].1), Some((1.234, 87)));
+
+        answer[9] ^= 0xff;
+        assert!(parser.push(&answer).is_empty());
+        assert!(parser.buffer.len() < HEADER_LEN);
+    }
+}
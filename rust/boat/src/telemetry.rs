@@ -0,0 +1,10 @@
+use std::fs;
+
+// In thousandths of a degree
+const CPU_TEMPERATURE: &str = "/sys/class/thermal/thermal_zone0/temp";
+
+/// Degrees Celsius, None where the kernel does not report it
+pub fn cpu_temperature() -> Option<f64> {
+    let millidegrees = fs::read_to_string(CPU_TEMPERATURE).ok()?.trim().parse::<f64>().ok()?;
+    Some(millidegrees / 1000.)
+}
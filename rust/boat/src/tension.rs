@@ -0,0 +1,67 @@
+// Load below which the sheet is trimmed back, as a fraction of the limit
+const TENSION_CLEAR_RATIO: f32 = 0.7;
+
+/// Eases a sail while its sheet load stays too high, protecting the rig in gusts
+#[derive(Debug)]
+pub struct TensionGuard {
+    limit_g: f32,       // Sheet load above which the sail is eased, in grams
+    hold_ms: u64,       // How long the load stays above the limit before easing, riding out short snatches
+    ease_us: i32,       // Sheet servo offset while eased
+    above_since_ms: Option<u64>,
+    easing: bool,
+}
+
+impl TensionGuard {
+    pub fn new(limit_g: f32, hold_ms: u64, ease_us: i32) -> Self {
+        TensionGuard { limit_g, hold_ms, ease_us, above_since_ms: None, easing: false }
+    }
+
+    /// Update from the latest sheet load, kept as it was without reading. True when easing.
+    pub fn update(&mut self, load_g: Option<f32>, now_ms: u64) -> bool {
+        let Some(load_g) = load_g else {
+            return self.easing;
+        };
+        if self.easing {
+            self.easing = load_g > self.limit_g * TENSION_CLEAR_RATIO;
+        } else if load_g > self.limit_g {
+            let since = *self.above_since_ms.get_or_insert(now_ms);
+            self.easing = now_ms.saturating_sub(since) >= self.hold_ms;
+        } else {
+            self.above_since_ms = None;
+        }
+        if self.easing {
+            self.above_since_ms = None;
+        }
+        self.easing
+    }
+
+    pub fn is_easing(&self) -> bool {
+        self.easing
+    }
+
+    /// Sheet servo pulse, eased when needed
+    pub fn ease(&self, pulse_width_us: u32) -> u32 {
+        if self.easing { pulse_width_us.saturating_add_signed(self.ease_us) } else { pulse_width_us }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ease_on_sustained_tension() {
+        let mut guard = TensionGuard::new(4000., 300, -200);
+        // A snatch shorter than the hold time
+        assert!(!guard.update(Some(5000.), 0));
+        assert!(!guard.update(Some(1000.), 100));
+        assert!(!guard.update(Some(5000.), 200));
+        assert!(!guard.update(None, 400));
+        assert!(guard.update(Some(5000.), 500));
+        assert_eq!(guard.ease(1500), 1300);
+        // Held until the load dropped well below the limit
+        assert!(guard.update(Some(3500.), 600));
+        assert!(!guard.update(Some(2500.), 700));
+        assert_eq!(guard.ease(1500), 1500);
+    }
+}
@@ -0,0 +1,72 @@
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+/// Sail positions for one apparent wind angle
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TrimPoint {
+    pub wind_angle: f32,    // Degrees off the bow, either tack
+    pub boom_us: u32,
+    pub genoa_us: u32,
+}
+
+// Default of the settings: sheeted in close-hauled, eased as the wind comes aft, towards 1000 us letting the sheets out on this boat.
+// Interpolated in between, the ends holding before the first and after the last.
+pub const TRIM_TABLE: [TrimPoint; 5] = [
+    TrimPoint { wind_angle: 30., boom_us: 1900, genoa_us: 1900 },
+    TrimPoint { wind_angle: 60., boom_us: 1700, genoa_us: 1750 },
+    TrimPoint { wind_angle: 90., boom_us: 1500, genoa_us: 1550 },
+    TrimPoint { wind_angle: 135., boom_us: 1250, genoa_us: 1300 },
+    TrimPoint { wind_angle: 180., boom_us: 1100, genoa_us: 1100 },
+];
+
+/// Table of the settings sorted by angle, the default one replacing an empty table
+pub fn sorted_table(points: &[TrimPoint]) -> Vec<TrimPoint> {
+    if points.is_empty() {
+        warn!("Empty trim table, using the default one");
+        return TRIM_TABLE.to_vec();
+    }
+    let mut table = points.to_vec();
+    table.sort_by(|a, b| a.wind_angle.total_cmp(&b.wind_angle));
+    table
+}
+
+/// Boom and genoa widths for the apparent wind angle of the vane, from -180 to 180, in a sorted table
+pub fn sail_pulses(table: &[TrimPoint], wind_angle: f32) -> (u32, u32) {
+    interpolate(table, wind_angle.abs())
+}
+
+fn interpolate(table: &[TrimPoint], wind_angle: f32) -> (u32, u32) {
+    let Some(after) = table.iter().position(|point| point.wind_angle > wind_angle) else {
+        let last = table[table.len() - 1];
+        return (last.boom_us, last.genoa_us);
+    };
+    if after == 0 {
+        return (table[0].boom_us, table[0].genoa_us);
+    }
+    let (from, to) = (table[after - 1], table[after]);
+    let ratio = (wind_angle - from.wind_angle) / (to.wind_angle - from.wind_angle);
+    let between = |from_us: u32, to_us: u32| (from_us as f32 + (to_us as f32 - from_us as f32) * ratio).round() as u32;
+    (between(from.boom_us, to.boom_us), between(from.genoa_us, to.genoa_us))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trim_table() {
+        assert_eq!(sail_pulses(&TRIM_TABLE, 90.), (1500, 1550));
+        assert_eq!(sail_pulses(&TRIM_TABLE, -45.), (1800, 1825));
+        // In irons, and dead downwind on either side of the stern
+        assert_eq!(sail_pulses(&TRIM_TABLE, 5.), (1900, 1900));
+        assert_eq!(sail_pulses(&TRIM_TABLE, 180.), (1100, 1100));
+        assert_eq!(sail_pulses(&TRIM_TABLE, -180.), (1100, 1100));
+    }
+
+    #[test]
+    fn test_sorted_table() {
+        let reversed: Vec<TrimPoint> = TRIM_TABLE.iter().rev().copied().collect();
+        assert_eq!(sorted_table(&reversed), TRIM_TABLE);
+        assert_eq!(sorted_table(&[]), TRIM_TABLE);
+    }
+}
@@ -0,0 +1,211 @@
+#[cfg(feature = "waveform")]
+use rust_pigpio::{set_mode, OUTPUT};
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use pizboat_hal::PwmOut;
+use tracing::{error, info};
+
+// Every output pulses once per frame, at the rate of standard servos
+pub const FRAME_RATE_HZ: u32 = 50;
+const FRAME_PERIOD_US: u32 = 1_000_000 / FRAME_RATE_HZ;
+// Checked for changed widths, a new waveform taking over at the end of the frame in progress
+const UPDATE_PERIOD: Duration = Duration::from_millis(20);
+
+/// Step of a waveform, as the gpioPulse_t of pigpio: GPIO bits set high, set low, then the delay until the next step
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pulse {
+    pub gpio_on: u32,
+    pub gpio_off: u32,
+    pub delay_us: u32,
+}
+
+/// Steps of one frame: every output rises at its start, and falls once its width is over
+pub fn frame_pulses(outputs: &[(u32, u32)], period_us: u32) -> Vec<Pulse> {
+    let mut falling = outputs.to_vec();
+    falling.sort_by_key(|&(_, width_us)| width_us);
+    let mut pulses = Vec::new();
+    let mut gpio_on = outputs.iter().fold(0, |mask, &(pin, _)| mask | 1 << pin);
+    let mut gpio_off = 0;
+    let mut at_us = 0;
+    for (pin, width_us) in falling {
+        // Outputs of the same width fall together
+        if width_us > at_us {
+            pulses.push(Pulse { gpio_on, gpio_off, delay_us: width_us - at_us });
+            gpio_on = 0;
+            gpio_off = 0;
+            at_us = width_us;
+        }
+        gpio_off |= 1 << pin;
+    }
+    pulses.push(Pulse { gpio_on, gpio_off, delay_us: period_us.saturating_sub(at_us) });
+    pulses
+}
+
+// Pin and last width of each output, None until first set
+type Widths = Arc<Mutex<Vec<(u32, Option<u32>)>>>;
+
+/// All servo outputs in one DMA waveform of pigpio, their pulses starting together and timed to the microsecond
+pub struct WaveformOutput {
+    widths: Widths,
+}
+
+impl WaveformOutput {
+    pub fn start(pins: &[u32]) -> Result<Self, Box<dyn std::error::Error>> {
+        claim_pins(pins)?;
+        info!("Waveform output on GPIO {:?}", pins);
+        let widths = Arc::new(Mutex::new(pins.iter().map(|&pin| (pin, None)).collect()));
+        let widths_clone = Arc::clone(&widths);
+        thread::spawn(move || waveform_thread(widths_clone));
+        Ok(WaveformOutput { widths })
+    }
+
+    /// Output of the pin given at that index
+    pub fn channel(&self, index: usize) -> WaveformChannel {
+        WaveformChannel { widths: Arc::clone(&self.widths), index }
+    }
+}
+
+/// One output of the waveform, low until its first pulse width
+pub struct WaveformChannel {
+    widths: Widths,
+    index: usize,
+}
+
+impl PwmOut for WaveformChannel {
+    fn set_pulse_us(&mut self, pulse_width_us: u32) -> Result<(), Box<dyn std::error::Error>> {
+        self.widths.lock().unwrap()[self.index].1 = Some(pulse_width_us);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "waveform")]
+fn claim_pins(pins: &[u32]) -> Result<(), Box<dyn std::error::Error>> {
+    for &pin in pins {
+        set_mode(pin, OUTPUT)?;
+    }
+    wave::clear()
+}
+
+#[cfg(not(feature = "waveform"))]
+fn claim_pins(_pins: &[u32]) -> Result<(), Box<dyn std::error::Error>> {
+    Err("built without the waveform feature".into())
+}
+
+fn waveform_thread(widths: Widths) {
+    // Pulses being sent and the id of their waveform
+    let mut sent: Option<(Vec<Pulse>, u32)> = None;
+    let mut failed = false;
+    loop {
+        let outputs: Vec<(u32, u32)> = widths.lock().unwrap().iter()
+            .filter_map(|&(pin, width_us)| width_us.map(|width_us| (pin, width_us)))
+            .collect();
+        let pulses = frame_pulses(&outputs, FRAME_PERIOD_US);
+        if sent.as_ref().is_none_or(|(sent_pulses, _)| *sent_pulses != pulses) {
+            match wave::send(&pulses, sent.as_ref().map(|&(_, id)| id)) {
+                Ok(id) => {
+                    sent = Some((pulses, id));
+                    failed = false;
+                }
+                // Once per failure
+                Err(e) if !failed => {
+                    error!("Waveform error: {}", e);
+                    failed = true;
+                }
+                Err(_) => {}
+            }
+        }
+        thread::sleep(UPDATE_PERIOD);
+    }
+}
+
+/// Waveform functions of the pigpio library, which rust_pigpio does not wrap
+#[cfg(feature = "waveform")]
+mod wave {
+    use super::Pulse;
+    use std::os::raw::{c_int, c_uint};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    // Repeated, taking over from the previous waveform at the end of its cycle
+    const PI_WAVE_MODE_REPEAT_SYNC: c_uint = 3;
+    // A new waveform starts within a frame
+    const SWITCH_TIMEOUT: Duration = Duration::from_millis(50);
+
+    #[link(name = "pigpio")]
+    unsafe extern "C" {
+        fn gpioWaveClear() -> c_int;
+        fn gpioWaveAddNew() -> c_int;
+        fn gpioWaveAddGeneric(num_pulses: c_uint, pulses: *mut Pulse) -> c_int;
+        fn gpioWaveCreate() -> c_int;
+        fn gpioWaveDelete(wave_id: c_uint) -> c_int;
+        fn gpioWaveTxSend(wave_id: c_uint, wave_mode: c_uint) -> c_int;
+        fn gpioWaveTxAt() -> c_int;
+    }
+
+    // Negative results are pigpio error codes
+    fn check(result: c_int) -> Result<c_int, Box<dyn std::error::Error>> {
+        if result < 0 {
+            return Err(format!("pigpio error {}", result).into());
+        }
+        Ok(result)
+    }
+
+    pub fn clear() -> Result<(), Box<dyn std::error::Error>> {
+        // SAFETY: no argument, pigpio initialized by the self-test
+        check(unsafe { gpioWaveClear() })?;
+        Ok(())
+    }
+
+    /// Repeat the pulses once the previous waveform ends its cycle, deleting it then
+    pub fn send(pulses: &[Pulse], previous: Option<u32>) -> Result<u32, Box<dyn std::error::Error>> {
+        let mut pulses = pulses.to_vec();
+        // SAFETY: pigpio copies the pulses, which outlive the call
+        let id = unsafe {
+            check(gpioWaveAddNew())?;
+            check(gpioWaveAddGeneric(pulses.len() as c_uint, pulses.as_mut_ptr()))?;
+            check(gpioWaveCreate())? as c_uint
+        };
+        // SAFETY: id was just created
+        check(unsafe { gpioWaveTxSend(id, PI_WAVE_MODE_REPEAT_SYNC) })?;
+        if let Some(previous) = previous {
+            let start = Instant::now();
+            // SAFETY: no argument
+            while unsafe { gpioWaveTxAt() } != id as c_int && start.elapsed() < SWITCH_TIMEOUT {
+                thread::sleep(Duration::from_millis(1));
+            }
+            // SAFETY: previous is no longer sent, or pigpio refuses to delete it
+            unsafe { gpioWaveDelete(previous) };
+        }
+        Ok(id)
+    }
+}
+
+#[cfg(not(feature = "waveform"))]
+mod wave {
+    use super::Pulse;
+
+    pub fn send(_pulses: &[Pulse], _previous: Option<u32>) -> Result<u32, Box<dyn std::error::Error>> {
+        Err("built without the waveform feature".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_pulses() {
+        let pulses = frame_pulses(&[(23, 1500), (24, 1200), (22, 1500)], 20_000);
+        assert_eq!(pulses, [
+            Pulse { gpio_on: 1 << 23 | 1 << 24 | 1 << 22, gpio_off: 0, delay_us: 1200 },
+            Pulse { gpio_on: 0, gpio_off: 1 << 24, delay_us: 300 },
+            Pulse { gpio_on: 0, gpio_off: 1 << 23 | 1 << 22, delay_us: 18_500 },
+        ]);
+        // Nothing set yet, the frame still lasts its period
+        assert_eq!(frame_pulses(&[], 20_000), [Pulse { gpio_on: 0, gpio_off: 0, delay_us: 20_000 }]);
+    }
+}
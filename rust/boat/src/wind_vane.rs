@@ -0,0 +1,146 @@
+#[cfg(feature = "i2c")]
+use rppal::i2c::I2c;
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::get_timestamp_ms;
+use tracing::{error, info};
+
+// Fixed address of the AS5600 magnetic encoder
+#[cfg(feature = "i2c")]
+const AS5600_ADDRESS: u16 = 0x36;
+const REG_STATUS: u8 = 0x0B;
+// 12 bits, high byte first, without the start and stop positions programmed into the chip
+const REG_RAW_ANGLE: u8 = 0x0C;
+const STATUS_MAGNET_DETECTED: u8 = 0x20;
+const STATUS_MAGNET_TOO_WEAK: u8 = 0x10;
+const STATUS_MAGNET_TOO_STRONG: u8 = 0x08;
+const ANGLE_STEPS: f32 = 4096.;
+
+// Angle read with the vane pointing at the bow, from the log of the start-up
+const VANE_OFFSET_DEG: f32 = 0.;
+const SAMPLE_PERIOD: Duration = Duration::from_millis(50);
+// Share of each sample in the angle, the vane swinging in the gusts and the waves
+const SMOOTHING: f32 = 0.2;
+// An angle older than this is considered lost
+const WIND_TIMEOUT_MS: u64 = 1000;
+
+// Reads the registers starting at the given one
+type RegisterReader = Box<dyn FnMut(u8, &mut [u8]) -> Result<(), Box<dyn std::error::Error>> + Send>;
+
+#[derive(Debug, Clone, Copy)]
+pub struct WindAngle {
+    pub degrees: f32,       // Apparent, from the bow, starboard positive, -180 to 180
+    pub timestamp: u64,     // Local time of the last sample in ms
+}
+
+impl WindAngle {
+    pub fn is_fresh(&self) -> bool {
+        get_timestamp_ms().saturating_sub(self.timestamp) < WIND_TIMEOUT_MS
+    }
+}
+
+/// Reads the masthead vane on an AS5600 on I2C bus 1, the magnet being checked first, returning the first apparent angle
+pub fn start(wind_mutex: Arc<Mutex<Option<WindAngle>>>) -> Result<f32, Box<dyn std::error::Error>> {
+    let mut read = open_i2c()?;
+    check_magnet(&mut read)?;
+    let raw = raw_angle(&mut read)?;
+    info!("Wind vane raw angle {:.1} deg, apparent {:.1} deg", raw as f32 * 360. / ANGLE_STEPS, apparent_angle(raw));
+    thread::spawn(move || wind_vane_thread(read, wind_mutex));
+    Ok(apparent_angle(raw))
+}
+
+#[cfg(feature = "i2c")]
+fn open_i2c() -> Result<RegisterReader, Box<dyn std::error::Error>> {
+    let mut i2c = I2c::with_bus(crate::selftest::I2C_BUS)?;
+    i2c.set_slave_address(AS5600_ADDRESS)?;
+    Ok(Box::new(move |register, buffer| {
+        i2c.write_read(&[register], buffer)?;
+        Ok(())
+    }))
+}
+
+#[cfg(not(feature = "i2c"))]
+fn open_i2c() -> Result<RegisterReader, Box<dyn std::error::Error>> {
+    Err("built without the i2c feature".into())
+}
+
+/// The angle is meaningless without the magnet at the right distance
+fn check_magnet(read: &mut RegisterReader) -> Result<(), Box<dyn std::error::Error>> {
+    let mut status = [0u8; 1];
+    read(REG_STATUS, &mut status)?;
+    if status[0] & STATUS_MAGNET_DETECTED == 0 {
+        return Err("no magnet".into());
+    }
+    if status[0] & STATUS_MAGNET_TOO_WEAK != 0 {
+        return Err("magnet too weak".into());
+    }
+    if status[0] & STATUS_MAGNET_TOO_STRONG != 0 {
+        return Err("magnet too strong".into());
+    }
+    Ok(())
+}
+
+fn raw_angle(read: &mut RegisterReader) -> Result<u16, Box<dyn std::error::Error>> {
+    let mut angle = [0u8; 2];
+    read(REG_RAW_ANGLE, &mut angle)?;
+    Ok(u16::from_be_bytes(angle) & 0x0FFF)
+}
+
+/// Degrees between -180 and 180
+fn wrap(degrees: f32) -> f32 {
+    (degrees + 180.).rem_euclid(360.) - 180.
+}
+
+/// Apparent wind angle of a raw reading, once the offset of the vane is taken out
+pub fn apparent_angle(raw: u16) -> f32 {
+    wrap(raw as f32 * 360. / ANGLE_STEPS - VANE_OFFSET_DEG)
+}
+
+/// Moves toward the sample the shorter way round, so that the angle crosses dead downwind instead of swinging through the bow
+fn smooth(previous: f32, degrees: f32) -> f32 {
+    wrap(previous + wrap(degrees - previous) * SMOOTHING)
+}
+
+fn wind_vane_thread(mut read: RegisterReader, wind_mutex: Arc<Mutex<Option<WindAngle>>>) {
+    let mut failed = false;
+    loop {
+        match raw_angle(&mut read) {
+            Ok(raw) => {
+                failed = false;
+                let degrees = apparent_angle(raw);
+                let mut wind = wind_mutex.lock().unwrap();
+                let degrees = match *wind {
+                    Some(previous) if previous.is_fresh() => smooth(previous.degrees, degrees),
+                    _ => degrees,
+                };
+                *wind = Some(WindAngle { degrees, timestamp: get_timestamp_ms() });
+            }
+            // Once per failure, the angle going stale
+            Err(e) if !failed => {
+                error!("Wind vane error: {}", e);
+                failed = true;
+            }
+            Err(_) => {}
+        }
+        thread::sleep(SAMPLE_PERIOD);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apparent_angle() {
+        assert_eq!(apparent_angle(0), 0.);
+        assert_eq!(apparent_angle(1024), 90.);
+        assert_eq!(apparent_angle(3072), -90.);
+        // Smoothed across dead downwind, not through the bow
+        assert!((smooth(170., -170.) - 174.).abs() < 0.01);
+        assert!((smooth(-170., 170.) + 174.).abs() < 0.01);
+        assert!((smooth(10., 20.) - 12.).abs() < 0.01);
+    }
+}
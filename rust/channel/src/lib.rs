@@ -0,0 +1,410 @@
+use serde::{Serialize, Deserialize};
+use std::collections::VecDeque;
+
+// Output curve points of a plain linear response
+pub const LINEAR_CURVE: [u16; 5] = [0, 25, 50, 75, 100];
+
+// Output pulse limits, in microseconds
+pub const OUTPUT_MIN: u16 = 500;
+pub const OUTPUT_MAX: u16 = 2500;
+// ADC resolution, 10 bits for the MCP3008
+pub const ADC_BITS_DEFAULT: u8 = 10;
+pub const ADC_BITS_MIN: u8 = 8;
+pub const ADC_BITS_MAX: u8 = 16;
+// ADC counts past the deadzone edge to leave it, at the default resolution
+const HYSTERESIS_DEFAULT: u16 = 8;
+// Slowest full travel of a slew limited output
+pub const TRAVEL_MAX_MS: u16 = 10000;
+
+// Sheet travel time from min to max in incremental mode, knob at full rate
+pub const SHEET_FULL_TRAVEL_MS: f32 = 2000.;
+
+/// Filter applied on the raw ADC values of a channel, against pot noise
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum AdcFilter {
+    None,
+    Mean,
+    Median
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChannelConfig {
+    pub name: String,
+    pub deadzone: u16,    // Deadzone around the ADC center
+    #[serde(default = "default_hysteresis")]
+    pub hysteresis: u16,  // ADC travel past the deadzone edge needed to leave it, against chatter at the edge
+    pub center: u16,      // Center output value
+    pub min: u16,         // Minimum output value
+    pub max: u16,         // Maximum output value
+    pub travel_ms: u16,   // Shortest time for a full min to max travel, 0 for no slew limit
+    #[serde(default)]
+    pub expo: u16,    // Exponential curve in percent, 0 is linear
+    pub invert: bool,     // Reversed pot wiring or mirrored servo
+    pub incremental: bool,    // Buttons move a sheet position kept once released, instead of a spring back to center
+    pub failsafe: u16,    // Output applied by the boat when the link is lost
+    pub curve: [u16; 5],  // Output in percent (0 min, 50 center, 100 max) at stick -100%, -50%, 0, 50%, 100%
+    pub adc_min: u16,     // ADC value at minimum stick position
+    pub adc_center: u16,  // ADC value at center stick position
+    pub adc_max: u16,     // ADC value at maximum stick position
+    #[serde(default = "default_adc_bits")]
+    pub adc_bits: u8,     // Resolution of the ADC read, full scale being 2^bits - 1
+    pub filter: AdcFilter,
+    pub filter_window: u16,   // Number of ADC samples filtered
+    
+    previous_value: u16,
+    #[serde(skip)]
+    adc_history: VecDeque<u16>,
+    // Incremental mode position, None until the first update
+    #[serde(skip)]
+    sheet_position: Option<f32>,
+    // Whether the stick was last read within the deadzone
+    #[serde(skip)]
+    in_deadzone: bool,
+    // Slew limited output, kept unrounded so that short updates still move
+    #[serde(skip)]
+    slew_position: Option<f32>
+}
+
+fn default_adc_bits() -> u8 {
+    ADC_BITS_DEFAULT
+}
+
+// Files from before the hysteresis get the one of new channels, not none
+fn default_hysteresis() -> u16 {
+    HYSTERESIS_DEFAULT
+}
+
+impl ChannelConfig {
+    pub fn new(_name: &str) -> Self {
+        ChannelConfig {
+            name: String::from(_name),
+            deadzone: 50,
+            hysteresis: HYSTERESIS_DEFAULT,
+            min: 1000,
+            max: 2000,
+            center: 1500,
+            travel_ms: 400,
+            expo: 0,
+            invert: false,
+            incremental: false,
+            failsafe: 1500,
+            curve: LINEAR_CURVE,
+            adc_min: 0,
+            adc_center: 512,
+            adc_max: 1023,
+            adc_bits: ADC_BITS_DEFAULT,
+            filter: AdcFilter::None,
+            filter_window: 5,
+            previous_value: 1500,
+            adc_history: VecDeque::new(),
+            sheet_position: None,
+            in_deadzone: true,
+            slew_position: None
+        }
+    }
+}
+
+impl ChannelConfig {
+    /// Highest ADC value at the channel resolution
+    pub fn adc_full_scale(&self) -> u16 {
+        ((1u32 << self.adc_bits.clamp(ADC_BITS_MIN, ADC_BITS_MAX)) - 1) as u16
+    }
+    
+    /// Half the ADC range
+    pub fn deadzone_max(&self) -> u16 {
+        self.adc_full_scale() / 2
+    }
+    
+    /// Switch to another ADC resolution, scaling the calibration and deadzone along
+    pub fn set_adc_bits(&mut self, bits: u8) {
+        let bits = bits.clamp(ADC_BITS_MIN, ADC_BITS_MAX);
+        let from = self.adc_full_scale() as u32 + 1;
+        self.adc_bits = bits;
+        let to = self.adc_full_scale() as u32 + 1;
+        let scale = |v: u16| (v as u32 * to / from).min(to - 1) as u16;
+        (self.adc_min, self.adc_center, self.adc_max) = (scale(self.adc_min), scale(self.adc_center), scale(self.adc_max));
+        self.deadzone = scale(self.deadzone).min(self.deadzone_max());
+        self.hysteresis = scale(self.hysteresis).min(self.deadzone_max() - self.deadzone);
+        self.adc_history.clear();
+    }
+    
+    // Bend the deflection past the deadzone, finer around center with full deflection unchanged
+    fn apply_expo(&self, normalized: i32, adc_range: i32) -> i32 {
+        let expo = self.expo.min(100) as f32 / 100.;
+        let x = normalized as f32 / adc_range as f32;
+        let curved = x * (1. - expo) + x * x * x * expo;
+        (curved * adc_range as f32).round() as i32
+    }
+    
+    fn filter_adc(&mut self, adc_value: u16) -> u16 {
+        self.adc_history.push_back(adc_value);
+        while self.adc_history.len() > self.filter_window.max(1) as usize {
+            self.adc_history.pop_front();
+        }
+        
+        match self.filter {
+            AdcFilter::None => adc_value,
+            AdcFilter::Mean => {
+                let sum: u32 = self.adc_history.iter().map(|&v| v as u32).sum();
+                (sum / self.adc_history.len() as u32) as u16
+            }
+            AdcFilter::Median => {
+                let mut sorted: Vec<u16> = self.adc_history.iter().copied().collect();
+                sorted.sort_unstable();
+                sorted[sorted.len() / 2]
+            }
+        }
+    }
+    
+    // Output for a stick position in [-1, 1], interpolated between the curve points
+    fn apply_curve(&self, position: f32) -> u16 {
+        let t = ((position.clamp(-1., 1.) + 1.) * 2.).min(3.999);
+        let i = t as usize;
+        let (a, b) = (self.curve[i] as f32, self.curve[i + 1] as f32);
+        let percent = a + (b - a) * (t - i as f32);
+        
+        // 0..50% of the curve covers [min, center], 50..100% covers [center, max]
+        let output = if percent >= 50. {
+            self.center as f32 + (percent - 50.) / 50. * (self.max as f32 - self.center as f32)
+        } else {
+            self.center as f32 - (50. - percent) / 50. * (self.center as f32 - self.min as f32)
+        };
+        output.round() as u16
+    }
+    
+    /// Stick channel, the output moving towards the stick position no faster than travel_ms allows
+    pub fn transform_adc(&mut self, adc_value: u16, elapsed_ms: u32) -> u16 {
+        let adc = self.filter_adc(adc_value) as i32;
+        let center = self.adc_center as i32;
+        
+        // Leaving the deadzone takes the hysteresis on top of it, entering it does not
+        let edge = if self.in_deadzone { self.deadzone + self.hysteresis } else { self.deadzone };
+        self.in_deadzone = (adc - center).abs() < edge as i32;
+        
+        // Stick position past the deadzone, in [-1, 1]
+        let position = if self.in_deadzone {
+            0.
+        } else if adc > center {
+            // Above center: [center+deadzone, adc_max]
+            let adc_range = (self.adc_max as i32 - (center + self.deadzone as i32)).max(1);
+            self.apply_expo((adc - center - self.deadzone as i32).max(0), adc_range) as f32 / adc_range as f32
+        } else {
+            // Below center: [adc_min, center-deadzone]
+            let adc_range = (center - self.deadzone as i32 - self.adc_min as i32).max(1);
+            -(self.apply_expo((center - self.deadzone as i32 - adc).max(0), adc_range) as f32 / adc_range as f32)
+        };
+        
+        // An inverted channel maps each stick side to the opposite one
+        let position = if self.invert { -position } else { position };
+        
+        let mut output = self.apply_curve(position) as f32;
+        if self.travel_ms > 0 {
+            let previous = self.slew_position.unwrap_or(self.previous_value as f32);
+            let max_change = self.max.saturating_sub(self.min) as f32 * elapsed_ms as f32 / self.travel_ms as f32;
+            output = output.clamp(previous - max_change, previous + max_change);
+        }
+        self.slew_position = Some(output);
+        // Last, so that a previous value outside a narrowed range cannot hold the output out of it
+        let output = (output.round() as u16).clamp(self.min, self.max);
+        
+        self.previous_value = output;
+        
+        output
+    }
+    
+    /// Sail channel driven by up/down buttons, the knob ADC value setting the throw or, in incremental mode, the rate
+    pub fn apply_button(&mut self, up: bool, down: bool, adc_value: u16, elapsed_ms: u32) -> u16 {
+        let out_range = self.max as u32 - self.min as u32;
+        let adc_range = self.adc_full_scale() as u32 + 1;
+        let diff = ((adc_value as u32 * out_range) / adc_range) as u16;
+        
+        let (up, down) = if self.invert { (down, up) } else { (up, down) };
+        
+        if self.incremental {
+            let rate = out_range as f32 * adc_value as f32 / adc_range as f32 / SHEET_FULL_TRAVEL_MS;
+            let mut position = self.sheet_position.unwrap_or(self.center as f32);
+            if up && !down {
+                position += rate * elapsed_ms as f32;
+            } else if down && !up {
+                position -= rate * elapsed_ms as f32;
+            }
+            let position = position.clamp(self.min as f32, self.max as f32);
+            self.sheet_position = Some(position);
+            return position.round() as u16;
+        }
+        
+        // Both pressed is contradictory (and used for combos): stay centered
+        if up && !down {
+            self.center.saturating_add(diff).min(self.max)
+        }
+        else if down && !up {
+            self.center.saturating_sub(diff).max(self.min)
+        }
+        else {
+            self.center
+        }
+    }
+    
+    /// Carry the filter history and last output over from the channel being replaced
+    pub fn keep_state_of(&mut self, previous: &mut ChannelConfig) {
+        self.previous_value = previous.previous_value;
+        self.slew_position = previous.slew_position;
+        self.in_deadzone = previous.in_deadzone;
+        self.adc_history = std::mem::take(&mut previous.adc_history);
+    }
+    
+    /// Check values edited outside of the menu, by hand or through the API
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.min <= self.center && self.center <= self.max) {
+            return Err(format!("{}: expected min <= center <= max", self.name));
+        }
+        if self.min < OUTPUT_MIN || self.max > OUTPUT_MAX {
+            return Err(format!("{}: outputs must be within {}-{}", self.name, OUTPUT_MIN, OUTPUT_MAX));
+        }
+        if !(self.min <= self.failsafe && self.failsafe <= self.max) {
+            return Err(format!("{}: failsafe must be within min and max", self.name));
+        }
+        if !(ADC_BITS_MIN..=ADC_BITS_MAX).contains(&self.adc_bits) {
+            return Err(format!("{}: adc_bits must be within {}-{}", self.name, ADC_BITS_MIN, ADC_BITS_MAX));
+        }
+        let full_scale = self.adc_full_scale();
+        if !(self.adc_min < self.adc_center && self.adc_center < self.adc_max && self.adc_max <= full_scale) {
+            return Err(format!("{}: expected adc_min < adc_center < adc_max <= {}", self.name, full_scale));
+        }
+        if self.deadzone as u32 + self.hysteresis as u32 > self.deadzone_max() as u32 || self.travel_ms > TRAVEL_MAX_MS {
+            return Err(format!("{}: deadzone and hysteresis must be at most {} together and travel_ms at most {}", self.name, self.deadzone_max(), TRAVEL_MAX_MS));
+        }
+        if self.expo > 100 || self.curve.iter().any(|&p| p > 100) {
+            return Err(format!("{}: expo and curve points are percentages", self.name));
+        }
+        if self.filter_window == 0 {
+            return Err(format!("{}: filter window must be at least 1", self.name));
+        }
+        Ok(())
+    }
+    
+    /// Clamp the values back within the rules of validate(), for hand edited files
+    pub fn sanitize(&mut self) {
+        self.min = self.min.clamp(OUTPUT_MIN, OUTPUT_MAX);
+        self.max = self.max.clamp(self.min, OUTPUT_MAX);
+        self.center = self.center.clamp(self.min, self.max);
+        self.failsafe = self.failsafe.clamp(self.min, self.max);
+        self.adc_bits = self.adc_bits.clamp(ADC_BITS_MIN, ADC_BITS_MAX);
+        self.deadzone = self.deadzone.min(self.deadzone_max());
+        self.hysteresis = self.hysteresis.min(self.deadzone_max() - self.deadzone);
+        self.travel_ms = self.travel_ms.min(TRAVEL_MAX_MS);
+        self.expo = self.expo.min(100);
+        for point in self.curve.iter_mut() {
+            *point = (*point).min(100);
+        }
+        self.filter_window = self.filter_window.max(1);
+        let full_scale = self.adc_full_scale();
+        if !(self.adc_min < self.adc_center && self.adc_center < self.adc_max && self.adc_max <= full_scale) {
+            (self.adc_min, self.adc_center, self.adc_max) = (0, full_scale / 2 + 1, full_scale);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channel_validation() {
+        let mut channel = ChannelConfig::new("Motor");
+        assert!(channel.validate().is_ok());
+        channel.center = 2100;
+        assert!(channel.validate().is_err());
+        channel.center = 1500;
+        channel.adc_center = 1023;
+        assert!(channel.validate().is_err());
+        channel.min = 2200;
+        channel.sanitize();
+        assert!(channel.validate().is_ok());
+        assert_eq!((channel.min, channel.center, channel.max), (2200, 2200, 2200));
+        assert_eq!(channel.adc_center, 512);
+    }
+
+    #[test]
+    fn test_incremental_sheet_position() {
+        let mut channel = ChannelConfig::new("Boom");
+        channel.incremental = true;
+        // Knob at half rate: half the 1000us range in 2s, 250us per second
+        let position = channel.apply_button(true, false, 512, 1000);
+        assert_eq!(position, 1750);
+        assert_eq!(channel.apply_button(false, false, 512, 1000), 1750);
+        assert_eq!(channel.apply_button(true, false, 512, 2000), 2000);
+        assert_eq!(channel.apply_button(false, true, 1023, 100), 1950);
+    }
+
+    #[test]
+    fn test_button_throw_off_center() {
+        let mut channel = ChannelConfig::new("Genoa");
+        // Full knob throw past the range on the side the center is close to
+        (channel.min, channel.center) = (0, 10);
+        assert_eq!(channel.apply_button(false, true, 1023, 40), 0);
+        assert_eq!(channel.apply_button(true, false, 1023, 40), channel.max);
+        (channel.min, channel.center) = (1000, 1990);
+        assert_eq!(channel.apply_button(true, false, 1023, 40), 2000);
+        assert_eq!(channel.apply_button(false, true, 1023, 40), 1000);
+    }
+
+    #[test]
+    fn test_transform_deadzone_and_travel() {
+        let mut channel = ChannelConfig::new("Motor");
+        assert_eq!(channel.transform_adc(540, 40), 1500);
+        // Full stick, the 1000us range travelled in 400ms whatever the update period
+        assert_eq!(channel.transform_adc(1023, 40), 1600);
+        assert_eq!(channel.transform_adc(1023, 80), 1800);
+        for _ in 0..10 {
+            channel.transform_adc(1023, 1);
+        }
+        assert_eq!(channel.transform_adc(1023, 0), 1825);
+        assert_eq!(channel.transform_adc(1023, 1000), 2000);
+        channel.travel_ms = 0;
+        assert_eq!(channel.transform_adc(0, 0), 1000);
+    }
+
+    #[test]
+    fn test_transform_expo_and_invert() {
+        let mut channel = ChannelConfig::new("RudderStar");
+        channel.travel_ms = 0;
+        assert_eq!(channel.transform_adc(792, 40), 1749);
+        channel.expo = 100;
+        assert_eq!(channel.transform_adc(792, 40), 1562);
+        channel.invert = true;
+        assert_eq!(channel.transform_adc(1023, 40), 1000);
+        assert_eq!(channel.transform_adc(0, 40), 2000);
+    }
+
+    #[test]
+    fn test_twelve_bit_off_center() {
+        let mut channel = ChannelConfig::new("RudderStar");
+        channel.travel_ms = 0;
+        channel.set_adc_bits(12);
+        assert_eq!((channel.adc_min, channel.adc_center, channel.adc_max, channel.deadzone), (0, 2048, 4092, 200));
+        // Pot resting off center, as measured by the calibration
+        (channel.adc_min, channel.adc_center, channel.adc_max) = (100, 1800, 4095);
+        assert!(channel.validate().is_ok());
+        assert_eq!(channel.transform_adc(1800, 40), 1500);
+        assert_eq!(channel.transform_adc(4095, 40), 2000);
+        assert_eq!(channel.transform_adc(100, 40), 1000);
+        channel.adc_max = 4096;
+        assert!(channel.validate().is_err());
+    }
+
+    #[test]
+    fn test_deadzone_hysteresis() {
+        let mut channel = ChannelConfig::new("RudderStar");
+        channel.travel_ms = 0;
+        // Resting at the edge of the 50 wide deadzone
+        assert_eq!(channel.transform_adc(562, 0), 1500);
+        assert_eq!(channel.transform_adc(569, 0), 1500);
+        let outside = channel.transform_adc(572, 0);
+        assert!(outside > 1500);
+        // Back near the edge, still out until within the deadzone
+        assert!(channel.transform_adc(564, 0) > 1500);
+        assert_eq!(channel.transform_adc(561, 0), 1500);
+        assert_eq!(channel.transform_adc(565, 0), 1500);
+    }
+}
@@ -0,0 +1,88 @@
+use pizboat_channel::{AdcFilter, ChannelConfig, OUTPUT_MAX, OUTPUT_MIN, TRAVEL_MAX_MS};
+use proptest::prelude::*;
+
+// Valid channel, as accepted by validate(), with a random curve
+fn channel() -> impl Strategy<Value = ChannelConfig> {
+    (OUTPUT_MIN..=OUTPUT_MAX, OUTPUT_MIN..=OUTPUT_MAX, 0u16..=100, 0u16..=200, 0u16..=TRAVEL_MAX_MS,
+        prop::array::uniform5(0u16..=100), 0u16..300, 400u16..700, 800u16..=1023, any::<bool>())
+        .prop_flat_map(|(a, b, expo, deadzone, travel_ms, curve, adc_min, adc_center, adc_max, invert)| {
+            let (min, max) = (a.min(b), a.max(b));
+            (min..=max).prop_map(move |center| {
+                let mut channel = ChannelConfig::new("Test");
+                (channel.min, channel.center, channel.max, channel.failsafe) = (min, center, max, center);
+                (channel.expo, channel.deadzone, channel.travel_ms, channel.curve, channel.invert) = (expo, deadzone, travel_ms, curve, invert);
+                (channel.adc_min, channel.adc_center, channel.adc_max) = (adc_min, adc_center, adc_max);
+                channel.filter = AdcFilter::None;
+                channel
+            })
+        })
+}
+
+// Output of a fresh copy, free from the slew limit of previous updates
+fn output(channel: &ChannelConfig, adc: u16) -> u16 {
+    let mut channel = channel.clone();
+    channel.travel_ms = 0;
+    channel.transform_adc(adc, 0)
+}
+
+proptest! {
+    #[test]
+    fn outputs_stay_within_min_max(channel in channel(), inputs in prop::collection::vec((0u16..=1023, 0u32..100), 1..50)) {
+        prop_assert!(channel.validate().is_ok());
+        let mut channel = channel;
+        for (adc, elapsed_ms) in inputs {
+            let value = channel.transform_adc(adc, elapsed_ms);
+            prop_assert!(channel.min <= value && value <= channel.max, "{} outside {}..{}", value, channel.min, channel.max);
+        }
+    }
+
+    #[test]
+    fn travel_limits_each_change(channel in channel(), inputs in prop::collection::vec((0u16..=1023, 0u32..100), 2..50)) {
+        let mut channel = channel;
+        channel.travel_ms = channel.travel_ms.max(1);
+        let mut previous = channel.transform_adc(inputs[0].0, inputs[0].1);
+        for &(adc, elapsed_ms) in &inputs[1..] {
+            let value = channel.transform_adc(adc, elapsed_ms);
+            // Rounding moves the output by at most one more unit
+            let max_change = (channel.max - channel.min) as f32 * elapsed_ms as f32 / channel.travel_ms as f32;
+            prop_assert!(value.abs_diff(previous) as f32 <= max_change.ceil() + 1., "{} to {} in {}ms", previous, value, elapsed_ms);
+            previous = value;
+        }
+    }
+
+    // With the curve rising from stick -100% to 100%
+    #[test]
+    fn monotonic_with_rising_curve(channel in channel(), a in 0u16..=1023, b in 0u16..=1023) {
+        let mut channel = channel;
+        channel.curve.sort_unstable();
+        let (low, high) = (output(&channel, a.min(b)), output(&channel, a.max(b)));
+        if channel.invert {
+            prop_assert!(low >= high);
+        } else {
+            prop_assert!(low <= high);
+        }
+    }
+
+    // Within the deadzone the stick reads as centered, whatever the curve says at center
+    #[test]
+    fn deadzone_holds_center(channel in channel(), offset in -200i32..=200) {
+        let adc = (channel.adc_center as i32 + offset).clamp(0, 1023) as u16;
+        if (adc as i32 - channel.adc_center as i32).abs() < channel.deadzone as i32 {
+            prop_assert_eq!(output(&channel, adc), output(&channel, channel.adc_center));
+        }
+    }
+
+    // Symmetric ADC travel, outputs and curve: both stick sides mirror each other
+    #[test]
+    fn deadzone_symmetry(deadzone in 0u16..=200, expo in 0u16..=100, throw in 0u16..=500, half_curve in prop::array::uniform2(0u16..=50), offset in 0u16..=511, invert in any::<bool>()) {
+        let mut channel = ChannelConfig::new("Test");
+        (channel.adc_min, channel.adc_center, channel.adc_max) = (1, 512, 1023);
+        (channel.min, channel.center, channel.max) = (1500 - throw, 1500, 1500 + throw);
+        channel.curve = [half_curve[0], half_curve[1], 50, 100 - half_curve[1], 100 - half_curve[0]];
+        (channel.deadzone, channel.expo, channel.invert) = (deadzone, expo, invert);
+
+        let above = output(&channel, 512 + offset) as i32 - 1500;
+        let below = 1500 - output(&channel, 512 - offset) as i32;
+        prop_assert!((above - below).abs() <= 1, "{} above center, {} below", above, below);
+    }
+}
@@ -0,0 +1,315 @@
+use std::collections::VecDeque;
+
+use crate::font::get_font_data;
+
+/// Area of the frame, from its top left corner
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: u8,
+    pub y: u8,
+    pub w: u8,
+    pub h: u8,
+}
+
+impl Rect {
+    pub const fn new(x: u8, y: u8, w: u8, h: u8) -> Self {
+        Rect { x, y, w, h }
+    }
+}
+
+/// 128x64 frame in the SSD1306 layout: 8 pages of 8 pixel rows, one byte per column
+pub struct DisplayBuffer {
+    pub(crate) buffer: [u8; 1024],
+    // Content of the panel, None until the first full transfer
+    sent: Option<[u8; 1024]>,
+}
+
+impl Default for DisplayBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DisplayBuffer {
+    pub fn new() -> Self {
+        DisplayBuffer {
+            buffer: [0u8; 1024],
+            sent: None,
+        }
+    }
+    
+    /// First and last columns of an 8 pixel page that differ from the panel, None if unchanged
+    pub fn dirty_columns(&self, page: usize) -> Option<(usize, usize)> {
+        let current = &self.buffer[page * 128..(page + 1) * 128];
+        let Some(sent) = &self.sent else { return Some((0, 127)) };
+        let sent = &sent[page * 128..(page + 1) * 128];
+        let first = (0..128).find(|&x| current[x] != sent[x])?;
+        let last = (0..128).rfind(|&x| current[x] != sent[x])?;
+        Some((first, last))
+    }
+    
+    pub fn mark_sent(&mut self) {
+        self.sent = Some(self.buffer);
+    }
+
+    pub fn clear(&mut self) {
+        self.buffer.fill(0);
+    }
+
+    pub fn set_pixel(&mut self, x: u8, y: u8, on: bool) {
+        if x >= 128 || y >= 64 {
+            return;
+        }
+        let byte_index = (y / 8) as usize * 128 + x as usize;
+        let bit_index = y % 8;
+        
+        if on {
+            self.buffer[byte_index] |= 1 << bit_index;
+        } else {
+            self.buffer[byte_index] &= !(1 << bit_index);
+        }
+    }
+
+    pub fn draw_char(&mut self, x: u8, y: u8, c: char) {
+        self.draw_char_color(x, y, c, true);
+    }
+    
+    // Only the glyph pixels are written, lit or cleared
+    pub fn draw_char_color(&mut self, x: u8, y: u8, c: char, on: bool) {
+        let font = get_font_data(c);
+        for dx in 0..5u8 {
+            let column = font[dx as usize];
+            for dy in 0..8u8 {
+                if (column >> dy) & 1 == 1 {
+                    self.set_pixel(x + dx, y + dy, on);
+                }
+            }
+        }
+    }
+
+    pub fn draw_text(&mut self, x: u8, y: u8, text: &str) {
+        for (i, c) in text.chars().enumerate() {
+            self.draw_char(x + (i as u8 * 6), y, c);
+        }
+    }
+    
+    // Centered horizontally on the screen
+    pub fn draw_text_centered(&mut self, y: u8, text: &str, scale: u8) {
+        // Wider than the screen for long texts, left aligned then
+        let width = text.chars().count() * 6 * scale.max(1) as usize;
+        self.draw_text_scaled(64usize.saturating_sub(width / 2) as u8, y, text, scale);
+    }
+    
+    // White box with the text cut out, for warnings
+    pub fn draw_text_inverted(&mut self, x: u8, y: u8, text: &str) {
+        self.fill_rect(x.saturating_sub(1), y.saturating_sub(1), text.len() as u8 * 6 + 1, 9);
+        for (i, c) in text.chars().enumerate() {
+            self.draw_char_color(x + i as u8 * 6, y, c, false);
+        }
+    }
+    
+    // Full width banner over the page, white on black or inverted
+    pub fn draw_banner(&mut self, y: u8, text: &str, inverted: bool) {
+        for dy in y..y + 20 {
+            for dx in 0..128u8 {
+                self.set_pixel(dx, dy, inverted);
+            }
+        }
+        self.draw_rect(0, y, 128, 20);
+        let x = 64u8.saturating_sub(text.chars().count() as u8 * 3);
+        for (i, c) in text.chars().enumerate() {
+            self.draw_char_color(x + i as u8 * 6, y + 6, c, !inverted);
+        }
+    }
+    
+    // Each font pixel drawn as a scale x scale square, 2 or 3 for values read at arm's length
+    pub fn draw_text_scaled(&mut self, x: u8, y: u8, text: &str, scale: u8) {
+        let scale = scale.max(1);
+        for (i, c) in text.chars().enumerate() {
+            let font = get_font_data(c);
+            let x0 = x as u16 + i as u16 * 6 * scale as u16;
+            if x0 >= 128 {
+                break;
+            }
+            let x0 = x0 as u8;
+            for dx in 0..5u8 {
+                for dy in 0..8u8 {
+                    if (font[dx as usize] >> dy) & 1 == 1 {
+                        self.fill_rect(x0 + dx * scale, y + dy * scale, scale, scale);
+                    }
+                }
+            }
+        }
+    }
+    
+    pub fn fill_rect(&mut self, x: u8, y: u8, w: u8, h: u8) {
+        for dx in x..x.saturating_add(w) {
+            for dy in y..y.saturating_add(h) {
+                self.set_pixel(dx, dy, true);
+            }
+        }
+    }
+    
+    // Outline only
+    pub fn draw_rect(&mut self, x: u8, y: u8, w: u8, h: u8) {
+        if w == 0 || h == 0 {
+            return;
+        }
+        self.draw_line(x, y, x + w - 1, y);
+        self.draw_line(x, y + h - 1, x + w - 1, y + h - 1);
+        self.draw_line(x, y, x, y + h - 1);
+        self.draw_line(x + w - 1, y, x + w - 1, y + h - 1);
+    }
+    
+    // Bresenham, both ends included
+    pub fn draw_line(&mut self, x0: u8, y0: u8, x1: u8, y1: u8) {
+        let (mut x, mut y) = (x0 as i16, y0 as i16);
+        let (x1, y1) = (x1 as i16, y1 as i16);
+        let dx = (x1 - x).abs();
+        let dy = -(y1 - y).abs();
+        let sx = if x < x1 { 1 } else { -1 };
+        let sy = if y < y1 { 1 } else { -1 };
+        let mut error = dx + dy;
+        loop {
+            self.set_pixel(x as u8, y as u8, true);
+            if x == x1 && y == y1 {
+                break;
+            }
+            let e2 = 2 * error;
+            if e2 >= dy {
+                error += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                error += dx;
+                y += sy;
+            }
+        }
+    }
+    
+    // Outlined bar filled from the left, value taken within min..max
+    pub fn draw_hbar(&mut self, Rect { x, y, w, h }: Rect, value: u16, min: u16, max: u16) {
+        self.draw_rect(x, y, w, h);
+        let fill = bar_fill(value, min, max, w.saturating_sub(2));
+        self.fill_rect(x + 1, y + 1, fill, h.saturating_sub(2));
+    }
+    
+    // Outlined bar filled from the bottom
+    pub fn draw_vbar(&mut self, Rect { x, y, w, h }: Rect, value: u16, min: u16, max: u16) {
+        self.draw_rect(x, y, w, h);
+        let fill = bar_fill(value, min, max, h.saturating_sub(2));
+        self.fill_rect(x + 1, y + h.saturating_sub(1) - fill, w.saturating_sub(2), fill);
+    }
+    
+    // Bar filled from center toward the value, for channels with a neutral
+    pub fn draw_hbar_centered(&mut self, Rect { x, y, w, h }: Rect, value: u16, min: u16, center: u16, max: u16) {
+        self.draw_rect(x, y, w, h);
+        let middle = x + w / 2;
+        if value >= center {
+            let fill = bar_fill(value, center, max, w.saturating_sub(2) / 2);
+            self.fill_rect(middle, y + 1, fill, h.saturating_sub(2));
+        } else {
+            let fill = bar_fill(center - value.max(min) + min, min, center, w.saturating_sub(2) / 2);
+            self.fill_rect(middle - fill, y + 1, fill, h.saturating_sub(2));
+        }
+        self.draw_line(middle, y, middle, y + h - 1);
+    }
+    
+    pub fn draw_vbar_centered(&mut self, Rect { x, y, w, h }: Rect, value: u16, min: u16, center: u16, max: u16) {
+        self.draw_rect(x, y, w, h);
+        let middle = y + h / 2;
+        if value >= center {
+            let fill = bar_fill(value, center, max, h.saturating_sub(2) / 2);
+            self.fill_rect(x + 1, middle - fill, w.saturating_sub(2), fill);
+        } else {
+            let fill = bar_fill(center - value.max(min) + min, min, center, h.saturating_sub(2) / 2);
+            self.fill_rect(x + 1, middle, w.saturating_sub(2), fill);
+        }
+        self.draw_line(x, middle, x + w - 1, middle);
+    }
+    
+    // Battery outline with a tip, filled according to the charge
+    pub fn draw_battery(&mut self, x: u8, y: u8, percent: u8) {
+        self.draw_rect(x, y, 18, 7);
+        self.fill_rect(x + 18, y + 2, 2, 3);
+        self.fill_rect(x + 2, y + 2, ((percent.min(100) as u16 * 14) / 100) as u8, 3);
+    }
+    
+    // 5 bars of increasing height, unlit bars drawn as a baseline dot
+    pub fn draw_signal_bars(&mut self, x: u8, y: u8, wireless_quality: i16) {
+        let bars = ((wireless_quality.clamp(0, 70) * 5 + 69) / 70) as u8;
+        for i in 0..5u8 {
+            let height = 2 + (i * 3) / 2;
+            if i < bars {
+                self.fill_rect(x + i * 4, y + 8 - height, 3, height);
+            } else {
+                self.fill_rect(x + i * 4, y + 7, 3, 1);
+            }
+        }
+    }
+    
+    // One column per sample, scaled on the highest value, gaps where the boat was not connected
+    // min_scale is the lowest full scale, so that steady values draw a flat line
+    pub fn draw_sparkline(&mut self, x: u8, y: u8, height: u8, values: &VecDeque<Option<u64>>, min_scale: u64) {
+        let max = values.iter().flatten().copied().max().unwrap_or(0).max(min_scale);
+        let to_y = |v: u64| y + height - 1 - ((v.min(max) * (height as u64 - 1)) / max) as u8;
+        let mut previous: Option<u8> = None;
+        for (i, value) in values.iter().enumerate() {
+            let Some(value) = value else {
+                previous = None;
+                continue;
+            };
+            let current = to_y(*value);
+            // Join with the previous point so that spikes stay visible
+            let (top, bottom) = match previous {
+                Some(p) => (p.min(current), p.max(current)),
+                None => (current, current),
+            };
+            for py in top..=bottom {
+                self.set_pixel(x + i as u8, py, true);
+            }
+            previous = Some(current);
+        }
+    }
+    
+    pub fn draw_blocks(&mut self, x0: u8, y0: u8, nb_blocks: u8) {
+        for n in 0..nb_blocks {
+            self.fill_rect(x0 + n * 6, y0, 3, 8);
+        }
+    }
+}
+
+// Filled length of a bar of the given inner length
+fn bar_fill(value: u16, min: u16, max: u16, length: u8) -> u8 {
+    let range = max.saturating_sub(min).max(1) as u32;
+    ((value.clamp(min, max.max(min)) - min) as u32 * length as u32 / range) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dirty_columns() {
+        let mut buffer = DisplayBuffer::new();
+        assert_eq!(buffer.dirty_columns(3), Some((0, 127)));
+        buffer.mark_sent();
+        assert_eq!(buffer.dirty_columns(3), None);
+
+        buffer.set_pixel(10, 25, true);
+        buffer.set_pixel(40, 30, true);
+        assert_eq!(buffer.dirty_columns(3), Some((10, 40)));
+        assert_eq!(buffer.dirty_columns(2), None);
+    }
+
+    #[test]
+    fn test_long_text_centered() {
+        let mut buffer = DisplayBuffer::new();
+        buffer.draw_text_centered(0, "OVER FORTY THREE CHARACTERS AT SCALE TWO", 2);
+        assert!(buffer.buffer[..128].iter().take(2).any(|&column| column != 0));
+        // 48 pixels wide from x = 40
+        buffer.draw_text_centered(24, "ABCD", 2);
+        assert!(buffer.buffer[3 * 128..3 * 128 + 40].iter().all(|&column| column == 0));
+        assert!(buffer.buffer[3 * 128 + 40] != 0);
+    }
+}
@@ -0,0 +1,12 @@
+//! SSD1306 driver and drawing primitives of the 128x64 OLED, the page layouts staying in each binary.
+
+mod buffer;
+mod font;
+mod ssd1306;
+mod spi;
+mod terminal;
+
+pub use buffer::{DisplayBuffer, Rect};
+pub use ssd1306::{I2cBus, SSD1306};
+pub use spi::{SpiBus, DC_PIN, RST_PIN};
+pub use terminal::TerminalBus;
@@ -0,0 +1,56 @@
+use rppal::gpio::{Gpio, OutputPin};
+use rppal::spi::{Bus, Mode, SlaveSelect, Spi};
+use std::thread;
+use std::time::Duration;
+
+use pizboat_hal::DisplayBus;
+use tracing::info;
+
+const SPI_DISPLAY_CLOCK_HZ: u32 = 8_000_000;
+pub const DC_PIN: u8 = 22;
+pub const RST_PIN: u8 = 17;
+// Largest transfer of the spidev driver by default
+const SPI_MAX_TRANSFER: usize = 4096;
+
+/// SSD1306 wired on SPI, DC telling commands (low) from data (high)
+pub struct SpiBus {
+    spi: Spi,
+    dc: OutputPin,
+    // Kept so that the pin is not released, which would leave the panel in reset
+    _rst: OutputPin,
+}
+
+impl SpiBus {
+    /// On a chip select of its own, such as SPI0 CE1 next to the MCP3008 of the remote on CE0
+    pub fn new(bus: Bus, slave_select: SlaveSelect) -> Result<Self, Box<dyn std::error::Error>> {
+        let spi = Spi::new(bus, slave_select, SPI_DISPLAY_CLOCK_HZ, Mode::Mode0)?;
+        let gpio = Gpio::new()?;
+        let dc = gpio.get(DC_PIN)?.into_output();
+        let mut rst = gpio.get(RST_PIN)?.into_output();
+
+        // Hardware reset pulse
+        rst.set_low();
+        thread::sleep(Duration::from_millis(10));
+        rst.set_high();
+        thread::sleep(Duration::from_millis(10));
+
+        info!("SSD1306 OLED on {:?} {:?}, DC pin {} RST pin {}", bus, slave_select, DC_PIN, RST_PIN);
+        Ok(SpiBus { spi, dc, _rst: rst })
+    }
+}
+
+impl DisplayBus for SpiBus {
+    fn write_commands(&mut self, commands: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        self.dc.set_low();
+        self.spi.write(commands)?;
+        Ok(())
+    }
+
+    fn write_data(&mut self, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        self.dc.set_high();
+        for chunk in data.chunks(SPI_MAX_TRANSFER) {
+            self.spi.write(chunk)?;
+        }
+        Ok(())
+    }
+}
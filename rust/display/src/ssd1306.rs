@@ -0,0 +1,101 @@
+use rppal::i2c::I2c;
+
+use pizboat_hal::DisplayBus;
+
+use crate::buffer::DisplayBuffer;
+use tracing::info;
+
+// Power on sequence of the SSD1306, 128x64 with horizontal addressing
+const SSD1306_INIT: [u8; 25] = [
+    0xAE, 0xD5, 0x80, 0xA8, 0x3F, 0xD3, 0x00, 0x40,
+    0x8D, 0x14, 0x20, 0x00, 0xA1, 0xC8, 0xDA, 0x12,
+    0x81, 0xCF, 0xD9, 0xF1, 0xDB, 0x40, 0xA4, 0xA6, 0xAF,
+];
+
+/// SSD1306 controller, over I2C or SPI
+pub struct SSD1306<B: DisplayBus> {
+    bus: B,
+}
+
+impl<B: DisplayBus> SSD1306<B> {
+    pub fn new(bus: B) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut display = SSD1306 { bus };
+        display.bus.write_commands(&SSD1306_INIT)?;
+        Ok(display)
+    }
+
+    pub fn bus(&self) -> &B {
+        &self.bus
+    }
+
+    pub fn set_contrast(&mut self, contrast: u8) -> Result<(), Box<dyn std::error::Error>> {
+        self.bus.write_commands(&[0x81, contrast])
+    }
+
+    /// Transmit only the changed columns of each page
+    pub fn display(&mut self, buffer: &DisplayBuffer) -> Result<(), Box<dyn std::error::Error>> {
+        for page in 0..8 {
+            if let Some((first, last)) = buffer.dirty_columns(page) {
+                self.bus.write_commands(&[0x21, first as u8, last as u8, 0x22, page as u8, page as u8])?;
+                self.bus.write_data(&buffer.buffer[page * 128 + first..=page * 128 + last])?;
+            }
+        }
+        self.bus.flush()
+    }
+}
+
+/// I2C bus 1 at address 0x3C, a control byte telling commands from data
+pub struct I2cBus {
+    i2c: I2c,
+}
+
+impl I2cBus {
+    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        let mut i2c = I2c::with_bus(1)?;
+        i2c.set_slave_address(0x3C)?;
+        info!("SSD1306 OLED on I2C bus 1, address 0x3C");
+        Ok(I2cBus { i2c })
+    }
+}
+
+impl DisplayBus for I2cBus {
+    fn write_commands(&mut self, commands: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        for &cmd in commands {
+            self.i2c.write(&[0x00, cmd])?;
+        }
+        Ok(())
+    }
+
+    fn write_data(&mut self, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        for chunk in data.chunks(16) {
+            let mut message = vec![0x40];
+            message.extend_from_slice(chunk);
+            self.i2c.write(&message)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pizboat_hal::mock::MockDisplayBus;
+
+    #[test]
+    fn test_partial_update() {
+        let bus = MockDisplayBus::default();
+        let mut display = SSD1306::new(bus.clone()).unwrap();
+        assert_eq!(bus.take_commands(), SSD1306_INIT);
+
+        let mut buffer = DisplayBuffer::new();
+        display.display(&buffer).unwrap();
+        buffer.mark_sent();
+        assert_eq!(bus.take_data().len(), 1024);
+        bus.take_commands();
+
+        buffer.set_pixel(10, 25, true);
+        display.display(&buffer).unwrap();
+        assert_eq!(bus.take_commands(), [0x21, 10, 10, 0x22, 3, 3]);
+        assert_eq!(bus.take_data(), [0x02]);
+    }
+}
@@ -0,0 +1,120 @@
+use pizboat_hal::DisplayBus;
+
+// Commands of SSD1306_INIT followed by one argument byte
+const ONE_ARGUMENT_COMMANDS: [u8; 9] = [0x20, 0x81, 0x8D, 0xA8, 0xD3, 0xD5, 0xD9, 0xDA, 0xDB];
+
+/// Stand-in for the panel on a desktop: keeps a copy of the SSD1306 RAM and draws it on the terminal at each frame
+pub struct TerminalBus {
+    ram: [u8; 1024],
+    columns: (usize, usize),
+    pages: (usize, usize),
+    cursor: (usize, usize),
+    changed: bool,
+}
+
+impl Default for TerminalBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TerminalBus {
+    pub fn new() -> Self {
+        TerminalBus { ram: [0; 1024], columns: (0, 127), pages: (0, 7), cursor: (0, 0), changed: false }
+    }
+
+    fn pixel(&self, x: usize, y: usize) -> bool {
+        (self.ram[(y / 8) * 128 + x] >> (y % 8)) & 1 == 1
+    }
+
+    /// Two pixel rows per line with half blocks, framed
+    pub fn frame(&self) -> String {
+        let mut frame = format!("┌{}┐\n", "─".repeat(128));
+        for y in (0..64).step_by(2) {
+            frame.push('│');
+            for x in 0..128 {
+                frame.push(match (self.pixel(x, y), self.pixel(x, y + 1)) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                });
+            }
+            frame.push_str("│\n");
+        }
+        frame.push_str(&format!("└{}┘", "─".repeat(128)));
+        frame
+    }
+}
+
+impl DisplayBus for TerminalBus {
+    // Only the addressing commands matter, the others being skipped with their argument
+    fn write_commands(&mut self, commands: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        let mut bytes = commands.iter().copied();
+        while let Some(command) = bytes.next() {
+            match command {
+                0x21 => {
+                    self.columns = (bytes.next().unwrap_or(0) as usize & 0x7F, bytes.next().unwrap_or(127) as usize & 0x7F);
+                    self.cursor.0 = self.columns.0;
+                }
+                0x22 => {
+                    self.pages = (bytes.next().unwrap_or(0) as usize & 0x07, bytes.next().unwrap_or(7) as usize & 0x07);
+                    self.cursor.1 = self.pages.0;
+                }
+                c if ONE_ARGUMENT_COMMANDS.contains(&c) => { bytes.next(); }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    // Horizontal addressing: along the column window, then down to the next page
+    fn write_data(&mut self, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        for &byte in data {
+            let (column, page) = self.cursor;
+            self.ram[page * 128 + column] = byte;
+            self.cursor = if column < self.columns.1 {
+                (column + 1, page)
+            } else if page < self.pages.1 {
+                (self.columns.0, page + 1)
+            } else {
+                (self.columns.0, self.pages.0)
+            };
+        }
+        self.changed = true;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.changed {
+            self.changed = false;
+            // Home and clear, so that the logs scroll below the last frame
+            println!("\x1b[H\x1b[2J{}", self.frame());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DisplayBuffer, SSD1306};
+
+    #[test]
+    fn test_terminal_frame() {
+        let mut display = SSD1306::new(TerminalBus::new()).unwrap();
+        let mut buffer = DisplayBuffer::new();
+        buffer.set_pixel(0, 0, true);
+        buffer.set_pixel(127, 63, true);
+        buffer.set_pixel(5, 33, true);
+        display.display(&buffer).unwrap();
+
+        let bus = display.bus();
+        let lines: Vec<Vec<char>> = bus.frame().lines().map(|l| l.chars().collect()).collect();
+        assert_eq!(lines.len(), 34);
+        assert_eq!(lines[1][1], '▀');
+        assert_eq!(lines[32][128], '▄');
+        assert_eq!(lines[17][6], '▄');
+        assert_eq!(lines[17][5], ' ');
+    }
+}
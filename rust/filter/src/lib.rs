@@ -0,0 +1,39 @@
+//! Rejection of corrupted readings, shared by the HX711 drivers of the boat and of the `hx711` tool.
+
+// Standard deviation of normally distributed readings per median absolute deviation
+const MAD_TO_SIGMA: f32 = 1.4826;
+
+/// Middle reading, ignoring up to half of the readings being corrupted.
+/// The lower one for an even count, so that the result is always one of them.
+pub fn median(values: &[i32]) -> Option<i32> {
+    let mut sorted = values.to_vec();
+    sorted.sort_unstable();
+    sorted.get(values.len().checked_sub(1)? / 2).copied()
+}
+
+/// Mean of the readings within `sigmas` standard deviations of the median, None without any reading
+pub fn sigma_clipped_mean(values: &[i32], sigmas: f32) -> Option<i32> {
+    let center = median(values)?;
+    // Deviation estimated from the median absolute deviation, which outliers barely move
+    let deviation = |v: i32| (v as i64 - center as i64).abs();
+    let deviations: Vec<i32> = values.iter().map(|&v| deviation(v) as i32).collect();
+    let limit = sigmas * MAD_TO_SIGMA * median(&deviations)? as f32;
+    // The median itself is always kept
+    let kept: Vec<i64> = values.iter().filter(|&&v| deviation(v) as f32 <= limit).map(|&v| v as i64).collect();
+    Some((kept.iter().sum::<i64>() / kept.len() as i64) as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_corrupted_reading_rejected() {
+        // A flipped high bit on one of the 24-bit reads
+        let values = [8_661_700, 8_661_790, 8_661_750, 16_777_215, 8_661_720];
+        assert_eq!(median(&values), Some(8_661_750));
+        assert_eq!(sigma_clipped_mean(&values, 3.), Some(8_661_740));
+        assert_eq!(sigma_clipped_mean(&[8_661_700; 4], 3.), Some(8_661_700));
+        assert_eq!(sigma_clipped_mean(&[], 3.), None);
+    }
+}
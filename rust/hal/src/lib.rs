@@ -0,0 +1,84 @@
+//! Hardware traits of the boat and remote drivers, implemented over rppal on the Pi and by the mocks of `mock` elsewhere.
+
+pub mod mock;
+#[cfg(feature = "rppal")]
+mod pi;
+
+/// Servo or ESC output
+pub trait PwmOut {
+    fn set_pulse_us(&mut self, pulse_width_us: u32) -> Result<(), Box<dyn std::error::Error>>;
+}
+
+/// Called with the new level of an input pin
+pub type InterruptCallback = Box<dyn FnMut(bool) + Send>;
+
+/// Input pin reporting its level changes, true being high
+pub trait DigitalIn {
+    fn is_high(&self) -> bool;
+    /// The callback runs in an interrupt thread on each change, until the pin is dropped
+    fn set_interrupt(&mut self, callback: InterruptCallback) -> Result<(), Box<dyn std::error::Error>>;
+}
+
+/// Output pin, such as the enable line of a radio
+pub trait DigitalOut {
+    fn set(&mut self, high: bool);
+}
+
+/// Full duplex link to one MCP3008, as many bytes read as written
+pub trait AdcSource {
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Box<dyn std::error::Error>>;
+}
+
+/// Full duplex SPI link to a radio module, as many bytes read as written
+pub trait RadioBus {
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Box<dyn std::error::Error>>;
+}
+
+/// Command and data link of a display controller
+pub trait DisplayBus {
+    fn write_commands(&mut self, commands: &[u8]) -> Result<(), Box<dyn std::error::Error>>;
+    fn write_data(&mut self, data: &[u8]) -> Result<(), Box<dyn std::error::Error>>;
+
+    /// End of a frame, for buses that do not transmit as they go
+    fn flush(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(())
+    }
+}
+
+impl<T: PwmOut + ?Sized> PwmOut for Box<T> {
+    fn set_pulse_us(&mut self, pulse_width_us: u32) -> Result<(), Box<dyn std::error::Error>> {
+        (**self).set_pulse_us(pulse_width_us)
+    }
+}
+
+impl<T: DigitalOut + ?Sized> DigitalOut for Box<T> {
+    fn set(&mut self, high: bool) {
+        (**self).set(high)
+    }
+}
+
+impl<T: AdcSource + ?Sized> AdcSource for Box<T> {
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        (**self).transfer(read, write)
+    }
+}
+
+impl<T: RadioBus + ?Sized> RadioBus for Box<T> {
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        (**self).transfer(read, write)
+    }
+}
+
+impl<T: DisplayBus + ?Sized> DisplayBus for Box<T> {
+    fn write_commands(&mut self, commands: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        (**self).write_commands(commands)
+    }
+
+    fn write_data(&mut self, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        (**self).write_data(data)
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        (**self).flush()
+    }
+}
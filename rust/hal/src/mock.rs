@@ -0,0 +1,108 @@
+//! In-memory hardware for unit tests. Each mock is a handle: clones share the state, so a test keeps one to drive or inspect the one handed to the driver.
+
+use std::sync::{Arc, Mutex};
+
+use crate::{AdcSource, DigitalIn, DisplayBus, InterruptCallback, PwmOut};
+
+/// Records every pulse width
+#[derive(Clone, Default)]
+pub struct MockPwm {
+    pulses: Arc<Mutex<Vec<u32>>>,
+}
+
+impl MockPwm {
+    pub fn pulses(&self) -> Vec<u32> {
+        self.pulses.lock().unwrap().clone()
+    }
+}
+
+impl PwmOut for MockPwm {
+    fn set_pulse_us(&mut self, pulse_width_us: u32) -> Result<(), Box<dyn std::error::Error>> {
+        self.pulses.lock().unwrap().push(pulse_width_us);
+        Ok(())
+    }
+}
+
+/// Pin whose level is set by the test, calling the interrupt callback like the GPIO driver would
+#[derive(Clone, Default)]
+pub struct MockPin {
+    level: Arc<Mutex<bool>>,
+    callback: Arc<Mutex<Option<InterruptCallback>>>,
+}
+
+impl MockPin {
+    pub fn set_level(&self, high: bool) {
+        *self.level.lock().unwrap() = high;
+        if let Some(callback) = self.callback.lock().unwrap().as_mut() {
+            callback(high);
+        }
+    }
+}
+
+impl DigitalIn for MockPin {
+    fn is_high(&self) -> bool {
+        *self.level.lock().unwrap()
+    }
+
+    fn set_interrupt(&mut self, callback: InterruptCallback) -> Result<(), Box<dyn std::error::Error>> {
+        *self.callback.lock().unwrap() = Some(callback);
+        Ok(())
+    }
+}
+
+/// MCP3008, or MCP3208 from its request, answering single ended conversions with the values set by the test
+#[derive(Clone, Default)]
+pub struct MockAdc {
+    values: Arc<Mutex<[u16; 8]>>,
+}
+
+impl MockAdc {
+    pub fn set_value(&self, channel: usize, value: u16) {
+        self.values.lock().unwrap()[channel] = value & 0xFFF;
+    }
+}
+
+impl AdcSource for MockAdc {
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        if write.len() != 3 || read.len() != 3 {
+            return Err("Unexpected ADC request".into());
+        }
+        let value = match write[0] {
+            0x01 if write[1] & 0x80 != 0 => self.values.lock().unwrap()[((write[1] >> 4) & 0x07) as usize] & 0x3FF,
+            0x06 | 0x07 => self.values.lock().unwrap()[(((write[0] & 0x01) << 2) | (write[1] >> 6)) as usize],
+            _ => return Err("Unexpected ADC request".into()),
+        };
+        read.copy_from_slice(&[0, (value >> 8) as u8, value as u8]);
+        Ok(())
+    }
+}
+
+/// Records the commands and data bytes written
+#[derive(Clone, Default)]
+pub struct MockDisplayBus {
+    commands: Arc<Mutex<Vec<u8>>>,
+    data: Arc<Mutex<Vec<u8>>>,
+}
+
+impl MockDisplayBus {
+    /// Bytes written since the last take
+    pub fn take_commands(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.commands.lock().unwrap())
+    }
+
+    pub fn take_data(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.data.lock().unwrap())
+    }
+}
+
+impl DisplayBus for MockDisplayBus {
+    fn write_commands(&mut self, commands: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        self.commands.lock().unwrap().extend_from_slice(commands);
+        Ok(())
+    }
+
+    fn write_data(&mut self, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        self.data.lock().unwrap().extend_from_slice(data);
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+use rppal::gpio::{InputPin, Level, OutputPin, Trigger};
+use rppal::spi::Spi;
+use std::time::Duration;
+
+use crate::{AdcSource, DigitalIn, DigitalOut, InterruptCallback, PwmOut, RadioBus};
+
+// Standard servo frame
+const SERVO_PERIOD_MS: u64 = 20;
+
+/// Software PWM of rppal
+impl PwmOut for OutputPin {
+    fn set_pulse_us(&mut self, pulse_width_us: u32) -> Result<(), Box<dyn std::error::Error>> {
+        self.set_pwm(Duration::from_millis(SERVO_PERIOD_MS), Duration::from_micros(pulse_width_us as u64))?;
+        Ok(())
+    }
+}
+
+impl DigitalIn for InputPin {
+    fn is_high(&self) -> bool {
+        InputPin::is_high(self)
+    }
+
+    fn set_interrupt(&mut self, mut callback: InterruptCallback) -> Result<(), Box<dyn std::error::Error>> {
+        self.set_async_interrupt(Trigger::Both, move |level| callback(level == Level::High))?;
+        Ok(())
+    }
+}
+
+impl DigitalOut for OutputPin {
+    fn set(&mut self, high: bool) {
+        if high {
+            self.set_high();
+        } else {
+            self.set_low();
+        }
+    }
+}
+
+impl AdcSource for Spi {
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        Spi::transfer(self, read, write)?;
+        Ok(())
+    }
+}
+
+impl RadioBus for Spi {
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        Spi::transfer(self, read, write)?;
+        Ok(())
+    }
+}
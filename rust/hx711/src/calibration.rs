@@ -0,0 +1,83 @@
+use serde::{Serialize, Deserialize};
+use std::fs;
+use std::io::{self, BufRead, Write};
+
+use crate::hx711::HX711;
+
+pub const CALIBRATION_FILE: &str = "hx711_calibration.json";
+// Readings averaged for the tare and for the known mass
+const CALIBRATION_READINGS: usize = 20;
+// Raw units per gram below which the known mass did not register
+const MIN_SCALE: f32 = 1.;
+
+/// Channel A zero and scale, as found by the wizard
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Calibration {
+    pub offset: i32,    // Raw value without load
+    pub scale: f32,     // Raw units per gram, negative with the cell mounted the other way
+}
+
+impl Calibration {
+    /// None when the known mass barely changed the reading
+    pub fn from_readings(offset: i32, loaded: i32, mass_g: f32) -> Option<Self> {
+        let scale = (loaded as f32 - offset as f32) / mass_g;
+        if mass_g.is_nan() || mass_g <= 0. || scale.abs() < MIN_SCALE {
+            return None;
+        }
+        Some(Calibration { offset, scale })
+    }
+
+    pub fn load(path: &str) -> io::Result<Self> {
+        let content = fs::read_to_string(path)?;
+        serde_json::from_str(&content).map_err(io::Error::other)
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        fs::write(path, json)
+    }
+
+    pub fn weight(&self, raw_value: i32) -> f32 {
+        (raw_value - self.offset) as f32 / self.scale
+    }
+}
+
+// On stderr like the other messages, stdout being kept for the readings
+fn prompt(text: &str) -> io::Result<String> {
+    eprint!("{}", text);
+    io::stderr().flush()?;
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    Ok(line.trim().to_string())
+}
+
+/// Tare, then scale from a known mass, prompting on the terminal
+pub fn run_wizard(hx711: &mut HX711) -> Result<Calibration, Box<dyn std::error::Error>> {
+    eprintln!("Calibration wizard");
+    prompt("Remove any load from the cell, then press Enter...")?;
+    let offset = hx711.get_value_average(CALIBRATION_READINGS)?;
+    eprintln!("Tare: {}", offset);
+
+    let mass_g: f32 = prompt("Place a known mass on the cell, then enter its weight in grams: ")?.parse()?;
+    let loaded = hx711.get_value_average(CALIBRATION_READINGS)?;
+    let calibration = Calibration::from_readings(offset, loaded, mass_g)
+        .ok_or("The known mass barely changed the reading, check the mass and the wiring")?;
+    eprintln!("Reading with {} g: {}, scale {:.2} per gram", mass_g, loaded, calibration.scale);
+    Ok(calibration)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calibration_from_readings() {
+        let calibration = Calibration::from_readings(8_661_777, 8_180_000, 500.).unwrap();
+        assert!((calibration.scale + 963.55).abs() < 0.01);
+        assert!((calibration.weight(8_180_000) - 500.).abs() < 0.01);
+        assert_eq!(calibration.weight(8_661_777), 0.);
+        // Mass not on the cell
+        assert_eq!(Calibration::from_readings(8_661_777, 8_661_900, 500.), None);
+        assert_eq!(Calibration::from_readings(8_661_777, 8_180_000, 0.), None);
+    }
+}
@@ -3,17 +3,50 @@
 // use rust_pigpio::pigpio;
 
 use rust_pigpio::{initialize, set_mode, read, write, terminate, INPUT, OUTPUT, ON, OFF};
+use tracing::debug;
 
 // use rust_pigpio::constants::*;
 // use rust_pigpio::pigpio;
 // use rust_pigpio::{INPUT, OUTPUT, ON, OFF};
 // use rust_pigpio::pwm::*;
 // use rust_pigpio::pigpio::constants::GpioMode;
+use std::fmt;
 use std::thread;
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
+
+// Longest wait for a conversion, which comes at 10 or 80 per second
+const READY_TIMEOUT: Duration = Duration::from_secs(1);
+// Readings further than this many standard deviations from the median are dropped from averages
+const AVERAGE_SIGMAS: f32 = 3.;
+// Conversions timed to find the actual sample rate
+const RATE_CHECK_CONVERSIONS: u32 = 8;
+
+/// Output data rate, selected by the RATE pin unless the board ties it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rate {
+    /// RATE low, the default of most boards
+    Sps10,
+    /// RATE high
+    Sps80,
+}
+
+impl Rate {
+    pub fn per_second(&self) -> f32 {
+        match self {
+            Rate::Sps10 => 10.,
+            Rate::Sps80 => 80.,
+        }
+    }
+
+    /// Nearest rate to a measured one
+    pub fn closest(per_second: f32) -> Rate {
+        // Geometric middle, the conversion period being what varies with the oscillator
+        if per_second < (Rate::Sps10.per_second() * Rate::Sps80.per_second()).sqrt() { Rate::Sps10 } else { Rate::Sps80 }
+    }
+}
 
 /// HX711 gain settings which also select the channel
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Gain {
     /// Channel A with gain of 128 (default)
     ChAGain128 = 1,
@@ -23,10 +56,34 @@ pub enum Gain {
     ChAGain64 = 3,
 }
 
+/// Failure of an HX711 read
+#[derive(Clone, Debug, PartialEq)]
+pub enum Hx711Error {
+    /// No conversion within READY_TIMEOUT, the HX711 being unpowered or disconnected
+    Timeout,
+    /// No conversion read, none having been asked for
+    NotReady,
+    /// Pin access failure, with the message of the GPIO library
+    GpioError(String),
+}
+
+impl fmt::Display for Hx711Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Hx711Error::Timeout => write!(f, "HX711 timeout, no conversion within {:?}", READY_TIMEOUT),
+            Hx711Error::NotReady => write!(f, "HX711 not ready, no conversion read"),
+            Hx711Error::GpioError(e) => write!(f, "HX711 GPIO error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Hx711Error {}
+
 pub struct HX711 {
     pd_sck_pin: u32,
     dout_pin: u32,
     gain: Gain,
+    gain_a: Gain,       // Gain restored when going back to channel A
     offset_a: i32,
     offset_b: i32,
     reference_unit_a: f32,
@@ -56,6 +113,7 @@ impl HX711 {
             pd_sck_pin,
             dout_pin,
             gain,
+            gain_a: if gain == Gain::ChBGain32 { Gain::ChAGain128 } else { gain },
             offset_a: 1,
             offset_b: 1,
             reference_unit_a: 1.0,
@@ -66,21 +124,39 @@ impl HX711 {
         thread::sleep(Duration::from_micros(100));
         
         // Reset to ensure proper state
-        hx711.reset();
+        hx711.reset()?;
         
-        hx711.init();
+        hx711.init()?;
         
         Ok(hx711)
     }
     
-    pub fn init(&self) {
+    /// Drive the RATE pin, wired to the given GPIO
+    pub fn set_rate(&mut self, rate_pin: u32, rate: Rate) -> Result<(), Hx711Error> {
+        set_mode(rate_pin, OUTPUT).map_err(Hx711Error::GpioError)?;
+        write(rate_pin, if rate == Rate::Sps80 { ON } else { OFF }).map_err(Hx711Error::GpioError)
+    }
+    
+    /// Conversions per second actually achieved, whatever the RATE pin is tied to
+    pub fn measure_sample_rate(&mut self) -> Result<f32, Hx711Error> {
+        // Starts on a conversion, a pending one being read first
+        self.read_raw_bytes()?;
+        let start = Instant::now();
+        for _ in 0..RATE_CHECK_CONVERSIONS {
+            self.read_raw_bytes()?;
+        }
+        Ok(RATE_CHECK_CONVERSIONS as f32 / start.elapsed().as_secs_f32())
+    }
+    
+    pub fn init(&self) -> Result<(), Hx711Error> {
         for n in 0..10 {
 //        loop {
-            write(self.pd_sck_pin, ON).unwrap();
+            self.set_clock(true)?;
             self.do_sleep();
-            write(self.pd_sck_pin, OFF).unwrap();
+            self.set_clock(false)?;
             self.do_sleep();
         }
+        Ok(())
     }
     
     pub fn do_sleep(&self) {
@@ -99,21 +175,24 @@ impl HX711 {
     }
 
     /// Check if the HX711 is ready to send data
-    pub fn is_ready(&self) -> bool {
+    pub fn is_ready(&self) -> Result<bool, Hx711Error> {
         // self.dout.is_low()
-        let value = read(self.dout_pin).unwrap();
-        return value == 0;
+        let value = read(self.dout_pin).map_err(Hx711Error::GpioError)?;
+        Ok(value == 0)
+    }
+    
+    fn set_clock(&self, high: bool) -> Result<(), Hx711Error> {
+        write(self.pd_sck_pin, if high { ON } else { OFF }).map_err(Hx711Error::GpioError)
     }
     
     /// Read raw 24-bit value from the HX711
-    fn read_raw_bytes(&mut self) -> i32 {
+    fn read_raw_bytes(&mut self) -> Result<i32, Hx711Error> {
         // Wait until HX711 is ready (with a simple timeout)
         
-        let mut timeout = 0;
-        while !self.is_ready() {
-            timeout += 1;
-            if timeout > 1000000 {
-                return -1; // Timeout after ~1 second
+        let start = std::time::Instant::now();
+        while !self.is_ready()? {
+            if start.elapsed() > READY_TIMEOUT {
+                return Err(Hx711Error::Timeout);
             }
             thread::sleep(Duration::from_micros(1));
         }
@@ -121,15 +200,15 @@ impl HX711 {
         
         // Read three bytes of data
         for i in 0..24 {
-            write(self.pd_sck_pin, ON).unwrap();
+            self.set_clock(true)?;
             self.do_sleep();
 
-            write(self.pd_sck_pin, OFF).unwrap();
+            self.set_clock(false)?;
             self.do_sleep();            
             
             // Read bit based on bit format
             // let bit_value = if self.dout.is_high() { 1 } else { 0 };
-            let bit_value = read(self.dout_pin).unwrap() as u8;
+            let bit_value = read(self.dout_pin).map_err(Hx711Error::GpioError)? as u8;
             
             count <<= 1;
             if ( bit_value == 1 )
@@ -141,12 +220,10 @@ impl HX711 {
         
         // Set gain for next reading by sending additional clock pulses
         for _ in 0..(self.gain as u8) {
-            // self.pd_sck.set_high();
-            write(self.pd_sck_pin, ON).unwrap();
+            self.set_clock(true)?;
             self.do_sleep();
             
-            //self.pd_sck.set_low();
-            write(self.pd_sck_pin, OFF).unwrap();
+            self.set_clock(false)?;
             self.do_sleep();
         }
         
@@ -157,59 +234,67 @@ impl HX711 {
         //}
         count = count ^ 0x800000;
         
-        count
+        Ok(count)
     }
     
     /// Get a single reading
-    pub fn get_value(&mut self) -> Option<i32> {
-        let value = self.read_raw_bytes();
-        if value == -1 { None } else { Some(value) }
+    pub fn get_value(&mut self) -> Result<i32, Hx711Error> {
+        self.read_raw_bytes()
     }
     
-    /// Get the average of multiple readings
-    pub fn get_value_average(&mut self, times: usize) -> Option<i32> {
+    // The successful readings, the last error when none succeeded
+    fn get_values(&mut self, times: usize) -> Result<Vec<i32>, Hx711Error> {
         let mut values = Vec::new();
-        
+        let mut error = Hx711Error::NotReady;
         for _ in 0..times {
-            if let Some(value) = self.get_value() {
-                values.push(value as i64);
+            match self.get_value() {
+                Ok(value) => values.push(value),
+                Err(e) => error = e,
             }
         }
-        
-        if values.is_empty() {
-            return None;
-        }
-        
-        let sum: i64 = values.iter().sum();
-        Some((sum / values.len() as i64) as i32)
+        if values.is_empty() { Err(error) } else { Ok(values) }
+    }
+    
+    /// Get the average of multiple readings, corrupted ones rejected
+    pub fn get_value_average(&mut self, times: usize) -> Result<i32, Hx711Error> {
+        let values = self.get_values(times)?;
+        pizboat_filter::sigma_clipped_mean(&values, AVERAGE_SIGMAS).ok_or(Hx711Error::NotReady)
+    }
+    
+    /// Get the median of multiple readings
+    pub fn get_value_median(&mut self, times: usize) -> Result<i32, Hx711Error> {
+        let values = self.get_values(times)?;
+        pizboat_filter::median(&values).ok_or(Hx711Error::NotReady)
     }
     
     /// Get weight in configured units for Channel A
-    pub fn get_weight(&mut self, times: usize) -> Option<f32> {
+    pub fn get_weight(&mut self, times: usize) -> Result<f32, Hx711Error> {
+        self.set_gain(self.gain_a)?;
         let value = self.get_value_average(times)?;
-        Some((value - self.offset_a) as f32 / self.reference_unit_a)
+        Ok((value - self.offset_a) as f32 / self.reference_unit_a)
     }
     
     /// Get weight in configured units for Channel B
-    pub fn get_weight_b(&mut self, times: usize) -> Option<f32> {
-        self.set_gain(Gain::ChBGain32);
+    pub fn get_weight_b(&mut self, times: usize) -> Result<f32, Hx711Error> {
+        self.set_gain(Gain::ChBGain32)?;
         let value = self.get_value_average(times)?;
-        Some((value - self.offset_b) as f32 / self.reference_unit_b)
+        Ok((value - self.offset_b) as f32 / self.reference_unit_b)
     }
     
     /// Tare the scale (set current reading as zero point) for Channel A
-    pub fn tare(&mut self, times: usize) {
-        if let Some(value) = self.get_value_average(times) {
-            self.set_offset_a(value);
-        }
+    pub fn tare(&mut self, times: usize) -> Result<(), Hx711Error> {
+        self.set_gain(self.gain_a)?;
+        let value = self.get_value_average(times)?;
+        self.set_offset_a(value);
+        Ok(())
     }
     
     /// Tare the scale for Channel B
-    pub fn tare_b(&mut self, times: usize) {
-        self.set_gain(Gain::ChBGain32);
-        if let Some(value) = self.get_value_average(times) {
-            self.set_offset_b(value);
-        }
+    pub fn tare_b(&mut self, times: usize) -> Result<(), Hx711Error> {
+        self.set_gain(Gain::ChBGain32)?;
+        let value = self.get_value_average(times)?;
+        self.set_offset_b(value);
+        Ok(())
     }
     
     /// Set the reference unit (scale factor) for Channel A
@@ -242,12 +327,19 @@ impl HX711 {
         self.offset_b
     }
     
-    /// Set the gain (which also selects the channel)
-    pub fn set_gain(&mut self, gain: Gain) {
+    /// Set the gain (which also selects the channel), so that channels A and B can be read alternately
+    pub fn set_gain(&mut self, gain: Gain) -> Result<(), Hx711Error> {
+        if gain == self.gain {
+            return Ok(());
+        }
         self.gain = gain;
+        if gain != Gain::ChBGain32 {
+            self.gain_a = gain;
+        }
         
-        // Read a value to apply the new gain setting
-        self.read_raw_bytes();
+        // Read a value to apply the new gain setting, the gain being clocked in after a read
+        self.read_raw_bytes()?;
+        Ok(())
     }
     
     /// Get the current gain setting
@@ -256,23 +348,24 @@ impl HX711 {
     }
     
     /// Power down the HX711
-    pub fn power_down(&mut self) {
-        println!("power_down()");
+    pub fn power_down(&mut self) -> Result<(), Hx711Error> {
+        debug!("power_down()");
         // self.pd_sck.set_low();
-        write(self.pd_sck_pin, OFF).unwrap();
+        self.set_clock(false)?;
         thread::sleep(Duration::from_micros(100));
         // self.pd_sck.set_high();
-        write(self.pd_sck_pin, ON).unwrap();
+        self.set_clock(true)?;
         
         // Wait 100 microseconds (HX711 powers down after 60us)
         thread::sleep(Duration::from_micros(100));
+        Ok(())
     }
     
     /// Power up the HX711
-    pub fn power_up(&mut self) {
-        println!("power_up()");
+    pub fn power_up(&mut self) -> Result<(), Hx711Error> {
+        debug!("power_up()");
         // self.pd_sck.set_low();
-        write(self.pd_sck_pin, OFF).unwrap();
+        self.set_clock(false)?;
         
         // Wait 100 microseconds for HX711 to power back up
         thread::sleep(Duration::from_micros(100));
@@ -282,12 +375,13 @@ impl HX711 {
         // if !matches!(self.gain, Gain::ChAGain128) {
         //            self.read_raw_bytes();
         //}
+        Ok(())
     }
     
     /// Reset the HX711 (power cycle)
-    pub fn reset(&mut self) {
-        self.power_down();
-        self.power_up();
+    pub fn reset(&mut self) -> Result<(), Hx711Error> {
+        self.power_down()?;
+        self.power_up()
     }
 }
 
@@ -301,15 +395,12 @@ mod tests {
     fn test_basic_reading() {
         let mut hx711 = HX711::new(5, 6, Gain::ChAGain128).unwrap();
         
-        // Set reading format if needed
-        hx711.set_reading_format(ByteFormat::MSB, BitFormat::MSB);
-        
         // Set calibration values
         hx711.set_offset_a(8388608);
         hx711.set_reference_unit_a(432.0);
         
         // Read weight
-        if let Some(weight) = hx711.get_weight(5) {
+        if let Ok(weight) = hx711.get_weight(5) {
             println!("Weight: {:.2} g", weight);
         }
     }
@@ -1,15 +1,35 @@
 use std::thread;
 use std::time::Duration;
+use tracing::{error, info, warn};
 
 mod hx711; // Assuming the driver is in hx711.rs
-use hx711::{HX711, Gain};
+mod calibration;
+mod output;
+use hx711::{HX711, Gain, Rate};
+use calibration::{Calibration, CALIBRATION_FILE};
+use output::{Reading, ReadingServer};
 
-// Your calibration constants
-const OFFSET: i32 = 8661777;  // Zero offset value
-const SCALE: f32 = -960.33;     // Scale factor (raw units per gram)
+// Readings per printed value, the corrupted ones rejected
+const READINGS: usize = 5;
+const READ_PAUSE: Duration = Duration::from_millis(200);
+// GPIO driving the HX711 RATE pin, when given a rate
+const RATE_PIN: u32 = 13;
 
+// Readings go to stdout, everything else to stderr so that they can be piped
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    println!("Initializing HX711...");
+    // On stderr, the readings being alone on stdout
+    let _log_guard = pizboat_logging::init_from_env("hx711");
+    // JSON lines instead of text, and/or sent to WebSocket clients
+    let json = std::env::args().any(|arg| arg == "--json");
+    let server = if std::env::args().any(|arg| arg == "--serve") { Some(ReadingServer::start()?) } else { None };
+    let rate = match std::env::args().skip_while(|arg| arg != "--rate").nth(1).as_deref() {
+        None => None,
+        Some("10") => Some(Rate::Sps10),
+        Some("80") => Some(Rate::Sps80),
+        Some(other) => return Err(format!("Unsupported rate {}, 10 or 80", other).into()),
+    };
+    
+    info!("Initializing HX711...");
     
     // Initialize HX711
     // DOUT = GPIO 5, PD_SCK = GPIO 6
@@ -17,26 +37,62 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // hx711.doloop();
     
+    if let Some(rate) = rate {
+        hx711.set_rate(RATE_PIN, rate)?;
+    }
+    // Boards often tie RATE instead of wiring it
+    let measured = hx711.measure_sample_rate()?;
+    let actual = Rate::closest(measured);
+    info!("Sample rate: {:.1} per second ({:?})", measured, actual);
+    if rate.is_some_and(|rate| rate != actual) {
+        warn!("RATE not following GPIO {}, check the wiring", RATE_PIN);
+    }
+    // At 80 per second every conversion is output, for dynamic load tests
+    let (readings, pause) = if actual == Rate::Sps80 { (1, Duration::ZERO) } else { (READINGS, READ_PAUSE) };
+    
+    // Calibration of a previous run, unless asked to redo it
+    let recalibrate = std::env::args().any(|arg| arg == "--calibrate");
+    let calibration = match Calibration::load(CALIBRATION_FILE) {
+        Ok(calibration) if !recalibrate => calibration,
+        result => {
+            if let Err(e) = result {
+                warn!("No calibration in {} ({})", CALIBRATION_FILE, e);
+            }
+            let calibration = calibration::run_wizard(&mut hx711)?;
+            calibration.save(CALIBRATION_FILE)?;
+            info!("Calibration saved to {}", CALIBRATION_FILE);
+            calibration
+        }
+    };
+    
     // Set calibration values
-    hx711.set_offset_a(OFFSET);
-    hx711.set_reference_unit_a(SCALE);
+    hx711.set_offset_a(calibration.offset);
+    hx711.set_reference_unit_a(calibration.scale);
     
-    println!("HX711 ready!");
-    println!("Starting continuous reading...\n");
+    info!("HX711 ready!");
+    info!("Starting continuous reading...");
     
     // Continuous reading loop
     loop {
-        match hx711.get_value() {
-            Some(raw_value) => {
+        match hx711.get_value_average(readings) {
+            Ok(raw_value) => {
                 // Calculate weight using calibration
-                let weight = (raw_value - OFFSET) as f32 / SCALE;
-                println!("Raw: {:8} | Weight: {:8.2} g", raw_value, weight);
+                let weight = calibration.weight(raw_value);
+                let reading = Reading::new(raw_value, weight);
+                if json {
+                    println!("{}", reading.to_json());
+                } else {
+                    println!("Raw: {:8} | Weight: {:8.2} g", raw_value, weight);
+                }
+                if let Some(server) = &server {
+                    server.send(&reading);
+                }
             }
-            None => {
-                println!("Error: Failed to read from sensor");
+            Err(e) => {
+                error!("Failed to read from sensor: {}", e);
             }
         }
         
-        thread::sleep(Duration::from_millis(200));
+        thread::sleep(pause);
     }
 }
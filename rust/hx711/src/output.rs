@@ -0,0 +1,85 @@
+use serde::Serialize;
+use std::net::TcpListener;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tungstenite::{accept, Message};
+use tracing::{error, info};
+
+pub const WEBSOCKET_PORT: u16 = 10014;
+
+/// One printed or served value, as a JSON object
+#[derive(Debug, Serialize)]
+pub struct Reading {
+    #[serde(rename = "type")]
+    msg_type: &'static str,
+    timestamp: u64,     // Milliseconds since the epoch
+    raw: i32,
+    weight: f32,        // Grams
+}
+
+impl Reading {
+    pub fn new(raw: i32, weight: f32) -> Self {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+        Reading { msg_type: "load", timestamp, raw, weight }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+/// WebSocket server sending each reading to all the connected clients
+pub struct ReadingServer {
+    clients: Arc<Mutex<Vec<Sender<String>>>>,
+}
+
+impl ReadingServer {
+    pub fn start() -> std::io::Result<Self> {
+        let server = TcpListener::bind(("0.0.0.0", WEBSOCKET_PORT))?;
+        info!("WebSocket server listening on port {}", WEBSOCKET_PORT);
+        let clients = Arc::new(Mutex::new(Vec::new()));
+        let clients_clone = Arc::clone(&clients);
+        thread::spawn(move || accept_thread(server, clients_clone));
+        Ok(ReadingServer { clients })
+    }
+
+    pub fn send(&self, reading: &Reading) {
+        let json = reading.to_json();
+        // Clients gone have dropped their receiver
+        self.clients.lock().unwrap().retain(|client| client.send(json.clone()).is_ok());
+    }
+}
+
+fn accept_thread(server: TcpListener, clients: Arc<Mutex<Vec<Sender<String>>>>) {
+    for stream in server.incoming() {
+        let stream = match stream {
+            Ok(s) => s,
+            Err(e) => {
+                error!("Connection error: {}", e);
+                continue;
+            }
+        };
+        let (sender, receiver) = mpsc::channel();
+        clients.lock().unwrap().push(sender);
+        thread::spawn(move || client_thread(stream, receiver));
+    }
+}
+
+fn client_thread(stream: std::net::TcpStream, readings: Receiver<String>) {
+    let mut websocket = match accept(stream) {
+        Ok(ws) => ws,
+        Err(e) => {
+            error!("WebSocket handshake error: {}", e);
+            return;
+        }
+    };
+    info!("New WebSocket client connected");
+    for json in readings {
+        if websocket.send(Message::Text(json)).is_err() {
+            info!("WebSocket client disconnected");
+            return;
+        }
+    }
+}
@@ -0,0 +1,80 @@
+/// nRF24 payload size, fixed
+pub const PACKET_SIZE: usize = 32;
+// Header byte: index of the chunk in the message, and the last chunk flag
+const LAST: u8 = 0x80;
+const INDEX_MASK: u8 = 0x7F;
+// Length byte after it
+const DATA_SIZE: usize = PACKET_SIZE - 2;
+
+/// Cut a message into packets of 30 bytes of data, up to 128 of them
+pub fn split(text: &str) -> Result<Vec<[u8; PACKET_SIZE]>, Box<dyn std::error::Error>> {
+    let chunks: Vec<&[u8]> = text.as_bytes().chunks(DATA_SIZE).collect();
+    if chunks.len() > INDEX_MASK as usize + 1 {
+        return Err(format!("message of {} bytes too long for the radio", text.len()).into());
+    }
+    let count = chunks.len().max(1);
+    Ok((0..count).map(|index| {
+        let data = chunks.get(index).copied().unwrap_or_default();
+        let mut packet = [0u8; PACKET_SIZE];
+        packet[0] = index as u8 | if index == count - 1 { LAST } else { 0 };
+        packet[1] = data.len() as u8;
+        packet[2..2 + data.len()].copy_from_slice(data);
+        packet
+    }).collect())
+}
+
+/// Rebuilds the messages from their packets, dropping those missing one
+#[derive(Default)]
+pub struct Assembler {
+    data: Vec<u8>,
+    next_index: u8,
+}
+
+impl Assembler {
+    /// The message once its last packet came
+    pub fn push(&mut self, packet: &[u8; PACKET_SIZE]) -> Option<String> {
+        let index = packet[0] & INDEX_MASK;
+        if index == 0 {
+            self.data.clear();
+        } else if index != self.next_index {
+            // Lost packet: wait for the start of the next message
+            self.data.clear();
+            self.next_index = 0;
+            return None;
+        }
+        let length = (packet[1] as usize).min(DATA_SIZE);
+        self.data.extend_from_slice(&packet[2..2 + length]);
+        self.next_index = index + 1;
+        if packet[0] & LAST == 0 {
+            return None;
+        }
+        self.next_index = 0;
+        String::from_utf8(std::mem::take(&mut self.data)).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_and_assemble() {
+        let text = "{\"type\":\"command\",\"rudder_star\":1500,\"motor\":1600}";
+        let packets = split(text).unwrap();
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0][..2], [0, 30]);
+        assert_eq!(packets[1][0], 0x81);
+
+        let mut assembler = Assembler::default();
+        assert_eq!(assembler.push(&packets[0]), None);
+        assert_eq!(assembler.push(&packets[1]).as_deref(), Some(text));
+
+        // The first packet lost, the message is dropped and the next one goes through
+        assert_eq!(assembler.push(&packets[1]), None);
+        let messages: Vec<Option<String>> = packets.iter().map(|packet| assembler.push(packet)).collect();
+        assert_eq!(messages, [None, Some(text.to_string())]);
+        assert_eq!(split("").unwrap().len(), 1);
+        assert_eq!(assembler.push(&split("").unwrap()[0]).as_deref(), Some(""));
+        assert!(split(&"x".repeat(30 * 129)).is_err());
+    }
+}
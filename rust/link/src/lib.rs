@@ -0,0 +1,27 @@
+//! Transports carrying the JSON messages between the boat and the remote: WebSocket over Wi-Fi, or nRF24L01 radios for range,
+//! and the statistics of the local Wi-Fi link both report.
+
+mod chunks;
+mod nrf24;
+mod websocket;
+pub mod wireless;
+
+pub use chunks::{Assembler, split};
+pub use nrf24::{Nrf24, Nrf24Transport};
+
+/// Message link between the boat and the remote, whatever carries it
+pub trait Transport {
+    fn send(&mut self, text: &str) -> Result<(), Box<dyn std::error::Error>>;
+    /// The next message, None once the read timeout of the transport elapsed without any
+    fn receive(&mut self) -> Result<Option<String>, Box<dyn std::error::Error>>;
+}
+
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn send(&mut self, text: &str) -> Result<(), Box<dyn std::error::Error>> {
+        (**self).send(text)
+    }
+
+    fn receive(&mut self) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        (**self).receive()
+    }
+}
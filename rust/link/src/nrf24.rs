@@ -0,0 +1,198 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use pizboat_hal::{DigitalOut, RadioBus};
+
+use crate::Transport;
+use crate::chunks::{self, Assembler, PACKET_SIZE};
+
+const R_REGISTER: u8 = 0x00;
+const W_REGISTER: u8 = 0x20;
+const R_RX_PAYLOAD: u8 = 0x61;
+const W_TX_PAYLOAD: u8 = 0xA0;
+const FLUSH_TX: u8 = 0xE1;
+const FLUSH_RX: u8 = 0xE2;
+
+const REG_CONFIG: u8 = 0x00;
+const REG_EN_AA: u8 = 0x01;
+const REG_EN_RXADDR: u8 = 0x02;
+const REG_SETUP_AW: u8 = 0x03;
+const REG_SETUP_RETR: u8 = 0x04;
+const REG_RF_CH: u8 = 0x05;
+const REG_RF_SETUP: u8 = 0x06;
+const REG_STATUS: u8 = 0x07;
+const REG_RX_ADDR_P0: u8 = 0x0A;
+const REG_TX_ADDR: u8 = 0x10;
+const REG_RX_PW_P0: u8 = 0x11;
+const REG_FIFO_STATUS: u8 = 0x17;
+
+// 2 bytes CRC, powered up, PRIM_RX added to listen
+const CONFIG_TX: u8 = 0x0E;
+const CONFIG_RX: u8 = 0x0F;
+const PIPE_0: u8 = 0x01;
+const ADDRESS_WIDTH_5: u8 = 0x03;
+// Up to 15 retransmissions, 1.5 ms apart: an ack takes longer at 250 kbps
+const RETRANSMIT: u8 = 0x5F;
+// 250 kbps at 0 dBm, for the best range
+const RF_SETUP: u8 = 0x26;
+
+const STATUS_RX_DR: u8 = 0x40;
+const STATUS_TX_DS: u8 = 0x20;
+const STATUS_MAX_RT: u8 = 0x10;
+const FIFO_RX_EMPTY: u8 = 0x01;
+
+// Both ends send to and listen on the same address, the acks coming back on pipe 0
+const ADDRESS: [u8; 5] = *b"PZB01";
+// Settling time from standby to TX or RX
+const SETTLE: Duration = Duration::from_micros(130);
+const TX_TIMEOUT: Duration = Duration::from_millis(100);
+const POLL: Duration = Duration::from_millis(1);
+
+/// nRF24L01(+) radio in Enhanced ShockBurst, 32 byte payloads acked by the other end
+pub struct Nrf24<B: RadioBus, P: DigitalOut> {
+    bus: B,
+    ce: P,
+}
+
+impl<B: RadioBus, P: DigitalOut> Nrf24<B, P> {
+    /// Set up on a channel, 0 to 125 for 2400 to 2525 MHz, and left listening
+    pub fn new(bus: B, ce: P, channel: u8) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut radio = Nrf24 { bus, ce };
+        radio.ce.set(false);
+        radio.write_register(REG_SETUP_AW, &[ADDRESS_WIDTH_5])?;
+        // No identification register, the address width must read back
+        if radio.read_register(REG_SETUP_AW)? != ADDRESS_WIDTH_5 {
+            return Err("no nRF24L01 found".into());
+        }
+        radio.write_register(REG_EN_AA, &[PIPE_0])?;
+        radio.write_register(REG_EN_RXADDR, &[PIPE_0])?;
+        radio.write_register(REG_SETUP_RETR, &[RETRANSMIT])?;
+        radio.write_register(REG_RF_CH, &[channel.min(125)])?;
+        radio.write_register(REG_RF_SETUP, &[RF_SETUP])?;
+        radio.write_register(REG_RX_ADDR_P0, &ADDRESS)?;
+        radio.write_register(REG_TX_ADDR, &ADDRESS)?;
+        radio.write_register(REG_RX_PW_P0, &[PACKET_SIZE as u8])?;
+        radio.command(FLUSH_TX, &mut [])?;
+        radio.command(FLUSH_RX, &mut [])?;
+        radio.write_register(REG_STATUS, &[STATUS_RX_DR | STATUS_TX_DS | STATUS_MAX_RT])?;
+        radio.listen()?;
+        Ok(radio)
+    }
+
+    /// Send one packet and wait for its ack, listening again after
+    pub fn send(&mut self, packet: &[u8; PACKET_SIZE]) -> Result<(), Box<dyn std::error::Error>> {
+        self.ce.set(false);
+        self.write_register(REG_CONFIG, &[CONFIG_TX])?;
+        let mut payload = *packet;
+        self.command(W_TX_PAYLOAD, &mut payload)?;
+        // A pulse of CE sends the payload
+        self.ce.set(true);
+        thread::sleep(SETTLE);
+        self.ce.set(false);
+
+        let start = Instant::now();
+        let result = loop {
+            let status = self.status()?;
+            if status & STATUS_TX_DS != 0 {
+                break Ok(());
+            }
+            if status & STATUS_MAX_RT != 0 {
+                break Err("no ack from the other radio".into());
+            }
+            if start.elapsed() > TX_TIMEOUT {
+                break Err("nRF24 transmission timed out".into());
+            }
+            thread::sleep(POLL);
+        };
+        if result.is_err() {
+            self.command(FLUSH_TX, &mut [])?;
+        }
+        self.write_register(REG_STATUS, &[STATUS_TX_DS | STATUS_MAX_RT])?;
+        self.listen()?;
+        result
+    }
+
+    /// The oldest packet received, if any
+    pub fn receive(&mut self) -> Result<Option<[u8; PACKET_SIZE]>, Box<dyn std::error::Error>> {
+        if self.read_register(REG_FIFO_STATUS)? & FIFO_RX_EMPTY != 0 {
+            return Ok(None);
+        }
+        let mut packet = [0u8; PACKET_SIZE];
+        self.command(R_RX_PAYLOAD, &mut packet)?;
+        self.write_register(REG_STATUS, &[STATUS_RX_DR])?;
+        Ok(Some(packet))
+    }
+
+    fn listen(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.write_register(REG_CONFIG, &[CONFIG_RX])?;
+        self.ce.set(true);
+        thread::sleep(SETTLE);
+        Ok(())
+    }
+
+    fn status(&mut self) -> Result<u8, Box<dyn std::error::Error>> {
+        // The status register comes out with every command
+        let mut read = [0u8; 1];
+        self.bus.transfer(&mut read, &[R_REGISTER | REG_STATUS])?;
+        Ok(read[0])
+    }
+
+    // Command byte followed by data, replaced by the bytes read
+    fn command(&mut self, command: u8, data: &mut [u8]) -> Result<(), Box<dyn std::error::Error>> {
+        let mut write = vec![command];
+        write.extend_from_slice(data);
+        let mut read = vec![0u8; write.len()];
+        self.bus.transfer(&mut read, &write)?;
+        data.copy_from_slice(&read[1..]);
+        Ok(())
+    }
+
+    fn read_register(&mut self, register: u8) -> Result<u8, Box<dyn std::error::Error>> {
+        let mut data = [0xFF];
+        self.command(R_REGISTER | register, &mut data)?;
+        Ok(data[0])
+    }
+
+    fn write_register(&mut self, register: u8, value: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        let mut data = value.to_vec();
+        self.command(W_REGISTER | register, &mut data)
+    }
+}
+
+/// The JSON messages over an nRF24 radio, cut into packets
+pub struct Nrf24Transport<B: RadioBus, P: DigitalOut> {
+    radio: Nrf24<B, P>,
+    assembler: Assembler,
+    timeout: Duration,
+}
+
+impl<B: RadioBus, P: DigitalOut> Nrf24Transport<B, P> {
+    /// `timeout` plays the part of the read timeout of a socket
+    pub fn new(radio: Nrf24<B, P>, timeout: Duration) -> Self {
+        Nrf24Transport { radio, assembler: Assembler::default(), timeout }
+    }
+}
+
+impl<B: RadioBus, P: DigitalOut> Transport for Nrf24Transport<B, P> {
+    fn send(&mut self, text: &str) -> Result<(), Box<dyn std::error::Error>> {
+        for packet in chunks::split(text)? {
+            self.radio.send(&packet)?;
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        let start = Instant::now();
+        while start.elapsed() < self.timeout {
+            match self.radio.receive()? {
+                Some(packet) => {
+                    if let Some(text) = self.assembler.push(&packet) {
+                        return Ok(Some(text));
+                    }
+                }
+                None => thread::sleep(POLL),
+            }
+        }
+        Ok(None)
+    }
+}
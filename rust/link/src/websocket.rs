@@ -0,0 +1,26 @@
+use std::io::{self, Read, Write};
+
+use tungstenite::{Message, WebSocket};
+
+use crate::Transport;
+
+/// Text messages of a WebSocket, the read timeout being the one of its stream
+impl<S: Read + Write> Transport for WebSocket<S> {
+    fn send(&mut self, text: &str) -> Result<(), Box<dyn std::error::Error>> {
+        WebSocket::send(self, Message::Text(text.to_string()))?;
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        loop {
+            match self.read() {
+                Ok(Message::Text(text)) => return Ok(Some(text)),
+                // Answered by tungstenite itself
+                Ok(Message::Ping(_) | Message::Pong(_)) => continue,
+                Ok(message) => return Err(format!("unsupported message: {:?}", message).into()),
+                Err(tungstenite::Error::Io(e)) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => return Ok(None),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
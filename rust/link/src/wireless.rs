@@ -0,0 +1,38 @@
+use serde::{Serialize, Deserialize};
+use std::fs;
+
+const WIRELESS_STATS: &str = "/proc/net/wireless";
+
+/// Link statistics of the local Wi-Fi interface
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WirelessStats {
+    pub quality: i16,       // Link quality, out of 70 on most drivers
+    pub signal_dbm: i16,    // Signal level (RSSI)
+}
+
+/// None without a wireless interface, or when the driver does not report
+pub fn read() -> Option<WirelessStats> {
+    parse(&fs::read_to_string(WIRELESS_STATS).ok()?)
+}
+
+// First interface line, after the two header lines:
+// wlan0: 0000   70.  -39.  -256        0      0      0      0      0        0
+fn parse(content: &str) -> Option<WirelessStats> {
+    let fields: Vec<&str> = content.lines().nth(2)?.split_whitespace().collect();
+    let value = |i: usize| fields.get(i)?.trim_end_matches('.').parse::<i16>().ok();
+    Some(WirelessStats { quality: value(2)?, signal_dbm: value(3)? })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_wireless_stats() {
+        let content = "Inter-| sta-|   Quality        |   Discarded packets               | Missed | WE\n \
+                       face | tus | link level noise |  nwid  crypt   frag  retry   misc | beacon | 22\n \
+                       wlan0: 0000   58.  -52.  -256        0      0      0      0      0        0\n";
+        assert_eq!(parse(content), Some(WirelessStats { quality: 58, signal_dbm: -52 }));
+        assert_eq!(parse(&content.lines().take(2).collect::<Vec<_>>().join("\n")), None);
+    }
+}
@@ -0,0 +1,73 @@
+//! Tracing set-up shared by the boat, the remote and the tools: leveled logs on stderr, and optionally in daily files.
+
+use std::path::Path;
+
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::{EnvFilter, fmt, prelude::*};
+
+// Filter used when neither RUST_LOG nor the configuration sets a valid one
+const DEFAULT_FILTER: &str = "info";
+// Environment variables of the programs without a configuration file
+const DIR_ENV: &str = "PIZBOAT_LOG_DIR";
+const MAX_FILES_ENV: &str = "PIZBOAT_LOG_MAX_FILES";
+const DEFAULT_MAX_FILES: usize = 7;
+
+/// Installs the global subscriber.
+/// `filter` takes the RUST_LOG syntax, such as `info,PizRemote::websocket=debug`, RUST_LOG itself overriding it.
+/// With a `dir`, the logs are also written to `<name>.<date>.log` files there, one per day, keeping the last `max_files`.
+/// The returned guard flushes the file when dropped, so it has to be kept until the end of main.
+pub fn init(name: &str, filter: &str, dir: Option<&Path>, max_files: usize) -> Option<WorkerGuard> {
+    let (filter, filter_error) = match std::env::var(EnvFilter::DEFAULT_ENV) {
+        Ok(env) => parse_filter(&env),
+        Err(_) => parse_filter(filter)
+    };
+
+    // On stderr, stdout being the output of the tools
+    let stderr_layer = fmt::layer().with_writer(std::io::stderr);
+    let mut file_error = None;
+    let (file_layer, guard) = match dir.map(|dir| file_appender(name, dir, max_files)) {
+        Some(Ok(appender)) => {
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            (Some(fmt::layer().with_ansi(false).with_writer(writer)), Some(guard))
+        },
+        Some(Err(e)) => {
+            file_error = Some(e);
+            (None, None)
+        },
+        None => (None, None)
+    };
+    tracing_subscriber::registry().with(filter).with(stderr_layer).with(file_layer).init();
+
+    if let Some(e) = filter_error {
+        tracing::warn!("Invalid log filter, using {}: {}", DEFAULT_FILTER, e);
+    }
+    if let Some(e) = file_error {
+        tracing::error!("Cannot log to files: {}", e);
+    }
+    guard
+}
+
+/// `init` configured from RUST_LOG, PIZBOAT_LOG_DIR and PIZBOAT_LOG_MAX_FILES
+pub fn init_from_env(name: &str) -> Option<WorkerGuard> {
+    let dir = std::env::var(DIR_ENV).ok().filter(|dir| !dir.is_empty());
+    let max_files = std::env::var(MAX_FILES_ENV).ok().and_then(|n| n.parse().ok()).unwrap_or(DEFAULT_MAX_FILES);
+    init(name, DEFAULT_FILTER, dir.as_deref().map(Path::new), max_files)
+}
+
+fn parse_filter(filter: &str) -> (EnvFilter, Option<String>) {
+    match EnvFilter::try_new(filter) {
+        Ok(filter) => (filter, None),
+        Err(e) => (EnvFilter::new(DEFAULT_FILTER), Some(e.to_string()))
+    }
+}
+
+fn file_appender(name: &str, dir: &Path, max_files: usize) -> Result<RollingFileAppender, Box<dyn std::error::Error>> {
+    std::fs::create_dir_all(dir)?;
+    Ok(RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix(name)
+        .filename_suffix("log")
+        .max_log_files(max_files.max(1))
+        .build(dir)?)
+}
@@ -0,0 +1,74 @@
+// First byte of every frame, other LoRa traffic on the channel is ignored
+const MAGIC: u8 = 0xB0;
+const COMMAND: u8 = 1;
+const ACK: u8 = 2;
+const FRAME_LENGTH: usize = 5;
+
+const MOTOR_CUT: u8 = 0x01;
+const RUDDER_CENTER: u8 = 0x02;
+
+/// Failsafe-critical orders of the remote, sent as its heartbeat
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Command {
+    pub seq: u16,
+    pub motor_cut: bool,
+    pub rudder_center: bool,
+}
+
+/// Frames of the backup link, 5 bytes: magic, type, sequence number (u16, little endian) and flags
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Frame {
+    /// Remote to boat
+    Command(Command),
+    /// Boat to remote, with the sequence number of the command received
+    Ack(u16),
+}
+
+impl Frame {
+    pub fn encode(&self) -> [u8; FRAME_LENGTH] {
+        let (kind, seq, flags) = match *self {
+            Frame::Command(command) => {
+                let flags = if command.motor_cut { MOTOR_CUT } else { 0 } | if command.rudder_center { RUDDER_CENTER } else { 0 };
+                (COMMAND, command.seq, flags)
+            }
+            Frame::Ack(seq) => (ACK, seq, 0),
+        };
+        let seq = seq.to_le_bytes();
+        [MAGIC, kind, seq[0], seq[1], flags]
+    }
+
+    /// None for anything else than a frame of ours
+    pub fn decode(bytes: &[u8]) -> Option<Frame> {
+        let &[MAGIC, kind, seq_low, seq_high, flags] = bytes else {
+            return None;
+        };
+        let seq = u16::from_le_bytes([seq_low, seq_high]);
+        match kind {
+            COMMAND => Some(Frame::Command(Command {
+                seq,
+                motor_cut: flags & MOTOR_CUT != 0,
+                rudder_center: flags & RUDDER_CENTER != 0,
+            })),
+            ACK => Some(Frame::Ack(seq)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frames() {
+        let command = Frame::Command(Command { seq: 0x1234, motor_cut: true, rudder_center: false });
+        assert_eq!(command.encode(), [0xB0, 1, 0x34, 0x12, 0x01]);
+        assert_eq!(Frame::decode(&command.encode()), Some(command));
+        assert_eq!(Frame::decode(&Frame::Ack(7).encode()), Some(Frame::Ack(7)));
+
+        // Foreign or truncated frames
+        assert_eq!(Frame::decode(&[0xAA, 1, 0, 0, 0]), None);
+        assert_eq!(Frame::decode(&[0xB0, 9, 0, 0, 0]), None);
+        assert_eq!(Frame::decode(&[0xB0, 1, 0, 0]), None);
+    }
+}
@@ -0,0 +1,7 @@
+//! SX127x LoRa driver and the frames of the backup control link, shared by the remote and the boat.
+
+mod frame;
+mod sx127x;
+
+pub use frame::{Command, Frame};
+pub use sx127x::{Packet, Sx127x};
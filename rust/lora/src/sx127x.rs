@@ -0,0 +1,149 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use pizboat_hal::RadioBus;
+
+const REG_FIFO: u8 = 0x00;
+const REG_OP_MODE: u8 = 0x01;
+const REG_FRF_MSB: u8 = 0x06;
+const REG_PA_CONFIG: u8 = 0x09;
+const REG_LNA: u8 = 0x0C;
+const REG_FIFO_ADDR_PTR: u8 = 0x0D;
+const REG_FIFO_TX_BASE_ADDR: u8 = 0x0E;
+const REG_FIFO_RX_BASE_ADDR: u8 = 0x0F;
+const REG_FIFO_RX_CURRENT_ADDR: u8 = 0x10;
+const REG_IRQ_FLAGS: u8 = 0x12;
+const REG_RX_NB_BYTES: u8 = 0x13;
+const REG_PKT_RSSI_VALUE: u8 = 0x1A;
+const REG_MODEM_CONFIG_1: u8 = 0x1D;
+const REG_MODEM_CONFIG_2: u8 = 0x1E;
+const REG_PAYLOAD_LENGTH: u8 = 0x22;
+const REG_MODEM_CONFIG_3: u8 = 0x26;
+const REG_VERSION: u8 = 0x42;
+
+const WRITE: u8 = 0x80;
+const SX127X_VERSION: u8 = 0x12;
+
+const MODE_LONG_RANGE: u8 = 0x80;
+const MODE_SLEEP: u8 = 0x00;
+const MODE_STANDBY: u8 = 0x01;
+const MODE_TX: u8 = 0x03;
+const MODE_RX_CONTINUOUS: u8 = 0x05;
+
+const IRQ_TX_DONE: u8 = 0x08;
+const IRQ_CRC_ERROR: u8 = 0x20;
+const IRQ_RX_DONE: u8 = 0x40;
+
+// 125 kHz bandwidth, 4/5 coding rate and explicit header, spreading factor 9 with CRC:
+// about 150 ms on air for a frame, and a few km of range
+const MODEM_CONFIG_1: u8 = 0x72;
+const MODEM_CONFIG_2: u8 = 0x94;
+// Automatic gain control
+const MODEM_CONFIG_3: u8 = 0x04;
+// PA_BOOST pin at 17 dBm, the only one wired on most modules
+const PA_CONFIG: u8 = 0x8F;
+// Maximum LNA gain with the HF boost
+const LNA_BOOST: u8 = 0x23;
+
+const CRYSTAL_HZ: u64 = 32_000_000;
+const TX_TIMEOUT: Duration = Duration::from_secs(1);
+const TX_POLL: Duration = Duration::from_millis(5);
+// RSSI offset of the high frequency port (868/915 MHz)
+const RSSI_OFFSET: i16 = -157;
+
+/// Packet received, with its signal strength
+pub struct Packet {
+    pub payload: Vec<u8>,
+    pub rssi_dbm: i16,
+}
+
+/// SX1276/77/78/79 radio in LoRa mode, polled over SPI: DIO0 is not needed
+pub struct Sx127x<B: RadioBus> {
+    bus: B,
+}
+
+impl<B: RadioBus> Sx127x<B> {
+    /// Check the chip and set it up on the frequency, left in standby
+    pub fn new(bus: B, frequency_hz: u32) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut radio = Sx127x { bus };
+        let version = radio.read_register(REG_VERSION)?;
+        if version != SX127X_VERSION {
+            return Err(format!("no SX127x found, version register reads 0x{:02X}", version).into());
+        }
+        // LoRa mode can only be selected in sleep mode
+        radio.write_register(REG_OP_MODE, MODE_LONG_RANGE | MODE_SLEEP)?;
+        let frf = ((frequency_hz as u64) << 19) / CRYSTAL_HZ;
+        radio.write_register(REG_FRF_MSB, (frf >> 16) as u8)?;
+        radio.write_register(REG_FRF_MSB + 1, (frf >> 8) as u8)?;
+        radio.write_register(REG_FRF_MSB + 2, frf as u8)?;
+        radio.write_register(REG_FIFO_TX_BASE_ADDR, 0)?;
+        radio.write_register(REG_FIFO_RX_BASE_ADDR, 0)?;
+        radio.write_register(REG_LNA, LNA_BOOST)?;
+        radio.write_register(REG_MODEM_CONFIG_1, MODEM_CONFIG_1)?;
+        radio.write_register(REG_MODEM_CONFIG_2, MODEM_CONFIG_2)?;
+        radio.write_register(REG_MODEM_CONFIG_3, MODEM_CONFIG_3)?;
+        radio.write_register(REG_PA_CONFIG, PA_CONFIG)?;
+        radio.write_register(REG_OP_MODE, MODE_LONG_RANGE | MODE_STANDBY)?;
+        Ok(radio)
+    }
+
+    /// Transmit one packet, waiting until it is sent. The radio is left in standby.
+    pub fn send(&mut self, payload: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        self.write_register(REG_OP_MODE, MODE_LONG_RANGE | MODE_STANDBY)?;
+        self.write_register(REG_FIFO_ADDR_PTR, 0)?;
+        let mut write = vec![REG_FIFO | WRITE];
+        write.extend_from_slice(payload);
+        let mut read = vec![0; write.len()];
+        self.bus.transfer(&mut read, &write)?;
+        self.write_register(REG_PAYLOAD_LENGTH, payload.len() as u8)?;
+        self.write_register(REG_OP_MODE, MODE_LONG_RANGE | MODE_TX)?;
+
+        let start = Instant::now();
+        while self.read_register(REG_IRQ_FLAGS)? & IRQ_TX_DONE == 0 {
+            if start.elapsed() > TX_TIMEOUT {
+                return Err("LoRa transmission timed out".into());
+            }
+            thread::sleep(TX_POLL);
+        }
+        self.write_register(REG_IRQ_FLAGS, 0xFF)
+    }
+
+    /// Listen continuously, packets being fetched by `receive`
+    pub fn listen(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.write_register(REG_IRQ_FLAGS, 0xFF)?;
+        self.write_register(REG_OP_MODE, MODE_LONG_RANGE | MODE_RX_CONTINUOUS)
+    }
+
+    /// The packet received since the last call if any, those with a bad CRC being dropped
+    pub fn receive(&mut self) -> Result<Option<Packet>, Box<dyn std::error::Error>> {
+        let flags = self.read_register(REG_IRQ_FLAGS)?;
+        if flags & IRQ_RX_DONE == 0 {
+            return Ok(None);
+        }
+        self.write_register(REG_IRQ_FLAGS, 0xFF)?;
+        if flags & IRQ_CRC_ERROR != 0 {
+            return Ok(None);
+        }
+        let length = self.read_register(REG_RX_NB_BYTES)? as usize;
+        let address = self.read_register(REG_FIFO_RX_CURRENT_ADDR)?;
+        self.write_register(REG_FIFO_ADDR_PTR, address)?;
+        let mut write = vec![0; length + 1];
+        write[0] = REG_FIFO;
+        let mut read = vec![0; length + 1];
+        self.bus.transfer(&mut read, &write)?;
+        let rssi_dbm = RSSI_OFFSET + self.read_register(REG_PKT_RSSI_VALUE)? as i16;
+        read.remove(0);
+        Ok(Some(Packet { payload: read, rssi_dbm }))
+    }
+
+    fn read_register(&mut self, register: u8) -> Result<u8, Box<dyn std::error::Error>> {
+        let mut read = [0u8; 2];
+        self.bus.transfer(&mut read, &[register & !WRITE, 0])?;
+        Ok(read[1])
+    }
+
+    fn write_register(&mut self, register: u8, value: u8) -> Result<(), Box<dyn std::error::Error>> {
+        let mut read = [0u8; 2];
+        self.bus.transfer(&mut read, &[register | WRITE, value])
+    }
+}
@@ -0,0 +1,150 @@
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tungstenite::{connect, Message};
+
+// Status requests, faster than the eye but far below the 25 Hz of the boat
+const POLL_PERIOD: Duration = Duration::from_millis(100);
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+// Latency samples kept for the graph, a minute at the poll period
+pub const HISTORY_LEN: usize = 600;
+
+/// Channel outputs of the last command sent to the boat
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Command {
+    pub rudder_star: u16,
+    pub rudder_port: u16,
+    pub motor: u16,
+    pub boom: u16,
+    pub genoa: u16,
+    pub bow_thruster: u16,
+    pub gimbal_pan: u16,
+    pub gimbal_tilt: u16,
+    pub nav_lights: bool,
+    pub spotlight: bool,
+    pub deck_light: bool,
+}
+
+impl Command {
+    pub fn channels(&self) -> [(&'static str, u16); 8] {
+        [("Rudder star", self.rudder_star), ("Rudder port", self.rudder_port), ("Motor", self.motor), ("Boom", self.boom),
+         ("Genoa", self.genoa), ("Bow thruster", self.bow_thruster), ("Gimbal pan", self.gimbal_pan), ("Gimbal tilt", self.gimbal_tilt)]
+    }
+}
+
+/// Telemetry of the last boat query, each field missing with older boats
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Query {
+    pub wireless_quality: Option<i16>,
+    pub wireless_signal: Option<i16>,
+    pub weight: Option<f32>,
+    pub battery_voltage: Option<f32>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub speed: Option<f32>,
+    pub heading: Option<f32>,
+    pub wind_angle: Option<f32>,
+    pub leak: Option<bool>,
+    pub tension_alarm: Option<bool>,
+}
+
+/// Telemetry message of the remote
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Status {
+    pub command: Option<Command>,
+    pub query: Option<Query>,
+    pub round_trip_ms: Option<u64>,
+}
+
+/// Message of the remote, its alarms coming between the telemetry answers
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Reply {
+    Telemetry(Status),
+    #[serde(other)]
+    Other,
+}
+
+/// What the monitor shows, updated by the client thread
+#[derive(Default)]
+pub struct MonitorState {
+    pub connected: bool,
+    pub error: Option<String>,
+    pub status: Status,
+    pub updated: Option<Instant>,
+    // Round trip to the boat, None while it is away
+    pub latency_history: VecDeque<Option<u64>>,
+}
+
+impl MonitorState {
+    pub fn update(&mut self, status: Status) {
+        if self.latency_history.len() == HISTORY_LEN {
+            self.latency_history.pop_front();
+        }
+        self.latency_history.push_back(status.query.as_ref().and(status.round_trip_ms));
+        self.status = status;
+        self.updated = Some(Instant::now());
+    }
+}
+
+pub fn start(url: String) -> Arc<Mutex<MonitorState>> {
+    let state = Arc::new(Mutex::new(MonitorState::default()));
+    let state_clone = Arc::clone(&state);
+    thread::spawn(move || loop {
+        let result = poll_remote(&url, &state_clone);
+        let mut state = state_clone.lock().unwrap();
+        state.connected = false;
+        state.error = result.err().map(|e| e.to_string());
+        drop(state);
+        thread::sleep(RECONNECT_DELAY);
+    });
+    state
+}
+
+// Only asks for telemetry, the remote never treats the monitor as a boat
+fn poll_remote(url: &str, state: &Mutex<MonitorState>) -> Result<(), Box<dyn std::error::Error>> {
+    let (mut socket, _response) = connect(url)?;
+    {
+        let mut state = state.lock().unwrap();
+        state.connected = true;
+        state.error = None;
+    }
+    loop {
+        socket.send(Message::Text(r#"{"type":"telemetry"}"#.to_string()))?;
+        while let Message::Text(text) = socket.read()? {
+            if let Reply::Telemetry(status) = serde_json::from_str(&text)? {
+                state.lock().unwrap().update(status);
+                break;
+            }
+        }
+        thread::sleep(POLL_PERIOD);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_update() {
+        let mut state = MonitorState::default();
+        let text = r#"{"type":"telemetry","command":{"timestamp":1,"motor":1600,"boom":1400},
+            "query":{"timestamp":1,"wireless_quality":55,"weight":120.5},"round_trip_ms":18}"#;
+        state.update(serde_json::from_str(text).unwrap());
+        // Boat gone, its last round trip is not plotted
+        state.update(serde_json::from_str(r#"{"type":"telemetry","command":null,"query":null,"round_trip_ms":null}"#).unwrap());
+        assert_eq!(state.latency_history, [Some(18), None]);
+        assert!(state.status.command.is_none());
+
+        for _ in 0..HISTORY_LEN {
+            state.update(Status::default());
+        }
+        assert_eq!(state.latency_history.len(), HISTORY_LEN);
+        assert!(matches!(serde_json::from_str(r#"{"type":"alarm","name":"Leak","raised":true}"#).unwrap(), Reply::Other));
+    }
+}
@@ -0,0 +1,33 @@
+mod client;
+mod ui;
+
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use std::time::Duration;
+
+// The remote, as the boat reaches it
+const DEFAULT_WS_URL: &str = "ws://10.250.1.1:10013";
+const REFRESH_PERIOD: Duration = Duration::from_millis(100);
+
+/// Read-only view of the remote: channel outputs, boat telemetry and link latency, in the terminal
+fn main() -> std::io::Result<()> {
+    let url = std::env::args().nth(1).unwrap_or_else(|| DEFAULT_WS_URL.to_string());
+    let state = client::start(url.clone());
+
+    let mut terminal = ratatui::init();
+    let result = loop {
+        if let Err(e) = terminal.draw(|frame| ui::draw(frame, &state.lock().unwrap(), &url)) {
+            break Err(e);
+        }
+        match event::poll(REFRESH_PERIOD) {
+            Ok(true) => match event::read() {
+                Ok(Event::Key(key)) if key.kind == KeyEventKind::Press && matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) => break Ok(()),
+                Ok(_) => {}
+                Err(e) => break Err(e),
+            },
+            Ok(false) => {}
+            Err(e) => break Err(e),
+        }
+    };
+    ratatui::restore();
+    result
+}
@@ -0,0 +1,93 @@
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Gauge, Paragraph, Sparkline};
+use ratatui::Frame;
+
+use crate::client::MonitorState;
+
+// Servo pulse range shown by the channel gauges
+const PULSE_MIN_US: u16 = 1000;
+const PULSE_MAX_US: u16 = 2000;
+// Round trip over which the latency shows as an alarm, as on the remote
+const LATENCY_ALARM_MS: u64 = 500;
+
+pub fn draw(frame: &mut Frame, state: &MonitorState, url: &str) {
+    let [header, body, graph] = Layout::vertical([Constraint::Length(3), Constraint::Min(10), Constraint::Length(8)]).areas(frame.area());
+    let [channels, telemetry] = Layout::horizontal([Constraint::Percentage(55), Constraint::Percentage(45)]).areas(body);
+
+    draw_header(frame, header, state, url);
+    draw_channels(frame, channels, state);
+    draw_telemetry(frame, telemetry, state);
+    draw_latency(frame, graph, state);
+}
+
+fn draw_header(frame: &mut Frame, area: Rect, state: &MonitorState, url: &str) {
+    let (text, color) = match (&state.error, state.connected, state.status.query.is_some()) {
+        (Some(e), _, _) => (format!("{}: {}", url, e), Color::Red),
+        (None, false, _) => (format!("Connecting to {}", url), Color::Yellow),
+        (None, true, false) => (format!("{}: remote up, no boat", url), Color::Yellow),
+        (None, true, true) => (format!("{}: boat linked", url), Color::Green),
+    };
+    let block = Block::default().borders(Borders::ALL).title(" pizboat-monitor (q to quit) ");
+    frame.render_widget(Paragraph::new(text).style(Style::default().fg(color)).block(block), area);
+}
+
+fn draw_channels(frame: &mut Frame, area: Rect, state: &MonitorState) {
+    let block = Block::default().borders(Borders::ALL).title(" Channels ");
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+    let Some(command) = &state.status.command else {
+        frame.render_widget(Paragraph::new("No command yet"), inner);
+        return;
+    };
+
+    let channels = command.channels();
+    let rows = Layout::vertical(channels.iter().map(|_| Constraint::Length(1))).split(inner);
+    for ((name, value), row) in channels.iter().zip(rows.iter()) {
+        let ratio = (value.saturating_sub(PULSE_MIN_US) as f64 / (PULSE_MAX_US - PULSE_MIN_US) as f64).clamp(0., 1.);
+        let gauge = Gauge::default()
+            .ratio(ratio)
+            .label(format!("{:<12} {:4}us", name, value))
+            .gauge_style(Style::default().fg(Color::Cyan));
+        frame.render_widget(gauge, *row);
+    }
+}
+
+fn optional<T: std::fmt::Display>(value: Option<T>, unit: &str) -> String {
+    value.map_or_else(|| "-".to_string(), |v| format!("{}{}", v, unit))
+}
+
+fn draw_telemetry(frame: &mut Frame, area: Rect, state: &MonitorState) {
+    let block = Block::default().borders(Borders::ALL).title(" Telemetry ");
+    let Some(query) = &state.status.query else {
+        frame.render_widget(Paragraph::new("No boat").block(block), area);
+        return;
+    };
+    let alarms: Vec<&str> = [(query.leak, "LEAK"), (query.tension_alarm, "SHEET EASED")].iter()
+        .filter(|(on, _)| *on == Some(true)).map(|(_, name)| *name).collect();
+    let lines = vec![
+        Line::from(format!("Round trip  {}", optional(state.status.round_trip_ms, " ms"))),
+        Line::from(format!("Wireless    {} ({})", optional(query.wireless_quality, "%"), optional(query.wireless_signal, " dBm"))),
+        Line::from(format!("Battery     {}", optional(query.battery_voltage.map(|v| format!("{:.2}", v)), " V"))),
+        Line::from(format!("Sheet load  {}", optional(query.weight.map(|w| format!("{:.0}", w)), " g"))),
+        Line::from(format!("Position    {}", optional(query.latitude.zip(query.longitude).map(|(lat, lon)| format!("{:.5} {:.5}", lat, lon)), ""))),
+        Line::from(format!("Speed       {}", optional(query.speed.map(|s| format!("{:.2}", s)), " m/s"))),
+        Line::from(format!("Heading     {}", optional(query.heading.map(|h| format!("{:.0}", h)), "°"))),
+        Line::from(format!("Wind angle  {}", optional(query.wind_angle.map(|a| format!("{:.0}", a)), "°"))),
+        Line::from(if alarms.is_empty() { String::new() } else { format!("ALARM {}", alarms.join(" ")) })
+            .style(Style::default().fg(Color::Red)),
+    ];
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+fn draw_latency(frame: &mut Frame, area: Rect, state: &MonitorState) {
+    // Most recent on the right, as much as fits
+    let width = area.width.saturating_sub(2) as usize;
+    let skip = state.latency_history.len().saturating_sub(width);
+    let data: Vec<u64> = state.latency_history.iter().skip(skip).map(|latency| latency.unwrap_or(0)).collect();
+    let max = data.iter().copied().max().unwrap_or(0);
+    let color = if max > LATENCY_ALARM_MS { Color::Red } else { Color::Green };
+    let block = Block::default().borders(Borders::ALL).title(format!(" Round trip, max {} ms ", max));
+    frame.render_widget(Sparkline::default().block(block).data(&data).style(Style::default().fg(color)), area);
+}
@@ -0,0 +1,282 @@
+//! Messages of the link between the remote and the boat, shared by both ends and the simulated boat.
+//!
+//! Each message is a JSON object whose `type` field gives its kind. Unknown types and unknown fields
+//! are refused, so that both ends find out at once when they disagree on the protocol.
+
+mod telemetry;
+
+pub use telemetry::TelemetryValue;
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Message {
+    Command(Command),       // Remote to boat, answering each query
+    Query(Query),           // Boat to remote, polling for the commands with its telemetry and alarms
+    Failsafe(Failsafe),     // Remote to boat, before the command, on connect and whenever the values change
+    Telemetry(Telemetry),   // Remote to the monitoring tools, answering an empty one with the state of the link
+    Config(Config),         // Configuration tools to the remote and its answers
+    Alarm(Alarm),           // Remote to the tools once they asked, whenever one of its alerts is raised or cleared
+}
+
+/// Channel outputs, as pulse widths in us.
+/// The remote leaves out those unchanged since its last keyframe when sending deltas, the boat keeping them.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Command {
+    pub boat_id: Option<u8>,            // Boat addressed, any without
+    pub timestamp: u64,                 // Of the query answered
+    pub remote_timestamp: Option<u64>,  // Remote clock when sent, echoed back by the boat
+    pub rudder_star: Option<u16>,
+    pub rudder_port: Option<u16>,
+    pub motor: Option<u16>,
+    pub boom: Option<u16>,
+    pub genoa: Option<u16>,
+    pub bow_thruster: Option<u16>,
+    pub gimbal_pan: Option<u16>,
+    pub gimbal_tilt: Option<u16>,
+    pub nav_lights: Option<bool>,
+    pub spotlight: Option<bool>,
+    pub deck_light: Option<bool>,
+    // Boat trimming the sails from the wind angle, boom and genoa then biasing its trim from their center
+    pub auto_trim: Option<bool>,
+    // Boat recording its magnetometer while driven in circles, calibrating its compass once false again
+    pub calibrate_compass: Option<bool>,
+    // Link as seen from the remote, for the boat logs
+    pub wireless_quality: Option<i16>,
+    pub wireless_signal: Option<i16>,
+}
+
+/// Boat state sent with each poll, fields the boat cannot tell being left out
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Query {
+    pub boat_id: Option<u8>,            // Boat sending, for a remote driving one of several
+    pub timestamp: u64,
+    pub wireless_quality: Option<i16>,
+    pub wireless_signal: Option<i16>,
+    pub latency: Option<u64>,
+    // remote_timestamp of the last command, and how long the boat held it before echoing
+    pub echo_timestamp: Option<u64>,
+    pub echo_delay_ms: Option<u64>,
+    pub weight: Option<f32>,            // Mainsheet load in grams, -1 without reading
+    pub jib_weight: Option<f32>,        // Jibsheet load
+    // A sheet eased by the boat, its load having stayed too high
+    pub tension_alarm: Option<bool>,
+    // Servo outputs of the boat, after its own limits, for the command it echoes
+    pub applied: Option<AppliedPulses>,
+    // Readings of other sensors by name, kept whatever the name
+    #[serde(default)]
+    pub telemetry: BTreeMap<String, TelemetryValue>,
+    pub battery_voltage: Option<f32>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub speed: Option<f32>,
+    pub heading: Option<f32>,           // Degrees
+    pub wind_angle: Option<f32>,        // Apparent, degrees from the bow
+    pub leak: Option<bool>,
+    // Start-up hardware checks, in the first query of each connection only
+    pub self_test: Option<Vec<HardwareCheck>>,
+}
+
+/// Pulse widths the boat actually applied, clamped, eased or cut where it overrode the remote
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AppliedPulses {
+    pub rudder_star: u16,
+    pub rudder_port: u16,
+    pub motor: u16,
+    pub boom: u16,
+    pub genoa: u16,
+    pub bow_thruster: u16,
+    pub gimbal_pan: u16,
+    pub gimbal_tilt: u16,
+}
+
+/// Outcome of one start-up check of the boat hardware
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HardwareCheck {
+    pub name: String,
+    pub passed: bool,
+    pub detail: String,     // What was found, or why it failed
+}
+
+/// Servo positions for the boat to hold when the link is lost, and when, as configured on the remote
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Failsafe {
+    pub boat_id: Option<u8>,            // Boat addressed, any without
+    pub timeout_ms: Option<u64>,        // Silence of the remote before the boat applies them, its own default without
+    pub rudder_star: u16,
+    pub rudder_port: u16,
+    pub motor: u16,
+    pub boom: u16,
+    pub genoa: u16,
+    pub bow_thruster: u16,
+    pub gimbal_pan: u16,
+    pub gimbal_tilt: u16,
+}
+
+/// Latest command sent and query received by the remote, null when not known
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Telemetry {
+    pub command: Option<Command>,
+    pub query: Option<Query>,
+    pub round_trip_ms: Option<u64>,
+}
+
+/// Settings of the remote, kept as JSON here since only the remote knows their fields
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Config {
+    // Empty rather than unit variants, unit ones accepting any field
+    Get {},                                                 // Settings and channels wanted
+    Set { channels: Vec<serde_json::Value> },               // Channels to replace live, by name
+    Save {},                                                // Live settings to write to their files
+    Settings { settings: serde_json::Value, channels: Vec<serde_json::Value> },  // Answer to get
+    Result { ok: bool, error: Option<String> },             // Answer to set and save, or to get before the settings are loaded
+}
+
+/// Alert shown on the remote, by the name of its banner
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Alarm {
+    pub name: String,
+    pub raised: bool,       // False once cleared or dismissed
+}
+
+impl Command {
+    /// Whether the boat of that id should apply it
+    pub fn is_for(&self, boat_id: u8) -> bool {
+        self.boat_id.is_none_or(|id| id == boat_id)
+    }
+
+    /// Take the fields of a delta, keeping those it leaves out, for a full command again
+    pub fn merge(&mut self, delta: &Command) {
+        fn keep<T: Copy>(field: &mut Option<T>, delta: Option<T>) {
+            *field = delta.or(*field);
+        }
+        self.boat_id = delta.boat_id;
+        self.timestamp = delta.timestamp;
+        self.remote_timestamp = delta.remote_timestamp;
+        keep(&mut self.rudder_star, delta.rudder_star);
+        keep(&mut self.rudder_port, delta.rudder_port);
+        keep(&mut self.motor, delta.motor);
+        keep(&mut self.boom, delta.boom);
+        keep(&mut self.genoa, delta.genoa);
+        keep(&mut self.bow_thruster, delta.bow_thruster);
+        keep(&mut self.gimbal_pan, delta.gimbal_pan);
+        keep(&mut self.gimbal_tilt, delta.gimbal_tilt);
+        keep(&mut self.nav_lights, delta.nav_lights);
+        keep(&mut self.spotlight, delta.spotlight);
+        keep(&mut self.deck_light, delta.deck_light);
+        keep(&mut self.auto_trim, delta.auto_trim);
+        keep(&mut self.calibrate_compass, delta.calibrate_compass);
+        keep(&mut self.wireless_quality, delta.wireless_quality);
+        keep(&mut self.wireless_signal, delta.wireless_signal);
+    }
+}
+
+impl Failsafe {
+    pub fn is_for(&self, boat_id: u8) -> bool {
+        self.boat_id.is_none_or(|id| id == boat_id)
+    }
+}
+
+/// Message which could not be read
+#[derive(Debug)]
+pub enum ProtocolError {
+    /// Not a JSON object with a type field
+    Malformed(String),
+    /// Type this end does not know
+    UnknownType(String),
+    /// Known type, with a field missing, unknown or of the wrong kind
+    Invalid { msg_type: String, error: String },
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProtocolError::Malformed(e) => write!(f, "malformed message: {}", e),
+            ProtocolError::UnknownType(msg_type) => write!(f, "unexpected message type \"{}\"", msg_type),
+            ProtocolError::Invalid { msg_type, error } => write!(f, "invalid {} message: {}", msg_type, error),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+// Only the type field, to tell an unknown type from an invalid message
+#[derive(Deserialize)]
+struct MessageType {
+    #[serde(rename = "type")]
+    msg_type: String,
+}
+
+/// Message of the type given by its type field
+pub fn parse(text: &str) -> Result<Message, ProtocolError> {
+    let msg_type = serde_json::from_str::<MessageType>(text)
+        .map_err(|e| ProtocolError::Malformed(e.to_string()))?
+        .msg_type;
+    if !matches!(msg_type.as_str(), "command" | "query" | "failsafe" | "telemetry" | "config" | "alarm") {
+        return Err(ProtocolError::UnknownType(msg_type));
+    }
+    serde_json::from_str(text).map_err(|e| ProtocolError::Invalid { msg_type, error: e.to_string() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tagged_messages() {
+        let command = Message::Command(Command { timestamp: 12, motor: Some(1600), ..Default::default() });
+        let json = serde_json::to_string(&command).unwrap();
+        assert!(json.starts_with(r#"{"type":"command","boat_id":null,"timestamp":12,"#));
+        assert_eq!(parse(&json).unwrap(), command);
+
+        // A delta command without most of its fields
+        let Ok(Message::Command(delta)) = parse(r#"{"type":"command","timestamp":13,"boom":1300}"#) else { panic!() };
+        assert_eq!((delta.boom, delta.motor), (Some(1300), None));
+        assert!(delta.is_for(2));
+        let Ok(Message::Command(addressed)) = parse(r#"{"type":"command","boat_id":1,"timestamp":14}"#) else { panic!() };
+        assert!(addressed.is_for(1) && !addressed.is_for(2));
+        let mut full = Command { motor: Some(1600), boom: Some(1200), ..Default::default() };
+        full.merge(&delta);
+        assert_eq!((full.timestamp, full.boom, full.motor), (13, Some(1300), Some(1600)));
+
+        let Ok(Message::Query(query)) = parse(r#"{"type":"query","timestamp":2,"self_test":[{"name":"I2C","passed":false,"detail":"no bus"}]}"#) else { panic!() };
+        assert_eq!(query.self_test.unwrap()[0], HardwareCheck { name: "I2C".into(), passed: false, detail: "no bus".into() });
+
+        assert_eq!(parse(r#"{"type":"status"}"#).unwrap_err().to_string(), r#"unexpected message type "status""#);
+        let error = parse(r#"{"type":"query","timestamp":1,"wieght":12}"#).unwrap_err().to_string();
+        assert!(error.starts_with("invalid query message: unknown field `wieght`"), "{}", error);
+        assert!(matches!(parse(r#"{"timestamp":1}"#), Err(ProtocolError::Malformed(_))));
+        assert!(matches!(parse(r#"{"type":"failsafe","motor":1500}"#), Err(ProtocolError::Invalid { .. })));
+    }
+
+    #[test]
+    fn test_tool_messages() {
+        assert_eq!(parse(r#"{"type":"telemetry"}"#).unwrap(), Message::Telemetry(Telemetry::default()));
+        assert_eq!(parse(r#"{"type":"config","action":"get"}"#).unwrap(), Message::Config(Config::Get {}));
+        let Ok(Message::Config(Config::Set { channels })) = parse(r#"{"type":"config","action":"set","channels":[{"name":"Motor"}]}"#) else { panic!() };
+        assert_eq!(channels[0]["name"], "Motor");
+        let result = Message::Config(Config::Result { ok: false, error: Some("Settings not loaded yet".into()) });
+        let json = serde_json::to_string(&result).unwrap();
+        assert_eq!(json, r#"{"type":"config","action":"result","ok":false,"error":"Settings not loaded yet"}"#);
+        assert_eq!(parse(&json).unwrap(), result);
+
+        let alarm = Message::Alarm(Alarm { name: "Leak".into(), raised: true });
+        assert_eq!(parse(&serde_json::to_string(&alarm).unwrap()).unwrap(), alarm);
+
+        let error = parse(r#"{"type":"config","action":"load"}"#).unwrap_err().to_string();
+        assert!(error.starts_with("invalid config message: unknown variant `load`"), "{}", error);
+        let error = parse(r#"{"type":"config","action":"save","path":"x"}"#).unwrap_err().to_string();
+        assert!(error.starts_with("invalid config message: unknown field `path`"), "{}", error);
+    }
+}
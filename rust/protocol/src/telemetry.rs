@@ -0,0 +1,63 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Reading of the open telemetry of the boat, a value with its unit, or anything else shown as it comes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TelemetryValue {
+    Reading {
+        value: Value,
+        #[serde(default)]
+        unit: String,
+    },
+    Bare(Value),
+}
+
+impl TelemetryValue {
+    pub fn number(value: f64, unit: &str) -> Self {
+        TelemetryValue::Reading { value: Value::from(value), unit: unit.to_string() }
+    }
+
+    pub fn string(value: &str) -> Self {
+        TelemetryValue::Reading { value: Value::from(value), unit: String::new() }
+    }
+
+    /// Number of a reading, for the alarms on a known name
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            TelemetryValue::Reading { value, .. } | TelemetryValue::Bare(value) => value.as_f64(),
+        }
+    }
+
+    /// Value and unit, numbers rounded to what fits on the display
+    pub fn text(&self) -> String {
+        let (value, unit) = match self {
+            TelemetryValue::Reading { value, unit } => (value, unit.as_str()),
+            TelemetryValue::Bare(value) => (value, ""),
+        };
+        let value = match value {
+            Value::Number(n) => n.as_f64().map_or(n.to_string(), |v| format!("{}", (v * 100.).round() / 100.)),
+            Value::String(text) => text.clone(),
+            other => other.to_string(),
+        };
+        format!("{}{}", value, unit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_unknown_telemetry() {
+        let telemetry: BTreeMap<String, TelemetryValue> = serde_json::from_str(
+            r#"{"cpu_temp":{"value":48.512,"unit":"C"},"link":{"value":"nrf24"},"depth":3,"gps":[1,2]}"#).unwrap();
+        let texts: Vec<String> = telemetry.values().map(TelemetryValue::text).collect();
+        assert_eq!(texts, ["48.51C", "3", "[1,2]", "nrf24"]);
+        assert_eq!(telemetry["cpu_temp"].as_f64(), Some(48.512));
+        assert_eq!(telemetry["link"].as_f64(), None);
+
+        assert_eq!(serde_json::to_string(&TelemetryValue::number(48.5, "C")).unwrap(), r#"{"value":48.5,"unit":"C"}"#);
+    }
+}
@@ -1,40 +1,141 @@
+use pizboat_hal::AdcSource;
 use rppal::spi::{Bus, Mode, SlaveSelect, Spi};
 
+use crate::config::SpiDevice;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tracing::{error, info};
+
+pub const CHANNELS_PER_CHIP: usize = 8;
+
 pub struct AdcReader {
-    spi: Spi,
+    spi: Vec<Box<dyn AdcSource + Send>>,    // One MCP3008 per chip select, channels 0-7 on SPI0 CE0 and 8-15 on the second one
+    oversample: u16,    // Conversions averaged per channel reading
+    bits: u8,           // 10 for MCP3008 chips, 12 for MCP3208
 }
 
 impl AdcReader {
-    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
-        let spi = Spi::new(Bus::Spi0, SlaveSelect::Ss0, 1_000_000, Mode::Mode0)?;
-        println!("MCP3008 ADC initialized on SPI0.0");
-        Ok(AdcReader { spi })
+    /// `second_chip` is the SPI device of the chip of channels 8-15, if any
+    pub fn new(oversample: u16, second_chip: Option<SpiDevice>, bits: u8) -> Result<Self, Box<dyn std::error::Error>> {
+        if bits != 10 && bits != 12 {
+            return Err(format!("Unsupported ADC resolution {} bits, expected 10 (MCP3008) or 12 (MCP3208)", bits).into());
+        }
+        let chip = if bits == 12 { "MCP3208" } else { "MCP3008" };
+        let mut spi: Vec<Box<dyn AdcSource + Send>> = vec![Box::new(Spi::new(Bus::Spi0, SlaveSelect::Ss0, 1_000_000, Mode::Mode0)?)];
+        info!("{} ADC initialized on SPI0.0, oversampling x{}", chip, oversample);
+        if let Some(second) = second_chip {
+            spi.push(Box::new(Spi::new(second.bus(), second.slave_select(), 1_000_000, Mode::Mode0)?));
+            info!("{} ADC initialized on {:?}", chip, second);
+        }
+        Ok(Self::from_sources(spi, oversample, bits))
+    }
+
+    /// Chips in channel order, 8 channels each
+    pub fn from_sources(spi: Vec<Box<dyn AdcSource + Send>>, oversample: u16, bits: u8) -> Self {
+        AdcReader { spi, oversample: oversample.max(1), bits }
     }
 
     fn read_channel(&mut self, channel: u8) -> Result<u16, Box<dyn std::error::Error>> {
-        if channel >= 8 {
-            return Err("Channel must be 0-7".into());
+        let chip = channel as usize / CHANNELS_PER_CHIP;
+        if chip >= self.spi.len() {
+            return Err(format!("Channel must be 0-{}", self.spi.len() * CHANNELS_PER_CHIP - 1).into());
         }
+        let channel = channel % CHANNELS_PER_CHIP as u8;
 
-        let tx_buffer = [
-            0x01,
-            (0x08 | channel) << 4,
-            0x00,
-        ];
+        // Start bit and single-ended mode, then the channel: the MCP3208 has them 2 bits earlier for its 2 more result bits
+        let tx_buffer = if self.bits == 12 {
+            [0x06 | (channel >> 2), (channel & 0x03) << 6, 0x00]
+        } else {
+            [0x01, (0x08 | channel) << 4, 0x00]
+        };
         let mut rx_buffer = [0u8; 3];
 
-        self.spi.transfer(&mut rx_buffer, &tx_buffer)?;
+        self.spi[chip].transfer(&mut rx_buffer, &tx_buffer)?;
         
         let buffer = rx_buffer;
-        let value = (((buffer[1] & 0x03) as u16) << 8) | (buffer[2] as u16);
+        let high_mask = if self.bits == 12 { 0x0F } else { 0x03 };
+        let value = (((buffer[1] & high_mask) as u16) << 8) | (buffer[2] as u16);
         Ok(value)
     }
+    
+    fn read_channel_oversampled(&mut self, channel: u8) -> Result<u16, Box<dyn std::error::Error>> {
+        let mut sum: u32 = 0;
+        for _ in 0..self.oversample {
+            sum += self.read_channel(channel)? as u32;
+        }
+        Ok((sum / self.oversample as u32) as u16)
+    }
 
     pub fn read_all_channels(&mut self) -> Result<[u16; crate::ADC_CHANNELS], Box<dyn std::error::Error>> {
+        // Channels of an absent second chip read as 0
         let mut values = [0u16; crate::ADC_CHANNELS];
-        for channel in 0..crate::ADC_CHANNELS {
-            values[channel] = self.read_channel(channel as u8)?;
+        for channel in 0..(self.spi.len() * CHANNELS_PER_CHIP).min(crate::ADC_CHANNELS) {
+            values[channel] = self.read_channel_oversampled(channel as u8)?;
         }
         Ok(values)
     }
 }
+
+/// Samples all ADC channels at its own rate in a thread, independently of the control loop
+pub struct AdcSampler {
+    values: Arc<Mutex<Option<[u16; crate::ADC_CHANNELS]>>>,
+}
+
+impl AdcSampler {
+    pub fn start(mut reader: AdcReader, sample_rate_hz: u16) -> Result<Self, Box<dyn std::error::Error>> {
+        // First sample read right away, so that values are available when returning
+        let values = Arc::new(Mutex::new(Some(reader.read_all_channels()?)));
+        let period = Duration::from_micros(1_000_000 / sample_rate_hz.max(1) as u64);
+        info!("ADC sampling at {} Hz", sample_rate_hz);
+        
+        let values_clone = Arc::clone(&values);
+        thread::spawn(move || {
+            loop {
+                let sample = match reader.read_all_channels() {
+                    Ok(v) => Some(v),
+                    Err(e) => {
+                        error!("ADC read error: {}", e);
+                        None
+                    }
+                };
+                *values_clone.lock().unwrap() = sample;
+                thread::sleep(period);
+            }
+        });
+        
+        Ok(AdcSampler { values })
+    }
+    
+    /// Latest sampled values
+    pub fn read_all_channels(&self) -> Result<[u16; crate::ADC_CHANNELS], Box<dyn std::error::Error>> {
+        (*self.values.lock().unwrap()).ok_or_else(|| "No ADC sample available".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pizboat_hal::mock::MockAdc;
+
+    #[test]
+    fn test_read_two_chips() {
+        let (first, second) = (MockAdc::default(), MockAdc::default());
+        first.set_value(3, 512);
+        second.set_value(7, 1023);
+        let mut reader = AdcReader::from_sources(vec![Box::new(first), Box::new(second)], 4, 10);
+        let values = reader.read_all_channels().unwrap();
+        assert_eq!(values[3], 512);
+        assert_eq!(values[15], 1023);
+        assert_eq!(values.iter().filter(|&&v| v != 0).count(), 2);
+        assert!(reader.read_channel(16).is_err());
+    }
+
+    #[test]
+    fn test_read_twelve_bits() {
+        let adc = MockAdc::default();
+        adc.set_value(5, 4000);
+        let mut reader = AdcReader::from_sources(vec![Box::new(adc)], 1, 12);
+        assert_eq!(reader.read_all_channels().unwrap()[5], 4000);
+    }
+}
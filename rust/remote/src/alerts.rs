@@ -0,0 +1,93 @@
+use serde::{Serialize, Deserialize};
+
+use crate::event_log;
+
+/// Alarms shown as a banner over the display, most urgent first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Alert {
+    LowBattery,
+    Gust,       // Wind gust of the boat anemometer above gust_alarm_speed
+    Shallow,    // Depth of the boat sonar below shallow_alarm_depth
+    SheetEased,
+    LinkLost,
+    NoBoat,     // No query for no_boat_timeout_ms, over the link lost banner once it lasts
+    Leak,
+}
+
+impl Alert {
+    pub fn text(self) -> &'static str {
+        match self {
+            Alert::LowBattery => "LOW BATTERY",
+            Alert::Gust => "GUST",
+            Alert::Shallow => "SHALLOW",
+            Alert::SheetEased => "SHEET EASED",
+            Alert::LinkLost => "LINK LOST",
+            Alert::NoBoat => "NO BOAT",
+            Alert::Leak => "LEAK!",
+        }
+    }
+}
+
+/// Alerts raised by the control loop, until their condition clears or a button dismisses them
+#[derive(Default)]
+pub struct AlertQueue {
+    active: Vec<Alert>,
+    // Dismissed while the condition is still there, not raised again until it clears
+    dismissed: Vec<Alert>,
+}
+
+impl AlertQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Update an alert from its condition, called every loop
+    pub fn set(&mut self, alert: Alert, condition: bool) {
+        if !condition {
+            self.active.retain(|a| *a != alert);
+            self.dismissed.retain(|a| *a != alert);
+        } else if !self.active.contains(&alert) && !self.dismissed.contains(&alert) {
+            event_log::record(format!("Alert {:?}", alert));
+            self.active.push(alert);
+        }
+    }
+
+    /// Most urgent alert to show
+    pub fn top(&self) -> Option<Alert> {
+        self.active.iter().max().copied()
+    }
+
+    /// Alerts raised and not dismissed, in the order they were raised
+    pub fn active(&self) -> &[Alert] {
+        &self.active
+    }
+
+    pub fn dismiss(&mut self) {
+        if let Some(alert) = self.top() {
+            self.active.retain(|a| *a != alert);
+            self.dismissed.push(alert);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alert_priority_and_dismiss() {
+        let mut alerts = AlertQueue::new();
+        alerts.set(Alert::LowBattery, true);
+        alerts.set(Alert::Leak, true);
+        assert_eq!(alerts.top(), Some(Alert::Leak));
+
+        alerts.dismiss();
+        alerts.set(Alert::Leak, true);
+        assert_eq!(alerts.top(), Some(Alert::LowBattery));
+
+        // Raised again once the condition came back
+        alerts.set(Alert::Leak, false);
+        alerts.set(Alert::Leak, true);
+        assert_eq!(alerts.top(), Some(Alert::Leak));
+    }
+}
@@ -0,0 +1,164 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde_json::json;
+
+use crate::config::{ChannelConfig, Settings};
+use crate::event_log;
+use crate::metrics::Metrics;
+use crate::touch::{TouchFrame, TouchLink};
+use tracing::{error, info};
+
+pub const API_PORT: u16 = 8080;
+// Bodies are a channel or two, refuse anything bigger
+const MAX_BODY: usize = 64 * 1024;
+const API_TIMEOUT_MS: u64 = 2000;
+const TOUCH_PAGE: &str = include_str!("touch.html");
+
+/// Changes requested over HTTP, applied by the control loop which owns the settings
+pub enum ApiRequest {
+    SetChannel(ChannelConfig, Sender<Result<(), String>>),
+    Save(Sender<Result<(), String>>),
+}
+
+/// Small HTTP/JSON API to tune the settings from a laptop:
+///
+/// - GET /settings: remote wide settings
+/// - GET /channels: channels of the current profile
+/// - PUT /channels/<name>: replace a channel, validated
+/// - POST /save: write settings and profile files
+/// - GET /metrics: link and control loop health, for Prometheus
+/// - GET /events: last notable events, newest first
+/// - GET /touch: touch joystick page for a phone, when enabled
+/// - POST /touch: state of the touch page, answered with who has control
+pub fn api_thread(settings_mutex: Arc<Mutex<Option<Settings>>>, requests: Sender<ApiRequest>, metrics: Arc<Metrics>, touch: Option<TouchLink>) {
+    let server = match TcpListener::bind(("0.0.0.0", API_PORT)) {
+        Ok(s) => s,
+        Err(e) => {
+            error!("Could not start settings API: {}", e);
+            return;
+        }
+    };
+    info!("Settings API listening on port {}", API_PORT);
+
+    for stream in server.incoming() {
+        let stream = match stream {
+            Ok(s) => s,
+            Err(e) => {
+                error!("API connection error: {}", e);
+                continue;
+            }
+        };
+        if let Err(e) = handle_connection(stream, &settings_mutex, &requests, &metrics, touch.as_ref()) {
+            error!("API error: {}", e);
+        }
+    }
+}
+
+fn handle_connection(mut stream: TcpStream, settings_mutex: &Mutex<Option<Settings>>, requests: &Sender<ApiRequest>,
+                     metrics: &Metrics, touch: Option<&TouchLink>) -> Result<(), Box<dyn std::error::Error>> {
+    stream.set_read_timeout(Some(Duration::from_millis(API_TIMEOUT_MS)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.trim().eq_ignore_ascii_case("content-length")
+        {
+            content_length = value.trim().parse().unwrap_or(0);
+        }
+    }
+    if content_length > MAX_BODY {
+        return respond(&mut stream, 413, &json!({ "error": "Body too large" }));
+    }
+    let mut body = vec![0u8; content_length];
+    reader.read_exact(&mut body)?;
+
+    // Available before the settings
+    if method == "GET" && path == "/metrics" {
+        return write_response(&mut stream, 200, "text/plain; version=0.0.4", &metrics.render());
+    }
+    if method == "GET" && path == "/events" {
+        return respond(&mut stream, 200, &serde_json::to_value(event_log::recent(usize::MAX))?);
+    }
+    if let Some(touch) = touch.filter(|_| path == "/touch") {
+        return match method.as_str() {
+            "GET" => write_response(&mut stream, 200, "text/html; charset=utf-8", TOUCH_PAGE),
+            "POST" => match serde_json::from_slice::<TouchFrame>(&body) {
+                Ok(frame) => respond(&mut stream, 200, &json!({ "control": touch.post(frame) })),
+                Err(e) => respond(&mut stream, 400, &json!({ "error": e.to_string() })),
+            },
+            _ => respond(&mut stream, 405, &json!({ "error": "Method not allowed" })),
+        };
+    }
+
+    let settings = settings_mutex.lock().unwrap().clone();
+    let Some(settings) = settings else {
+        return respond(&mut stream, 503, &json!({ "error": "Settings not loaded yet" }));
+    };
+
+    match (method.as_str(), path.as_str()) {
+        ("GET", "/settings") => respond(&mut stream, 200, &serde_json::to_value(&settings)?),
+        ("GET", "/channels") => respond(&mut stream, 200, &serde_json::to_value(&settings.channels)?),
+        ("PUT", path) if path.starts_with("/channels/") => {
+            let name = &path["/channels/".len()..];
+            let channel: ChannelConfig = match serde_json::from_slice(&body) {
+                Ok(c) => c,
+                Err(e) => return respond(&mut stream, 400, &json!({ "error": e.to_string() })),
+            };
+            if channel.name != name {
+                return respond(&mut stream, 400, &json!({ "error": "Channel name does not match the path" }));
+            }
+            let (tx, rx) = mpsc::channel();
+            requests.send(ApiRequest::SetChannel(channel, tx))?;
+            reply(&mut stream, rx)
+        }
+        ("POST", "/save") => {
+            let (tx, rx) = mpsc::channel();
+            requests.send(ApiRequest::Save(tx))?;
+            reply(&mut stream, rx)
+        }
+        _ => respond(&mut stream, 404, &json!({ "error": "Not found" })),
+    }
+}
+
+// Outcome from the control loop, which may be stuck in a blocking call
+fn reply(stream: &mut TcpStream, rx: Receiver<Result<(), String>>) -> Result<(), Box<dyn std::error::Error>> {
+    match rx.recv_timeout(Duration::from_millis(API_TIMEOUT_MS)) {
+        Ok(Ok(())) => respond(stream, 200, &json!({ "status": "ok" })),
+        Ok(Err(e)) => respond(stream, 422, &json!({ "error": e })),
+        Err(_) => respond(stream, 503, &json!({ "error": "No answer from the control loop" })),
+    }
+}
+
+fn respond(stream: &mut TcpStream, status: u16, body: &serde_json::Value) -> Result<(), Box<dyn std::error::Error>> {
+    write_response(stream, status, "application/json", &body.to_string())
+}
+
+fn write_response(stream: &mut TcpStream, status: u16, content_type: &str, body: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        422 => "Unprocessable Entity",
+        _ => "Service Unavailable",
+    };
+    write!(stream, "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status, reason, content_type, body.len(), body)?;
+    Ok(())
+}
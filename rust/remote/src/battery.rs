@@ -0,0 +1,68 @@
+use std::time::{Duration, Instant};
+
+use crate::buzzer::{self, BuzzerEvent};
+use crate::config::Settings;
+use tracing::info;
+
+// Weight of a new reading in the smoothed voltage, the ADC is noisy under load
+const BATTERY_SMOOTHING: f32 = 0.05;
+// Below this many ADC counts, nothing is wired to the battery input
+const BATTERY_MIN_ADC: u16 = 10;
+// Voltage to climb back above the threshold to clear the warning
+const BATTERY_HYSTERESIS: f32 = 0.1;
+// Beep reminder while the battery stays low
+const BATTERY_BEEP_PERIOD: Duration = Duration::from_secs(30);
+
+/// Transmitter battery voltage, measured through a divider on an ADC input
+pub struct BatteryMonitor {
+    voltage: Option<f32>,
+    low: bool,
+    last_beep: Option<Instant>,
+}
+
+impl BatteryMonitor {
+    pub fn new() -> Self {
+        BatteryMonitor { voltage: None, low: false, last_beep: None }
+    }
+
+    pub fn update(&mut self, adc_value: u16, settings: &Settings) {
+        if adc_value < BATTERY_MIN_ADC {
+            self.voltage = None;
+            self.low = false;
+            return;
+        }
+        let reading = adc_value as f32 * settings.battery_scale;
+        let voltage = match self.voltage {
+            Some(v) => v + (reading - v) * BATTERY_SMOOTHING,
+            None => reading,
+        };
+        self.voltage = Some(voltage);
+
+        if voltage < settings.battery_low_voltage && !self.low {
+            info!("[EVENT] Remote battery low: {:.2}V", voltage);
+            self.low = true;
+        } else if voltage > settings.battery_low_voltage + BATTERY_HYSTERESIS {
+            self.low = false;
+        }
+        
+        if self.low && self.last_beep.is_none_or(|t| t.elapsed() >= BATTERY_BEEP_PERIOD) {
+            buzzer::play(BuzzerEvent::LowBattery);
+            self.last_beep = Some(Instant::now());
+        }
+    }
+
+    pub fn voltage(&self) -> Option<f32> {
+        self.voltage
+    }
+
+    pub fn is_low(&self) -> bool {
+        self.low
+    }
+
+    /// Charge estimate between the empty and full voltages, linear which is rough but enough for a bar
+    pub fn percent(&self, settings: &Settings) -> u8 {
+        let Some(voltage) = self.voltage else { return 0 };
+        let range = (settings.battery_full_voltage - settings.battery_empty_voltage).max(0.1);
+        ((voltage - settings.battery_empty_voltage) / range * 100.).clamp(0., 100.) as u8
+    }
+}
@@ -0,0 +1,69 @@
+use std::fs;
+use std::process::Command;
+use tracing::info;
+
+const INPUT_DEVICES: &str = "/proc/bus/input/devices";
+
+/// Pair a gamepad through BlueZ on first use, then connect it. bluetoothctl makes the D-Bus calls,
+/// the gamepad having to be in pairing mode for the first time.
+pub fn connect(address: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let info = bluetoothctl(&["info", address])?;
+    if !info.contains("Paired: yes") {
+        info!("Pairing Bluetooth gamepad {}", address);
+        bluetoothctl(&["--timeout", "20", "scan", "on"])?;
+        bluetoothctl(&["pair", address])?;
+        // Trusted devices may reconnect on their own
+        bluetoothctl(&["trust", address])?;
+    }
+    if !info.contains("Connected: yes") {
+        bluetoothctl(&["connect", address])?;
+    }
+    Ok(())
+}
+
+/// Joystick device of a connected Bluetooth gamepad, such as /dev/input/js1
+pub fn joystick_device(address: &str) -> Option<String> {
+    find_joystick(&fs::read_to_string(INPUT_DEVICES).ok()?, address)
+}
+
+fn bluetoothctl(args: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
+    let output = Command::new("bluetoothctl").args(args).output()?;
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    if !output.status.success() {
+        return Err(format!("bluetoothctl {} failed: {}", args.join(" "), stdout.lines().last().unwrap_or_default()).into());
+    }
+    Ok(stdout)
+}
+
+// One block per device, Uniq holding the Bluetooth address of wireless ones:
+// U: Uniq=e4:17:d8:12:34:56
+// H: Handlers=event4 js1
+fn find_joystick(devices: &str, address: &str) -> Option<String> {
+    devices.split("\n\n").find(|block| {
+        block.lines().any(|line| line.strip_prefix("U: Uniq=").is_some_and(|uniq| uniq.trim().eq_ignore_ascii_case(address)))
+    })?.lines()
+        .filter_map(|line| line.strip_prefix("H: Handlers="))
+        .flat_map(str::split_whitespace)
+        .find(|handler| handler.starts_with("js"))
+        .map(|handler| format!("/dev/input/{}", handler))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_joystick() {
+        let devices = "I: Bus=0003 Vendor=045e Product=028e Version=0114\n\
+                       N: Name=\"Xbox 360 Controller\"\n\
+                       U: Uniq=\n\
+                       H: Handlers=event3 js0\n\
+                       \n\
+                       I: Bus=0005 Vendor=2dc8 Product=6001 Version=0001\n\
+                       N: Name=\"8BitDo SN30 Pro\"\n\
+                       U: Uniq=e4:17:d8:12:34:56\n\
+                       H: Handlers=event4 js1\n";
+        assert_eq!(find_joystick(devices, "E4:17:D8:12:34:56").as_deref(), Some("/dev/input/js1"));
+        assert_eq!(find_joystick(devices, "00:11:22:33:44:55"), None);
+    }
+}
@@ -0,0 +1,81 @@
+use std::fmt;
+use std::sync::mpsc::SyncSender;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::buttons::{ButtonReader, Edge};
+use crate::display::{BootCheck, DisplayMessage};
+use tracing::{error, info};
+
+// How long failures stay on the boot screen, any button going on earlier
+const FAILURE_HOLD: Duration = Duration::from_secs(10);
+
+/// Reports each hardware init step to the boot screen as startup goes
+pub struct BootReporter {
+    tx: SyncSender<DisplayMessage>,
+    failed: bool,
+}
+
+impl BootReporter {
+    pub fn new(tx: SyncSender<DisplayMessage>) -> Self {
+        BootReporter { tx, failed: false }
+    }
+
+    fn report(&mut self, name: &'static str, error: Option<String>) {
+        match &error {
+            Some(e) => {
+                error!("[BOOT] {} FAILED: {}", name, e);
+                self.failed = true;
+            }
+            None => info!("[BOOT] {} ok", name),
+        }
+        // Blocking, so that no step is lost; fails right away without a display
+        let _ = self.tx.send(DisplayMessage::Boot(BootCheck { name, error }));
+    }
+
+    /// Step the remote can run without, None when it failed
+    pub fn check<T, E: fmt::Display>(&mut self, name: &'static str, result: Result<T, E>) -> Option<T> {
+        match result {
+            Ok(value) => {
+                self.report(name, None);
+                Some(value)
+            }
+            Err(e) => {
+                self.report(name, Some(e.to_string()));
+                None
+            }
+        }
+    }
+
+    /// Step the remote cannot run without, the failure stays on screen before the error is returned
+    pub fn fatal<T, E>(&mut self, name: &'static str, result: Result<T, E>) -> Result<T, Box<dyn std::error::Error>>
+    where E: Into<Box<dyn std::error::Error>> + fmt::Display {
+        match result {
+            Ok(value) => {
+                self.report(name, None);
+                Ok(value)
+            }
+            Err(e) => {
+                self.report(name, Some(e.to_string()));
+                thread::sleep(FAILURE_HOLD);
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Keep the failures on screen until a button is pressed or the hold time is over
+    pub fn hold_failures(&self, button_reader: &mut ButtonReader) {
+        if !self.failed {
+            return;
+        }
+        info!("[BOOT] Holding failures on screen, press any button to go on");
+        let start = Instant::now();
+        while start.elapsed() < FAILURE_HOLD {
+            // Presses are reported on release, which leaves nothing for the main loop to act on
+            if button_reader.read_and_detect_edges().iter().any(|(_, edge)| *edge != Edge::Rising) {
+                break;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+}
@@ -1,12 +1,21 @@
-use rppal::gpio::{Gpio, InputPin, Level};
+use pizboat_hal::DigitalIn;
+use rppal::gpio::{Gpio, Level};
+use std::sync::mpsc::{self, Receiver, Sender};
 use std::time::{Duration, Instant};
+use tracing::debug;
 
 const DEBOUNCE_MS: u64 = 50;
+// Held for that long, a press is a long press and its release is not reported
+const LONG_PRESS_MS: u64 = 800;
+// A second release within that delay is a double click, reported instead of the second Falling
+const DOUBLE_CLICK_MS: u64 = 400;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Edge {
     Rising,
     Falling,
+    LongPress,
+    DoubleClick,
 }
 
 struct ButtonState {
@@ -14,6 +23,8 @@ struct ButtonState {
     last_stable: Level,
     last_change: Instant,
     press_start: Option<Instant>,
+    long_pressed: bool,
+    last_click: Option<Instant>,
 }
 
 impl ButtonState {
@@ -23,67 +34,142 @@ impl ButtonState {
             last_stable: Level::Low,
             last_change: Instant::now(),
             press_start: None,
+            long_pressed: false,
+            last_click: None,
         }
     }
 
-    fn update(&mut self, new_level: Level) -> Option<Edge> {
-        if new_level != self.current {
-            self.current = new_level;
-            self.last_change = Instant::now();
-            return None;
+    // Level change reported by the interrupt, first settling the previous level
+    fn change(&mut self, level: Level, at: Instant) -> Option<Edge> {
+        let edge = self.settle(at);
+        if level != self.current {
+            self.current = level;
+            self.last_change = at;
         }
+        edge
+    }
 
-        if self.last_change.elapsed() >= Duration::from_millis(DEBOUNCE_MS)
+    // Commit the current level once stable for the debounce time, and detect long presses
+    fn settle(&mut self, now: Instant) -> Option<Edge> {
+        if now.duration_since(self.last_change) >= Duration::from_millis(DEBOUNCE_MS)
             && self.current != self.last_stable
         {
             let edge = if self.current == Level::High {
-                self.press_start = Some(Instant::now());
+                self.press_start = Some(self.last_change);
                 Some(Edge::Rising)
             } else {
                 self.press_start = None;
-                Some(Edge::Falling)
+                if self.long_pressed {
+                    None
+                } else if self.last_click.is_some_and(|t| self.last_change.duration_since(t) < Duration::from_millis(DOUBLE_CLICK_MS)) {
+                    self.last_click = None;
+                    Some(Edge::DoubleClick)
+                } else {
+                    self.last_click = Some(self.last_change);
+                    Some(Edge::Falling)
+                }
             };
+            self.long_pressed = false;
             self.last_stable = self.current;
             return edge;
         }
 
+        if !self.long_pressed && self.press_start.is_some_and(|t| now.duration_since(t) >= Duration::from_millis(LONG_PRESS_MS)) {
+            self.long_pressed = true;
+            return Some(Edge::LongPress);
+        }
+
         None
     }
 }
 
 pub struct ButtonReader {
-    pins: Vec<InputPin>,
+    // Kept for their interrupt to stay registered
+    _pins: Vec<Box<dyn DigitalIn>>,
     states: Vec<ButtonState>,
+    // Level changes (button, level, time) pushed by the interrupt handlers
+    events: Receiver<(usize, Level, Instant)>,
+    sender: Sender<(usize, Level, Instant)>,
 }
 
 impl ButtonReader {
     pub fn new(pin_numbers: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
         let gpio = Gpio::new()?;
-        let mut pins = Vec::new();
-        let mut states = Vec::new();
-
+        let mut pins: Vec<Box<dyn DigitalIn>> = Vec::new();
         for &pin_num in pin_numbers {
-            let pin = gpio.get(pin_num)?.into_input_pulldown();
-            println!("GPIO Button {} initialized", pin_num);
-            pins.push(pin);
-            states.push(ButtonState::new());
+            pins.push(Box::new(gpio.get(pin_num)?.into_input_pulldown()));
+            debug!("GPIO Button {} initialized", pin_num);
         }
-
-        Ok(ButtonReader { pins, states })
+        Self::from_pins(pins)
+    }
+    
+    /// Buttons on any input, pressed when high
+    pub fn from_pins(mut pins: Vec<Box<dyn DigitalIn>>) -> Result<Self, Box<dyn std::error::Error>> {
+        let (tx, events) = mpsc::channel();
+        for (i, pin) in pins.iter_mut().enumerate() {
+            let tx = tx.clone();
+            pin.set_interrupt(Box::new(move |high| {
+                let _ = tx.send((i, if high { Level::High } else { Level::Low }, Instant::now()));
+            }))?;
+        }
+        let states = pins.iter().map(|_| ButtonState::new()).collect();
+        Ok(ButtonReader { _pins: pins, states, events, sender: tx })
+    }
+    
+    /// Buttons only fed through event_sender(), without GPIO
+    pub fn simulated(count: usize) -> Self {
+        let (sender, events) = mpsc::channel();
+        ButtonReader { _pins: Vec::new(), states: (0..count).map(|_| ButtonState::new()).collect(), events, sender }
+    }
+    
+    /// Feed level changes from another input, like a gamepad
+    pub fn event_sender(&self) -> Sender<(usize, Level, Instant)> {
+        self.sender.clone()
     }
 
-    pub fn read_and_detect_edges(&mut self) -> Vec<Option<Edge>> {
-        self.pins
-            .iter()
-            .enumerate()
-            .map(|(i, pin)| {
-                let level = pin.read();
-                self.states[i].update(level)
-            })
-            .collect()
+    /// Edges since the last call, in order, as (button, edge)
+    pub fn read_and_detect_edges(&mut self) -> Vec<(usize, Edge)> {
+        let mut edges = Vec::new();
+        while let Ok((i, level, at)) = self.events.try_recv() {
+            if let Some(edge) = self.states.get_mut(i).and_then(|state| state.change(level, at)) {
+                edges.push((i, edge));
+            }
+        }
+        let now = Instant::now();
+        for (i, state) in self.states.iter_mut().enumerate() {
+            if let Some(edge) = state.settle(now) {
+                edges.push((i, edge));
+            }
+        }
+        edges
     }
 
     pub fn get_current_states(&self) -> Vec<bool> {
         self.states.iter().map(|s| s.last_stable == Level::High).collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pizboat_hal::mock::MockPin;
+
+    #[test]
+    fn test_debounced_press() {
+        let pin = MockPin::default();
+        let mut reader = ButtonReader::from_pins(vec![Box::new(MockPin::default()), Box::new(pin.clone())]).unwrap();
+
+        // Bounces within the debounce time make a single press
+        pin.set_level(true);
+        pin.set_level(false);
+        pin.set_level(true);
+        assert!(reader.read_and_detect_edges().is_empty());
+        std::thread::sleep(Duration::from_millis(DEBOUNCE_MS + 10));
+        assert_eq!(reader.read_and_detect_edges(), [(1, Edge::Rising)]);
+
+        pin.set_level(false);
+        std::thread::sleep(Duration::from_millis(DEBOUNCE_MS + 10));
+        assert_eq!(reader.read_and_detect_edges(), [(1, Edge::Falling)]);
+        assert_eq!(reader.get_current_states(), [false, false]);
+    }
+}
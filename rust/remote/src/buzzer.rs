@@ -0,0 +1,71 @@
+use rppal::gpio::{Gpio, OutputPin};
+use std::sync::OnceLock;
+use std::sync::mpsc::{self, SyncSender, Receiver};
+use std::thread;
+use std::time::Duration;
+use tracing::{error, info};
+
+// Events waiting to be played, further ones are dropped
+const BUZZER_QUEUE: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BuzzerEvent {
+    ButtonPress,
+    ModeChange,
+    LinkLost,
+    NoBoat,
+    LowBattery,
+    TelemetryAlarm,
+    RaceSignal,
+    RaceStart
+}
+
+// Notes of a pattern: (frequency in Hz, on time in ms, silence after in ms)
+fn pattern(event: BuzzerEvent) -> &'static [(f64, u64, u64)] {
+    match event {
+        BuzzerEvent::ButtonPress => &[(4000., 15, 0)],
+        BuzzerEvent::ModeChange => &[(2000., 40, 30), (3000., 40, 0)],
+        BuzzerEvent::LinkLost => &[(1500., 300, 100), (1000., 500, 0)],
+        BuzzerEvent::NoBoat => &[(1000., 200, 100), (1000., 200, 100), (800., 600, 0)],
+        BuzzerEvent::LowBattery => &[(2500., 100, 100), (2500., 100, 100), (2500., 100, 0)],
+        BuzzerEvent::TelemetryAlarm => &[(3500., 150, 50), (2500., 150, 50), (3500., 150, 50), (2500., 150, 0)],
+        BuzzerEvent::RaceSignal => &[(3000., 200, 0)],
+        BuzzerEvent::RaceStart => &[(3000., 1000, 0)],
+    }
+}
+
+static BUZZER: OnceLock<SyncSender<BuzzerEvent>> = OnceLock::new();
+
+/// Start the buzzer thread on a GPIO, tones are made with software PWM
+pub fn start(pin_number: u8) -> Result<(), Box<dyn std::error::Error>> {
+    let pin = Gpio::new()?.get(pin_number)?.into_output_low();
+    let (tx, rx) = mpsc::sync_channel(BUZZER_QUEUE);
+    if BUZZER.set(tx).is_err() {
+        return Err("Buzzer already started".into());
+    }
+    info!("Buzzer initialized on GPIO {}", pin_number);
+
+    thread::spawn(move || buzzer_thread(pin, rx));
+    Ok(())
+}
+
+/// Queue the pattern of an event, does nothing if the buzzer is not started or busy
+pub fn play(event: BuzzerEvent) {
+    if let Some(tx) = BUZZER.get() {
+        let _ = tx.try_send(event);
+    }
+}
+
+fn buzzer_thread(mut pin: OutputPin, rx: Receiver<BuzzerEvent>) {
+    for event in rx {
+        for &(frequency, on_ms, off_ms) in pattern(event) {
+            if let Err(e) = pin.set_pwm_frequency(frequency, 0.5) {
+                error!("Buzzer error: {}", e);
+            }
+            thread::sleep(Duration::from_millis(on_ms));
+            let _ = pin.clear_pwm();
+            pin.set_low();
+            thread::sleep(Duration::from_millis(off_ms));
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use crate::config::ChannelConfig;
+
+// Travel (in ADC counts) needed on each side of center for an input to be calibrated
+const MIN_TRAVEL: u16 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CalibrationStep {
+    Center,     // Waiting for the sticks to be centered
+    Extremes,   // Recording the extremes while the sticks are moved around
+    Done
+}
+
+/// State of the stick calibration wizard, per ADC input
+#[derive(Debug, Clone, PartialEq)]
+pub struct Calibration {
+    pub step: CalibrationStep,
+    pub current: Vec<u16>,
+    pub center: Vec<u16>,
+    pub min: Vec<u16>,
+    pub max: Vec<u16>,
+}
+
+impl Calibration {
+    pub fn new() -> Self {
+        Calibration {
+            step: CalibrationStep::Center,
+            current: Vec::new(),
+            center: Vec::new(),
+            min: Vec::new(),
+            max: Vec::new(),
+        }
+    }
+
+    pub fn update(&mut self, adc_values: &[u16]) {
+        self.current = adc_values.to_vec();
+        if self.step == CalibrationStep::Extremes {
+            for (i, &value) in adc_values.iter().enumerate() {
+                self.min[i] = self.min[i].min(value);
+                self.max[i] = self.max[i].max(value);
+            }
+        }
+    }
+
+    pub fn next_step(&mut self) {
+        match self.step {
+            CalibrationStep::Center => {
+                self.center = self.current.clone();
+                self.min = self.current.clone();
+                self.max = self.current.clone();
+                self.step = CalibrationStep::Extremes;
+            }
+            CalibrationStep::Extremes => { self.step = CalibrationStep::Done; }
+            CalibrationStep::Done => {}
+        }
+    }
+
+    /// Store the recorded range of an ADC input in a channel, false if the stick was not moved enough
+    pub fn apply(&self, input: usize, channel: &mut ChannelConfig) -> bool {
+        if input >= self.center.len() {
+            return false;
+        }
+        let (min, center, max) = (self.min[input], self.center[input], self.max[input]);
+        if center - min < MIN_TRAVEL || max - center < MIN_TRAVEL {
+            return false;
+        }
+        channel.adc_min = min;
+        channel.adc_center = center;
+        channel.adc_max = max;
+        true
+    }
+}
@@ -1,100 +1,178 @@
 use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
 use std::fs;
 use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 
-#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+use crate::mixer::{self, MixerRule};
+use crate::calibration::{Calibration, CalibrationStep};
+use crate::event_log;
+pub use pizboat_channel::ChannelConfig;
+use pizboat_channel::{LINEAR_CURVE, OUTPUT_MIN, OUTPUT_MAX, TRAVEL_MAX_MS};
+use pizboat_display::{DC_PIN, RST_PIN};
+use rppal::spi::{Bus, SlaveSelect};
+use tracing::{error, info, warn};
+
+const DEFAULT_PROFILE: &str = "default";
+// Snapshot names offered even before they are saved
+const SNAPSHOT_NAMES: [&str; 3] = ["light-wind", "medium-wind", "heavy-wind"];
+
+// System page steps
+const CONTRAST_STEP: u8 = 16;
+const DIM_DELAY_STEP_S: u16 = 10;
+const DIM_DELAY_MAX_S: u16 = 600;
+// Boats one remote can be switched between
+const BOAT_ID_MAX: u8 = 4;
+// Full travel time change of one menu press
+const TRAVEL_STEP_MS: u16 = 100;
+// Control loop period the per-update step of version 1 files was tuned at
+const LEGACY_LOOP_MS: u32 = 40;
+
+// Version of the settings and profile files, bump it when a migration step is added in migrate()
+const SETTINGS_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
 pub enum ControlMode {
+    #[default]
     Normal,
     Settings,
-    SettingsValue
+    SettingsValue,
+    Profile,
+    Snapshot,
+    Calibration,
+    System,     // Remote wide settings, not tied to a profile
+    Factory     // Factory defaults of the current channel, which can be restored
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub struct ChannelConfig {
-    pub name: String,
-    pub deadzone: u16,    // Deadzone around center (512)
-    pub center: u16,      // Center output value
-    pub min: u16,         // Minimum output value
-    pub max: u16,         // Maximum output value
-    pub step: u16,    // Maximum change in values between two updates
-    
-    previous_value: u16
+/// Source of the stick values and buttons
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum InputBackend {
+    Adc,        // Pots on the MCP3008 and GPIO buttons
+    Gamepad,    // USB gamepad, GPIO buttons still working
+    Ppm,        // Transmitter on the trainer port, over the pots while it sends
+    Bluetooth,  // Paired Bluetooth gamepad, over the pots while connected
+    Sim         // Commands on stdin, without any GPIO (--sim)
+}
+
+/// Radio carrying the boat link
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum LinkTransport {
+    WebSocket,  // Over the Wi-Fi access point
+    Nrf24       // nRF24L01 on nrf24_spi, for range, the tools still using Wi-Fi
+}
+
+/// Commands sent to the boat
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum CommandEncoding {
+    Full,   // Every field in every message
+    Delta   // Fields changed since the last keyframe, with a full keyframe every period
+}
+
+/// File format of the telemetry log
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum TelemetryFormat {
+    Off,
+    Csv,
+    Jsonl
+}
+
+/// Wiring of the OLED display
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum DisplayBus {
+    I2c,    // I2C bus 1, address 0x3C
+    Spi,    // On display_spi with DC and RST pins, keeps I2C free for sensors
+    Terminal    // Drawn on stdout, for desktop development
 }
 
-impl ChannelConfig {
-    fn new(_name: &'static str) -> Self {
-        ChannelConfig {
-            name: String::from(_name),
-            deadzone: 50,
-            min: 1000,
-            max: 2000,
-            center: 1500,
-            step: 100,
-            previous_value: 1500
+/// SPI bus and chip select of a device, each serving a single device
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SpiDevice {
+    Spi0Ce0,    // First ADC chip
+    Spi0Ce1,
+    // SPI1 takes GPIO 16 to 21 with dtoverlay=spi1-3cs, the LEDs, a button and the display RST pin moving off them
+    Spi1Ce0,
+    Spi1Ce1,
+    Spi1Ce2,
+}
+
+impl SpiDevice {
+    pub fn bus(self) -> Bus {
+        match self {
+            SpiDevice::Spi0Ce0 | SpiDevice::Spi0Ce1 => Bus::Spi0,
+            SpiDevice::Spi1Ce0 | SpiDevice::Spi1Ce1 | SpiDevice::Spi1Ce2 => Bus::Spi1,
         }
     }
-}
 
-impl ChannelConfig {
-    pub fn transform_adc(&mut self, adc_value: u16) -> u16 {
-        let center_adc = 512;
-        let adc = adc_value as i32;
-        let center = center_adc as i32;
-        
-        // Apply deadzone
-        if (adc - center).abs() < self.deadzone as i32 {
-            let output = self.center.clamp(self.previous_value - self.step, self.previous_value + self.step);
-            self.previous_value = output;
-            return output;
+    pub fn slave_select(self) -> SlaveSelect {
+        match self {
+            SpiDevice::Spi0Ce0 | SpiDevice::Spi1Ce0 => SlaveSelect::Ss0,
+            SpiDevice::Spi0Ce1 | SpiDevice::Spi1Ce1 => SlaveSelect::Ss1,
+            SpiDevice::Spi1Ce2 => SlaveSelect::Ss2,
         }
-        
-        let mut output: u16;
-        
-        // Map ADC range to output range
-        if adc > center {
-            // Above center: map [center+deadzone, 1023] to [center, max]
-            let adc_range = 1023 - (center + self.deadzone as i32);
-            let out_range = self.max as i32 - self.center as i32;
-            let normalized = (adc - center - self.deadzone as i32).max(0);
-            output = (self.center as i32 + (normalized * out_range / adc_range)) as u16;
-            // output = output.clamp(self.center as i32, self.max as i32) as u16
-        } else {
-            // Below center: map [0, center-deadzone] to [min, center]
-            let adc_range = center - self.deadzone as i32;
-            let out_range = self.center as i32 - self.min as i32;
-            let normalized = (center - self.deadzone as i32 - adc).max(0);
-            output = (self.center as i32 - (normalized * out_range / adc_range)) as u16;
-            // output = output.clamp(self.min as i32, self.center as i32) as u16
+    }
+}
+
+/// Value shown on the OctLed bar graph
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum LedGauge {
+    Weight,
+    Throttle,
+    LinkQuality,
+    BoatBattery
+}
+
+impl LedGauge {
+    fn next(self) -> Self {
+        match self {
+            LedGauge::Weight => LedGauge::Throttle,
+            LedGauge::Throttle => LedGauge::LinkQuality,
+            LedGauge::LinkQuality => LedGauge::BoatBattery,
+            LedGauge::BoatBattery => LedGauge::Weight,
         }
-        
-        output = output.clamp(self.min, self.max);
-        output = output.clamp(self.previous_value - self.step, self.previous_value + self.step);
-        
-        self.previous_value = output;
-        
-        return output
     }
     
-    pub fn apply_button(&self, up: bool, down: bool, adc_value: u16) -> u16 {
-        let out_range = self.max as u32 - self.min as u32;
-        let diff = ((adc_value as u32 * out_range) / 1024) as u16;
-        
-        // eprintln!("adc_value {} diff {}", adc_value, diff);
-        
-        if up {
-            self.center + diff
-        }
-        else if down {
-            self.center - diff
-        }
-        else {
-            self.center
+    fn previous(self) -> Self {
+        match self {
+            LedGauge::Weight => LedGauge::BoatBattery,
+            LedGauge::Throttle => LedGauge::Weight,
+            LedGauge::LinkQuality => LedGauge::Throttle,
+            LedGauge::BoatBattery => LedGauge::LinkQuality,
         }
     }
 }
 
+/// Value edited on the System page
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SystemItem {
+    #[default]
+    LedGauge,
+    Contrast,
+    DimDelay,
+    Boat        // Boat driven by the remote
+}
 
+impl SystemItem {
+    fn next(self) -> Self {
+        match self {
+            SystemItem::LedGauge => SystemItem::Contrast,
+            SystemItem::Contrast => SystemItem::DimDelay,
+            SystemItem::DimDelay => SystemItem::Boat,
+            SystemItem::Boat => SystemItem::LedGauge,
+        }
+    }
+    
+    fn previous(self) -> Self {
+        match self {
+            SystemItem::LedGauge => SystemItem::Boat,
+            SystemItem::Contrast => SystemItem::LedGauge,
+            SystemItem::DimDelay => SystemItem::Contrast,
+            SystemItem::Boat => SystemItem::DimDelay,
+        }
+    }
+}
 
+// Logical navigation buttons, mapped to physical buttons by the ButtonMap
 const BUTTON_CANCEL_MODE: usize = 0;
 const BUTTON_UP: usize = 1;
 const BUTTON_CHANGE_MODE: usize = 2;
@@ -102,13 +180,179 @@ const BUTTON_LEFT: usize = 3;
 const BUTTON_DOWN: usize = 4;
 const BUTTON_RIGHT: usize = 5;
 
+/// Physical button index of each logical action, for the enclosure layout.
+///
+/// Navigation buttons are used in settings pages, sail buttons in normal mode, so they overlap.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ButtonMap {
+    pub cancel: usize,
+    pub up: usize,
+    pub change_mode: usize,
+    pub left: usize,
+    pub down: usize,
+    pub right: usize,
+    pub boom_up: usize,
+    pub boom_down: usize,
+    pub genoa_up: usize,
+    pub genoa_down: usize,
+}
+
+impl Default for ButtonMap {
+    fn default() -> Self {
+        ButtonMap {
+            cancel: 0, up: 1, change_mode: 2, left: 3, down: 4, right: 5,
+            boom_up: 0, boom_down: 3, genoa_up: 1, genoa_down: 4,
+        }
+    }
+}
+
+impl ButtonMap {
+    /// Logical navigation button of a physical button
+    fn navigation(&self, physical: usize) -> Option<usize> {
+        [self.cancel, self.up, self.change_mode, self.left, self.down, self.right]
+            .iter().position(|&b| b == physical)
+    }
+    
+    pub fn is_valid(&self, button_count: usize) -> bool {
+        [self.cancel, self.up, self.change_mode, self.left, self.down, self.right,
+         self.boom_up, self.boom_down, self.genoa_up, self.genoa_down]
+            .iter().all(|&b| b < button_count)
+    }
+}
+
+// Users of the same resource, named with what they share
+fn shared<T: PartialEq>(users: &[(&str, T)], describe: impl Fn(&T) -> String) -> Option<String> {
+    users.iter().find_map(|(_, used)| {
+        let sharing: Vec<&str> = users.iter().filter(|(_, other)| other == used).map(|(name, _)| *name).collect();
+        (sharing.len() > 1).then(|| format!("{} all use {}", sharing.join(", "), describe(used)))
+    })
+}
+
+/// Channel setup of one boat/rig, stored in its own file under profiles/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Profile {
+    #[serde(default)]
+    version: u32,
+    name: String,
+    channels: Vec<ChannelConfig>,
+    #[serde(default)]
+    mixer: Vec<MixerRule>,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Settings {
+    #[serde(default)]
+    version: u32,
     pub mode: ControlMode,
     settings_path: String,
+    #[serde(default = "default_profile")]
+    pub profile: String,
+    // Channels are saved in the profile file, only read here from older settings files
+    #[serde(default, skip_serializing)]
     pub channels: Vec<ChannelConfig>,
+    #[serde(default, skip_serializing)]
+    pub mixer: Vec<MixerRule>,
     current_channel: usize,
-    pub current_value: SettingsValue
+    pub current_value: SettingsValue,
+    
+    // ADC sampling, done independently of the control loop
+    pub adc_oversample: u16,    // Conversions averaged per sample
+    pub adc_sample_rate: u16,   // Samples per second
+    pub adc_second_chip: bool,  // Second MCP3008 on adc_second_spi for channels 8-15, the first one being on SPI0 CE0
+    pub adc_second_spi: SpiDevice,
+    pub adc_bits: u8,           // ADC resolution, 10 for MCP3008 chips, 12 for MCP3208
+    
+    pub input: InputBackend,
+    pub gamepad_axes: Vec<usize>,       // ADC input fed by each gamepad axis
+    pub gamepad_buttons: Vec<usize>,    // Remote button pressed by each gamepad button
+    pub bluetooth_gamepad: String,      // Address of the Bluetooth gamepad, such as "E4:17:D8:12:34:56"
+    pub touch_control: bool,            // Touch joystick page served on the API port, as a backup controller
+    pub ppm_channels: Vec<usize>,       // ADC input fed by each trainer port channel
+    
+    // Transmitter battery, 2S LiPo by default
+    pub battery_scale: f32,         // Volts per ADC count, divider included
+    pub battery_empty_voltage: f32,
+    pub battery_full_voltage: f32,
+    pub battery_low_voltage: f32,   // Warning threshold
+    
+    pub race_countdown_s: u16,      // Race timer countdown before the start
+    
+    pub no_boat_timeout_ms: u32,    // Time without any boat query before the NO BOAT alarm
+    pub failsafe_timeout_ms: u32,   // Time without any command before the boat applies the failsafe values, sent to it with them
+    pub gust_alarm_speed: f32,      // Wind gust measured by the boat, in m/s, above which the GUST alarm rises, 0 for none
+    pub shallow_alarm_depth: f32,   // Depth under the boat, in m, below which the SHALLOW alarm rises, 0 for none
+    
+    pub telemetry_format: TelemetryFormat,
+    pub telemetry_period_ms: u32,   // Time between two logged records
+    pub telemetry_max_kb: u32,      // A new file is started past this size
+    pub telemetry_max_files: u16,   // Oldest files are removed past this count
+    
+    pub mqtt_broker: String,        // host:port of the broker receiving the telemetry, empty for none
+    pub mqtt_period_ms: u32,        // Time between two published records
+    
+    pub signalk_server: String,     // host:port of the SignalK server receiving the boat deltas, empty for none
+    pub signalk_period_ms: u32,     // Time between two deltas
+    
+    pub udp_broadcast: String,      // Broadcast address:port of the compact telemetry packets, empty for none
+    pub udp_period_ms: u32,         // Time between two packets
+    
+    pub boat_id: u8,                    // Boat driven, 1 to 4, the boat_id of its boat.json, commands of other ids being ignored
+    pub link_transport: LinkTransport,
+    pub command_encoding: CommandEncoding,
+    pub keyframe_period_ms: u32,        // Time between two full commands in Delta encoding
+    pub nrf24_channel: u8,              // 0 to 125 for 2400 to 2525 MHz, same on the boat
+    pub nrf24_spi: SpiDevice,
+    
+    pub lora_enabled: bool,             // SX127x backup link on lora_spi
+    pub lora_spi: SpiDevice,
+    pub lora_frequency_hz: u32,         // Same on the boat, 868.1 MHz in Europe, 915 MHz in America
+    pub lora_failover_latency_ms: u32,  // Wi-Fi latency above which LoRa takes over
+    pub lora_fallback_ms: u32,          // Time of good Wi-Fi before LoRa is released
+    
+    pub log_filter: String,         // Levels per module, such as "info,PizRemote::websocket=debug", RUST_LOG taking over
+    pub log_dir: String,            // Directory of the daily log files, empty to only log on stderr
+    pub log_max_files: u16,         // Oldest log files are removed past this count
+    
+    pub led_gauge: LedGauge,
+    
+    pub display_bus: DisplayBus,
+    pub display_spi: SpiDevice,
+    pub display_contrast: u8,   // SSD1306 contrast, 0-255
+    pub display_dim_s: u16,     // Idle time before dimming the display, 0 to never dim
+    
+    pub button_map: ButtonMap,
+    
+    // Lights step cycled from the remote: 0 off, 1 nav lights, 2 + deck light, 3 + spotlight
+    #[serde(skip)]
+    pub lights: u8,
+    
+    // System page state
+    #[serde(skip)]
+    pub system_item: SystemItem,
+    
+    // Profile page state
+    #[serde(skip)]
+    pub profiles: Vec<String>,
+    #[serde(skip)]
+    selected_profile: usize,
+    
+    // Snapshot page state
+    #[serde(skip)]
+    pub snapshots: Vec<String>,
+    #[serde(skip)]
+    selected_snapshot: usize,
+    
+    // Stick calibration wizard state
+    #[serde(skip)]
+    pub calibration: Option<Calibration>,
+    
+    // Page on which the last click landed, for a double click to act on it rather than on where the click led
+    #[serde(skip)]
+    clicked_mode: ControlMode
+}
+
+fn default_profile() -> String {
+    String::from(DEFAULT_PROFILE)
 }
 
 impl Settings {
@@ -120,8 +364,53 @@ impl Settings {
         channels.push(ChannelConfig::new("Boom"));
         channels.push(ChannelConfig::new("Genoa"));
         channels.push(ChannelConfig::new("Misc"));
+        channels.push(ChannelConfig::new("BowThruster"));
+        channels.push(ChannelConfig::new("GimbalPan"));
+        channels.push(ChannelConfig::new("GimbalTilt"));
         
-        Settings{mode: ControlMode::Normal, settings_path: settings_path.to_string(), channels: channels, current_channel: 0, current_value: SettingsValue::Deadzone}
+        Settings{version: SETTINGS_VERSION, mode: ControlMode::Normal, settings_path: settings_path.to_string(), profile: default_profile(), channels: channels, mixer: Vec::new(),
+            current_channel: 0, current_value: SettingsValue::Deadzone, adc_oversample: 4, adc_sample_rate: 200, adc_second_chip: false, adc_second_spi: SpiDevice::Spi0Ce1, adc_bits: 10,
+            input: InputBackend::Adc,
+            // Left stick for rudder and motor, right stick for the camera
+            gamepad_axes: vec![crate::RUDDER_ADC, crate::MOTOR_ADC, crate::GIMBAL_PAN_ADC, crate::GIMBAL_TILT_ADC],
+            gamepad_buttons: (0..6).collect(), bluetooth_gamepad: String::new(), touch_control: false,
+            // AETR transmitters: aileron stick to the rudder, throttle to the motor
+            ppm_channels: vec![crate::RUDDER_ADC, crate::GIMBAL_TILT_ADC, crate::MOTOR_ADC, crate::GIMBAL_PAN_ADC],
+            battery_scale: 3.3 * 3. / 1024., battery_empty_voltage: 6.6, battery_full_voltage: 8.4, battery_low_voltage: 7.0,
+            race_countdown_s: 300,
+            no_boat_timeout_ms: 3000, failsafe_timeout_ms: 1000, gust_alarm_speed: 6., shallow_alarm_depth: 0.3,
+            telemetry_format: TelemetryFormat::Off, telemetry_period_ms: 200, telemetry_max_kb: 1024, telemetry_max_files: 20,
+            mqtt_broker: String::new(), mqtt_period_ms: 1000,
+            signalk_server: String::new(), signalk_period_ms: 1000,
+            udp_broadcast: String::new(), udp_period_ms: 100,
+            boat_id: 1, link_transport: LinkTransport::WebSocket, command_encoding: CommandEncoding::Full, keyframe_period_ms: 1000, nrf24_channel: 76, nrf24_spi: SpiDevice::Spi0Ce1,
+            lora_enabled: false, lora_spi: SpiDevice::Spi0Ce1, lora_frequency_hz: 868_100_000, lora_failover_latency_ms: 400, lora_fallback_ms: 3000,
+            log_filter: String::from("info"), log_dir: String::new(), log_max_files: 7,
+            led_gauge: LedGauge::Weight, display_bus: DisplayBus::I2c, display_spi: SpiDevice::Spi0Ce1, display_contrast: 0xCF, display_dim_s: 30, system_item: SystemItem::LedGauge,
+            button_map: ButtonMap::default(),
+            lights: 0, profiles: Vec::new(), selected_profile: 0,
+            snapshots: Vec::new(), selected_snapshot: 0, calibration: None, clicked_mode: ControlMode::Normal}
+    }
+
+    /// Devices sharing a chip select or a control pin, None when each has its own.
+    /// `display_bus` and `input` are those in use, the pots being read on all inputs but the gamepad and the sim.
+    pub fn spi_conflict(&self, display_bus: DisplayBus, input: InputBackend) -> Option<String> {
+        let pots = matches!(input, InputBackend::Adc | InputBackend::Ppm | InputBackend::Bluetooth);
+        let display = display_bus == DisplayBus::Spi;
+        let nrf24 = self.link_transport == LinkTransport::Nrf24;
+        let devices: Vec<(&str, SpiDevice)> = [
+            (pots, "first ADC chip", SpiDevice::Spi0Ce0),
+            (pots && self.adc_second_chip, "second ADC chip", self.adc_second_spi),
+            (display, "SPI display", self.display_spi),
+            (self.lora_enabled, "LoRa radio", self.lora_spi),
+            (nrf24, "nRF24 radio", self.nrf24_spi),
+        ].into_iter().filter(|(used, _, _)| *used).map(|(_, name, spi)| (name, spi)).collect();
+        let pins: Vec<(&str, u8)> = [
+            (display, "SPI display DC", DC_PIN),
+            (display, "SPI display RST", RST_PIN),
+            (nrf24, "nRF24 CE", crate::NRF24_CE_PIN),
+        ].into_iter().filter(|(used, _, _)| *used).map(|(_, name, pin)| (name, pin)).collect();
+        shared(&devices, |spi| format!("{:?}", spi)).or_else(|| shared(&pins, |pin| format!("GPIO {}", pin)))
     }
     
     fn previous_channel(&mut self) {
@@ -132,7 +421,7 @@ impl Settings {
         self.current_channel = if self.current_channel == self.channels.len()-1 { 0 } else { self.current_channel + 1};
     }
 
-    fn current_channel(&self) -> &ChannelConfig {
+    pub fn current_channel(&self) -> &ChannelConfig {
         &(self.channels[self.current_channel])
     }
 
@@ -146,59 +435,117 @@ impl Settings {
     
     fn previous_value(&mut self) {
         self.current_value = match self.current_value {
-            SettingsValue::Deadzone => SettingsValue::Step,
-            SettingsValue::Center => SettingsValue::Deadzone,
+            SettingsValue::Deadzone => SettingsValue::Curve(4),
+            SettingsValue::Hysteresis => SettingsValue::Deadzone,
+            SettingsValue::Center => SettingsValue::Hysteresis,
             SettingsValue::Min => SettingsValue::Center,
             SettingsValue::Max => SettingsValue::Min,
-            SettingsValue::Step => SettingsValue::Max
+            SettingsValue::Travel => SettingsValue::Max,
+            SettingsValue::Expo => SettingsValue::Travel,
+            SettingsValue::Invert => SettingsValue::Expo,
+            SettingsValue::Incremental => SettingsValue::Invert,
+            SettingsValue::Failsafe => SettingsValue::Incremental,
+            SettingsValue::Curve(0) => SettingsValue::Failsafe,
+            SettingsValue::Curve(point) => SettingsValue::Curve(point - 1)
         }
     }
     
     fn next_value(&mut self) {
         self.current_value = match self.current_value {
-            SettingsValue::Deadzone => SettingsValue::Center,
+            SettingsValue::Deadzone => SettingsValue::Hysteresis,
+            SettingsValue::Hysteresis => SettingsValue::Center,
             SettingsValue::Center => SettingsValue::Min,
             SettingsValue::Min => SettingsValue::Max,
-            SettingsValue::Max => SettingsValue::Step,
-            SettingsValue::Step => SettingsValue::Deadzone
+            SettingsValue::Max => SettingsValue::Travel,
+            SettingsValue::Travel => SettingsValue::Expo,
+            SettingsValue::Expo => SettingsValue::Invert,
+            SettingsValue::Invert => SettingsValue::Incremental,
+            SettingsValue::Incremental => SettingsValue::Failsafe,
+            SettingsValue::Failsafe => SettingsValue::Curve(0),
+            SettingsValue::Curve(point) if point + 1 < LINEAR_CURVE.len() => SettingsValue::Curve(point + 1),
+            SettingsValue::Curve(_) => SettingsValue::Deadzone
         }
     }
     
     pub fn get_value(&self) -> u16 {
         match self.current_value {
         SettingsValue::Deadzone => self.current_channel().deadzone,
+        SettingsValue::Hysteresis => self.current_channel().hysteresis,
         SettingsValue::Center => self.current_channel().center,
         SettingsValue::Min => self.current_channel().min,
         SettingsValue::Max => self.current_channel().max,
-        SettingsValue::Step => self.current_channel().step,
+        SettingsValue::Travel => self.current_channel().travel_ms,
+        SettingsValue::Expo => self.current_channel().expo,
+        SettingsValue::Invert => self.current_channel().invert as u16,
+        SettingsValue::Incremental => self.current_channel().incremental as u16,
+        SettingsValue::Failsafe => self.current_channel().failsafe,
+        SettingsValue::Curve(point) => self.current_channel().curve[point],
         }
     }
     
-    fn add_value(&mut self, diff: u16) {
+    /// Current value as shown in the menu
+    pub fn value_text(&self) -> String {
         match self.current_value {
-        SettingsValue::Deadzone => { self.mut_current_channel().deadzone += diff; }
-        SettingsValue::Center => { self.mut_current_channel().center += diff; }
-        SettingsValue::Min => { self.mut_current_channel().min += diff; }
-        SettingsValue::Max => { self.mut_current_channel().max += diff; }
-        SettingsValue::Step => { self.mut_current_channel().step += 1; }
+        SettingsValue::Travel if self.get_value() == 0 => String::from("no limit"),
+        SettingsValue::Travel => format!("{:.1} s", self.get_value() as f32 / 1000.),
+        _ => self.get_value().to_string(),
+        }
+    }
+    
+    // Edits saturate instead of breaking min <= center <= max
+    fn add_value(&mut self, diff: u16) {
+        let value = self.current_value;
+        let channel = self.mut_current_channel();
+        match value {
+        SettingsValue::Deadzone => { channel.deadzone = channel.deadzone.saturating_add(diff).min(channel.deadzone_max() - channel.hysteresis); }
+        SettingsValue::Hysteresis => { channel.hysteresis = (channel.hysteresis + 1).min(channel.deadzone_max() - channel.deadzone); }
+        SettingsValue::Center => { channel.center = channel.center.saturating_add(diff).min(channel.max); }
+        SettingsValue::Min => { channel.min = channel.min.saturating_add(diff).min(channel.center); }
+        SettingsValue::Max => { channel.max = channel.max.saturating_add(diff).min(OUTPUT_MAX); }
+        SettingsValue::Travel => { channel.travel_ms = channel.travel_ms.saturating_add(TRAVEL_STEP_MS).min(TRAVEL_MAX_MS); }
+        SettingsValue::Expo => { channel.expo = channel.expo.saturating_add(diff).min(100); }
+        SettingsValue::Invert => { channel.invert = true; }
+        SettingsValue::Incremental => { channel.incremental = true; }
+        SettingsValue::Failsafe => { channel.failsafe = channel.failsafe.saturating_add(diff).min(channel.max); }
+        SettingsValue::Curve(point) => { channel.curve[point] = (channel.curve[point] + 1).min(100); }
         }
+        // Min or max may have moved past the failsafe value
+        channel.failsafe = channel.failsafe.clamp(channel.min, channel.max);
     }
 
     fn sub_value(&mut self, diff: u16) {
-        match self.current_value {
-        SettingsValue::Deadzone => { self.mut_current_channel().deadzone -= diff; }
-        SettingsValue::Center => { self.mut_current_channel().center -= diff; }
-        SettingsValue::Min => { self.mut_current_channel().min -= diff; }
-        SettingsValue::Max => { self.mut_current_channel().max -= diff; }
-        SettingsValue::Step => { self.mut_current_channel().step -= 1; }
+        let value = self.current_value;
+        let channel = self.mut_current_channel();
+        match value {
+        SettingsValue::Deadzone => { channel.deadzone = channel.deadzone.saturating_sub(diff); }
+        SettingsValue::Hysteresis => { channel.hysteresis = channel.hysteresis.saturating_sub(1); }
+        SettingsValue::Center => { channel.center = channel.center.saturating_sub(diff).max(channel.min); }
+        SettingsValue::Min => { channel.min = channel.min.saturating_sub(diff).max(OUTPUT_MIN); }
+        SettingsValue::Max => { channel.max = channel.max.saturating_sub(diff).max(channel.center); }
+        SettingsValue::Travel => { channel.travel_ms = channel.travel_ms.saturating_sub(TRAVEL_STEP_MS); }
+        SettingsValue::Expo => { channel.expo = channel.expo.saturating_sub(diff); }
+        SettingsValue::Invert => { channel.invert = false; }
+        SettingsValue::Incremental => { channel.incremental = false; }
+        SettingsValue::Failsafe => { channel.failsafe = channel.failsafe.saturating_sub(diff).max(channel.min); }
+        SettingsValue::Curve(point) => { channel.curve[point] = channel.curve[point].saturating_sub(1); }
+        }
+        // Min or max may have moved past the failsafe value
+        channel.failsafe = channel.failsafe.clamp(channel.min, channel.max);
+    }
+    
+    pub fn handle_button(&mut self, physical: usize) {
+        if let Some(button) = self.button_map.navigation(physical) {
+            self.clicked_mode = self.mode;
+            self.handle_navigation(button);
         }
     }
     
-    pub fn handle_button(&mut self, button: usize) {
+    fn handle_navigation(&mut self, button: usize) {
         match self.mode {
             ControlMode::Normal => {
                 match button {
                     BUTTON_CHANGE_MODE => { self.mode = ControlMode::Settings; }
+                    BUTTON_RIGHT => { self.lights = (self.lights + 1) % 4; }
                     _ => {}
                 }
             }
@@ -208,6 +555,8 @@ impl Settings {
                     BUTTON_CANCEL_MODE => { self.mode = ControlMode::Normal; let _ = self.save(); }
                     BUTTON_LEFT => { self.previous_channel(); }
                     BUTTON_RIGHT => { self.next_channel(); }
+                    BUTTON_UP => { self.enter_profile_page(); }
+                    BUTTON_DOWN => { self.enter_snapshot_page(); }
                     _ => {}
                 }
             }
@@ -222,33 +571,554 @@ impl Settings {
                     _ => {}
                 }
             }
+            ControlMode::Profile => {
+                match button {
+                    BUTTON_CHANGE_MODE => { self.switch_profile(); self.mode = ControlMode::Settings; }
+                    BUTTON_CANCEL_MODE => { self.mode = ControlMode::Settings; }
+                    BUTTON_LEFT => { self.previous_profile(); }
+                    BUTTON_RIGHT => { self.next_profile(); }
+                    BUTTON_UP => { self.new_profile(); }
+                    BUTTON_DOWN => { self.mode = ControlMode::System; }
+                    _ => {}
+                }
+            }
+            ControlMode::Snapshot => {
+                match button {
+                    BUTTON_CHANGE_MODE => { self.load_snapshot(); self.mode = ControlMode::Settings; }
+                    BUTTON_CANCEL_MODE => { self.mode = ControlMode::Settings; }
+                    BUTTON_LEFT => { self.previous_snapshot(); }
+                    BUTTON_RIGHT => { self.next_snapshot(); }
+                    BUTTON_UP => { self.save_snapshot(); }
+                    _ => {}
+                }
+            }
+            ControlMode::Calibration => {
+                match button {
+                    BUTTON_CHANGE_MODE => { if let Some(calibration) = self.calibration.as_mut() { calibration.next_step(); } }
+                    BUTTON_CANCEL_MODE => { self.calibration = None; self.mode = ControlMode::Settings; }
+                    _ => {}
+                }
+            }
+            ControlMode::Factory => {
+                match button {
+                    BUTTON_CHANGE_MODE => { self.reset_current_channel(); self.mode = ControlMode::Normal; let _ = self.save(); self.mode = ControlMode::Settings; }
+                    BUTTON_CANCEL_MODE => { self.mode = ControlMode::Settings; }
+                    _ => {}
+                }
+            }
+            ControlMode::System => {
+                match button {
+                    BUTTON_CHANGE_MODE => { self.mode = ControlMode::Normal; let _ = self.save(); self.mode = ControlMode::Settings; }
+                    BUTTON_CANCEL_MODE => { self.mode = ControlMode::Settings; }
+                    BUTTON_LEFT => { self.system_item = self.system_item.previous(); }
+                    BUTTON_RIGHT => { self.system_item = self.system_item.next(); }
+                    BUTTON_UP => { self.change_system_item(true); }
+                    BUTTON_DOWN => { self.change_system_item(false); }
+                    _ => {}
+                }
+            }
         };  
     }
     
+    fn change_system_item(&mut self, up: bool) {
+        match (self.system_item, up) {
+            (SystemItem::LedGauge, true) => { self.led_gauge = self.led_gauge.next(); }
+            (SystemItem::LedGauge, false) => { self.led_gauge = self.led_gauge.previous(); }
+            (SystemItem::Contrast, true) => { self.display_contrast = self.display_contrast.saturating_add(CONTRAST_STEP); }
+            (SystemItem::Contrast, false) => { self.display_contrast = self.display_contrast.saturating_sub(CONTRAST_STEP); }
+            (SystemItem::DimDelay, true) => { self.display_dim_s = (self.display_dim_s + DIM_DELAY_STEP_S).min(DIM_DELAY_MAX_S); }
+            (SystemItem::DimDelay, false) => { self.display_dim_s = self.display_dim_s.saturating_sub(DIM_DELAY_STEP_S); }
+            (SystemItem::Boat, true) => { self.boat_id = (self.boat_id + 1).min(BOAT_ID_MAX); }
+            (SystemItem::Boat, false) => { self.boat_id = self.boat_id.saturating_sub(1).max(1); }
+        }
+    }
+    
+    /// Long press on change mode saves and leaves the settings from any settings page,
+    /// long press on down starts the stick calibration from the settings page
+    pub fn handle_long_press(&mut self, physical: usize) {
+        let Some(button) = self.button_map.navigation(physical) else { return };
+        match (self.mode, button) {
+            (ControlMode::Normal, _) | (ControlMode::Calibration, _) => {}
+            (ControlMode::Settings, BUTTON_DOWN) => {
+                self.calibration = Some(Calibration::new());
+                self.mode = ControlMode::Calibration;
+            }
+            (_, BUTTON_CHANGE_MODE) => {
+                self.mode = ControlMode::Normal;
+                if let Err(e) = self.save() {
+                    error!("Could not save settings: {}", e);
+                }
+            }
+            _ => {}
+        }
+    }
+    
+    /// Double click on cancel opens the factory view of the current channel, from the settings or value page.
+    ///
+    /// The first click has already been handled, leaving the page, so the page it was clicked on is looked at.
+    pub fn handle_double_click(&mut self, physical: usize) {
+        let Some(button) = self.button_map.navigation(physical) else { return };
+        match (self.clicked_mode, self.mode, button) {
+            (ControlMode::Settings, ControlMode::Normal, BUTTON_CANCEL_MODE)
+            | (ControlMode::SettingsValue, ControlMode::Settings, BUTTON_CANCEL_MODE) => {
+                self.mode = ControlMode::Factory;
+            }
+            // Otherwise a double click is two clicks
+            _ => self.handle_navigation(button),
+        }
+    }
+    
+    pub fn factory_channel(&self) -> ChannelConfig {
+        ChannelConfig::new(&self.current_channel_name())
+    }
+    
+    fn reset_current_channel(&mut self) {
+        let factory = self.factory_channel();
+        info!("Channel {} reset to factory defaults", factory.name);
+        *self.mut_current_channel() = factory;
+    }
+    
+    /// Replace the channel of the same name, keeping its runtime state
+    pub fn set_channel(&mut self, mut channel: ChannelConfig) -> Result<(), String> {
+        channel.validate()?;
+        let current = self.channels.iter_mut().find(|c| c.name == channel.name)
+            .ok_or_else(|| format!("Unknown channel {}", channel.name))?;
+        channel.keep_state_of(current);
+        *current = channel;
+        Ok(())
+    }
+    
+    fn profiles_dir(&self) -> PathBuf {
+        Path::new(&self.settings_path).with_file_name("profiles")
+    }
+    
+    fn profile_path(&self, name: &str) -> PathBuf {
+        self.profiles_dir().join(format!("{}.json", name))
+    }
+    
+    fn list_profiles(&self) -> Vec<String> {
+        let mut profiles = list_json_files(&self.profiles_dir());
+        if !profiles.contains(&self.profile) {
+            profiles.push(self.profile.clone());
+        }
+        profiles.sort();
+        profiles
+    }
+    
+    fn enter_profile_page(&mut self) {
+        self.profiles = self.list_profiles();
+        self.selected_profile = self.profiles.iter().position(|p| *p == self.profile).unwrap_or(0);
+        self.mode = ControlMode::Profile;
+    }
+    
+    fn previous_profile(&mut self) {
+        self.selected_profile = if self.selected_profile == 0 { self.profiles.len()-1 } else { self.selected_profile - 1};
+    }
+    
+    fn next_profile(&mut self) {
+        self.selected_profile = if self.selected_profile == self.profiles.len()-1 { 0 } else { self.selected_profile + 1};
+    }
+    
+    pub fn selected_profile_name(&self) -> String {
+        self.profiles.get(self.selected_profile).cloned().unwrap_or_default()
+    }
+    
+    /// Copy the current channels into a new profile, which becomes the current one
+    fn new_profile(&mut self) {
+        let name = (1..).map(|n| format!("boat{}", n)).find(|name| !self.profiles.contains(name)).unwrap();
+        self.profile = name;
+        self.mode = ControlMode::Normal;
+        if let Err(e) = self.save() {
+            error!("Could not create profile {}: {}", self.profile, e);
+        }
+        self.enter_profile_page();
+    }
+    
+    fn switch_profile(&mut self) {
+        let name = self.selected_profile_name();
+        if name == self.profile {
+            return;
+        }
+        self.mode = ControlMode::Normal;
+        let _ = self.save();
+        match self.load_profile(&name) {
+            Ok(_) => { info!("Switched to profile {}", name); let _ = self.save(); }
+            Err(e) => error!("Could not load profile {}: {}", name, e),
+        }
+    }
+    
+    fn snapshots_dir(&self) -> PathBuf {
+        Path::new(&self.settings_path).with_file_name("snapshots")
+    }
+    
+    fn snapshot_path(&self, name: &str) -> PathBuf {
+        self.snapshots_dir().join(format!("{}.json", name))
+    }
+    
+    fn enter_snapshot_page(&mut self) {
+        let mut snapshots = list_json_files(&self.snapshots_dir());
+        for name in SNAPSHOT_NAMES {
+            if !snapshots.iter().any(|s| s == name) {
+                snapshots.push(name.to_string());
+            }
+        }
+        snapshots.sort();
+        self.snapshots = snapshots;
+        self.selected_snapshot = self.selected_snapshot.min(self.snapshots.len() - 1);
+        self.mode = ControlMode::Snapshot;
+    }
+    
+    fn previous_snapshot(&mut self) {
+        self.selected_snapshot = if self.selected_snapshot == 0 { self.snapshots.len()-1 } else { self.selected_snapshot - 1};
+    }
+    
+    fn next_snapshot(&mut self) {
+        self.selected_snapshot = if self.selected_snapshot == self.snapshots.len()-1 { 0 } else { self.selected_snapshot + 1};
+    }
+    
+    pub fn selected_snapshot_name(&self) -> String {
+        self.snapshots.get(self.selected_snapshot).cloned().unwrap_or_default()
+    }
+    
+    /// Save the current channels and mixer as the selected snapshot
+    fn save_snapshot(&mut self) {
+        let name = self.selected_snapshot_name();
+        let snapshot = Profile { version: SETTINGS_VERSION, name: name.clone(), channels: self.channels.clone(), mixer: self.mixer.clone() };
+        let result = serde_json::to_string_pretty(&snapshot)
+            .map_err(io::Error::other)
+            .and_then(|json| {
+                fs::create_dir_all(self.snapshots_dir())?;
+                fs::write(self.snapshot_path(&name), json)
+            });
+        match result {
+            Ok(_) => info!("Saved snapshot {}", name),
+            Err(e) => error!("Could not save snapshot {}: {}", name, e),
+        }
+    }
+    
+    /// Replace the current channels and mixer by the selected snapshot, keeping the profile
+    fn load_snapshot(&mut self) {
+        let name = self.selected_snapshot_name();
+        let defaults = Profile { version: SETTINGS_VERSION, name: name.clone(), channels: Vec::new(), mixer: Vec::new() };
+        let snapshot = fs::read_to_string(self.snapshot_path(&name))
+            .and_then(|content| parse_migrated(&content, &defaults));
+        match snapshot {
+            Ok(snapshot) => {
+                self.channels = snapshot.channels;
+                self.mixer = snapshot.mixer;
+                self.complete_channels();
+                mixer::check_rules(&self.mixer, &self.channels);
+                info!("Loaded snapshot {}", name);
+                self.mode = ControlMode::Normal;
+                let _ = self.save();
+            }
+            Err(e) => error!("Could not load snapshot {}: {}", name, e),
+        }
+    }
+    
+    /// Feed the calibration wizard, and store its results once done.
+    ///
+    /// `channel_adc` gives the ADC input of each channel, None for channels not driven by a stick.
+    pub fn update_calibration(&mut self, adc_values: &[u16], channel_adc: &[Option<usize>]) {
+        let Some(calibration) = self.calibration.as_mut() else { return };
+        calibration.update(adc_values);
+        if calibration.step != CalibrationStep::Done {
+            return;
+        }
+        
+        let calibration = self.calibration.take().unwrap();
+        for (channel, input) in self.channels.iter_mut().zip(channel_adc) {
+            if let Some(input) = *input {
+                if calibration.apply(input, channel) {
+                    info!("Channel {} calibrated: {} {} {}", channel.name, channel.adc_min, channel.adc_center, channel.adc_max);
+                } else {
+                    warn!("Channel {} not calibrated, stick not moved enough", channel.name);
+                }
+            }
+        }
+        self.mode = ControlMode::Normal;
+        let _ = self.save();
+        self.mode = ControlMode::Settings;
+    }
+    
+    fn load_profile(&mut self, name: &str) -> io::Result<()> {
+        let content = fs::read_to_string(self.profile_path(name))?;
+        let defaults = Profile { version: SETTINGS_VERSION, name: name.to_string(), channels: Vec::new(), mixer: Vec::new() };
+        let profile: Profile = parse_migrated(&content, &defaults)?;
+        self.profile = profile.name;
+        self.channels = profile.channels;
+        self.mixer = profile.mixer;
+        self.current_channel = 0;
+        self.complete_channels();
+        mixer::check_rules(&self.mixer, &self.channels);
+        Ok(())
+    }
+    
+    // Files written before a channel was added lack it: append the defaults.
+    // Out of range values are clamped, the editor being unable to fix some of them.
+    fn complete_channels(&mut self) {
+        let defaults = Settings::new("").channels;
+        for channel in defaults.into_iter().skip(self.channels.len()) {
+            self.channels.push(channel);
+        }
+        for channel in self.channels.iter_mut() {
+            // Calibrated with another ADC: scale along, recalibrating being advised
+            if channel.adc_bits != self.adc_bits {
+                info!("Channel {} scaled from {} to {} bit ADC values", channel.name, channel.adc_bits, self.adc_bits);
+                channel.set_adc_bits(self.adc_bits);
+            }
+            if let Err(e) = channel.validate() {
+                warn!("Invalid channel settings, clamped: {}", e);
+                channel.sanitize();
+            }
+        }
+    }
+    
+    pub fn nav_lights(&self) -> bool { self.lights >= 1 }
+    pub fn deck_light(&self) -> bool { self.lights >= 2 }
+    pub fn spotlight(&self) -> bool { self.lights >= 3 }
+    
     pub fn save(&self) -> io::Result<()> {
         let json = serde_json::to_string_pretty(self)
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+            .map_err(io::Error::other)?;
         
         let mut file = fs::File::create(self.settings_path.clone())?;
         file.write_all(json.as_bytes())?;
+        
+        let profile = Profile { version: SETTINGS_VERSION, name: self.profile.clone(), channels: self.channels.clone(), mixer: self.mixer.clone() };
+        let json = serde_json::to_string_pretty(&profile)
+            .map_err(io::Error::other)?;
+        
+        fs::create_dir_all(self.profiles_dir())?;
+        let mut file = fs::File::create(self.profile_path(&self.profile))?;
+        file.write_all(json.as_bytes())?;
+        event_log::record(format!("Settings saved, profile {}", self.profile));
         Ok(())
     }
     
     pub fn load(&mut self) -> io::Result<()> {
         let content = fs::read_to_string(self.settings_path.clone())?;
-        let loaded: Settings = serde_json::from_str(&content)
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        let mut loaded: Settings = parse_migrated(&content, &Settings::new(""))?;
+        loaded.settings_path = self.settings_path.clone();
+        
+        // Older settings files hold the channels themselves, keep them until the profile is saved
+        let name = loaded.profile.clone();
+        if loaded.profile_path(&name).exists() {
+            loaded.load_profile(&name)?;
+        }
+        loaded.complete_channels();
         *self = loaded;
         Ok(())
     }
 }
     
 
+/// Names of the JSON files in a directory, without extension
+fn list_json_files(dir: &Path) -> Vec<String> {
+    match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .filter_map(|path| path.file_stem().and_then(|stem| stem.to_str()).map(String::from))
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Add the fields of `defaults` missing from `document`, recursing into objects
+fn fill_defaults(document: &mut Value, defaults: &Value) {
+    if let (Value::Object(fields), Value::Object(default_fields)) = (document, defaults) {
+        for (key, default) in default_fields {
+            match fields.get_mut(key) {
+                Some(value) => fill_defaults(value, default),
+                None => { fields.insert(key.clone(), default.clone()); }
+            }
+        }
+    }
+}
+
+/// Upgrade a settings or profile document to SETTINGS_VERSION
+///
+/// Version 0 files (unversioned) may hold the channels in settings.json, which load() keeps
+/// until they are saved in the profile. Fields added since are filled with their defaults.
+fn migrate(document: &mut Value, defaults: &Value) {
+    let version = document.get("version").and_then(Value::as_u64).unwrap_or(0);
+    if version > SETTINGS_VERSION as u64 {
+        warn!("Settings version {} is newer than {}, unknown fields are ignored", version, SETTINGS_VERSION);
+    }
+    
+    // Version 2: the per-update step became a full travel time
+    if version < 2 && let Some(Value::Array(channels)) = document.get_mut("channels") {
+        channels.iter_mut().for_each(step_to_travel);
+    }
+    
+    fill_defaults(document, defaults);
+    if let Some(Value::Array(channels)) = document.get_mut("channels") {
+        for channel in channels {
+            let name = channel.get("name").and_then(Value::as_str).unwrap_or_default().to_string();
+            if let Ok(channel_defaults) = serde_json::to_value(ChannelConfig::new(&name)) {
+                fill_defaults(channel, &channel_defaults);
+            }
+        }
+    }
+    if let Value::Object(fields) = document {
+        fields.insert(String::from("version"), Value::from(SETTINGS_VERSION));
+    }
+}
+
+fn step_to_travel(channel: &mut Value) {
+    let Some(fields) = channel.as_object_mut() else { return };
+    let Some(step) = fields.remove("step").and_then(|step| step.as_u64()) else { return };
+    let defaults = ChannelConfig::new("");
+    let output = |name: &str, default: u16| fields.get(name).and_then(Value::as_u64).unwrap_or(default as u64);
+    let range = output("max", defaults.max).saturating_sub(output("min", defaults.min));
+    let travel_ms = (range * LEGACY_LOOP_MS as u64 / step.max(1)).min(TRAVEL_MAX_MS as u64);
+    fields.insert(String::from("travel_ms"), Value::from(travel_ms));
+}
+
+fn parse_migrated<T: Serialize + DeserializeOwned>(content: &str, defaults: &T) -> io::Result<T> {
+    let mut document: Value = serde_json::from_str(content)
+        .map_err(io::Error::other)?;
+    let defaults = serde_json::to_value(defaults)
+        .map_err(io::Error::other)?;
+    migrate(&mut document, &defaults);
+    serde_json::from_value(document)
+        .map_err(io::Error::other)
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum SettingsValue {
     Deadzone,
+    Hysteresis,
     Center,
     Min,
     Max,
-    Step
+    #[serde(alias = "Step")]
+    Travel,     // Full travel time, replacing the per-update step of version 1
+    Expo,
+    Invert,
+    Incremental,
+    Failsafe,
+    Curve(usize)    // Point of the output curve
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrate_unversioned_settings() {
+        let content = r#"{
+            "mode": "Normal",
+            "settings_path": "settings.json",
+            "channels": [
+                { "name": "RudderStar", "deadzone": 40, "center": 1570, "min": 1000, "max": 2000, "step": 1000, "previous_value": 1570 },
+                { "name": "RudderPort", "center": 1510 }
+            ],
+            "current_channel": 1,
+            "current_value": "Center"
+        }"#;
+        let settings: Settings = parse_migrated(content, &Settings::new("")).unwrap();
+        assert_eq!(settings.version, SETTINGS_VERSION);
+        assert_eq!(settings.profile, DEFAULT_PROFILE);
+        assert_eq!(settings.channels.len(), 2);
+        assert_eq!(settings.channels[0].deadzone, 40);
+        assert_eq!(settings.channels[0].expo, 0);
+        assert_eq!(settings.channels[1].center, 1510);
+        assert_eq!(settings.channels[1].deadzone, 50);
+        // 1000us per update of the 40ms loop
+        assert_eq!(settings.channels[0].travel_ms, 40);
+        assert_eq!(settings.channels[1].travel_ms, 400);
+    }
+
+    #[test]
+    fn test_channel_without_hysteresis() {
+        let mut channel = serde_json::to_value(ChannelConfig::new("Motor")).unwrap();
+        channel.as_object_mut().unwrap().remove("hysteresis");
+        let channel: ChannelConfig = serde_json::from_value(channel).unwrap();
+        assert_eq!(channel.hysteresis, 8);
+    }
+
+    #[test]
+    fn test_spi_conflict() {
+        let mut settings = Settings::new("");
+        settings.lora_enabled = true;
+        assert_eq!(settings.spi_conflict(DisplayBus::I2c, InputBackend::Adc), None);
+        assert_eq!(settings.spi_conflict(DisplayBus::Spi, InputBackend::Adc), Some(String::from("SPI display, LoRa radio all use Spi0Ce1")));
+        settings.display_spi = SpiDevice::Spi1Ce0;
+        assert_eq!(settings.spi_conflict(DisplayBus::Spi, InputBackend::Adc), None);
+        // Without the pots, SPI0 CE0 is free
+        settings.lora_spi = SpiDevice::Spi0Ce0;
+        assert!(settings.spi_conflict(DisplayBus::Spi, InputBackend::Adc).is_some());
+        assert_eq!(settings.spi_conflict(DisplayBus::Spi, InputBackend::Gamepad), None);
+        // The nRF24 enable line off the display pins
+        settings.link_transport = LinkTransport::Nrf24;
+        settings.nrf24_spi = SpiDevice::Spi1Ce1;
+        assert_eq!(settings.spi_conflict(DisplayBus::Spi, InputBackend::Gamepad), None);
+    }
+
+    #[test]
+    fn test_migrate_newer_version() {
+        let content = r#"{ "version": 99, "mode": "Settings", "future_field": true }"#;
+        let settings: Settings = parse_migrated(content, &Settings::new("")).unwrap();
+        assert_eq!(settings.mode, ControlMode::Settings);
+        assert_eq!(settings.current_value, SettingsValue::Deadzone);
+    }
+
+    #[test]
+    fn test_double_click_cancel() {
+        let mut settings = Settings::new("");
+        let cancel = settings.button_map.cancel;
+        // From the settings page, the first click having left it
+        settings.mode = ControlMode::Settings;
+        settings.clicked_mode = settings.mode;
+        settings.mode = ControlMode::Normal;
+        settings.handle_double_click(cancel);
+        assert_eq!(settings.mode, ControlMode::Factory);
+        settings.handle_button(cancel);
+        assert_eq!(settings.mode, ControlMode::Settings);
+
+        settings.mode = ControlMode::SettingsValue;
+        settings.clicked_mode = settings.mode;
+        settings.mode = ControlMode::Settings;
+        settings.handle_double_click(cancel);
+        assert_eq!(settings.mode, ControlMode::Factory);
+
+        // Anywhere else, two clicks
+        settings.mode = ControlMode::Normal;
+        settings.clicked_mode = ControlMode::Normal;
+        settings.handle_double_click(cancel);
+        assert_eq!(settings.mode, ControlMode::Normal);
+    }
+
+    #[test]
+    fn test_value_editing_saturates() {
+        let mut settings = Settings::new("");
+        settings.current_value = SettingsValue::Deadzone;
+        settings.channels[0].deadzone = 5;
+        settings.sub_value(10);
+        assert_eq!(settings.channels[0].deadzone, 0);
+
+        settings.current_value = SettingsValue::Min;
+        for _ in 0..100 {
+            settings.add_value(10);
+        }
+        assert_eq!(settings.channels[0].min, settings.channels[0].center);
+
+        settings.channels[1].failsafe = 1800;
+        settings.current_channel = 1;
+        settings.current_value = SettingsValue::Max;
+        settings.sub_value(300);
+        assert_eq!((settings.channels[1].max, settings.channels[1].failsafe), (1700, 1700));
+        assert!(settings.channels[1].validate().is_ok());
+        settings.current_channel = 0;
+
+        settings.current_value = SettingsValue::Travel;
+        settings.channels[0].travel_ms = 50;
+        settings.sub_value(10);
+        assert_eq!(settings.channels[0].travel_ms, 0);
+        assert_eq!(settings.value_text(), "no limit");
+        assert!(settings.channels[0].validate().is_ok());
+    }
 }
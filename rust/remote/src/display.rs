@@ -1,11 +1,64 @@
 use serde::{Serialize, Deserialize};
-use rppal::i2c::I2c;
 use std::sync::mpsc::{self, Receiver};
+use std::collections::VecDeque;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use crate::config::ControlMode;
-use crate::config::Settings;
+use crate::config::{ControlMode, SystemItem, DisplayBus, SpiDevice};
+use crate::alerts::Alert;
+use crate::event_log::Event;
+use crate::calibration::CalibrationStep;
+use crate::config::{ChannelConfig, Settings};
+use pizboat_link::wireless::WirelessStats;
+use crate::lora::LoraStatus;
+use pizboat_display::{DisplayBuffer, I2cBus, Rect, SSD1306, SpiBus, TerminalBus};
+use pizboat_protocol::HardwareCheck;
+use tracing::error;
+
+// Latency sparkline: one sample every 500 ms over the last 30 s
+const LATENCY_SAMPLE_MS: u64 = 500;
+const LATENCY_SAMPLES: usize = 60;
+// Lowest full scale of the sparkline, so that a steady link draws a flat line
+const LATENCY_MIN_SCALE_MS: u64 = 100;
+// Contrast once idle for display_dim_s
+const DIM_CONTRAST: u8 = 0x01;
+// Init results that fit below the title of the boot screen
+const BOOT_LINES: usize = 4;
+// Characters of an error message after the step name
+const BOOT_ERROR_CHARS: usize = 13;
+// Events on the events page, and characters of their text after the time
+pub const EVENT_LINES: usize = 5;
+const EVENT_CHARS: usize = 15;
+// Readings on the sensors page, and characters of their name
+const SENSOR_LINES: usize = 5;
+const SENSOR_NAME_CHARS: usize = 11;
+// Checks on the boat self-test page
+const SELF_TEST_LINES: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum DisplayPage {
+    Normal,
+    Telemetry,
+    Sensors,    // Readings the boat sends by name
+    SelfTest,   // Hardware checks of the boat start-up
+    Channels,   // Output of every channel
+    Network,
+    Events      // Last notable events, newest first
+}
+
+impl DisplayPage {
+    pub fn next(self) -> Self {
+        match self {
+            DisplayPage::Normal => DisplayPage::Telemetry,
+            DisplayPage::Telemetry => DisplayPage::Sensors,
+            DisplayPage::Sensors => DisplayPage::SelfTest,
+            DisplayPage::SelfTest => DisplayPage::Channels,
+            DisplayPage::Channels => DisplayPage::Network,
+            DisplayPage::Network => DisplayPage::Events,
+            DisplayPage::Events => DisplayPage::Normal,
+        }
+    }
+}
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct DisplayData {
@@ -15,197 +68,137 @@ pub struct DisplayData {
     pub motor_value: u16,        // Transformed motor value (ADC channel 1)
     pub boom: u16,
     pub genoa: u16,
+    pub bow_thruster: u16,
+    pub gimbal_pan: u16,
+    pub gimbal_tilt: u16,
+    pub motor_cut: bool,
+    pub armed: bool,
+    pub phone_control: bool,            // Touch page of a phone driving the rudder and motor
+    pub auto_trim: bool,                // Sails trimmed by the boat, boom and genoa being its bias
+    pub compass_calibration: bool,      // Boat recording its magnetometer, to be driven in circles
+    pub battery_voltage: Option<f32>,   // Transmitter battery, None if not wired
+    pub battery_percent: u8,
+    pub battery_warning: bool,          // Low battery, toggled to flash
+    pub race_timer: Option<String>,     // Countdown or elapsed time when the race timer runs
+    pub page: DisplayPage,              // Page shown in normal mode
+    pub idle_ms: u64,                   // Time since the last button event
+    pub alert: Option<Alert>,           // Shown over any page until dismissed
+    pub outputs: Vec<u16>,              // Sent values, indexed like the channels
+    pub overridden: Vec<Option<u16>>,   // Value applied by the boat instead of the sent one, indexed like the outputs
+    pub events: Vec<Event>,             // Last events, newest first, for the events page
+    pub sensors: Vec<(String, String)>, // Names and values of the open telemetry, for the sensors page
+    pub self_test: Vec<HardwareCheck>,  // Sent by the boat on connection, empty until then
+    pub lora: Option<LoraStatus>,       // Backup link, None without radio
     
+    pub connected: bool,
     pub wireless_quality: i16,
+    pub wireless_signal: Option<i16>,               // Boat side RSSI
+    pub local_wireless: Option<WirelessStats>,      // Remote side link
     pub latency: u64,
     pub weight: f32,
+    pub boat_battery: Option<f32>,
+    pub position: Option<(f64, f64)>,
+    pub speed: Option<f32>,
+    pub water_speed: Option<f32>,   // Paddle wheel of the boat, through the water
 }
 
-
-pub struct DisplayBuffer {
-    buffer: [u8; 1024],
-}
-
-impl DisplayBuffer {
-    fn new() -> Self {
-        DisplayBuffer {
-            buffer: [0u8; 1024],
-        }
-    }
-
-    fn clear(&mut self) {
-        self.buffer.fill(0);
-    }
-
-    fn set_pixel(&mut self, x: u8, y: u8, on: bool) {
-        if x >= 128 || y >= 64 {
-            return;
-        }
-        let byte_index = (y / 8) as usize * 128 + x as usize;
-        let bit_index = y % 8;
-        
-        if on {
-            self.buffer[byte_index] |= 1 << bit_index;
-        } else {
-            self.buffer[byte_index] &= !(1 << bit_index);
-        }
-    }
-
-    fn draw_char(&mut self, x: u8, y: u8, c: char) {
-        let font = get_font_data(c);
-        for dx in 0..5u8 {
-            let column = font[dx as usize];
-            for dy in 0..8u8 {
-                if (column >> dy) & 1 == 1 {
-                    self.set_pixel(x + dx, y + dy, true);
-                }
-            }
-        }
-    }
-
-    fn draw_text(&mut self, x: u8, y: u8, text: &str) {
-        for (i, c) in text.to_uppercase().chars().enumerate() {
-            self.draw_char(x + (i as u8 * 6), y, c);
-        }
-    }
-    
-    fn draw_rectangle(&mut self, x: u8, y: u8, w: u8, h: u8) {
-        for dx in x..(x+w) {
-            for dy in y..(y+h) {
-                self.set_pixel(dx, dy, true);
-            }
-        }
-        
-    }
-    
-    fn draw_blocks(&mut self, x0: u8, y0: u8, nb_blocks: u8) {
-        let mut x = x0;
-        for n in 0..nb_blocks {
-            self.draw_rectangle(x, y0, 3, 8);
-            x += 6;
-        }
-    }
+/// Outcome of one hardware init step, listed on the boot screen
+pub struct BootCheck {
+    pub name: &'static str,
+    pub error: Option<String>,
 }
 
-pub struct SSD1306 {
-    i2c: I2c,
+pub enum DisplayMessage {
+    Boot(BootCheck),        // Startup self-test, shown until the first data
+    Data(Box<DisplayData>), // Boxed, the settings making it far larger than a boot check
 }
 
-impl SSD1306 {
-    fn new() -> Result<Self, Box<dyn std::error::Error>> {
-        let mut i2c = I2c::with_bus(1)?;
-        i2c.set_slave_address(0x3C)?;
-        
-        let mut display = SSD1306 { i2c };
-        display.init()?;
-        
-        println!("SSD1306 OLED initialized on I2C bus 1, address 0x3C");
-        Ok(display)
-    }
-
-    fn init(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        let init_commands = [
-            0xAE, 0xD5, 0x80, 0xA8, 0x3F, 0xD3, 0x00, 0x40,
-            0x8D, 0x14, 0x20, 0x00, 0xA1, 0xC8, 0xDA, 0x12,
-            0x81, 0xCF, 0xD9, 0xF1, 0xDB, 0x40, 0xA4, 0xA6, 0xAF,
-        ];
+// Firmware version and init results in startup order, failures kept in view over the older successes
+fn draw_boot(buffer: &mut DisplayBuffer, checks: &[BootCheck]) {
+    buffer.draw_text(0, 0, &format!("PizRemote v{}", env!("CARGO_PKG_VERSION")));
+    buffer.draw_line(0, 9, 127, 9);
 
-        for &cmd in &init_commands {
-            self.send_command(cmd)?;
+    let failed = checks.iter().filter(|c| c.error.is_some()).count();
+    for (line, check) in failures_kept(checks, BOOT_LINES, |c| c.error.is_some()).into_iter().enumerate() {
+        let y = 12 + line as u8 * 10;
+        buffer.draw_text(0, y, check.name);
+        match &check.error {
+            None => buffer.draw_text(48, y, "ok"),
+            Some(e) => buffer.draw_text_inverted(48, y, &e.chars().take(BOOT_ERROR_CHARS).collect::<String>()),
         }
-
-        Ok(())
     }
 
-    fn send_command(&mut self, cmd: u8) -> Result<(), Box<dyn std::error::Error>> {
-        self.i2c.write(&[0x00, cmd])?;
-        Ok(())
+    if failed > 0 {
+        buffer.draw_text(0, 55, "ANY BUTTON: CONTINUE");
     }
+}
 
-    fn display(&mut self, buffer: &DisplayBuffer) -> Result<(), Box<dyn std::error::Error>> {
-        self.send_command(0x21)?;
-        self.send_command(0)?;
-        self.send_command(127)?;
-        self.send_command(0x22)?;
-        self.send_command(0)?;
-        self.send_command(7)?;
-
-        for chunk in buffer.buffer.chunks(16) {
-            let mut data = vec![0x40];
-            data.extend_from_slice(chunk);
-            self.i2c.write(&data)?;
+// Checks in their order, the first passed ones left out when they do not all fit
+fn failures_kept<T>(checks: &[T], lines: usize, failed: impl Fn(&T) -> bool) -> Vec<&T> {
+    let failures = checks.iter().filter(|&c| failed(c)).count();
+    let skipped_ok = (checks.len() - failures).saturating_sub(lines.saturating_sub(failures));
+    let mut ok_index = 0;
+    checks.iter().filter(|&c| {
+        if failed(c) {
+            return true;
         }
+        ok_index += 1;
+        ok_index > skipped_ok
+    }).take(lines).collect()
+}
 
-        Ok(())
-    }
+// Gimbal position as a dot in a box, pan across and tilt up
+fn draw_gimbal(buffer: &mut DisplayBuffer, Rect { x, y, w, h }: Rect, pan: u16, tilt: u16, channels: &[ChannelConfig]) {
+    buffer.draw_rect(x, y, w, h);
+    let along = |value: u16, channel: &ChannelConfig, length: u8| {
+        let range = channel.max.saturating_sub(channel.min).max(1) as u32;
+        ((value.clamp(channel.min, channel.max.max(channel.min)) - channel.min) as u32 * length as u32 / range) as u8
+    };
+    let dot_x = x + 1 + along(pan, &channels[0], w.saturating_sub(4));
+    let dot_y = y + h.saturating_sub(3) - along(tilt, &channels[1], h.saturating_sub(4));
+    buffer.fill_rect(dot_x, dot_y, 2, 2);
 }
 
-fn get_font_data(c: char) -> [u8; 5] {
-    match c {
-        '0' => [0x3E, 0x51, 0x49, 0x45, 0x3E],
-        '1' => [0x00, 0x42, 0x7F, 0x40, 0x00],
-        '2' => [0x62, 0x51, 0x49, 0x49, 0x46],
-        '3' => [0x22, 0x41, 0x49, 0x49, 0x36],
-        '4' => [0x18, 0x14, 0x12, 0x7F, 0x10],
-        '5' => [0x27, 0x45, 0x45, 0x45, 0x39],
-        '6' => [0x3C, 0x4A, 0x49, 0x49, 0x30],
-        '7' => [0x01, 0x71, 0x09, 0x05, 0x03],
-        '8' => [0x36, 0x49, 0x49, 0x49, 0x36],
-        '9' => [0x06, 0x49, 0x49, 0x29, 0x1E],
-        'A' => [0x7C, 0x12, 0x11, 0x12, 0x7C],
-        'B' => [0x7F, 0x49, 0x49, 0x49, 0x36],
-        'C' => [0x3E, 0x41, 0x41, 0x41, 0x22],
-        'D' => [0x7F, 0x41, 0x41, 0x41, 0x3E],
-        'E' => [0x7F, 0x49, 0x49, 0x49, 0x41],
-        'F' => [0x7F, 0x09, 0x09, 0x09, 0x01],
-        'G' => [0x3E, 0x41, 0x49, 0x49, 0x3A],
-        'H' => [0x7F, 0x04, 0x04, 0x04, 0x7F],
-        'I' => [0x00, 0x41, 0x7F, 0x41, 0x00],
-        'J' => [0x41, 0x41, 0x3F, 0x01, 0x01],
-        'K' => [0x7F, 0x08, 0x14, 0x22, 0x41],
-        'L' => [0x7F, 0x40, 0x40, 0x40, 0x40],
-        'M' => [0x7F, 0x02, 0x0C, 0x02, 0x7F],
-        'N' => [0x7F, 0x02, 0x04, 0x08, 0x7F],
-        'O' => [0x3E, 0x41, 0x41, 0x41, 0x3E],
-        'P' => [0x7F, 0x09, 0x09, 0x09, 0x06],
-        'Q' => [0x3E, 0x41, 0x51, 0x61, 0x7E],
-        'R' => [0x7F, 0x09, 0x19, 0x29, 0x46],
-        'S' => [0x26, 0x49, 0x49, 0x49, 0x32],
-        'T' => [0x01, 0x01, 0x7F, 0x01, 0x01],
-        'U' => [0x3F, 0x40, 0x40, 0x40, 0x3F],
-        'V' => [0x07, 0x18, 0x60, 0x18, 0x07],
-        'W' => [0x7F, 0x80, 0x7C, 0x80, 0x7F],
-        'X' => [0x63, 0x14, 0x08, 0x14, 0x63],
-        'Y' => [0x03, 0x0C, 0x70, 0x0C, 0x03],
-        'Z' => [0x61, 0x51, 0x49, 0x45, 0x43],
-        ':' => [0x00, 0x36, 0x36, 0x00, 0x00],
-        '.' => [0x00, 0x60, 0x60, 0x00, 0x00],
-        ' ' => [0x00, 0x00, 0x00, 0x00, 0x00],
-        '-' => [0x08, 0x08, 0x08, 0x08, 0x08],
-        '$' => [0x24, 0x4A, 0xFF, 0x4A, 0x32],
-        '§' => [0x20, 0x42, 0xFF, 0x42, 0x20], 
-        '*' => [0x2A, 0x1C, 0x7F, 0x1C, 0x2A],
-        '&' => [0x0E, 0x1F, 0x3E, 0x1F, 0x0E],
-        _ => [0x7F, 0x41, 0x41, 0x41, 0x7F],
+// Latency inverted above the alarm threshold
+fn draw_latency(buffer: &mut DisplayBuffer, x: u8, y: u8, text: &str, latency: u64) {
+    if latency > crate::LATENCY_ALARM_MS {
+        buffer.draw_text_inverted(x, y, text);
+    } else {
+        buffer.draw_text(x, y, text);
     }
 }
 
-pub fn display_thread(rx: Receiver<DisplayData>) {
-    let mut display = match SSD1306::new() {
+pub fn display_thread(rx: Receiver<DisplayMessage>, bus: DisplayBus, spi: SpiDevice) {
+    let panel_bus: Result<Box<dyn pizboat_hal::DisplayBus>, _> = match bus {
+        DisplayBus::I2c => I2cBus::new().map(|b| Box::new(b) as Box<dyn pizboat_hal::DisplayBus>),
+        DisplayBus::Spi => SpiBus::new(spi.bus(), spi.slave_select()).map(|b| Box::new(b) as Box<dyn pizboat_hal::DisplayBus>),
+        DisplayBus::Terminal => Ok(Box::new(TerminalBus::new()) as Box<dyn pizboat_hal::DisplayBus>),
+    };
+    let display = panel_bus.and_then(SSD1306::new);
+    let mut display = match display {
         Ok(d) => d,
         Err(e) => {
-            eprintln!("Failed to initialize display: {}", e);
+            error!("Failed to initialize display: {}", e);
             return;
         }
     };
 
     let mut display_buffer = DisplayBuffer::new();
     let mut current_data: Option<DisplayData> = None;
+    let mut boot_checks = vec![BootCheck { name: "Display", error: None }];
+    let mut latency_history: VecDeque<Option<u64>> = VecDeque::new();
+    let mut last_latency_sample = Instant::now();
+    // As set by init()
+    let mut contrast: u8 = 0xCF;
+    let started = Instant::now();
 
     loop {
         match rx.try_recv() {
-            Ok(data) => {
-                current_data = Some(data);
+            Ok(DisplayMessage::Boot(check)) => {
+                boot_checks.push(check);
+            }
+            Ok(DisplayMessage::Data(data)) => {
+                current_data = Some(*data);
             }
             Err(mpsc::TryRecvError::Disconnected) => break,
             Err(mpsc::TryRecvError::Empty) => {
@@ -214,30 +207,228 @@ pub fn display_thread(rx: Receiver<DisplayData>) {
         }
 
         if let Some(ref data) = current_data {
+            if last_latency_sample.elapsed() >= Duration::from_millis(LATENCY_SAMPLE_MS) {
+                last_latency_sample = Instant::now();
+                latency_history.push_back(data.connected.then_some(data.latency));
+                while latency_history.len() > LATENCY_SAMPLES {
+                    latency_history.pop_front();
+                }
+            }
+            
+            // Dim when idle, any button bringing the contrast back
+            let settings = &data.settings;
+            let target = if settings.display_dim_s > 0 && data.idle_ms >= settings.display_dim_s as u64 * 1000 {
+                DIM_CONTRAST.min(settings.display_contrast)
+            } else {
+                settings.display_contrast
+            };
+            if target != contrast {
+                match display.set_contrast(target) {
+                    Ok(()) => contrast = target,
+                    Err(e) => error!("Display contrast error: {}", e),
+                }
+            }
+            
             display_buffer.clear();
             
             let mode_settings = format!("Settings");
             
             // Display mode on top
             match data.settings.mode {
-                ControlMode::Normal => {
-                    // Normal mode display
-                    let rudder_text = format!("§ RUD:{} {}", data.rudder_star, data.rudder_port);
-                    display_buffer.draw_text(0, 0, &rudder_text);
-                    
-                    let motor_text = format!("MOT:{}", data.motor_value);
-                    display_buffer.draw_text(0, 10, &motor_text);
-                    
+                ControlMode::Normal if data.page == DisplayPage::Telemetry => {
+                    display_buffer.draw_text(0, 0, "Telemetry");
+                    if !data.connected {
+                        display_buffer.draw_text(0, 24, "No boat");
+                    } else {
+                        display_buffer.draw_text(0, 10, &format!("LOAD:{:.0}G", data.weight));
+                        display_buffer.draw_blocks(70, 10, (data.weight.clamp(0., 500.) * 9. / 500.) as u8);
+
+                        display_buffer.draw_text(0, 20, &format!("LINK:{}/70", data.wireless_quality));
+                        display_buffer.draw_blocks(70, 20, ((data.wireless_quality.clamp(0, 70) * 9) / 70) as u8);
+
+                        draw_latency(&mut display_buffer, 0, 30, &format!("LAT:{}MS", data.latency), data.latency);
+
+                        let battery = data.boat_battery.map_or("--".to_string(), |v| format!("{:.1}V", v));
+                        display_buffer.draw_text(0, 40, &format!("BAT:{}", battery));
+                        if let Some(voltage) = data.boat_battery {
+                            // In centivolts, over the 3S LiPo range of the LED gauge
+                            let empty = (crate::BOAT_BATTERY_EMPTY * 100.) as u16;
+                            let full = (crate::BOAT_BATTERY_FULL * 100.) as u16;
+                            display_buffer.draw_vbar(Rect::new(56, 39, 5, 9), (voltage * 100.) as u16, empty, full);
+                        }
+                        let speed = data.speed.map_or("--".to_string(), |v| format!("{:.1}", v));
+                        display_buffer.draw_text(64, 40, &format!("SOG:{}", speed));
+                        // Along the track, the current or leeway making up for the difference
+                        if let Some(water_speed) = data.water_speed {
+                            display_buffer.draw_text(64, 30, &format!("STW:{:.1}", water_speed));
+                            if let Some(speed) = data.speed {
+                                display_buffer.draw_text(64, 0, &format!("DRIFT:{:+.1}", speed - water_speed));
+                            }
+                        }
+
+                        let position = data.position.map_or("GPS:--".to_string(), |(lat, lon)| format!("{:.5} {:.5}", lat, lon));
+                        display_buffer.draw_text(0, 50, &position);
+                    }
+                }
+                ControlMode::Normal if data.page == DisplayPage::Sensors => {
+                    // Count of those which do not fit
+                    let hidden = data.sensors.len().saturating_sub(SENSOR_LINES);
+                    display_buffer.draw_text(0, 0, &if hidden > 0 { format!("Sensors +{}", hidden) } else { String::from("Sensors") });
+                    if data.sensors.is_empty() {
+                        display_buffer.draw_text(0, 24, "No reading");
+                    }
+                    for (i, (name, value)) in data.sensors.iter().take(SENSOR_LINES).enumerate() {
+                        let name: String = name.chars().take(SENSOR_NAME_CHARS).collect();
+                        display_buffer.draw_text(0, 12 + i as u8 * 10, &name);
+                        display_buffer.draw_text(72, 12 + i as u8 * 10, value);
+                    }
+                }
+                ControlMode::Normal if data.page == DisplayPage::SelfTest => {
+                    display_buffer.draw_text(0, 0, "Boat self-test");
+                    if data.self_test.is_empty() {
+                        display_buffer.draw_text(0, 24, "No report");
+                    }
+                    for (line, check) in failures_kept(&data.self_test, SELF_TEST_LINES, |c| !c.passed).into_iter().enumerate() {
+                        let y = 12 + line as u8 * 10;
+                        display_buffer.draw_text(0, y, &check.name.chars().take(7).collect::<String>());
+                        let detail: String = check.detail.chars().take(BOOT_ERROR_CHARS).collect();
+                        match (check.passed, detail.is_empty()) {
+                            (true, true) => display_buffer.draw_text(48, y, "ok"),
+                            (true, false) => display_buffer.draw_text(48, y, &detail),
+                            (false, _) => display_buffer.draw_text_inverted(48, y, &detail),
+                        }
+                    }
+                }
+                ControlMode::Normal if data.page == DisplayPage::Channels => {
+                    display_buffer.draw_text(0, 0, "Channels");
+                    // Two columns of name prefix, value and a bar of the position between min and max
+                    for (i, (channel, &value)) in data.settings.channels.iter().zip(&data.outputs).enumerate().take(10) {
+                        let x = (i / 5) as u8 * 64;
+                        let y = 10 + (i % 5) as u8 * 10;
+                        let name: String = channel.name.chars().take(3).collect();
+                        // What the boat applies, inverted, when its limits override the sent value
+                        match data.overridden.get(i).copied().flatten() {
+                            Some(applied) => display_buffer.draw_text_inverted(x, y, &format!("{}{}", name, applied)),
+                            None => display_buffer.draw_text(x, y, &format!("{}{}", name, value)),
+                        }
+                        display_buffer.draw_hbar(Rect::new(x + 44, y + 1, 18, 6), value, channel.min, channel.max);
+                    }
+                }
+                ControlMode::Normal if data.page == DisplayPage::Network => {
+                    display_buffer.draw_text(0, 0, "Network");
+                    if let Some(lora) = data.lora {
+                        let text = format!("LORA:{}", lora.rssi_dbm.map_or(String::from("--"), |rssi| rssi.to_string()));
+                        // Inverted while it carries the link
+                        if lora.engaged {
+                            display_buffer.draw_text_inverted(64, 0, &text);
+                        } else {
+                            display_buffer.draw_text(64, 0, &text);
+                        }
+                    }
+                    display_buffer.draw_text(0, 12, &format!("PORT:{}", crate::websocket::WEBSOCKET_PORT));
+                    display_buffer.draw_text(0, 22, if data.connected { "BOAT:CONNECTED" } else { "BOAT:WAITING" });
+                    if data.connected {
+                        // Boat end then remote end
+                        let signal = |s: Option<i16>| s.map_or(String::from("--"), |s| s.to_string());
+                        let remote_quality = data.local_wireless.map_or(String::from("--"), |w| w.quality.to_string());
+                        display_buffer.draw_text(0, 32, &format!("Q:{}/{} DBM:{}/{}", data.wireless_quality, remote_quality,
+                            signal(data.wireless_signal), signal(data.local_wireless.map(|w| w.signal_dbm))));
+                        draw_latency(&mut display_buffer, 0, 42, &format!("LATENCY:{}MS", data.latency), data.latency);
+                    }
+                    display_buffer.draw_text(0, 54, "30S");
+                    display_buffer.draw_sparkline(24, 52, 12, &latency_history, LATENCY_MIN_SCALE_MS);
+                }
+                ControlMode::Normal if data.page == DisplayPage::Events => {
+                    display_buffer.draw_text(0, 0, "Events");
+                    if data.events.is_empty() {
+                        display_buffer.draw_text(0, 24, "No event");
+                    }
+                    // Hours and minutes only, to leave room for the text
+                    for (i, event) in data.events.iter().take(EVENT_LINES).enumerate() {
+                        let text: String = event.text.chars().take(EVENT_CHARS).collect();
+                        display_buffer.draw_text(0, 12 + i as u8 * 10, &format!("{} {}", &event.time[..5], text));
+                    }
+                }
+                ControlMode::Normal if data.race_timer.is_some() => {
+                    // Race timer replaces the servo values, centered in large digits
+                    let text = data.race_timer.as_deref().unwrap_or_default();
+                    display_buffer.draw_text_centered(4, text, 3);
+
                     let boom_text = format!("SAIL:{} {}", data.boom, data.genoa);
-                    display_buffer.draw_text(0, 20, &boom_text);
+                    display_buffer.draw_text(0, 36, &boom_text);
+
+                    if !data.armed {
+                        display_buffer.draw_text(22, 48, "** DISARMED **");
+                    } else if data.motor_cut {
+                        display_buffer.draw_text(22, 48, "** MOTOR CUT **");
+                    }
+
+                    display_buffer.draw_signal_bars(0, 56, data.wireless_quality);
+                    display_buffer.draw_sparkline(24, 56, 8, &latency_history, LATENCY_MIN_SCALE_MS);
+                }
+                ControlMode::Normal => {
+                    // Normal mode display: motor on the left, rudder, sails and bow thruster as bars, numbers where they fit,
+                    // the gimbal as a dot next to the sails
+                    let channels = &data.settings.channels;
+                    let motor = &channels[2];
+                    display_buffer.draw_vbar_centered(Rect::new(0, 0, 9, 54), data.motor_value, motor.min, motor.center, motor.max);
 
-                    let weight_text = format!("WE:{:04}", data.weight as u32);
-                    display_buffer.draw_text(0, 30, &weight_text);
+                    display_buffer.draw_text(12, 0, "RUD");
+                    display_buffer.draw_hbar_centered(Rect::new(32, 0, 96, 7), data.rudder_star, channels[0].min, channels[0].center, channels[0].max);
+                    display_buffer.draw_text(32, 9, &format!("{} {}", data.rudder_star, data.rudder_port));
+                    display_buffer.draw_text(86, 9, &format!("WE:{:04}", data.weight as u32));
 
-                    display_buffer.draw_blocks(2, 56, ((data.wireless_quality * 12) / 70) as u8);
+                    for (i, (label, value)) in [("BM", data.boom), ("GN", data.genoa)].into_iter().enumerate() {
+                        let channel = &channels[3 + i];
+                        let y = 18 + i as u8 * 9;
+                        if data.auto_trim {
+                            display_buffer.draw_text_inverted(12, y, label);
+                        } else {
+                            display_buffer.draw_text(12, y, label);
+                        }
+                        display_buffer.draw_hbar(Rect::new(32, y, 40, 7), value, channel.min, channel.max);
+                        display_buffer.draw_text(74, y, &value.to_string());
+                    }
+                    draw_gimbal(&mut display_buffer, Rect::new(100, 18, 28, 16), data.gimbal_pan, data.gimbal_tilt, &channels[7..9]);
 
-                    let wifi = format!("L: {}", data.latency);
-                    display_buffer.draw_text(80, 56, &wifi);
+                    let bow = &channels[6];
+                    display_buffer.draw_text(12, 46, "BOW");
+                    display_buffer.draw_hbar_centered(Rect::new(32, 46, 40, 7), data.bow_thruster, bow.min, bow.center, bow.max);
+
+                    // Warnings take the place of the throttle
+                    if data.battery_warning {
+                        display_buffer.draw_text_inverted(13, 37, "LOW BATT");
+                    } else if !data.armed {
+                        display_buffer.draw_text_inverted(13, 37, "DISARMED");
+                    } else if data.motor_cut {
+                        display_buffer.draw_text_inverted(13, 37, "MOTOR CUT");
+                    } else if data.phone_control {
+                        display_buffer.draw_text_inverted(13, 37, "PHONE");
+                    } else if data.compass_calibration {
+                        display_buffer.draw_text_inverted(13, 37, "CIRCLE");
+                    } else {
+                        let travel = if data.motor_value >= motor.center { motor.max - motor.center } else { motor.center - motor.min };
+                        let percent = (data.motor_value as i32 - motor.center as i32) * 100 / travel.max(1) as i32;
+                        display_buffer.draw_text(12, 37, &format!("MOT:{:+}%", percent));
+                    }
+
+                    if let Some(voltage) = data.battery_voltage {
+                        display_buffer.draw_text(76, 37, &format!("{:.1}V", voltage));
+                        display_buffer.draw_battery(106, 37, data.battery_percent);
+                    }
+                    if data.overridden.iter().any(Option::is_some) {
+                        display_buffer.draw_text_inverted(76, 46, "BOAT LIM");
+                    } else if data.self_test.iter().any(|check| !check.passed) {
+                        display_buffer.draw_text_inverted(76, 46, "BOAT HW");
+                    } else if data.settings.lights > 0 {
+                        display_buffer.draw_text(76, 46, &format!("LIGHTS:{}", data.settings.lights));
+                    }
+
+                    display_buffer.draw_signal_bars(0, 56, data.wireless_quality);
+                    display_buffer.draw_sparkline(24, 56, 8, &latency_history, LATENCY_MIN_SCALE_MS);
+
+                    let wifi = format!("L:{}", data.latency);
+                    draw_latency(&mut display_buffer, 90, 56, &wifi, data.latency);
                     
                     // let extra = "* &".to_string();
                     // display_buffer.draw_text(0, 56, &extra);
@@ -247,6 +438,11 @@ pub fn display_thread(rx: Receiver<DisplayData>) {
 
                     let settings = format!("Channel: {}", data.settings.current_channel_name());
                     display_buffer.draw_text(0, 12, &settings);
+
+                    let profile = format!("Profile: {}", data.settings.profile);
+                    display_buffer.draw_text(0, 24, &profile);
+
+                    display_buffer.draw_text(0, 56, "HOLD DN:CALIBRATE");
                 }
                 ControlMode::SettingsValue => {
                     display_buffer.draw_text(0, 0, &mode_settings);
@@ -257,9 +453,90 @@ pub fn display_thread(rx: Receiver<DisplayData>) {
                     let value_name = format!("Settings: {:?}", data.settings.current_value);
                     display_buffer.draw_text(0, 24, &value_name);
                     
-                    let value = format!("Value: {}", data.settings.get_value());
+                    let value = format!("Value: {}", data.settings.value_text());
                     display_buffer.draw_text(0, 36, &value);
                 }
+                ControlMode::Profile => {
+                    display_buffer.draw_text(0, 0, &mode_settings);
+
+                    let profile = format!("Profile: {}", data.settings.profile);
+                    display_buffer.draw_text(0, 12, &profile);
+
+                    let selected = format!("> {}", data.settings.selected_profile_name());
+                    display_buffer.draw_text(0, 24, &selected);
+
+                    display_buffer.draw_text(0, 48, "UP:NEW OK:LOAD");
+                    display_buffer.draw_text(0, 56, "DN:SYSTEM");
+                }
+                ControlMode::Snapshot => {
+                    display_buffer.draw_text(0, 0, &mode_settings);
+
+                    let profile = format!("Profile: {}", data.settings.profile);
+                    display_buffer.draw_text(0, 12, &profile);
+
+                    let selected = format!("> {}", data.settings.selected_snapshot_name());
+                    display_buffer.draw_text(0, 24, &selected);
+
+                    display_buffer.draw_text(0, 56, "UP:SAVE OK:LOAD");
+                }
+                ControlMode::Factory => {
+                    display_buffer.draw_text(0, 0, "Factory");
+
+                    let settings = format!("Channel: {}", data.settings.current_channel_name());
+                    display_buffer.draw_text(0, 10, &settings);
+
+                    let current = data.settings.current_channel();
+                    let factory = data.settings.factory_channel();
+                    display_buffer.draw_text(0, 20, &format!("CTR {} > {}", current.center, factory.center));
+                    display_buffer.draw_text(0, 28, &format!("MIN {} > {}", current.min, factory.min));
+                    display_buffer.draw_text(0, 36, &format!("MAX {} > {}", current.max, factory.max));
+                    display_buffer.draw_text(0, 44, &format!("DZ {} > {}", current.deadzone, factory.deadzone));
+
+                    display_buffer.draw_text(0, 56, "OK:RESET X:BACK");
+                }
+                ControlMode::System => {
+                    display_buffer.draw_text(0, 0, "System");
+
+                    let dim = if data.settings.display_dim_s == 0 { String::from("never") } else { format!("{}s", data.settings.display_dim_s) };
+                    let items = [
+                        (SystemItem::LedGauge, format!("LED: {:?}", data.settings.led_gauge)),
+                        (SystemItem::Contrast, format!("Contrast: {}", data.settings.display_contrast)),
+                        (SystemItem::DimDelay, format!("Dim after: {}", dim)),
+                        (SystemItem::Boat, format!("Boat: {}", data.settings.boat_id)),
+                    ];
+                    for (i, (item, text)) in items.iter().enumerate() {
+                        let y = 12 + i as u8 * 10;
+                        if *item == data.settings.system_item {
+                            display_buffer.draw_text(0, y, ">");
+                        }
+                        display_buffer.draw_text(8, y, text);
+                    }
+
+                    display_buffer.draw_text(0, 56, "UP/DN:CHANGE OK:SAVE");
+                }
+                ControlMode::Calibration => {
+                    display_buffer.draw_text(0, 0, "Calibration");
+
+                    if let Some(ref calibration) = data.settings.calibration {
+                        match calibration.step {
+                            CalibrationStep::Center => {
+                                display_buffer.draw_text(0, 12, "Center sticks");
+                            }
+                            _ => {
+                                display_buffer.draw_text(0, 12, "Move to extremes");
+                                let inputs: Vec<usize> = crate::CHANNEL_ADC.iter().flatten().copied()
+                                    .filter(|&i| i < calibration.min.len()).collect();
+                                for (line, pair) in inputs.chunks(2).enumerate().take(4) {
+                                    let text: Vec<String> = pair.iter()
+                                        .map(|&i| format!("{}:{}-{}", i, calibration.min[i], calibration.max[i]))
+                                        .collect();
+                                    display_buffer.draw_text(0, 22 + line as u8 * 8, &text.join(" "));
+                                }
+                            }
+                        }
+                    }
+                    display_buffer.draw_text(0, 56, "OK:NEXT X:CANCEL");
+                }
             }
             
             /*
@@ -285,10 +562,24 @@ pub fn display_thread(rx: Receiver<DisplayData>) {
             }
             */
             
-            if let Err(e) = display.display(&display_buffer) {
-                eprintln!("Display error: {}", e);
+            // Alert banner flashing over whatever page is shown
+            if let Some(alert) = data.alert {
+                let flash = started.elapsed().as_millis() % 1000 < 500;
+                display_buffer.draw_banner(22, alert.text(), flash);
+            }
+            
+            match display.display(&display_buffer) {
+                Ok(()) => display_buffer.mark_sent(),
+                Err(e) => error!("Display error: {}", e),
             }
             
+        } else {
+            display_buffer.clear();
+            draw_boot(&mut display_buffer, &boot_checks);
+            match display.display(&display_buffer) {
+                Ok(()) => display_buffer.mark_sent(),
+                Err(e) => error!("Display error: {}", e),
+            }
         }
     }
 }
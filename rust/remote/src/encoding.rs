@@ -0,0 +1,82 @@
+use serde_json::{Map, Value};
+use std::time::{Duration, Instant};
+
+use crate::config::CommandEncoding;
+use pizboat_protocol::{Command, Message};
+
+// Sent in every message, the boat needing them to check it is addressed, answer and measure the link
+const ALWAYS_SENT: [&str; 4] = ["type", "boat_id", "timestamp", "remote_timestamp"];
+
+/// Encodes the commands of one client, whole or as deltas.
+///
+/// A delta holds the fields changed since the last keyframe, not since the last message:
+/// a lost delta is made up by the next one, and a keyframe with all the fields goes out every period.
+pub struct CommandEncoder {
+    encoding: CommandEncoding,
+    keyframe_period: Duration,
+    keyframe: Option<(Map<String, Value>, Instant)>,
+}
+
+impl CommandEncoder {
+    pub fn new(encoding: CommandEncoding, keyframe_period: Duration) -> Self {
+        CommandEncoder { encoding, keyframe_period, keyframe: None }
+    }
+
+    pub fn encode(&mut self, command: &Command) -> Result<String, serde_json::Error> {
+        let message = Message::Command(command.clone());
+        let Value::Object(fields) = serde_json::to_value(&message)? else {
+            return serde_json::to_string(&message);
+        };
+        if self.encoding == CommandEncoding::Full {
+            return serde_json::to_string(&fields);
+        }
+        match &self.keyframe {
+            Some((keyframe, at)) if at.elapsed() < self.keyframe_period => {
+                let delta: Map<String, Value> = fields.into_iter()
+                    .filter(|(name, value)| ALWAYS_SENT.contains(&name.as_str()) || keyframe.get(name) != Some(value))
+                    .collect();
+                serde_json::to_string(&delta)
+            }
+            _ => {
+                let json = serde_json::to_string(&fields);
+                self.keyframe = Some((fields, Instant::now()));
+                json
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(motor: u16) -> Command {
+        Command {
+            boat_id: Some(1), timestamp: 1, remote_timestamp: Some(2),
+            rudder_star: Some(1500), rudder_port: Some(1500), motor: Some(motor), boom: Some(1200), genoa: Some(1200),
+            bow_thruster: Some(1500), gimbal_pan: Some(1500), gimbal_tilt: Some(1500),
+            nav_lights: Some(false), spotlight: Some(false), deck_light: Some(false), auto_trim: Some(false), calibrate_compass: Some(false),
+            wireless_quality: None, wireless_signal: None,
+        }
+    }
+
+    fn fields(json: &str) -> Vec<String> {
+        let Ok(Value::Object(fields)) = serde_json::from_str::<Value>(json) else { panic!("not an object: {}", json) };
+        fields.keys().cloned().collect()
+    }
+
+    #[test]
+    fn test_delta_since_keyframe() {
+        let mut encoder = CommandEncoder::new(CommandEncoding::Delta, Duration::from_secs(60));
+        assert_eq!(fields(&encoder.encode(&command(1500)).unwrap()).len(), 19);
+        assert_eq!(fields(&encoder.encode(&command(1500)).unwrap()), ["boat_id", "remote_timestamp", "timestamp", "type"]);
+        // Still sent while it differs from the keyframe
+        let json = encoder.encode(&command(1600)).unwrap();
+        assert!(json.contains("\"motor\":1600"));
+        assert!(encoder.encode(&command(1600)).unwrap().contains("\"motor\":1600"));
+
+        let mut encoder = CommandEncoder::new(CommandEncoding::Delta, Duration::ZERO);
+        encoder.encode(&command(1500)).unwrap();
+        assert_eq!(fields(&encoder.encode(&command(1500)).unwrap()).len(), 19);
+    }
+}
@@ -0,0 +1,70 @@
+use serde::{Serialize, Deserialize};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use tracing::info;
+
+// Events kept in memory, the oldest dropped first
+const EVENT_LOG_SIZE: usize = 100;
+
+static EVENTS: Mutex<EventLog> = Mutex::new(EventLog::new(EVENT_LOG_SIZE));
+
+/// Notable event, stamped with the local time of day
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Event {
+    pub time: String,   // HH:MM:SS
+    pub text: String,
+}
+
+/// Ring buffer of the last events
+pub struct EventLog {
+    events: VecDeque<Event>,
+    capacity: usize,
+}
+
+impl EventLog {
+    pub const fn new(capacity: usize) -> Self {
+        EventLog { events: VecDeque::new(), capacity }
+    }
+
+    pub fn push(&mut self, event: Event) {
+        if self.events.len() >= self.capacity {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    /// Up to `count` events, newest first
+    pub fn recent(&self, count: usize) -> Vec<Event> {
+        self.events.iter().rev().take(count).cloned().collect()
+    }
+}
+
+/// Keep an event for the display page and the API, logging it as well.
+/// Process wide, so that any thread can record what it saw.
+pub fn record(text: impl Into<String>) {
+    let text = text.into();
+    info!("[EVENT] {}", text);
+    let time = chrono::Local::now().format("%H:%M:%S").to_string();
+    EVENTS.lock().unwrap().push(Event { time, text });
+}
+
+/// Up to `count` of the recorded events, newest first
+pub fn recent(count: usize) -> Vec<Event> {
+    EVENTS.lock().unwrap().recent(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_oldest_events_dropped() {
+        let mut log = EventLog::new(3);
+        for i in 0..5 {
+            log.push(Event { time: String::from("12:00:00"), text: i.to_string() });
+        }
+        let texts: Vec<String> = log.recent(10).into_iter().map(|e| e.text).collect();
+        assert_eq!(texts, vec!["4", "3", "2"]);
+        assert_eq!(log.recent(1)[0].text, "4");
+    }
+}
@@ -0,0 +1,113 @@
+use rppal::gpio::Level;
+use std::fs::File;
+use std::io::Read;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::bluetooth;
+use tracing::{error, info, warn};
+
+// Linux joystick API device, no extra dependency needed
+pub const GAMEPAD_DEVICE: &str = "/dev/input/js0";
+
+// struct js_event: u32 time, i16 value, u8 type, u8 number
+const JS_EVENT_SIZE: usize = 8;
+const JS_EVENT_BUTTON: u8 = 0x01;
+const JS_EVENT_AXIS: u8 = 0x02;
+// Set on the synthetic events giving the initial state
+const JS_EVENT_INIT: u8 = 0x80;
+
+/// USB or Bluetooth gamepad read as an alternative to the sticks and buttons of the remote
+pub struct Gamepad {
+    axes: Arc<Mutex<Vec<i16>>>,
+    // The pots take over while the gamepad is away, instead of centered axes
+    over_pots: bool,
+}
+
+impl Gamepad {
+    /// Read the gamepad in a thread, its buttons are sent as level changes of the mapped remote buttons
+    pub fn start(device: &'static str, buttons: Sender<(usize, Level, Instant)>, button_map: Vec<usize>) -> Self {
+        let axes = Arc::new(Mutex::new(Vec::new()));
+        let axes_clone = Arc::clone(&axes);
+        thread::spawn(move || gamepad_thread(|| Ok(device.to_string()), axes_clone, buttons, button_map));
+        Gamepad { axes, over_pots: false }
+    }
+
+    /// Paired and connected through BlueZ, reconnected when it comes back, the pots working while it is away
+    pub fn start_bluetooth(address: String, buttons: Sender<(usize, Level, Instant)>, button_map: Vec<usize>) -> Self {
+        let axes = Arc::new(Mutex::new(Vec::new()));
+        let axes_clone = Arc::clone(&axes);
+        let device = move || {
+            bluetooth::connect(&address)?;
+            bluetooth::joystick_device(&address).ok_or_else(|| format!("no joystick device for {}", address).into())
+        };
+        thread::spawn(move || gamepad_thread(device, axes_clone, buttons, button_map));
+        Gamepad { axes, over_pots: true }
+    }
+
+    /// Replace the ADC values fed by gamepad axes, axis_map giving the ADC input of each axis
+    pub fn apply(&self, axis_map: &[usize], adc_values: &mut [u16], adc_bits: u8) {
+        let full_scale = (1i32 << adc_bits) - 1;
+        let axes = self.axes.lock().unwrap();
+        if self.over_pots && axes.is_empty() {
+            return;
+        }
+        for (axis, &adc) in axis_map.iter().enumerate() {
+            // Axes not reported yet (or unplugged gamepad) are centered
+            let value = axes.get(axis).copied().unwrap_or(0);
+            if adc < adc_values.len() {
+                // Full axis range onto the ADC range, 0 landing on the default ADC center
+                adc_values[adc] = ((value as i64 + 32768) * full_scale as i64 / 65535) as u16;
+            }
+        }
+    }
+}
+
+// `device` gives the path to open, once the gamepad is there
+fn gamepad_thread(device: impl Fn() -> Result<String, Box<dyn std::error::Error>>, axes: Arc<Mutex<Vec<i16>>>,
+                  buttons: Sender<(usize, Level, Instant)>, button_map: Vec<usize>) {
+    loop {
+        let opened = device().and_then(|device| Ok((File::open(&device)?, device)));
+        let (mut file, device) = match opened {
+            Ok(opened) => opened,
+            Err(e) => {
+                error!("Could not open gamepad: {}", e);
+                thread::sleep(Duration::from_secs(5));
+                continue;
+            }
+        };
+        info!("Gamepad reading from {}", device);
+
+        let mut event = [0u8; JS_EVENT_SIZE];
+        while let Ok(()) = file.read_exact(&mut event) {
+            let value = i16::from_le_bytes([event[4], event[5]]);
+            let number = event[7] as usize;
+            match event[6] & !JS_EVENT_INIT {
+                JS_EVENT_AXIS => {
+                    let mut axes = axes.lock().unwrap();
+                    if axes.len() <= number {
+                        axes.resize(number + 1, 0);
+                    }
+                    axes[number] = value;
+                }
+                JS_EVENT_BUTTON => {
+                    if let Some(&button) = button_map.get(number) {
+                        let level = if value != 0 { Level::High } else { Level::Low };
+                        let _ = buttons.send((button, level, Instant::now()));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // Unplugged: center the axes and release the buttons
+        warn!("Gamepad {} disconnected", device);
+        axes.lock().unwrap().clear();
+        for &button in &button_map {
+            let _ = buttons.send((button, Level::Low, Instant::now()));
+        }
+        thread::sleep(Duration::from_secs(1));
+    }
+}
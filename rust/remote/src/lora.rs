@@ -0,0 +1,160 @@
+use pizboat_lora::{Command, Frame, Sx127x};
+use serde::{Serialize, Deserialize};
+use rppal::spi::{Mode, Spi};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::Settings;
+use crate::event_log;
+use tracing::{error, info};
+
+const LORA_SPI_CLOCK_HZ: u32 = 1_000_000;
+// Time between two heartbeats while engaged, a frame taking about 150 ms on air
+const HEARTBEAT_PERIOD: Duration = Duration::from_millis(500);
+// Time left to the boat to answer a heartbeat
+const ACK_WINDOW: Duration = Duration::from_millis(300);
+const RECEIVE_POLL: Duration = Duration::from_millis(10);
+// Without ack for that long, the boat is out of LoRa range too
+const ACK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Switches to LoRa when the Wi-Fi link is too slow or gone, with hysteresis to come back
+pub struct Failover {
+    latency_ms: u64,
+    fallback: Duration,
+    engaged: bool,
+    good_since: Option<Instant>,
+}
+
+impl Failover {
+    pub fn new(latency_ms: u64, fallback: Duration) -> Self {
+        Failover { latency_ms, fallback, engaged: false, good_since: None }
+    }
+
+    /// Engaged at once on a bad Wi-Fi link, released once it stayed good for the fallback time
+    pub fn update(&mut self, wifi_up: bool, latency_ms: u64, now: Instant) -> bool {
+        if !wifi_up || latency_ms > self.latency_ms {
+            self.engaged = true;
+            self.good_since = None;
+        } else if self.engaged {
+            let good_since = *self.good_since.get_or_insert(now);
+            self.engaged = now.duration_since(good_since) < self.fallback;
+        }
+        self.engaged
+    }
+}
+
+/// What the main loop shows of the backup link
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LoraStatus {
+    pub engaged: bool,
+    pub rssi_dbm: Option<i16>,  // Of the last ack, None when the boat does not answer
+}
+
+#[derive(Default)]
+struct LinkState {
+    engaged: bool,
+    command: Command,
+    last_ack: Option<(Instant, i16)>,
+}
+
+/// Backup control link over an SX127x, idle until the failover engages.
+/// It then sends heartbeats carrying the motor cut and rudder center orders, the boat acking each.
+pub struct LoraLink {
+    state: Arc<Mutex<LinkState>>,
+    failover: Failover,
+}
+
+impl LoraLink {
+    pub fn start(settings: &Settings) -> Result<Self, Box<dyn std::error::Error>> {
+        let spi = Spi::new(settings.lora_spi.bus(), settings.lora_spi.slave_select(), LORA_SPI_CLOCK_HZ, Mode::Mode0)?;
+        let radio = Sx127x::new(spi, settings.lora_frequency_hz)?;
+        info!("LoRa backup link on {} Hz", settings.lora_frequency_hz);
+        let state = Arc::new(Mutex::new(LinkState::default()));
+        let state_clone = Arc::clone(&state);
+        thread::spawn(move || lora_thread(radio, state_clone));
+        let failover = Failover::new(settings.lora_failover_latency_ms as u64, Duration::from_millis(settings.lora_fallback_ms as u64));
+        Ok(LoraLink { state, failover })
+    }
+
+    /// Called every loop with the Wi-Fi link state and the critical orders to relay
+    pub fn update(&mut self, wifi_up: bool, latency_ms: u64, motor_cut: bool, rudder_center: bool) -> LoraStatus {
+        let engaged = self.failover.update(wifi_up, latency_ms, Instant::now());
+        let mut state = self.state.lock().unwrap();
+        if engaged != state.engaged {
+            event_log::record(if engaged { "LoRa failover engaged" } else { "Wi-Fi back, LoRa released" });
+        }
+        state.engaged = engaged;
+        state.command.motor_cut = motor_cut;
+        state.command.rudder_center = rudder_center;
+        let rssi_dbm = state.last_ack.filter(|(at, _)| at.elapsed() < ACK_TIMEOUT).map(|(_, rssi)| rssi);
+        LoraStatus { engaged, rssi_dbm }
+    }
+}
+
+fn lora_thread(mut radio: Sx127x<Spi>, state: Arc<Mutex<LinkState>>) {
+    let mut seq: u16 = 0;
+    let mut failed = false;
+    loop {
+        let start = Instant::now();
+        let command = {
+            let state = state.lock().unwrap();
+            state.engaged.then_some(state.command)
+        };
+        if let Some(command) = command {
+            seq = seq.wrapping_add(1);
+            let frame = Frame::Command(Command { seq, ..command });
+            match heartbeat(&mut radio, &frame, seq) {
+                Ok(ack) => {
+                    failed = false;
+                    if let Some(rssi_dbm) = ack {
+                        state.lock().unwrap().last_ack = Some((Instant::now(), rssi_dbm));
+                    }
+                }
+                // Once per failure
+                Err(e) if !failed => {
+                    error!("LoRa error: {}", e);
+                    failed = true;
+                }
+                Err(_) => {}
+            }
+        }
+        thread::sleep(HEARTBEAT_PERIOD.saturating_sub(start.elapsed()));
+    }
+}
+
+// RSSI of the ack when the boat answered
+fn heartbeat(radio: &mut Sx127x<Spi>, frame: &Frame, seq: u16) -> Result<Option<i16>, Box<dyn std::error::Error>> {
+    radio.send(&frame.encode())?;
+    radio.listen()?;
+    let start = Instant::now();
+    while start.elapsed() < ACK_WINDOW {
+        if let Some(packet) = radio.receive()?
+            && Frame::decode(&packet.payload) == Some(Frame::Ack(seq))
+        {
+            return Ok(Some(packet.rssi_dbm));
+        }
+        thread::sleep(RECEIVE_POLL);
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failover_hysteresis() {
+        let mut failover = Failover::new(400, Duration::from_secs(3));
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        assert!(!failover.update(true, 50, at(0)));
+        assert!(failover.update(true, 600, at(100)));
+        // Good again, held until it stays good for 3 s
+        assert!(failover.update(true, 50, at(200)));
+        assert!(failover.update(true, 50, at(3100)));
+        assert!(failover.update(false, 0, at(3150)));
+        assert!(failover.update(true, 50, at(3200)));
+        assert!(!failover.update(true, 50, at(6200)));
+    }
+}
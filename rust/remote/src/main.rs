@@ -4,46 +4,139 @@ mod adc;
 mod buttons;
 mod websocket;
 mod octled;
+mod mixer;
+mod calibration;
+mod battery;
+mod buzzer;
+mod race_timer;
+mod gamepad;
+mod bluetooth;
+mod touch;
+mod encoding;
+mod ppm;
+mod sim;
+mod api;
+mod alerts;
+mod recorder;
+mod telemetry_log;
+mod mqtt;
+mod metrics;
+mod signalk;
+mod udp_telemetry;
+mod boot;
+mod event_log;
+mod lora;
+mod nrf24;
 
-use websocket::{websocket_thread, CommandMessage, QueryMessage};
-use config::{Settings, ControlMode};
-use display::{DisplayData, display_thread};
-use adc::AdcReader;
+use websocket::{websocket_thread, BoatQuery, LinkContext, LinkStats, OUTPUT_COUNT};
+use pizboat_protocol::{Command, Failsafe, TelemetryValue};
+use pizboat_link::wireless;
+use config::{Settings, ControlMode, LedGauge, InputBackend, DisplayBus, LinkTransport};
+use display::{DisplayData, DisplayMessage, DisplayPage, display_thread};
+use adc::{AdcReader, AdcSampler};
 use buttons::{ButtonReader, Edge};
-use octled::OctLed;
+use octled::{OctLed, LedState, octled_thread};
+use battery::BatteryMonitor;
+use buzzer::BuzzerEvent;
+use race_timer::RaceTimer;
+use gamepad::{Gamepad, GAMEPAD_DEVICE};
+use ppm::PpmInput;
+use touch::{TouchControl, TouchLink};
+use sim::SimInput;
+use api::{ApiRequest, api_thread};
+use alerts::{Alert, AlertQueue};
+use recorder::{Recorder, SESSIONS_DIR};
+use telemetry_log::{TelemetryRecord, TelemetrySender};
+use metrics::Metrics;
+use boot::BootReporter;
+use lora::LoraLink;
 
+use std::collections::BTreeMap;
 use std::sync::mpsc::{self, SyncSender, Receiver};
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::path::Path;
+use std::time::{Duration, Instant};
 
 use rppal::gpio::Gpio;
+use tracing::{debug, error, info, trace, warn};
 
 const BUTTON_PINS: [u8; 6] = [0, 25, 24, 23, 18, 15];
 const LED_PINS: [u8; 8] = [16, 20, 21, 26, 19, 13, 6, 5];
 
 const MISC_PIN: u8 = 12;
 const MISC_ADC: usize = 2;
+const BOW_THRUSTER_ADC: usize = 3;
+const GIMBAL_PAN_ADC: usize = 4;
+const GIMBAL_TILT_ADC: usize = 5;
+const RUDDER_ADC: usize = 6;
+const MOTOR_ADC: usize = 7;
+// Transmitter battery through a divider, on the second MCP3008
+const BATTERY_ADC: usize = 8;
+const BUZZER_PIN: u8 = 27;
+// PPM sum of the trainer port, off the DC (22) and RST (17) pins of the SPI display
+const PPM_PIN: u8 = 4;
+// Enable line of the nRF24L01, on the UART TX pin, the serial port being off for the button on GPIO 15
+const NRF24_CE_PIN: u8 = 14;
+// Latency reported by the boat above which the telemetry alarm sounds
+const LATENCY_ALARM_MS: u64 = 500;
+// Repeat of the NO BOAT beeps until the boat comes back or the alert is dismissed
+const NO_BOAT_BEEP_PERIOD: Duration = Duration::from_secs(5);
+// Boat battery range shown on the LED gauge, 3S LiPo
+const BOAT_BATTERY_EMPTY: f32 = 9.9;
+const BOAT_BATTERY_FULL: f32 = 12.6;
+// Control loop sleep between two iterations
+const LOOP_PERIOD_MS: u64 = 40;
 
-const ADC_CHANNELS: usize = 8;
+// ADC input of each channel driven by transform_adc, boom and genoa knobs are not sticks
+const CHANNEL_ADC: [Option<usize>; 9] = [
+    Some(RUDDER_ADC), Some(RUDDER_ADC), Some(MOTOR_ADC), None, None,
+    Some(MISC_ADC), Some(BOW_THRUSTER_ADC), Some(GIMBAL_PAN_ADC), Some(GIMBAL_TILT_ADC)
+];
+
+// Two MCP3008, the second one being optional
+const ADC_CHANNELS: usize = 16;
 // const DISPLAY_CHANNELS: [usize; 5] = [0, 1, 2, 6, 7];
 
-fn handle_buttons_for_settings(settings: &mut Settings, button_reader: &mut ButtonReader) {
+/// Returns whether any button moved, to wake the display up
+fn handle_buttons_for_settings(settings: &mut Settings, button_reader: &mut ButtonReader, page: &mut DisplayPage, alerts: &mut AlertQueue) -> bool {
     let edges = button_reader.read_and_detect_edges();
+    let any_input = !edges.is_empty();
         
     // Handle button events based on mode
-    for (i, &edge) in edges.iter().enumerate() {
-        if let Some(Edge::Falling) = edge {
-            println!("[EVENT] Button {} pressed in mode {:?}", i, settings.mode);
-            settings.handle_button(i);
+    for (i, edge) in edges {
+        match edge {
+            // Cancel dismisses the alert shown instead of acting on the page below
+            Edge::Falling if i == settings.button_map.cancel && alerts.top().is_some() => {
+                event_log::record(format!("Alert {:?} dismissed", alerts.top()));
+                alerts.dismiss();
+            }
+            Edge::Falling => {
+                info!("[EVENT] Button {} pressed in mode {:?}", i, settings.mode);
+                buzzer::play(BuzzerEvent::ButtonPress);
+                settings.handle_button(i);
+            }
+            // Long press on the lights button cycles the display pages
+            Edge::LongPress if i == settings.button_map.right && settings.mode == ControlMode::Normal => {
+                *page = page.next();
+                info!("[EVENT] Display page {:?}", page);
+                buzzer::play(BuzzerEvent::ButtonPress);
+            }
+            Edge::LongPress => {
+                info!("[EVENT] Button {} long press in mode {:?}", i, settings.mode);
+                settings.handle_long_press(i);
+            }
+            Edge::DoubleClick => {
+                info!("[EVENT] Button {} double click in mode {:?}", i, settings.mode);
+                buzzer::play(BuzzerEvent::ButtonPress);
+                settings.handle_double_click(i);
+            }
+            _ => {}
         }
     }
+    any_input
 }
 
-const BUTTON_BOOM_UP:    usize = 0;
-const BUTTON_BOOM_DOWN:  usize = 3;
-const BUTTON_GENOA_UP:   usize = 1;
-const BUTTON_GENOA_DOWN: usize = 4;
 
 const PERIOD_MS: u64 = 20;
 const PULSE_MIN_US: u64 = 1200;
@@ -52,103 +145,482 @@ const PULSE_MAX_US: u64 = 1800;
 
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    println!("Starting RC Boat Controller with WebSocket");
+    // Desktop development: stdin instead of ADC, buttons and other GPIO
+    let sim = std::env::args().any(|arg| arg == "--sim");
+    // Log the boat traffic, or serve a logged session again instead of driving the boat
+    let record = std::env::args().any(|arg| arg == "--record");
+    let replay = std::env::args().skip_while(|arg| arg != "--replay").nth(1);
 
-    let mut button_reader = ButtonReader::new(&BUTTON_PINS)?;
-    let mut adc_reader = AdcReader::new()?;
+    let mut settings = Settings::new("settings.json");
     
-    let mut led = OctLed::new(&LED_PINS)?;
+    let zero_buttons = vec![false; BUTTON_PINS.len()];
     
-    led.k2000();
+    // Loaded first for its log settings, the outcome being logged once they apply
+    let loaded = settings.load();
+    let log_dir = Some(settings.log_dir.as_str()).filter(|dir| !dir.is_empty()).map(Path::new);
+    let _log_guard = pizboat_logging::init("remote", &settings.log_filter, log_dir, settings.log_max_files.into());
+    info!("Starting RC Boat Controller with WebSocket");
     
-    let (tx_display, rx_display): (SyncSender<DisplayData>, Receiver<DisplayData>) = mpsc::sync_channel(1);
+    match loaded {
+        Ok(_) => info!("Loaded successfully"),
+        Err(e) => {
+            warn!("Error loading: {}", e);
+        }
+    }
     
+    // Up first, to show the boot screen while the rest of the hardware starts
+    let (tx_display, rx_display): (SyncSender<DisplayMessage>, Receiver<DisplayMessage>) = mpsc::sync_channel(1);
+    // The sim has no panel, its pages are drawn on the terminal
+    let display_bus = if sim { DisplayBus::Terminal } else { settings.display_bus };
+    // Without the pots hardware, the gamepad or stdin feed the ADC values
+    let input = if sim { InputBackend::Sim } else { settings.input };
+    // The sim opens no SPI device
+    if !sim && let Some(conflict) = settings.spi_conflict(display_bus, input) {
+        error!("Not starting, {}: set them apart", conflict);
+        return Err(conflict.into());
+    }
+    let display_spi = settings.display_spi;
     thread::spawn(move || {
-        display_thread(rx_display);
+        display_thread(rx_display, display_bus, display_spi);
     });
+    let mut boot = BootReporter::new(tx_display.clone());
 
-    let data_mutex: Arc<Mutex<Option<CommandMessage>>> = Arc::new(Mutex::new(None));
-    let query_mutex: Arc<Mutex<Option<QueryMessage>>> = Arc::new(Mutex::new(None));
+    let buttons = if sim { Ok(ButtonReader::simulated(BUTTON_PINS.len())) } else { ButtonReader::new(&BUTTON_PINS) };
+    let mut button_reader = boot.fatal("Buttons", buttons)?;
+    
+    let (tx_led, rx_led): (SyncSender<LedState>, Receiver<LedState>) = mpsc::sync_channel(1);
+    
+    if !sim {
+        let mut led = boot.fatal("LEDs", OctLed::new(&LED_PINS))?;
+        
+        led.k2000();
+        
+        thread::spawn(move || {
+            octled_thread(led, rx_led);
+        });
+    }
+    
+    let data_mutex: Arc<Mutex<Option<Command>>> = Arc::new(Mutex::new(None));
+    let query_mutex: Arc<Mutex<Option<BoatQuery>>> = Arc::new(Mutex::new(None));
+    let failsafe_mutex: Arc<Mutex<Option<Failsafe>>> = Arc::new(Mutex::new(None));
+    let link_mutex: Arc<Mutex<Option<LinkStats>>> = Arc::new(Mutex::new(None));
+    let metrics = Arc::new(Metrics::new());
+    let alarms_mutex: Arc<Mutex<Vec<Alert>>> = Arc::new(Mutex::new(Vec::new()));
 
-    let data_mutex_clone = Arc::clone(&data_mutex);
-    let query_mutex_clone = Arc::clone(&query_mutex);
-    thread::spawn(move || {
-        websocket_thread(data_mutex_clone, query_mutex_clone);
-    });
+    let settings_mutex: Arc<Mutex<Option<Settings>>> = Arc::new(Mutex::new(None));
+    let (tx_api, rx_api) = mpsc::channel::<ApiRequest>();
 
-    let mut settings = Settings::new("settings.json");
+    let recorder = if record { Some(Arc::new(Recorder::create(SESSIONS_DIR)?)) } else { None };
+    let link_context = LinkContext {
+        data_mutex: Arc::clone(&data_mutex),
+        query_mutex: Arc::clone(&query_mutex),
+        failsafe_mutex: Arc::clone(&failsafe_mutex),
+        settings_mutex: Arc::clone(&settings_mutex),
+        requests: tx_api.clone(),
+        recorder,
+        link_mutex: Arc::clone(&link_mutex),
+        metrics: Arc::clone(&metrics),
+        alarms_mutex: Arc::clone(&alarms_mutex),
+    };
+    // Without the server the remote still drives its outputs, the failure is only held on screen
+    if let Some(server) = boot.check("Network", websocket::bind()) {
+        let link_context = link_context.clone();
+        thread::spawn(move || {
+            websocket_thread(server, link_context);
+        });
+    }
+    if settings.link_transport == LinkTransport::Nrf24 && !sim {
+        boot.check("nRF24", nrf24::start(settings.nrf24_channel, settings.nrf24_spi, NRF24_CE_PIN, link_context));
+    }
     
-    let zero_buttons = vec![false; 6];
+    if let Some(path) = replay {
+        return recorder::replay(&path, &data_mutex);
+    }
     
-    match settings.load() {
-        Ok(_) => println!("Loaded successfully"),
-        Err(e) => {
-            println!("Error loading: {}", e);
+    let touch_link = settings.touch_control.then(TouchLink::default);
+    let mut touch_control = touch_link.clone().map(TouchControl::new);
+    let settings_mutex_clone = Arc::clone(&settings_mutex);
+    let metrics_clone = Arc::clone(&metrics);
+    thread::spawn(move || {
+        api_thread(settings_mutex_clone, tx_api, metrics_clone, touch_link);
+    });
+
+    let (mut adc_sampler, mut gamepad, mut sim_input, mut ppm) = (None, None, None, None);
+    match input {
+        InputBackend::Adc | InputBackend::Ppm | InputBackend::Bluetooth => {
+            let adc = AdcReader::new(settings.adc_oversample, settings.adc_second_chip.then_some(settings.adc_second_spi), settings.adc_bits)
+                .and_then(|reader| AdcSampler::start(reader, settings.adc_sample_rate));
+            adc_sampler = Some(boot.fatal("ADC", adc)?);
+            // The pots stay as a fallback
+            if input == InputBackend::Ppm {
+                ppm = boot.check("PPM", PpmInput::new(PPM_PIN));
+            }
+            if input == InputBackend::Bluetooth && settings.bluetooth_gamepad.is_empty() {
+                warn!("Bluetooth input without bluetooth_gamepad address, the pots only are read");
+            } else if input == InputBackend::Bluetooth {
+                gamepad = Some(Gamepad::start_bluetooth(settings.bluetooth_gamepad.clone(), button_reader.event_sender(), settings.gamepad_buttons.clone()));
+            }
         }
+        InputBackend::Gamepad => { gamepad = Some(Gamepad::start(GAMEPAD_DEVICE, button_reader.event_sender(), settings.gamepad_buttons.clone())); }
+        InputBackend::Sim => { sim_input = Some(SimInput::start(button_reader.event_sender(), settings.button_map)); }
     }
-
     
-    let mut misc_pwm = Gpio::new()?.get(MISC_PIN)?.into_output();
+    let mut misc_pwm = if sim { None } else {
+        let pin = Gpio::new().and_then(|gpio| gpio.get(MISC_PIN)).map(|pin| pin.into_output());
+        Some(boot.fatal("Misc PWM", pin)?)
+    };
+    if !sim {
+        boot.check("Buzzer", buzzer::start(BUZZER_PIN));
+    }
+    let mut lora_link = if sim || !settings.lora_enabled { None } else { boot.check("LoRa", LoraLink::start(&settings)) };
+    boot.hold_failures(&mut button_reader);
+    
+    let mut battery = BatteryMonitor::new();
+    // Log file and network sinks of the telemetry, each at its own period
+    let mut telemetry_sinks: Vec<TelemetrySender> = [
+        telemetry_log::start(&settings),
+        mqtt::start(&settings),
+        signalk::start(&settings),
+        udp_telemetry::start(&settings),
+    ].into_iter().flatten().collect();
+    let start = Instant::now();
     
 
+    if !settings.button_map.is_valid(BUTTON_PINS.len()) {
+        warn!("Button map refers to missing buttons, using the default layout");
+        settings.button_map = Default::default();
+    }
+    
     settings.save()?;
+    
+    let mut motor_cut = false;
+    let mut motor_cut_combo = false;
+    
+    // Outputs stay at failsafe values until armed
+    let mut armed = false;
+    let mut arm_combo = false;
+    let mut was_connected = false;
+    let mut ever_connected = false;
+    let mut latency_alarm = false;
+    // Last boat query, counted from start-up until the first one
+    let mut last_poll = Instant::now();
+    let mut no_boat_beep: Option<Instant> = None;
+    // Remote side Wi-Fi statistics, read at the telemetry period rather than on every loop
+    let mut local_wireless = None;
+    let mut last_wireless_read: Option<Instant> = None;
+    // Channels whose value the boat replaced with its own, such as a clamp or an eased sheet
+    let mut overridden = [None; OUTPUT_COUNT];
+    
+    let mut race_timer = RaceTimer::new();
+    let mut race_timer_combo = false;
+    // Sails trimmed by the boat from its wind angle, the sail buttons biasing its trim
+    let mut auto_trim = false;
+    let mut auto_trim_combo = false;
+    // Boat recording its magnetometer while driven in circles
+    let mut compass_calibration = false;
+    let mut compass_calibration_combo = false;
+    
+    let mut page = DisplayPage::Normal;
+    let mut last_loop = Instant::now();
+    let mut last_input = Instant::now();
+    let mut alerts = AlertQueue::new();
 
     loop {
+        let elapsed_ms = last_loop.elapsed().as_millis() as u32;
+        last_loop = Instant::now();
+        metrics.observe_loop_jitter((elapsed_ms as u64).saturating_sub(LOOP_PERIOD_MS));
         let previous_mode = settings.mode;
         
-        handle_buttons_for_settings(&mut settings, &mut button_reader);
+        if handle_buttons_for_settings(&mut settings, &mut button_reader, &mut page, &mut alerts) {
+            last_input = Instant::now();
+        }
+        if settings.mode != previous_mode {
+            buzzer::play(BuzzerEvent::ModeChange);
+        }
+        
+        while let Ok(request) = rx_api.try_recv() {
+            match request {
+                ApiRequest::SetChannel(channel, reply) => {
+                    let _ = reply.send(settings.set_channel(channel));
+                }
+                ApiRequest::Save(reply) => {
+                    // Saved in normal mode, whatever page the remote is on
+                    let mode = settings.mode;
+                    settings.mode = ControlMode::Normal;
+                    let _ = reply.send(settings.save().map_err(|e| e.to_string()));
+                    settings.mode = mode;
+                }
+            }
+        }
+        *settings_mutex.lock().unwrap() = Some(settings.clone());
         
-        let adc_values = adc_reader.read_all_channels()?;
+        let mut adc_values = match (&adc_sampler, &sim_input) {
+            (Some(sampler), _) => sampler.read_all_channels()?,
+            (None, Some(sim_input)) => sim_input.read_all_channels(settings.adc_bits),
+            (None, None) => [0u16; ADC_CHANNELS],
+        };
+        if let Some(gamepad) = &gamepad {
+            gamepad.apply(&settings.gamepad_axes, &mut adc_values, settings.adc_bits);
+        }
+        if let Some(ppm) = &ppm {
+            ppm.apply(&settings.ppm_channels, &mut adc_values, settings.adc_bits);
+        }
+        // Over any other input once the phone took control
+        let touch_buttons = touch_control.as_mut().and_then(|touch| touch.apply(RUDDER_ADC, MOTOR_ADC, &mut adc_values, settings.adc_bits));
+        
+        battery.update(adc_values[BATTERY_ADC], &settings);
+        // Warning flashes every second
+        let battery_warning = battery.is_low() && start.elapsed().as_millis() % 1000 < 500;
+        
+        if settings.mode == ControlMode::Calibration {
+            settings.update_calibration(&adc_values, &CHANNEL_ADC);
+        }
 
         // Transform ADC values (rudder on channel 0, motor on channel 1)
-        let rudder_star = settings.channels[0].transform_adc(adc_values[6]);
-        let rudder_port = settings.channels[1].transform_adc(adc_values[6]);
-        let motor_value = settings.channels[2].transform_adc(adc_values[7]);
+        let rudder_star = settings.channels[0].transform_adc(adc_values[RUDDER_ADC], elapsed_ms);
+        let rudder_port = settings.channels[1].transform_adc(adc_values[RUDDER_ADC], elapsed_ms);
+        let motor_value = settings.channels[2].transform_adc(adc_values[MOTOR_ADC], elapsed_ms);
         
-        // println!("adc 0 {} 1 {} 2 {} 6 {} 7 {}", adc_values[0], adc_values[1], adc_values[2], adc_values[6], adc_values[7]);
+        trace!("adc 0 {} 1 {} 2 {} 6 {} 7 {}", adc_values[0], adc_values[1], adc_values[2], adc_values[6], adc_values[7]);
 
         let button_states = if previous_mode == ControlMode::Normal { button_reader.get_current_states() } else { zero_buttons.clone() };
         
-        // println!("previous_mode {:?} mode {:?} button_states[0] = {}", previous_mode, settings.mode, button_states[0]);
+        trace!("previous_mode {:?} mode {:?} button_states[0] = {}", previous_mode, settings.mode, button_states[0]);
+        
+        let map = settings.button_map;
+        // Sail buttons of the phone, kept out of the button combos
+        let touch = touch_buttons.unwrap_or_default();
+        let boom = settings.channels[3].apply_button(button_states[map.boom_up] || touch.boom_up, button_states[map.boom_down] || touch.boom_down,
+            adc_values[1], elapsed_ms);
+        let genoa = settings.channels[4].apply_button(button_states[map.genoa_up] || touch.genoa_up, button_states[map.genoa_down] || touch.genoa_down,
+            adc_values[0], elapsed_ms);
+        
+        let misc = settings.channels[5].transform_adc(adc_values[MISC_ADC], elapsed_ms);
+        let bow_thruster = settings.channels[6].transform_adc(adc_values[BOW_THRUSTER_ADC], elapsed_ms);
+        let gimbal_pan = settings.channels[7].transform_adc(adc_values[GIMBAL_PAN_ADC], elapsed_ms);
+        let gimbal_tilt = settings.channels[8].transform_adc(adc_values[GIMBAL_TILT_ADC], elapsed_ms);
+        
+        // Outputs indexed like settings.channels
+        let mut outputs = [rudder_star, rudder_port, motor_value, boom, genoa, misc, bow_thruster, gimbal_pan, gimbal_tilt];
+        mixer::mix(&settings.mixer, &settings.channels, &mut outputs);
+        metrics.set_outputs(&settings.channels, &outputs);
+        
+        // Pressing both genoa buttons together arms (throttle at neutral) or disarms
+        let combo = button_states[map.genoa_up] && button_states[map.genoa_down];
+        if combo && !arm_combo {
+            if armed {
+                armed = false;
+                event_log::record("Disarmed");
+            } else if outputs[2] == settings.channels[2].center {
+                armed = true;
+                event_log::record("Armed");
+            } else {
+                event_log::record("Arming refused, throttle is not at neutral");
+            }
+        }
+        arm_combo = combo;
+        
+        // Disarm when the boat goes away, so that it does not start the instant it reconnects
+        let connected = query_mutex.lock().unwrap().is_some();
+        if was_connected && !connected {
+            buzzer::play(BuzzerEvent::LinkLost);
+            if armed {
+                armed = false;
+                event_log::record("Boat disconnected, disarmed");
+            }
+        }
+        was_connected = connected;
+        ever_connected |= connected;
+        alerts.set(Alert::LinkLost, ever_connected && !connected);
+        alerts.set(Alert::LowBattery, battery.is_low());
+        
+        // Disarmed, the boat holds what it would with the link lost; sticks are moved to their extremes while calibrating
+        if !armed || settings.mode == ControlMode::Calibration {
+            for (output, channel) in outputs.iter_mut().zip(&settings.channels) {
+                *output = if armed { channel.center } else { channel.failsafe };
+            }
+        }
+        let [rudder_star, rudder_port, motor_value, boom, genoa, misc, bow_thruster, gimbal_pan, gimbal_tilt] = outputs;
+        
+        // Pressing both boom buttons together toggles the motor cut
+        let combo = button_states[map.boom_up] && button_states[map.boom_down];
+        if combo && !motor_cut_combo {
+            motor_cut = !motor_cut;
+            event_log::record(format!("Motor cut {}", if motor_cut { "engaged" } else { "released" }));
+        }
+        motor_cut_combo = combo;
+        
+        // Pressing both up buttons together starts or resets the race timer
+        let combo = button_states[map.boom_up] && button_states[map.genoa_up];
+        if combo && !race_timer_combo {
+            if race_timer.is_running() {
+                race_timer.reset();
+            } else {
+                race_timer.start(settings.race_countdown_s);
+            }
+        }
+        race_timer_combo = combo;
+        race_timer.update();
+        
+        // Pressing both down buttons together engages or releases the auto trim, the remote taking the sails back at once
+        let combo = button_states[map.boom_down] && button_states[map.genoa_down];
+        if combo && !auto_trim_combo && armed {
+            auto_trim = !auto_trim;
+            event_log::record(format!("Auto trim {}", if auto_trim { "engaged" } else { "released" }));
+        } else if auto_trim && !armed {
+            auto_trim = false;
+            event_log::record("Disarmed, auto trim released");
+        }
+        auto_trim_combo = combo;
         
-        let boom = settings.channels[3].apply_button(button_states[BUTTON_BOOM_UP], button_states[BUTTON_BOOM_DOWN], adc_values[1]);
-        let genoa = settings.channels[4].apply_button(button_states[BUTTON_GENOA_UP], button_states[BUTTON_GENOA_DOWN], adc_values[0]);
+        // Pressing boom up and genoa down together starts the compass calibration, then ends it once the boat went round
+        let combo = button_states[map.boom_up] && button_states[map.genoa_down];
+        if combo && !compass_calibration_combo && armed {
+            compass_calibration = !compass_calibration;
+            event_log::record(format!("Compass calibration {}", if compass_calibration { "started" } else { "finished" }));
+        } else if compass_calibration && !armed {
+            compass_calibration = false;
+            event_log::record("Disarmed, compass calibration finished");
+        }
+        compass_calibration_combo = combo;
         
-        let misc = settings.channels[5].transform_adc(adc_values[MISC_ADC]);
+        // Motor cut forces neutral whatever the stick says
+        let motor_value = if motor_cut { settings.channels[2].center } else { motor_value };
         
         let misc_width_us = misc.clamp(1000, 2000);
 
-        println!("Servo at PIN {} sending {} (from {})", MISC_PIN, misc_width_us, adc_values[MISC_ADC]);
+        if let Some(misc_pwm) = misc_pwm.as_mut() {
+            debug!("Servo at PIN {} sending {} (from {})", MISC_PIN, misc_width_us, adc_values[MISC_ADC]);
 
-        misc_pwm.set_pwm(
-            Duration::from_millis(PERIOD_MS),
-            Duration::from_micros(misc_width_us.into()),
-        )?;
+            misc_pwm.set_pwm(
+                Duration::from_millis(PERIOD_MS),
+                Duration::from_micros(misc_width_us.into()),
+            )?;
+        }
        
         let mut wireless_quality: i16 = -1;
+        let mut wireless_signal: Option<i16> = None;
         let mut latency: u64 = 0;
         let mut weight: f32 = (-1) as f32;
+        let mut boat_battery: Option<f32> = None;
+        let mut position: Option<(f64, f64)> = None;
+        let mut speed: Option<f32> = None;
+        let mut heading: Option<f32> = None;
+        let mut wind_angle: Option<f32> = None;
+        let mut leak = false;
+        let mut tension_alarm = false;
+        let mut telemetry = BTreeMap::new();
+        let mut self_test = Vec::new();
         
         {
             match query_mutex.lock().unwrap().as_ref() {
-                Some(query) => { 
+                Some(BoatQuery { query, received }) => { 
                     wireless_quality = query.wireless_quality.unwrap_or(0-1);
-                    latency = query.latency.unwrap_or(0);
-                    /*
-                    if wireless_quality > 0 && wireless_quality <= 70
-                    {
-                        let qual8 = (wireless_quality * 8) / 70;
-                        led.display_value(qual8 as u8);
+                    wireless_signal = query.wireless_signal;
+                    // Measured here when the boat echoes the commands, as reported by older boats otherwise
+                    let link = *link_mutex.lock().unwrap();
+                    latency = match link {
+                        Some(link) => link.round_trip_ms,
+                        None => query.latency.unwrap_or(0),
+                    };
+                    let applied = link.map_or([None; OUTPUT_COUNT], |link| link.overridden);
+                    for ((channel, was), now) in settings.channels.iter().zip(overridden).zip(applied) {
+                        if let (None, Some(value)) = (was, now) {
+                            event_log::record(format!("Boat overrides {}: {}us", channel.name, value));
+                        }
+                    }
+                    overridden = applied;
+                    if latency > LATENCY_ALARM_MS && !latency_alarm {
+                        buzzer::play(BuzzerEvent::TelemetryAlarm);
                     }
-                    */
                     weight = query.weight.unwrap_or(0 as f32);
-                    let w8 = ((weight * 8.) / 500.) as u8;
-                    led.display_value(w8);
+                    
+                    boat_battery = query.battery_voltage;
+                    position = query.latitude.zip(query.longitude);
+                    speed = query.speed;
+                    heading = query.heading;
+                    wind_angle = query.wind_angle;
+                    leak = query.leak.unwrap_or(false);
+                    tension_alarm = query.tension_alarm.unwrap_or(false);
+                    telemetry = query.telemetry.clone();
+                    self_test = query.self_test.clone().unwrap_or_default();
+                    last_poll = last_poll.max(*received);
                 }
                 None => { }
             }
         }
         
+        latency_alarm = latency > LATENCY_ALARM_MS;
+        alerts.set(Alert::Leak, leak);
+        alerts.set(Alert::SheetEased, tension_alarm);
+        let gust = telemetry.get("wind_gust").and_then(TelemetryValue::as_f64);
+        alerts.set(Alert::Gust, settings.gust_alarm_speed > 0. && gust.is_some_and(|gust| gust > settings.gust_alarm_speed as f64));
+        let depth = telemetry.get("depth").and_then(TelemetryValue::as_f64);
+        alerts.set(Alert::Shallow, settings.shallow_alarm_depth > 0. && depth.is_some_and(|depth| depth < settings.shallow_alarm_depth as f64));
+        
+        // Boat silent, whether its connection closed or hung: beeps again while the banner is shown
+        let no_boat = last_poll.elapsed() > Duration::from_millis(settings.no_boat_timeout_ms as u64);
+        alerts.set(Alert::NoBoat, no_boat);
+        if !no_boat {
+            no_boat_beep = None;
+        } else if alerts.top() == Some(Alert::NoBoat) && no_boat_beep.is_none_or(|t| t.elapsed() >= NO_BOAT_BEEP_PERIOD) {
+            buzzer::play(BuzzerEvent::NoBoat);
+            no_boat_beep = Some(Instant::now());
+        }
+        *alarms_mutex.lock().unwrap() = alerts.active().to_vec();
+        
+        // Failsafe-critical orders relayed over LoRa while Wi-Fi is slow or gone
+        let lora = lora_link.as_mut().map(|link| {
+            link.update(connected && !no_boat, latency, motor_cut || !armed, rudder_star == settings.channels[0].center)
+        });
+        if last_wireless_read.is_none_or(|t| t.elapsed() >= Duration::from_millis(settings.telemetry_period_ms as u64)) {
+            local_wireless = wireless::read();
+            last_wireless_read = Some(Instant::now());
+        }
+        
+        // LED bar graph, in eighths
+        let gauge = match settings.led_gauge {
+            LedGauge::Weight => (weight * 8.) / 500.,
+            LedGauge::Throttle => {
+                let motor = &settings.channels[2];
+                let travel = motor.max.saturating_sub(motor.center).max(motor.center.saturating_sub(motor.min)).max(1);
+                (motor_value as f32 - motor.center as f32).abs() * 8. / travel as f32
+            }
+            LedGauge::LinkQuality => (wireless_quality * 8) as f32 / 70.,
+            LedGauge::BoatBattery => boat_battery.map_or(0., |v| (v - BOAT_BATTERY_EMPTY) * 8. / (BOAT_BATTERY_FULL - BOAT_BATTERY_EMPTY)),
+        };
+        let led_state = if connected {
+            LedState::Linked(gauge.clamp(0., 8.).round() as u8)
+        } else if ever_connected {
+            // The boat has lost the link and is applying its failsafe values
+            LedState::Failsafe
+        } else {
+            LedState::Connecting
+        };
+        let led_state = if battery.is_low() && led_state != LedState::Failsafe { LedState::LowBattery } else { led_state };
+        let _ = tx_led.try_send(led_state);
+        
+        let telemetry_record = || TelemetryRecord {
+            time_ms: chrono::Utc::now().timestamp_millis(),
+            connected,
+            no_boat,
+            wireless_quality,
+            latency,
+            weight,
+            boat_battery,
+            latitude: position.map(|p| p.0),
+            longitude: position.map(|p| p.1),
+            speed,
+            heading,
+            wind_angle,
+            battery_voltage: battery.voltage(),
+            outputs: vec![rudder_star, rudder_port, motor_value, boom, genoa, misc, bow_thruster, gimbal_pan, gimbal_tilt],
+            telemetry: telemetry.clone(),
+        };
+        for sink in telemetry_sinks.iter_mut() {
+            sink.send(telemetry_record);
+        }
+        
         let display_data = DisplayData {
             settings: settings.clone(),
             rudder_star,
@@ -156,22 +628,69 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             motor_value,
             boom,
             genoa,
+            bow_thruster,
+            gimbal_pan,
+            gimbal_tilt,
+            motor_cut,
+            armed,
+            phone_control: touch_buttons.is_some(),
+            auto_trim,
+            compass_calibration,
+            battery_voltage: battery.voltage(),
+            battery_percent: battery.percent(&settings),
+            battery_warning,
+            race_timer: race_timer.text(),
+            page,
+            idle_ms: last_input.elapsed().as_millis() as u64,
+            alert: alerts.top(),
+            outputs: vec![rudder_star, rudder_port, motor_value, boom, genoa, misc, bow_thruster, gimbal_pan, gimbal_tilt],
+            overridden: overridden.to_vec(),
+            lora,
+            events: if page == DisplayPage::Events { event_log::recent(display::EVENT_LINES) } else { Vec::new() },
+            // The angle of the wind vane first, a field of the query shown on no other page
+            sensors: if page == DisplayPage::Sensors {
+                wind_angle.map(|angle| ("wind_angle".to_string(), format!("{:.0}deg", angle))).into_iter()
+                  .chain(telemetry.iter().map(|(name, value)| (name.clone(), value.text()))).collect()
+            } else {
+                Vec::new()
+            },
+            self_test,
         
+            connected,
             wireless_quality,
+            wireless_signal,
+            local_wireless,
             latency,
-            weight
+            weight,
+            boat_battery,
+            position,
+            speed,
+            water_speed: telemetry.get("water_speed").and_then(TelemetryValue::as_f64).map(|v| v as f32),
         };
-        let _ = tx_display.try_send(display_data);
+        let _ = tx_display.try_send(DisplayMessage::Data(Box::new(display_data)));
         
         
-        let command_message = CommandMessage {
-            msg_type: String::from("command"),
+        let command_message = Command {
+            boat_id: Some(settings.boat_id),
             timestamp: 0,
-            rudder_star,
-            rudder_port,
-            motor: motor_value,
-            boom,
-            genoa
+            remote_timestamp: None,
+            rudder_star: Some(rudder_star),
+            rudder_port: Some(rudder_port),
+            motor: Some(motor_value),
+            boom: Some(boom),
+            genoa: Some(genoa),
+            bow_thruster: Some(bow_thruster),
+            gimbal_pan: Some(gimbal_pan),
+            gimbal_tilt: Some(gimbal_tilt),
+            
+            nav_lights: Some(settings.nav_lights()),
+            spotlight: Some(settings.spotlight()),
+            deck_light: Some(settings.deck_light()),
+            auto_trim: Some(auto_trim),
+            calibrate_compass: Some(compass_calibration),
+            
+            wireless_quality: local_wireless.map(|w| w.quality),
+            wireless_signal: local_wireless.map(|w| w.signal_dbm)
         };
         
         {
@@ -179,6 +698,25 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             *locked_data = Some(command_message);
         }
         
-        thread::sleep(Duration::from_millis(40));
+        let failsafe = |i: usize| settings.channels[i].failsafe;
+        let failsafe_message = Failsafe {
+            boat_id: Some(settings.boat_id),
+            timeout_ms: Some(settings.failsafe_timeout_ms as u64),
+            rudder_star: failsafe(0),
+            rudder_port: failsafe(1),
+            motor: failsafe(2),
+            boom: failsafe(3),
+            genoa: failsafe(4),
+            bow_thruster: failsafe(6),
+            gimbal_pan: failsafe(7),
+            gimbal_tilt: failsafe(8)
+        };
+        
+        {
+            let mut locked_failsafe = failsafe_mutex.lock().unwrap();
+            *locked_failsafe = Some(failsafe_message);
+        }
+        
+        thread::sleep(Duration::from_millis(LOOP_PERIOD_MS));
     }
 }
@@ -0,0 +1,129 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use crate::config::ChannelConfig;
+
+// Histogram bucket upper bounds, in milliseconds
+const LATENCY_BUCKETS_MS: [u64; 8] = [5, 10, 20, 50, 100, 200, 500, 1000];
+const JITTER_BUCKETS_MS: [u64; 7] = [1, 2, 5, 10, 20, 50, 100];
+
+struct Histogram {
+    bounds: &'static [u64],
+    counts: Vec<u64>,   // Per bucket, not cumulative, the last one above all bounds
+    sum: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [u64]) -> Self {
+        Histogram { bounds, counts: vec![0; bounds.len() + 1], sum: 0 }
+    }
+
+    fn observe(&mut self, value: u64) {
+        let bucket = self.bounds.iter().position(|&bound| value <= bound).unwrap_or(self.bounds.len());
+        self.counts[bucket] += 1;
+        self.sum += value;
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        let _ = writeln!(out, "# HELP {} {}\n# TYPE {} histogram", name, help, name);
+        let mut cumulative = 0;
+        for (bound, count) in self.bounds.iter().zip(&self.counts) {
+            cumulative += count;
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, cumulative);
+        }
+        let total: u64 = self.counts.iter().sum();
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}\n{}_sum {}\n{}_count {}", name, total, name, self.sum, name, total);
+    }
+}
+
+/// Link and control loop health, scraped by Prometheus from GET /metrics
+pub struct Metrics {
+    commands_sent: AtomicU64,
+    command_bytes: AtomicU64,
+    boat_connections: AtomicU64,
+    latency: Mutex<Histogram>,
+    loop_jitter: Mutex<Histogram>,
+    outputs: Mutex<Vec<(String, u16)>>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics {
+            commands_sent: AtomicU64::new(0),
+            command_bytes: AtomicU64::new(0),
+            boat_connections: AtomicU64::new(0),
+            latency: Mutex::new(Histogram::new(&LATENCY_BUCKETS_MS)),
+            loop_jitter: Mutex::new(Histogram::new(&JITTER_BUCKETS_MS)),
+            outputs: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn command_sent(&self, bytes: usize) {
+        self.commands_sent.fetch_add(1, Ordering::Relaxed);
+        self.command_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn boat_connected(&self) {
+        self.boat_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn observe_latency(&self, round_trip_ms: u64) {
+        self.latency.lock().unwrap().observe(round_trip_ms);
+    }
+
+    /// Control loop period beyond the nominal one
+    pub fn observe_loop_jitter(&self, late_ms: u64) {
+        self.loop_jitter.lock().unwrap().observe(late_ms);
+    }
+
+    pub fn set_outputs(&self, channels: &[ChannelConfig], outputs: &[u16]) {
+        *self.outputs.lock().unwrap() = channels.iter().zip(outputs).map(|(channel, &value)| (channel.name.clone(), value)).collect();
+    }
+
+    /// Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "# HELP pizboat_commands_sent_total Commands sent to the boat\n# TYPE pizboat_commands_sent_total counter\npizboat_commands_sent_total {}",
+            self.commands_sent.load(Ordering::Relaxed));
+        let _ = writeln!(out, "# HELP pizboat_command_bytes_total Command bytes sent to the boat\n# TYPE pizboat_command_bytes_total counter\npizboat_command_bytes_total {}",
+            self.command_bytes.load(Ordering::Relaxed));
+        let _ = writeln!(out, "# HELP pizboat_boat_connections_total Boat connections, reconnects included\n# TYPE pizboat_boat_connections_total counter\npizboat_boat_connections_total {}",
+            self.boat_connections.load(Ordering::Relaxed));
+        self.latency.lock().unwrap().render(&mut out, "pizboat_latency_ms", "Command round trip to the boat");
+        self.loop_jitter.lock().unwrap().render(&mut out, "pizboat_loop_jitter_ms", "Control loop period beyond the nominal one");
+        let _ = writeln!(out, "# HELP pizboat_channel_output_us Channel output pulse width\n# TYPE pizboat_channel_output_us gauge");
+        for (name, value) in self.outputs.lock().unwrap().iter() {
+            let _ = writeln!(out, "pizboat_channel_output_us{{channel=\"{}\"}} {}", name, value);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_metrics() {
+        let metrics = Metrics::new();
+        metrics.command_sent(200);
+        metrics.command_sent(50);
+        metrics.observe_latency(12);
+        metrics.observe_latency(3000);
+        metrics.set_outputs(&[ChannelConfig::new("Motor")], &[1600]);
+        let text = metrics.render();
+        assert!(text.contains("pizboat_commands_sent_total 2\n"));
+        assert!(text.contains("pizboat_command_bytes_total 250\n"));
+        assert!(text.contains("pizboat_latency_ms_bucket{le=\"10\"} 0\n"));
+        assert!(text.contains("pizboat_latency_ms_bucket{le=\"20\"} 1\n"));
+        assert!(text.contains("pizboat_latency_ms_bucket{le=\"+Inf\"} 2\npizboat_latency_ms_sum 3012\npizboat_latency_ms_count 2\n"));
+        assert!(text.contains("pizboat_channel_output_us{channel=\"Motor\"} 1600\n"));
+    }
+}
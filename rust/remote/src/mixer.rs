@@ -0,0 +1,46 @@
+use serde::{Serialize, Deserialize};
+
+use crate::config::ChannelConfig;
+use tracing::warn;
+
+/// One line of the mixing matrix: adds the deflection of a source channel to a destination channel
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MixerRule {
+    pub source: String,         // Source channel name
+    pub destination: String,    // Destination channel name
+    pub weight: f32,            // Applied to the source deviation from its center
+    #[serde(default)]
+    pub offset: i32,            // Constant added to the destination
+}
+
+/// Apply the mixer rules on the transformed channel outputs, indexed like the channels.
+///
+/// Rules all read the unmixed outputs, so their order does not matter.
+pub fn mix(rules: &[MixerRule], channels: &[ChannelConfig], outputs: &mut [u16]) {
+    let inputs = outputs.to_vec();
+    let index_of = |name: &str| channels.iter().position(|c| c.name == name);
+
+    for rule in rules {
+        let (Some(source), Some(destination)) = (index_of(&rule.source), index_of(&rule.destination)) else {
+            continue;
+        };
+        if source >= inputs.len() || destination >= outputs.len() {
+            continue;
+        }
+        let deviation = inputs[source] as i32 - channels[source].center as i32;
+        let mixed = outputs[destination] as i32 + (deviation as f32 * rule.weight).round() as i32 + rule.offset;
+        let dest = &channels[destination];
+        outputs[destination] = mixed.clamp(dest.min as i32, dest.max as i32) as u16;
+    }
+}
+
+/// Warn about rules referring to channels that do not exist, which are ignored by mix()
+pub fn check_rules(rules: &[MixerRule], channels: &[ChannelConfig]) {
+    for rule in rules {
+        for name in [&rule.source, &rule.destination] {
+            if !channels.iter().any(|c| c.name == *name) {
+                warn!("Mixer rule {} -> {} ignored: unknown channel {}", rule.source, rule.destination, name);
+            }
+        }
+    }
+}
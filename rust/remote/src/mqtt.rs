@@ -0,0 +1,142 @@
+use serde_json::json;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant};
+
+use crate::config::Settings;
+use crate::telemetry_log::{TelemetryRecord, TelemetrySender};
+use tracing::{error, info};
+
+const CLIENT_ID: &str = "pizboat-remote";
+// Broker timeout of a silent client, the remote publishing far more often
+const KEEP_ALIVE_S: u16 = 60;
+// Delay before connecting again to a broker that failed
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+const NETWORK_TIMEOUT: Duration = Duration::from_secs(2);
+// Records waiting to be published, dropped beyond
+const MQTT_QUEUE: usize = 4;
+
+/// Publisher of the telemetry to an MQTT broker, None without broker in the settings
+pub fn start(settings: &Settings) -> Option<TelemetrySender> {
+    if settings.mqtt_broker.is_empty() {
+        return None;
+    }
+    let broker = settings.mqtt_broker.clone();
+    Some(TelemetrySender::spawn(MQTT_QUEUE, settings.mqtt_period_ms, move |receiver| mqtt_thread(broker, receiver)))
+}
+
+// Topics and JSON payloads of a record
+fn messages(record: &TelemetryRecord) -> Vec<(&'static str, String)> {
+    let mut messages = vec![
+        ("pizboat/battery", json!({ "boat": record.boat_battery, "remote": record.battery_voltage }).to_string()),
+        ("pizboat/load", json!({ "weight": record.weight }).to_string()),
+        ("pizboat/link", json!({ "connected": record.connected, "no_boat": record.no_boat, "latency": record.latency }).to_string()),
+    ];
+    if let (Some(latitude), Some(longitude)) = (record.latitude, record.longitude) {
+        messages.push(("pizboat/gps", json!({ "latitude": latitude, "longitude": longitude, "speed": record.speed }).to_string()));
+    }
+    messages
+}
+
+// MQTT 3.1.1 remaining length, 7 bits per byte
+fn encode_length(packet: &mut Vec<u8>, mut length: usize) {
+    loop {
+        let byte = (length % 128) as u8;
+        length /= 128;
+        packet.push(if length > 0 { byte | 0x80 } else { byte });
+        if length == 0 {
+            break;
+        }
+    }
+}
+
+fn encode_string(body: &mut Vec<u8>, text: &str) {
+    body.extend_from_slice(&(text.len() as u16).to_be_bytes());
+    body.extend_from_slice(text.as_bytes());
+}
+
+fn packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![header];
+    encode_length(&mut packet, body.len());
+    packet.extend_from_slice(body);
+    packet
+}
+
+fn connect_packet() -> Vec<u8> {
+    let mut body = Vec::new();
+    encode_string(&mut body, "MQTT");
+    // Protocol level 4, clean session
+    body.extend_from_slice(&[4, 0x02]);
+    body.extend_from_slice(&KEEP_ALIVE_S.to_be_bytes());
+    encode_string(&mut body, CLIENT_ID);
+    packet(0x10, &body)
+}
+
+// QoS 0, retained so that dashboards show the last values as soon as they subscribe
+fn publish_packet(topic: &str, payload: &str) -> Vec<u8> {
+    let mut body = Vec::new();
+    encode_string(&mut body, topic);
+    body.extend_from_slice(payload.as_bytes());
+    packet(0x31, &body)
+}
+
+fn connect(broker: &str) -> io::Result<TcpStream> {
+    let mut stream = TcpStream::connect(broker)?;
+    stream.set_read_timeout(Some(NETWORK_TIMEOUT))?;
+    stream.set_write_timeout(Some(NETWORK_TIMEOUT))?;
+    stream.write_all(&connect_packet())?;
+    let mut connack = [0u8; 4];
+    stream.read_exact(&mut connack)?;
+    if connack[0] != 0x20 || connack[3] != 0 {
+        return Err(io::Error::new(io::ErrorKind::ConnectionRefused, format!("broker refused the connection, code {}", connack[3])));
+    }
+    info!("MQTT connected to {}", broker);
+    Ok(stream)
+}
+
+fn mqtt_thread(broker: String, receiver: Receiver<TelemetryRecord>) {
+    let mut stream: Option<TcpStream> = None;
+    let mut last_attempt: Option<Instant> = None;
+
+    for record in receiver {
+        if stream.is_none() {
+            if last_attempt.is_some_and(|last| last.elapsed() < RECONNECT_DELAY) {
+                continue;
+            }
+            last_attempt = Some(Instant::now());
+            stream = match connect(&broker) {
+                Ok(s) => Some(s),
+                Err(e) => {
+                    error!("Could not connect to MQTT broker {}: {}", broker, e);
+                    continue;
+                }
+            };
+        }
+
+        if let Some(s) = stream.as_mut() {
+            let result = messages(&record).iter().try_for_each(|(topic, payload)| s.write_all(&publish_packet(topic, payload)));
+            if let Err(e) = result {
+                error!("MQTT publish error: {}", e);
+                stream = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mqtt_packets() {
+        assert_eq!(publish_packet("a/b", "42"), vec![0x31, 7, 0, 3, b'a', b'/', b'b', b'4', b'2']);
+        let mut length = Vec::new();
+        encode_length(&mut length, 321);
+        assert_eq!(length, vec![0xC1, 0x02]);
+        let connect = connect_packet();
+        assert_eq!(connect[0], 0x10);
+        assert_eq!(connect[1] as usize, connect.len() - 2);
+        assert_eq!(&connect[2..8], &[0, 4, b'M', b'Q', b'T', b'T']);
+    }
+}
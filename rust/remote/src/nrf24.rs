@@ -0,0 +1,25 @@
+use pizboat_link::{Nrf24, Nrf24Transport};
+use rppal::gpio::Gpio;
+use rppal::spi::{Mode, Spi};
+use std::thread;
+use std::time::Duration;
+
+use crate::config::SpiDevice;
+use crate::websocket::{serve_client, LinkContext, CLIENT_TIMEOUT_MS};
+use tracing::info;
+
+const NRF24_SPI_CLOCK_HZ: u32 = 4_000_000;
+
+/// Serve the boat over an nRF24L01 instead of Wi-Fi, the WebSocket server staying up for the tools
+pub fn start(channel: u8, spi: SpiDevice, ce_pin: u8, context: LinkContext) -> Result<(), Box<dyn std::error::Error>> {
+    let spi = Spi::new(spi.bus(), spi.slave_select(), NRF24_SPI_CLOCK_HZ, Mode::Mode0)?;
+    let ce = Gpio::new()?.get(ce_pin)?.into_output();
+    let radio = Nrf24::new(spi, ce, channel)?;
+    info!("nRF24 link on channel {}, CE on GPIO {}", channel, ce_pin);
+    let mut transport = Nrf24Transport::new(radio, Duration::from_millis(CLIENT_TIMEOUT_MS));
+    // One boat at a time, waiting for the next once it is gone
+    thread::spawn(move || loop {
+        serve_client(&mut transport, &context);
+    });
+    Ok(())
+}
@@ -1,6 +1,20 @@
 use rppal::gpio::{Gpio, OutputPin};
+use std::sync::mpsc::{self, Receiver};
 use std::thread;
 use std::time::Duration;
+use tracing::debug;
+
+// Animation frame period
+const FRAME_MS: u64 = 40;
+
+/// What the LED bar shows, chosen by the control loop
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LedState {
+    Connecting,     // Sweep back and forth
+    Linked(u8),     // Solid gauge, in eighths
+    Failsafe,       // Fast blink of the whole bar
+    LowBattery,     // Chase
+}
 
 pub struct OctLed {
     pins: Vec<OutputPin>,
@@ -13,7 +27,7 @@ impl OctLed {
 
         for &pin_num in pin_numbers {
             let pin = gpio.get(pin_num)?.into_output();
-            println!("GPIO Led {} initialized", pin_num);
+            debug!("GPIO Led {} initialized", pin_num);
             pins.push(pin);
         }
 
@@ -54,4 +68,44 @@ impl OctLed {
             else { self.pins[n as usize].set_low(); }
         }
     }
+    
+    fn display_bits(&mut self, bits: u8) {
+        for (n, pin) in self.pins.iter_mut().enumerate() {
+            if bits & (1 << n) != 0 { pin.set_high(); } else { pin.set_low(); }
+        }
+    }
+    
+    fn display_frame(&mut self, state: LedState, frame: u32) {
+        match state {
+            LedState::Connecting => {
+                // Two frames per step, over 0..7 and back
+                let step = (frame / 2) % 14;
+                let position = if step < 8 { step } else { 14 - step };
+                self.display_bits(1 << position);
+            }
+            LedState::Linked(value) => self.display_value(value),
+            LedState::Failsafe => self.display_bits(if (frame / 2).is_multiple_of(2) { 0xFF } else { 0x00 }),
+            LedState::LowBattery => self.display_bits(0x11 << ((frame / 3) % 4)),
+        }
+    }
+}
+
+/// Animate the LED bar with the latest state received
+pub fn octled_thread(mut led: OctLed, rx: Receiver<LedState>) {
+    let mut state = LedState::Connecting;
+    let mut frame: u32 = 0;
+    
+    loop {
+        loop {
+            match rx.try_recv() {
+                Ok(s) => { state = s; }
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => return,
+            }
+        }
+        
+        led.display_frame(state, frame);
+        frame = frame.wrapping_add(1);
+        thread::sleep(Duration::from_millis(FRAME_MS));
+    }
 }
@@ -0,0 +1,142 @@
+use pizboat_hal::DigitalIn;
+use rppal::gpio::Gpio;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::info;
+
+// Longer intervals between rising edges end a frame
+const PPM_SYNC_US: u64 = 2700;
+// Channel intervals outside of this range are noise, the frame is dropped
+const PPM_MIN_US: u64 = 700;
+const PPM_MAX_US: u64 = 2200;
+const PPM_MIN_CHANNELS: usize = 4;
+const PPM_MAX_CHANNELS: usize = 16;
+// Range mapped onto the ADC range
+const PPM_LOW_US: u16 = 1000;
+const PPM_HIGH_US: u16 = 2000;
+// Without a frame for that long, the transmitter is off or unplugged
+const PPM_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// PPM sum decoder, fed with the time between consecutive rising edges
+#[derive(Default)]
+pub struct PpmDecoder {
+    channels: Vec<u16>,
+    synced: bool,
+}
+
+impl PpmDecoder {
+    /// The channel widths in us once a sync gap ends a complete frame
+    pub fn interval(&mut self, interval_us: u64) -> Option<Vec<u16>> {
+        if interval_us >= PPM_SYNC_US {
+            let frame = std::mem::take(&mut self.channels);
+            let complete = self.synced && frame.len() >= PPM_MIN_CHANNELS;
+            self.synced = true;
+            return complete.then_some(frame);
+        }
+        if !self.synced {
+            return None;
+        }
+        if (PPM_MIN_US..=PPM_MAX_US).contains(&interval_us) && self.channels.len() < PPM_MAX_CHANNELS {
+            self.channels.push(interval_us as u16);
+        } else {
+            // Glitch: wait for the next sync gap
+            self.channels.clear();
+            self.synced = false;
+        }
+        None
+    }
+}
+
+/// Transmitter plugged into the trainer port, as an alternative to the sticks of the remote
+pub struct PpmInput {
+    // Kept for its interrupt to stay registered
+    _pin: Box<dyn DigitalIn>,
+    frame: Arc<Mutex<Option<LastFrame>>>,
+}
+
+// Channel widths in us, and when the frame ended
+type LastFrame = (Vec<u16>, Instant);
+
+impl PpmInput {
+    pub fn new(pin_number: u8) -> Result<Self, Box<dyn std::error::Error>> {
+        let pin = Gpio::new()?.get(pin_number)?.into_input();
+        info!("PPM input on GPIO {}", pin_number);
+        Self::from_pin(Box::new(pin))
+    }
+
+    /// Decodes the pulses of any input, timed as the interrupts come
+    pub fn from_pin(mut pin: Box<dyn DigitalIn>) -> Result<Self, Box<dyn std::error::Error>> {
+        let frame = Arc::new(Mutex::new(None));
+        let frame_clone = Arc::clone(&frame);
+        let mut decoder = PpmDecoder::default();
+        let mut last_edge: Option<Instant> = None;
+        pin.set_interrupt(Box::new(move |high| {
+            if !high {
+                return;
+            }
+            let now = Instant::now();
+            let interval_us = last_edge.replace(now).map(|last| now.duration_since(last).as_micros() as u64);
+            if let Some(channels) = interval_us.and_then(|us| decoder.interval(us)) {
+                *frame_clone.lock().unwrap() = Some((channels, now));
+            }
+        }))?;
+        Ok(PpmInput { _pin: pin, frame })
+    }
+
+    /// Replace the ADC values fed by PPM channels, channel_map giving the ADC input of each channel.
+    /// Without transmitter the values are left alone, the pots taking over again.
+    pub fn apply(&self, channel_map: &[usize], adc_values: &mut [u16], adc_bits: u8) {
+        let full_scale = (1u32 << adc_bits) - 1;
+        let frame = self.frame.lock().unwrap();
+        let Some((channels, _)) = frame.as_ref().filter(|(_, at)| at.elapsed() < PPM_TIMEOUT) else {
+            return;
+        };
+        for (&width, &adc) in channels.iter().zip(channel_map) {
+            if adc < adc_values.len() {
+                let travel = (width.clamp(PPM_LOW_US, PPM_HIGH_US) - PPM_LOW_US) as u32;
+                adc_values[adc] = (travel * full_scale / (PPM_HIGH_US - PPM_LOW_US) as u32) as u16;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pizboat_hal::mock::MockPin;
+
+    #[test]
+    fn test_ppm_frames() {
+        let mut decoder = PpmDecoder::default();
+        // Mid-frame start, dropped until the first sync gap
+        assert_eq!(decoder.interval(1500), None);
+        assert_eq!(decoder.interval(9000), None);
+        for width in [1000, 1500, 2000, 1200] {
+            assert_eq!(decoder.interval(width), None);
+        }
+        assert_eq!(decoder.interval(9000), Some(vec![1000, 1500, 2000, 1200]));
+
+        // A glitch drops the frame
+        for width in [1000, 150, 2000, 1200, 1500] {
+            assert_eq!(decoder.interval(width), None);
+        }
+        assert_eq!(decoder.interval(9000), None);
+        for width in [1100, 1500, 1900, 1500] {
+            decoder.interval(width);
+        }
+        assert_eq!(decoder.interval(9000), Some(vec![1100, 1500, 1900, 1500]));
+    }
+
+    #[test]
+    fn test_ppm_input_over_pots() {
+        let input = PpmInput::from_pin(Box::new(MockPin::default())).unwrap();
+        let mut adc_values = [300u16; 8];
+        input.apply(&[6, 7], &mut adc_values, 10);
+        assert_eq!(adc_values, [300; 8]);
+
+        // Third channel not mapped
+        *input.frame.lock().unwrap() = Some((vec![1000, 2000, 1500], Instant::now()));
+        input.apply(&[6, 7], &mut adc_values, 10);
+        assert_eq!(adc_values[5..], [300, 0, 1023]);
+    }
+}
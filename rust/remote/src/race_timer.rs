@@ -0,0 +1,69 @@
+use std::time::{Duration, Instant};
+
+use crate::buzzer::{self, BuzzerEvent};
+use tracing::info;
+
+// Remaining times of the countdown (ms) announced with a beep
+const RACE_SIGNALS_MS: [i64; 3] = [60_000, 30_000, 10_000];
+
+/// Regatta countdown, turning into a stopwatch at the start
+pub struct RaceTimer {
+    countdown: Duration,
+    started: Option<Instant>,
+    last_remaining_ms: i64,
+}
+
+impl RaceTimer {
+    pub fn new() -> Self {
+        RaceTimer { countdown: Duration::ZERO, started: None, last_remaining_ms: 0 }
+    }
+
+    pub fn start(&mut self, countdown_s: u16) {
+        info!("[EVENT] Race countdown started: {}s", countdown_s);
+        self.countdown = Duration::from_secs(countdown_s as u64);
+        self.started = Some(Instant::now());
+        self.last_remaining_ms = self.countdown.as_millis() as i64;
+    }
+
+    pub fn reset(&mut self) {
+        info!("[EVENT] Race timer reset");
+        self.started = None;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.started.is_some()
+    }
+
+    // Time left before the start, negative once the race is on
+    fn remaining_ms(&self) -> Option<i64> {
+        let started = self.started?;
+        Some(self.countdown.as_millis() as i64 - started.elapsed().as_millis() as i64)
+    }
+
+    /// Beep when crossing the countdown signals and the start
+    pub fn update(&mut self) {
+        let Some(remaining) = self.remaining_ms() else { return };
+        let crossed = |signal: i64| self.last_remaining_ms > signal && remaining <= signal;
+
+        if crossed(0) {
+            info!("[EVENT] Race started");
+            buzzer::play(BuzzerEvent::RaceStart);
+        } else if RACE_SIGNALS_MS.iter().any(|&signal| crossed(signal)) {
+            buzzer::play(BuzzerEvent::RaceSignal);
+        }
+        self.last_remaining_ms = remaining;
+    }
+
+    /// "-M:SS" counting down to the start, then "M:SS" elapsed since the start
+    pub fn text(&self) -> Option<String> {
+        let remaining = self.remaining_ms()?;
+        Some(if remaining > 0 {
+            // Rounded up, so that -0:01 is followed by 0:00 at the start
+            let seconds = (remaining + 999) / 1000;
+            format!("-{}:{:02}", seconds / 60, seconds % 60)
+        } else {
+            let seconds = -remaining / 1000;
+            format!("{}:{:02}", seconds / 60, seconds % 60)
+        })
+    }
+}
@@ -0,0 +1,118 @@
+use serde::{Serialize, Deserialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use pizboat_protocol::{Command, Message};
+use tracing::{error, info};
+
+pub const SESSIONS_DIR: &str = "sessions";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Direction {
+    // Sent to the boat
+    Command,
+    // Received from the boat
+    Query,
+}
+
+/// One line of a session file
+#[derive(Serialize, Deserialize)]
+pub struct RecordEntry {
+    // Unix time in milliseconds
+    pub time_ms: i64,
+    pub direction: Direction,
+    pub message: serde_json::Value,
+}
+
+/// Logs the WebSocket traffic to a JSON lines file, shared by the client threads
+pub struct Recorder {
+    file: Mutex<BufWriter<File>>,
+}
+
+impl Recorder {
+    /// New session file named after the local time, in dir
+    pub fn create(dir: &str) -> std::io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let name = chrono::Local::now().format("session-%Y%m%d-%H%M%S.jsonl").to_string();
+        let path = Path::new(dir).join(name);
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        info!("Recording session to {}", path.display());
+        Ok(Recorder { file: Mutex::new(BufWriter::new(file)) })
+    }
+
+    pub fn record(&self, direction: Direction, text: &str) {
+        let message = match serde_json::from_str(text) {
+            Ok(m) => m,
+            Err(_) => serde_json::Value::String(text.to_string()),
+        };
+        let entry = RecordEntry { time_ms: chrono::Utc::now().timestamp_millis(), direction, message };
+        let mut file = self.file.lock().unwrap();
+        // A lost line is not worth stopping the link
+        if let Err(e) = serde_json::to_writer(&mut *file, &entry).map_err(std::io::Error::from)
+            .and_then(|_| file.write_all(b"\n")).and_then(|_| file.flush()) {
+            error!("Could not record message: {}", e);
+        }
+    }
+}
+
+pub fn load(path: &str) -> Result<Vec<RecordEntry>, Box<dyn std::error::Error>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut entries = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            entries.push(serde_json::from_str(&line)?);
+        }
+    }
+    Ok(entries)
+}
+
+/// Serve the recorded commands again with their original timing, the WebSocket thread sending them to whoever connects
+pub fn replay(path: &str, data_mutex: &Arc<Mutex<Option<Command>>>) -> Result<(), Box<dyn std::error::Error>> {
+    let entries = load(path)?;
+    let mut commands: Vec<(i64, Command)> = Vec::new();
+    for entry in entries.into_iter().filter(|e| e.direction == Direction::Command) {
+        match pizboat_protocol::parse(&entry.message.to_string())? {
+            Message::Command(command) => commands.push((entry.time_ms, command)),
+            other => return Err(format!("not a command: {:?}", other).into()),
+        }
+    }
+    info!("Replaying {} commands from {}", commands.len(), path);
+
+    let mut previous_ms = commands.first().map(|(t, _)| *t).unwrap_or_default();
+    for (time_ms, command) in commands {
+        thread::sleep(Duration::from_millis((time_ms - previous_ms).max(0) as u64));
+        previous_ms = time_ms;
+        *data_mutex.lock().unwrap() = Some(command);
+    }
+
+    *data_mutex.lock().unwrap() = None;
+    info!("Replay done");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_load() {
+        let dir = std::env::temp_dir().join(format!("pizremote-recorder-{}", std::process::id()));
+        let recorder = Recorder::create(dir.to_str().unwrap()).unwrap();
+        recorder.record(Direction::Query, r#"{"type":"query","timestamp":12}"#);
+        recorder.record(Direction::Command, "not json");
+
+        let path = fs::read_dir(&dir).unwrap().next().unwrap().unwrap().path();
+        let entries = load(path.to_str().unwrap()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].direction, Direction::Query);
+        assert_eq!(entries[0].message["timestamp"], 12);
+        assert_eq!(entries[1].message, "not json");
+    }
+}
@@ -0,0 +1,111 @@
+use serde_json::{json, Value};
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant};
+use tungstenite::{connect, Message};
+
+use crate::config::Settings;
+use crate::telemetry_log::{TelemetryRecord, TelemetrySender};
+use tracing::{error, info};
+
+// Delta stream of the server, without its own updates sent back
+const STREAM_PATH: &str = "/signalk/v1/stream?subscribe=none";
+const SOURCE_LABEL: &str = "pizboat";
+// Delay before connecting again to a server that failed
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+// Records waiting to be sent, dropped beyond
+const SIGNALK_QUEUE: usize = 4;
+
+/// Sender of the boat telemetry as SignalK deltas to a server, None without server in the settings
+pub fn start(settings: &Settings) -> Option<TelemetrySender> {
+    if settings.signalk_server.is_empty() {
+        return None;
+    }
+    let url = format!("ws://{}{}", settings.signalk_server, STREAM_PATH);
+    Some(TelemetrySender::spawn(SIGNALK_QUEUE, settings.signalk_period_ms, move |receiver| signalk_thread(url, receiver)))
+}
+
+/// Delta of the self vessel, in SignalK units (radians, m/s, volts), None without boat values
+fn delta(record: &TelemetryRecord, timestamp: &str) -> Option<Value> {
+    let mut values = Vec::new();
+    if let (Some(latitude), Some(longitude)) = (record.latitude, record.longitude) {
+        values.push(json!({ "path": "navigation.position", "value": { "latitude": latitude, "longitude": longitude } }));
+    }
+    if let Some(speed) = record.speed {
+        values.push(json!({ "path": "navigation.speedOverGround", "value": speed }));
+    }
+    if let Some(heading) = record.heading {
+        values.push(json!({ "path": "navigation.headingMagnetic", "value": heading.to_radians() }));
+    }
+    if let Some(wind_angle) = record.wind_angle {
+        values.push(json!({ "path": "environment.wind.angleApparent", "value": wind_angle.to_radians() }));
+    }
+    if let Some(voltage) = record.boat_battery {
+        values.push(json!({ "path": "electrical.batteries.boat.voltage", "value": voltage }));
+    }
+    if values.is_empty() {
+        return None;
+    }
+    Some(json!({
+        "context": "vessels.self",
+        "updates": [{ "source": { "label": SOURCE_LABEL }, "timestamp": timestamp, "values": values }],
+    }))
+}
+
+fn signalk_thread(url: String, receiver: Receiver<TelemetryRecord>) {
+    let mut socket = None;
+    let mut last_attempt: Option<Instant> = None;
+
+    for record in receiver {
+        let timestamp = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+        let Some(delta) = delta(&record, &timestamp) else { continue };
+
+        if socket.is_none() {
+            if last_attempt.is_some_and(|last| last.elapsed() < RECONNECT_DELAY) {
+                continue;
+            }
+            last_attempt = Some(Instant::now());
+            socket = match connect(url.as_str()) {
+                Ok((s, _response)) => {
+                    info!("SignalK connected to {}", url);
+                    Some(s)
+                }
+                Err(e) => {
+                    error!("Could not connect to SignalK server {}: {}", url, e);
+                    continue;
+                }
+            };
+        }
+
+        if let Some(s) = socket.as_mut()
+            && let Err(e) = s.send(Message::Text(delta.to_string()))
+        {
+            error!("SignalK send error: {}", e);
+            socket = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signalk_delta() {
+        let mut record = TelemetryRecord {
+            time_ms: 0, connected: true, no_boat: false, wireless_quality: 60, latency: 20, weight: 0.,
+            boat_battery: Some(11.8), latitude: Some(47.5), longitude: Some(-2.9), speed: Some(1.5),
+            heading: Some(90.), wind_angle: None, battery_voltage: None, outputs: Vec::new(), telemetry: Default::default(),
+        };
+        let message = delta(&record, "2024-06-01T10:00:00.000Z").unwrap();
+        let values = &message["updates"][0]["values"];
+        assert_eq!(values[0]["value"]["latitude"], 47.5);
+        assert_eq!(values[2]["path"], "navigation.headingMagnetic");
+        assert!((values[2]["value"].as_f64().unwrap() - std::f64::consts::FRAC_PI_2).abs() < 1e-6);
+        assert_eq!(values[3]["path"], "electrical.batteries.boat.voltage");
+
+        // Nothing known about the boat
+        record.boat_battery = None;
+        (record.latitude, record.longitude, record.speed, record.heading) = (None, None, None, None);
+        assert_eq!(delta(&record, "2024-06-01T10:00:00.000Z"), None);
+    }
+}
@@ -0,0 +1,113 @@
+use rppal::gpio::Level;
+use std::io::{self, BufRead};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::ButtonMap;
+use tracing::info;
+
+// Sail trim rate knobs at half travel, sticks centered
+const SIM_KNOB_ADC: u16 = 512;
+const SIM_CENTER_ADC: u16 = 512;
+// Stick change of one + or - command
+const SIM_STEP_ADC: i32 = 64;
+// Time a navigation button is held by the "p" command
+const SIM_CLICK_MS: u64 = 100;
+
+const SIM_HELP: &str = "Sim commands: r/m <0-1023|+|-> rudder/motor, b/g <+|-|0> boom/genoa, p <button> click, c center, w <ms> wait, # comment, h help";
+
+/// Desktop input from stdin commands, replacing the ADC and GPIO buttons
+pub struct SimInput {
+    adc_values: Arc<Mutex<[u16; crate::ADC_CHANNELS]>>,
+}
+
+impl SimInput {
+    pub fn start(buttons: Sender<(usize, Level, Instant)>, button_map: ButtonMap) -> Self {
+        let adc_values = Arc::new(Mutex::new(centered()));
+        let adc_values_clone = Arc::clone(&adc_values);
+        thread::spawn(move || sim_thread(adc_values_clone, buttons, button_map));
+        SimInput { adc_values }
+    }
+
+    /// Simulated values are 10 bit, scaled to the resolution of the channels
+    pub fn read_all_channels(&self, adc_bits: u8) -> [u16; crate::ADC_CHANNELS] {
+        self.adc_values.lock().unwrap().map(|v| (v as u32 * (1 << adc_bits) / 1024) as u16)
+    }
+}
+
+fn centered() -> [u16; crate::ADC_CHANNELS] {
+    let mut values = [0u16; crate::ADC_CHANNELS];
+    values[0] = SIM_KNOB_ADC;
+    values[1] = SIM_KNOB_ADC;
+    for adc in crate::CHANNEL_ADC.iter().flatten() {
+        values[*adc] = SIM_CENTER_ADC;
+    }
+    values
+}
+
+fn set_stick(value: &mut u16, argument: &str) -> Result<(), String> {
+    *value = match argument {
+        "+" => (*value as i32 + SIM_STEP_ADC).min(1023) as u16,
+        "-" => (*value as i32 - SIM_STEP_ADC).max(0) as u16,
+        v => v.parse::<u16>().map_err(|e| e.to_string())?.min(1023),
+    };
+    Ok(())
+}
+
+// Hold the up or down button of a sail, "0" releasing both
+fn set_sail(buttons: &Sender<(usize, Level, Instant)>, up: usize, down: usize, argument: &str) -> Result<(), String> {
+    let (up_level, down_level) = match argument {
+        "+" => (Level::High, Level::Low),
+        "-" => (Level::Low, Level::High),
+        "0" => (Level::Low, Level::Low),
+        _ => return Err(format!("Expected +, - or 0, got {}", argument)),
+    };
+    let _ = buttons.send((up, up_level, Instant::now()));
+    let _ = buttons.send((down, down_level, Instant::now()));
+    Ok(())
+}
+
+fn sim_thread(adc_values: Arc<Mutex<[u16; crate::ADC_CHANNELS]>>, buttons: Sender<(usize, Level, Instant)>, map: ButtonMap) {
+    println!("{}", SIM_HELP);
+
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else { break };
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or_default();
+        let argument = words.next().unwrap_or_default();
+
+        let result = match command {
+            "r" => set_stick(&mut adc_values.lock().unwrap()[crate::RUDDER_ADC], argument),
+            "m" => set_stick(&mut adc_values.lock().unwrap()[crate::MOTOR_ADC], argument),
+            "b" => set_sail(&buttons, map.boom_up, map.boom_down, argument),
+            "g" => set_sail(&buttons, map.genoa_up, map.genoa_down, argument),
+            "p" => match argument.parse::<usize>() {
+                Ok(button) => {
+                    let _ = buttons.send((button, Level::High, Instant::now()));
+                    thread::sleep(Duration::from_millis(SIM_CLICK_MS));
+                    let _ = buttons.send((button, Level::Low, Instant::now()));
+                    Ok(())
+                }
+                Err(e) => Err(e.to_string()),
+            },
+            "c" => { *adc_values.lock().unwrap() = centered(); Ok(()) }
+            // Pacing of scripts piped on stdin
+            "w" => match argument.parse::<u64>() {
+                Ok(ms) => {
+                    thread::sleep(Duration::from_millis(ms));
+                    Ok(())
+                }
+                Err(e) => Err(e.to_string()),
+            },
+            "" => Ok(()),
+            c if c.starts_with('#') => Ok(()),
+            _ => Err(SIM_HELP.to_string()),
+        };
+        if let Err(e) = result {
+            eprintln!("{}", e);
+        }
+    }
+    info!("Sim input closed");
+}
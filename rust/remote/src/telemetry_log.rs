@@ -0,0 +1,176 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::{Settings, TelemetryFormat};
+use pizboat_protocol::TelemetryValue;
+use tracing::{error, info};
+
+pub const TELEMETRY_DIR: &str = "telemetry";
+// Records waiting to be written, dropped beyond
+const TELEMETRY_QUEUE: usize = 16;
+
+const CSV_HEADER: &str = "time_ms,connected,no_boat,wireless_quality,latency,weight,boat_battery,latitude,longitude,speed,heading,wind_angle,battery_voltage,outputs,telemetry";
+
+/// One line of the telemetry log
+#[derive(Serialize)]
+pub struct TelemetryRecord {
+    pub time_ms: i64,
+    pub connected: bool,
+    pub no_boat: bool,              // NO BOAT alarm
+    pub wireless_quality: i16,
+    pub latency: u64,
+    pub weight: f32,
+    pub boat_battery: Option<f32>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub speed: Option<f32>,
+    pub heading: Option<f32>,       // Degrees
+    pub wind_angle: Option<f32>,    // Apparent, degrees from the bow
+    pub battery_voltage: Option<f32>,
+    pub outputs: Vec<u16>,
+    pub telemetry: BTreeMap<String, TelemetryValue>,    // Open telemetry of the boat
+}
+
+impl TelemetryRecord {
+    fn csv(&self) -> String {
+        let optional = |v: Option<String>| v.unwrap_or_default();
+        let outputs: Vec<String> = self.outputs.iter().map(|o| o.to_string()).collect();
+        // Names change with the boat, so in one column of name=value pairs
+        let telemetry: Vec<String> = self.telemetry.iter()
+            .map(|(name, value)| format!("{}={}", name, value.text()).replace([',', ' '], "_"))
+            .collect();
+        format!("{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}", self.time_ms, self.connected, self.no_boat, self.wireless_quality, self.latency, self.weight,
+            optional(self.boat_battery.map(|v| v.to_string())), optional(self.latitude.map(|v| v.to_string())),
+            optional(self.longitude.map(|v| v.to_string())), optional(self.speed.map(|v| v.to_string())),
+            optional(self.heading.map(|v| v.to_string())), optional(self.wind_angle.map(|v| v.to_string())),
+            optional(self.battery_voltage.map(|v| v.to_string())), outputs.join(" "), telemetry.join(" "))
+    }
+}
+
+/// Throttles the records of the control loop and hands them to the thread of a sink,
+/// dropping them when it falls behind so that the loop never waits on a disk or the network
+pub struct TelemetrySender {
+    sender: SyncSender<TelemetryRecord>,
+    period: Duration,
+    last: Option<Instant>,
+}
+
+impl TelemetrySender {
+    /// Spawn `sink` on the other end of a queue of `queue` records
+    pub fn spawn(queue: usize, period_ms: u32, sink: impl FnOnce(Receiver<TelemetryRecord>) + Send + 'static) -> Self {
+        let (sender, receiver) = mpsc::sync_channel(queue);
+        thread::spawn(move || sink(receiver));
+        TelemetrySender { sender, period: Duration::from_millis(period_ms as u64), last: None }
+    }
+
+    /// Build and queue a record when the period has elapsed
+    pub fn send(&mut self, record: impl FnOnce() -> TelemetryRecord) {
+        if self.last.is_some_and(|last| last.elapsed() < self.period) {
+            return;
+        }
+        self.last = Some(Instant::now());
+        let _ = self.sender.try_send(record());
+    }
+}
+
+/// Log writer, None when logging is off in the settings
+pub fn start(settings: &Settings) -> Option<TelemetrySender> {
+    if settings.telemetry_format == TelemetryFormat::Off {
+        return None;
+    }
+    let writer = LogWriter {
+        dir: PathBuf::from(TELEMETRY_DIR),
+        session: chrono::Local::now().format("%Y%m%d-%H%M%S").to_string(),
+        format: settings.telemetry_format,
+        max_bytes: settings.telemetry_max_kb as u64 * 1024,
+        max_files: settings.telemetry_max_files as usize,
+    };
+    Some(TelemetrySender::spawn(TELEMETRY_QUEUE, settings.telemetry_period_ms, move |receiver| telemetry_thread(writer, receiver)))
+}
+
+struct LogWriter {
+    dir: PathBuf,
+    session: String,
+    format: TelemetryFormat,
+    max_bytes: u64,
+    max_files: usize,
+}
+
+impl LogWriter {
+    fn extension(&self) -> &'static str {
+        if self.format == TelemetryFormat::Csv { "csv" } else { "jsonl" }
+    }
+
+    fn open(&self, part: u32) -> std::io::Result<BufWriter<File>> {
+        fs::create_dir_all(&self.dir)?;
+        self.remove_old_files()?;
+        let path = self.dir.join(format!("telemetry-{}-{:03}.{}", self.session, part, self.extension()));
+        info!("Logging telemetry to {}", path.display());
+        let mut file = BufWriter::new(File::create(path)?);
+        if self.format == TelemetryFormat::Csv {
+            writeln!(file, "{}", CSV_HEADER)?;
+        }
+        Ok(file)
+    }
+
+    // Leave room for the file about to be created
+    fn remove_old_files(&self) -> std::io::Result<()> {
+        let mut files: Vec<PathBuf> = fs::read_dir(&self.dir)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| is_log_file(path))
+            .collect();
+        // Names sort by date
+        files.sort();
+        let excess = (files.len() + 1).saturating_sub(self.max_files.max(1));
+        for path in &files[..excess] {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    fn line(&self, record: &TelemetryRecord) -> String {
+        match self.format {
+            TelemetryFormat::Csv => record.csv(),
+            _ => serde_json::to_string(record).unwrap_or_default(),
+        }
+    }
+}
+
+fn is_log_file(path: &Path) -> bool {
+    path.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.starts_with("telemetry-"))
+}
+
+fn telemetry_thread(writer: LogWriter, receiver: Receiver<TelemetryRecord>) {
+    let mut part = 0;
+    let mut file = None;
+    let mut written = 0;
+
+    for record in receiver {
+        if file.is_none() || written >= writer.max_bytes {
+            file = match writer.open(part) {
+                Ok(f) => Some(f),
+                Err(e) => {
+                    error!("Could not open telemetry log: {}", e);
+                    return;
+                }
+            };
+            part += 1;
+            written = 0;
+        }
+
+        let line = writer.line(&record);
+        if let Some(f) = file.as_mut()
+            && let Err(e) = writeln!(f, "{}", line).and_then(|_| f.flush())
+        {
+            error!("Could not write telemetry log: {}", e);
+            return;
+        }
+        written += line.len() as u64 + 1;
+    }
+}
@@ -0,0 +1,140 @@
+use serde::{Serialize, Deserialize};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::event_log;
+
+// The phone sends a frame every 100 ms, the pots take over when they stop
+const TOUCH_TIMEOUT: Duration = Duration::from_millis(500);
+// Moving a stick of the remote by an eighth of its travel takes control back
+const RECLAIM_FRACTION: u32 = 8;
+
+/// State of the touch page, posted by the phone
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct TouchFrame {
+    pub rudder: f32,    // -1 to 1, port to starboard
+    pub motor: f32,     // -1 to 1, astern to ahead
+    pub boom_in: bool,
+    pub boom_out: bool,
+    pub genoa_in: bool,
+    pub genoa_out: bool,
+    // Changed by each tap on "take control", 0 to leave it to the remote
+    pub takeover: u32,
+}
+
+/// Sail buttons pressed on the phone
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TouchButtons {
+    pub boom_up: bool,
+    pub boom_down: bool,
+    pub genoa_up: bool,
+    pub genoa_down: bool,
+}
+
+#[derive(Default)]
+struct Shared {
+    frame: Option<(TouchFrame, Instant)>,
+    in_control: bool,
+}
+
+/// Handle of the API thread, storing the frames and telling the phone who has control
+#[derive(Clone, Default)]
+pub struct TouchLink {
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl TouchLink {
+    /// Store a frame, true when the phone has control
+    pub fn post(&self, frame: TouchFrame) -> bool {
+        let mut shared = self.shared.lock().unwrap();
+        shared.frame = Some((frame, Instant::now()));
+        shared.in_control
+    }
+}
+
+/// Touch joysticks of the phone page, an emergency backup of the sticks.
+///
+/// The phone only drives once it asked for control, and over the gamepad or PPM as well.
+/// The remote keeps priority: moving one of its sticks gives control back to it,
+/// until the next tap on "take control". Without frames from the phone, the sticks are back too.
+pub struct TouchControl {
+    link: TouchLink,
+    // Takeover token of the phone, and the pots when it took over
+    holder: Option<(u32, [u16; 2])>,
+    refused_takeover: u32,
+}
+
+impl TouchControl {
+    pub fn new(link: TouchLink) -> Self {
+        TouchControl { link, holder: None, refused_takeover: 0 }
+    }
+
+    /// Replace the rudder and motor ADC values while the phone has control, the sail buttons it presses being returned
+    pub fn apply(&mut self, rudder_adc: usize, motor_adc: usize, adc_values: &mut [u16], adc_bits: u8) -> Option<TouchButtons> {
+        let full_scale = (1u32 << adc_bits) - 1;
+        let mut shared = self.link.shared.lock().unwrap();
+        let frame = shared.frame.filter(|(_, at)| at.elapsed() < TOUCH_TIMEOUT).map(|(frame, _)| frame);
+        let sticks = [adc_values[rudder_adc], adc_values[motor_adc]];
+
+        match (frame, self.holder) {
+            (Some(frame), None) if frame.takeover != 0 && frame.takeover != self.refused_takeover => {
+                event_log::record("Phone took control");
+                self.holder = Some((frame.takeover, sticks));
+            }
+            (Some(frame), Some((takeover, at_takeover))) if frame.takeover == takeover => {
+                let moved = sticks.iter().zip(at_takeover).any(|(&now, then)| now.abs_diff(then) as u32 > full_scale / RECLAIM_FRACTION);
+                if moved {
+                    event_log::record("Remote stick moved, control taken back from the phone");
+                    self.refused_takeover = takeover;
+                    self.holder = None;
+                }
+            }
+            (_, Some(_)) => {
+                event_log::record(if frame.is_none() { "Phone gone, control back to the remote" } else { "Phone released control" });
+                self.holder = None;
+            }
+            _ => {}
+        }
+        shared.in_control = self.holder.is_some();
+
+        let frame = frame.filter(|_| self.holder.is_some())?;
+        let to_adc = |value: f32| ((value.clamp(-1., 1.) + 1.) / 2. * full_scale as f32).round() as u16;
+        adc_values[rudder_adc] = to_adc(frame.rudder);
+        adc_values[motor_adc] = to_adc(frame.motor);
+        Some(TouchButtons { boom_up: frame.boom_in, boom_down: frame.boom_out, genoa_up: frame.genoa_in, genoa_down: frame.genoa_out })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_touch_takeover() {
+        let link = TouchLink::default();
+        let mut control = TouchControl::new(link.clone());
+        let mut adc_values = [512u16; 8];
+
+        // Sticks shown, control not asked
+        assert!(!link.post(TouchFrame { rudder: 1., ..Default::default() }));
+        assert_eq!(control.apply(6, 7, &mut adc_values, 10), None);
+        assert_eq!(adc_values[6], 512);
+
+        let frame = TouchFrame { rudder: 1., motor: -1., boom_in: true, takeover: 1, ..Default::default() };
+        link.post(frame);
+        let buttons = control.apply(6, 7, &mut adc_values, 10);
+        assert_eq!(buttons.map(|b| b.boom_up), Some(true));
+        assert_eq!(adc_values[6..], [1023, 0]);
+        assert!(link.post(frame));
+
+        // The remote rudder pot moved: control back to the remote, until the next tap
+        let mut adc_values = [512u16; 8];
+        adc_values[6] = 700;
+        assert_eq!(control.apply(6, 7, &mut adc_values, 10), None);
+        assert_eq!(adc_values[6], 700);
+        assert!(!link.post(frame));
+        assert_eq!(control.apply(6, 7, &mut adc_values, 10), None);
+        link.post(TouchFrame { takeover: 2, ..frame });
+        assert!(control.apply(6, 7, &mut adc_values, 10).is_some());
+    }
+}
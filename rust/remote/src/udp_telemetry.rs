@@ -0,0 +1,105 @@
+use std::net::UdpSocket;
+use std::sync::mpsc::Receiver;
+
+use crate::config::Settings;
+use crate::telemetry_log::{TelemetryRecord, TelemetrySender};
+use tracing::{error, info};
+
+const MAGIC: &[u8; 4] = b"PZT1";
+// Records waiting to be sent, dropped beyond
+const UDP_QUEUE: usize = 4;
+
+/// Broadcaster of a compact telemetry packet on the local network, None without broadcast address in the settings.
+/// Shore-side viewers only listen, the control link is left alone.
+pub fn start(settings: &Settings) -> Option<TelemetrySender> {
+    if settings.udp_broadcast.is_empty() {
+        return None;
+    }
+    let socket = match UdpSocket::bind(("0.0.0.0", 0)).and_then(|s| s.set_broadcast(true).map(|_| s)) {
+        Ok(s) => s,
+        Err(e) => {
+            error!("Could not open the telemetry broadcast socket: {}", e);
+            return None;
+        }
+    };
+    info!("Broadcasting telemetry to {}", settings.udp_broadcast);
+    let address = settings.udp_broadcast.clone();
+    Some(TelemetrySender::spawn(UDP_QUEUE, settings.udp_period_ms, move |receiver| udp_thread(socket, address, receiver)))
+}
+
+/// Little endian, NaN for the values unknown:
+///
+/// | bytes | field |
+/// |-------|-------|
+/// | 4  | "PZT1" |
+/// | 8  | time_ms, u64 |
+/// | 1  | flags, u8: bit 0 connected, bit 1 NO BOAT alarm |
+/// | 1  | wireless_quality, i8 |
+/// | 2  | latency in ms, u16 |
+/// | 4  | weight in grams, f32 |
+/// | 4  | boat battery in volts, f32 |
+/// | 8  | latitude, f64 |
+/// | 8  | longitude, f64 |
+/// | 4  | speed in m/s, f32 |
+/// | 4  | heading in degrees, f32 |
+/// | 4  | remote battery in volts, f32 |
+/// | 1  | output count n, u8 |
+/// | 2n | outputs in us, u16 |
+fn encode(record: &TelemetryRecord) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(53 + 2 * record.outputs.len());
+    packet.extend_from_slice(MAGIC);
+    packet.extend_from_slice(&(record.time_ms as u64).to_le_bytes());
+    packet.push(record.connected as u8 | (record.no_boat as u8) << 1);
+    packet.push(record.wireless_quality.clamp(i8::MIN as i16, i8::MAX as i16) as i8 as u8);
+    packet.extend_from_slice(&(record.latency.min(u16::MAX as u64) as u16).to_le_bytes());
+    packet.extend_from_slice(&record.weight.to_le_bytes());
+    packet.extend_from_slice(&record.boat_battery.unwrap_or(f32::NAN).to_le_bytes());
+    packet.extend_from_slice(&record.latitude.unwrap_or(f64::NAN).to_le_bytes());
+    packet.extend_from_slice(&record.longitude.unwrap_or(f64::NAN).to_le_bytes());
+    packet.extend_from_slice(&record.speed.unwrap_or(f32::NAN).to_le_bytes());
+    packet.extend_from_slice(&record.heading.unwrap_or(f32::NAN).to_le_bytes());
+    packet.extend_from_slice(&record.battery_voltage.unwrap_or(f32::NAN).to_le_bytes());
+    packet.push(record.outputs.len().min(u8::MAX as usize) as u8);
+    for output in record.outputs.iter().take(u8::MAX as usize) {
+        packet.extend_from_slice(&output.to_le_bytes());
+    }
+    packet
+}
+
+fn udp_thread(socket: UdpSocket, address: String, receiver: Receiver<TelemetryRecord>) {
+    let mut failed = false;
+    for record in receiver {
+        match socket.send_to(&encode(&record), address.as_str()) {
+            Ok(_) => failed = false,
+            // Once per failure, the network may come back
+            Err(e) if !failed => {
+                error!("Telemetry broadcast error: {}", e);
+                failed = true;
+            }
+            Err(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_packet() {
+        let record = TelemetryRecord {
+            time_ms: 1_000, connected: true, no_boat: true, wireless_quality: 60, latency: 25, weight: 120.,
+            boat_battery: Some(11.8), latitude: None, longitude: None, speed: None,
+            heading: None, wind_angle: None, battery_voltage: Some(7.9), outputs: vec![1500, 1600], telemetry: Default::default(),
+        };
+        let packet = encode(&record);
+        assert_eq!(packet.len(), 53 + 4);
+        assert_eq!(&packet[..4], b"PZT1");
+        assert_eq!(u64::from_le_bytes(packet[4..12].try_into().unwrap()), 1_000);
+        assert_eq!(packet[12..16], [3, 60, 25, 0]);
+        assert_eq!(f32::from_le_bytes(packet[20..24].try_into().unwrap()), 11.8);
+        assert!(f64::from_le_bytes(packet[24..32].try_into().unwrap()).is_nan());
+        assert_eq!(packet[52], 2);
+        assert_eq!(u16::from_le_bytes(packet[55..57].try_into().unwrap()), 1600);
+    }
+}
@@ -1,108 +1,394 @@
-use serde::{Serialize, Deserialize};
+use std::sync::mpsc::{self, Sender};
 use std::sync::{Arc, Mutex};
 use std::net::TcpListener;
-use tungstenite::{accept, Message};
+use tungstenite::accept;
 use std::thread;
-use std::time::{Duration};
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct QueryMessage {
-    #[serde(rename = "type")]
-    msg_type: String,
-    timestamp: u64,
-    pub wireless_quality: Option<i16>,
-    pub latency: Option<u64>,
-    pub weight: Option<f32>,
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::alerts::Alert;
+use crate::api::ApiRequest;
+use crate::config::{ChannelConfig, CommandEncoding, Settings};
+use crate::encoding::CommandEncoder;
+use crate::metrics::Metrics;
+use crate::event_log;
+use crate::recorder::{Direction, Recorder};
+use pizboat_link::Transport;
+use pizboat_protocol::{Alarm, AppliedPulses, Command, Config, Failsafe, Message, Query, Telemetry};
+use tracing::{error, info, trace};
+
+pub const WEBSOCKET_PORT: u16 = 10013;
+// A boat not polling for that long is considered gone
+pub const CLIENT_TIMEOUT_MS: u64 = 1000;
+// Channel outputs, misc included
+pub const OUTPUT_COUNT: usize = 9;
+// Rounding on the boat side, not an override
+const APPLIED_TOLERANCE_US: u16 = 2;
+
+/// Last query of the boat
+#[derive(Debug)]
+pub struct BoatQuery {
+    pub query: Query,
+    // To tell a silent boat from a slow one
+    pub received: Instant,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
-pub struct CommandMessage {
-    #[serde(rename = "type")]
-    pub msg_type: String,
-    pub timestamp: u64,
-    
-    pub rudder_star: u16,
-    pub rudder_port: u16,
-    pub motor: u16,
-    pub boom: u16,
-    pub genoa: u16
+/// Applied values differing from the sent ones, indexed like the channel outputs, the misc channel staying on the remote.
+/// The sails are left out while the boat trims them, the values sent only biasing its trim.
+fn overridden(applied: &AppliedPulses, sent: &Command) -> [Option<u16>; OUTPUT_COUNT] {
+    let sail = |value: Option<u16>| value.filter(|_| sent.auto_trim != Some(true));
+    let pairs = [
+        (applied.rudder_star, sent.rudder_star), (applied.rudder_port, sent.rudder_port), (applied.motor, sent.motor),
+        (applied.boom, sail(sent.boom)), (applied.genoa, sail(sent.genoa)), (0, None), (applied.bow_thruster, sent.bow_thruster),
+        (applied.gimbal_pan, sent.gimbal_pan), (applied.gimbal_tilt, sent.gimbal_tilt),
+    ];
+    pairs.map(|(applied, sent)| sent.filter(|&sent| applied.abs_diff(sent) > APPLIED_TOLERANCE_US).map(|_| applied))
+}
+
+/// Link measured by the WebSocket thread of the boat
+#[derive(Debug, Clone, Copy)]
+pub struct LinkStats {
+    pub round_trip_ms: u64,
+    pub overridden: [Option<u16>; OUTPUT_COUNT],
+}
+
+fn timestamp_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64)
 }
 
+/// Answer to a configuration tool
+fn handle_config(config: Config, settings_mutex: &Mutex<Option<Settings>>, requests: &Sender<ApiRequest>) -> Config {
+    let result = match config {
+        Config::Get {} => {
+            return match settings_mutex.lock().unwrap().as_ref() {
+                Some(settings) => match (serde_json::to_value(settings), settings.channels.iter().map(serde_json::to_value).collect()) {
+                    (Ok(value), Ok(channels)) => Config::Settings { settings: value, channels },
+                    (Err(e), _) | (_, Err(e)) => Config::Result { ok: false, error: Some(e.to_string()) },
+                },
+                None => Config::Result { ok: false, error: Some(String::from("Settings not loaded yet")) },
+            };
+        }
+        Config::Set { channels } => channels.into_iter().try_for_each(|channel| {
+            let channel = serde_json::from_value::<ChannelConfig>(channel).map_err(|e| e.to_string())?;
+            let (tx, rx) = mpsc::channel();
+            requests.send(ApiRequest::SetChannel(channel, tx)).map_err(|e| e.to_string())?;
+            rx.recv().map_err(|e| e.to_string())?
+        }),
+        Config::Save {} => {
+            let (tx, rx) = mpsc::channel();
+            requests.send(ApiRequest::Save(tx)).map_err(|e| e.to_string())
+                .and_then(|_| rx.recv().map_err(|e| e.to_string())?)
+        }
+        Config::Settings { .. } | Config::Result { .. } => Err(String::from("Answer sent to the remote")),
+    };
+    Config::Result { ok: result.is_ok(), error: result.err() }
+}
 
-pub fn websocket_thread(data_mutex: Arc<Mutex<Option<CommandMessage>>>, query_mutex: Arc<Mutex<Option<QueryMessage>>>) {
-    let server = TcpListener::bind("0.0.0.0:10013").expect("Failed to bind WebSocket server");
-    println!("WebSocket server listening on port 10013");
+/// Latest command sent, query of the boat and round trip
+fn telemetry(data_mutex: &Mutex<Option<Command>>, query_mutex: &Mutex<Option<BoatQuery>>,
+             link_mutex: &Mutex<Option<LinkStats>>) -> Telemetry {
+    Telemetry {
+        command: data_mutex.lock().unwrap().clone(),
+        query: query_mutex.lock().unwrap().as_ref().map(|q| q.query.clone()),
+        round_trip_ms: link_mutex.lock().unwrap().map(|link| link.round_trip_ms),
+    }
+}
+
+/// Send a tool its answer, if any, then the alerts raised or cleared since it was last told
+fn send_to_tool(transport: &mut impl Transport, reply: Option<Message>, alarms_mutex: &Mutex<Vec<Alert>>,
+                sent_alarms: &mut Vec<Alert>) -> Result<(), Box<dyn std::error::Error>> {
+    let active = alarms_mutex.lock().unwrap().clone();
+    let alarm = |alert: &Alert, raised| Message::Alarm(Alarm { name: format!("{:?}", alert), raised });
+    let raised = active.iter().filter(|alert| !sent_alarms.contains(alert)).map(|alert| alarm(alert, true));
+    let cleared = sent_alarms.iter().filter(|alert| !active.contains(alert)).map(|alert| alarm(alert, false));
+    for message in reply.into_iter().chain(raised).chain(cleared) {
+        transport.send(&serde_json::to_string(&message)?)?;
+    }
+    *sent_alarms = active;
+    Ok(())
+}
+
+pub fn bind() -> std::io::Result<TcpListener> {
+    let server = TcpListener::bind(("0.0.0.0", WEBSOCKET_PORT))?;
+    info!("WebSocket server listening on port {}", WEBSOCKET_PORT);
+    Ok(server)
+}
+
+/// State shared by the threads serving the clients, whatever their transport
+#[derive(Clone)]
+pub struct LinkContext {
+    pub data_mutex: Arc<Mutex<Option<Command>>>,
+    pub query_mutex: Arc<Mutex<Option<BoatQuery>>>,
+    pub failsafe_mutex: Arc<Mutex<Option<Failsafe>>>,
+    pub settings_mutex: Arc<Mutex<Option<Settings>>>,
+    pub requests: Sender<ApiRequest>,
+    pub recorder: Option<Arc<Recorder>>,
+    pub link_mutex: Arc<Mutex<Option<LinkStats>>>,
+    pub metrics: Arc<Metrics>,
+    // Alerts shown on the remote, pushed to the tools
+    pub alarms_mutex: Arc<Mutex<Vec<Alert>>>,
+}
 
+pub fn websocket_thread(server: TcpListener, context: LinkContext) {
     for stream in server.incoming() {
         let stream = match stream {
             Ok(s) => s,
             Err(e) => {
-                eprintln!("Connection error: {}", e);
+                error!("Connection error: {}", e);
                 continue;
             }
         };
 
-        let data_mutex = Arc::clone(&data_mutex);
-        let query_mutex = Arc::clone(&query_mutex);
+        let context = context.clone();
         thread::spawn(move || {
             
+            if let Err(e) = stream.set_read_timeout(Some(Duration::from_millis(CLIENT_TIMEOUT_MS))) {
+                error!("Could not set client timeout: {}", e);
+            }
+            
             let mut websocket = match accept(stream) {
                 Ok(ws) => ws,
                 Err(e) => {
-                    eprintln!("WebSocket handshake error: {}", e);
+                    error!("WebSocket handshake error: {}", e);
                     return;
                 }
             };
 
-            println!("New WebSocket client connected");
-
-            loop {
-                let mut timestamp: u64 = 0;
-                
-                match websocket.read() {
-                    Ok(Message::Text(text)) => {
-                        match serde_json::from_str::<QueryMessage>(&text) {
-                            Ok(query) => {
-                                timestamp = query.timestamp;
-                                // println!("W {}", query.wireless_quality);
-                                {
-                                    let mut locked_query = query_mutex.lock().unwrap();
-                                    *locked_query = Some(query);
+            info!("New WebSocket client connected");
+            serve_client(&mut websocket, &context);
+        });
+    }
+}
+
+/// Answer the boat queries with the commands, and the configuration tools with what they ask, until the client goes
+pub fn serve_client(transport: &mut impl Transport, context: &LinkContext) {
+    let LinkContext { data_mutex, query_mutex, failsafe_mutex, settings_mutex, requests, recorder, link_mutex, metrics, alarms_mutex } = context;
+    let mut sent_failsafe: Option<Failsafe> = None;
+    // Compared with what the boat applied once it echoes it
+    let mut last_sent: Option<Command> = None;
+    let encoding = settings_mutex.lock().unwrap().as_ref().map(|s| (s.command_encoding, s.keyframe_period_ms));
+    let (encoding, keyframe_period_ms) = encoding.unwrap_or((CommandEncoding::Full, 0));
+    let mut encoder = CommandEncoder::new(encoding, Duration::from_millis(keyframe_period_ms as u64));
+    // Configuration tools connect too, only the boat sends queries
+    let mut is_boat = false;
+    // Tools get the alarms once they sent a message
+    let mut is_tool = false;
+    let mut sent_alarms: Vec<Alert> = Vec::new();
+    // Id of a boat which is not the one selected, once reported
+    let mut ignored_boat: Option<u8> = None;
+
+    loop {
+        let mut timestamp: u64 = 0;
+        
+        match transport.receive() {
+            Ok(Some(text)) => {
+                let message = pizboat_protocol::parse(&text);
+                // Configuration and monitoring tools, answered at once
+                let reply = match &message {
+                    Ok(Message::Config(config)) => Some(Message::Config(handle_config(config.clone(), settings_mutex, requests))),
+                    Ok(Message::Telemetry(_)) => Some(Message::Telemetry(telemetry(data_mutex, query_mutex, link_mutex))),
+                    _ => None,
+                };
+                if reply.is_some() {
+                    is_tool = true;
+                    if send_to_tool(transport, reply, alarms_mutex, &mut sent_alarms).is_err() {
+                        info!("Client disconnected");
+                        break;
+                    }
+                    continue;
+                }
+                // Another boat on the access point: still answered, so that it keeps polling,
+                // but with the commands of the selected boat, which it ignores
+                let selected = settings_mutex.lock().unwrap().as_ref().map(|s| s.boat_id);
+                if let Ok(Message::Query(query)) = &message
+                    && let (Some(id), Some(selected)) = (query.boat_id, selected)
+                    && id != selected {
+                    if ignored_boat != Some(id) {
+                        event_log::record(format!("Boat {} ignored, boat {} selected", id, selected));
+                        ignored_boat = Some(id);
+                    }
+                    if is_boat {
+                        forget_boat(query_mutex, link_mutex);
+                        is_boat = false;
+                    }
+                } else {
+                    ignored_boat = None;
+
+                    if !is_boat {
+                        metrics.boat_connected();
+                        event_log::record("Boat connected");
+                    }
+                    is_boat = true;
+                    if let Some(recorder) = recorder {
+                        recorder.record(Direction::Query, &text);
+                    }
+                    match message {
+                        Ok(Message::Query(mut query)) => {
+                            timestamp = query.timestamp;
+                            // Round trip of the last command, without the time the boat kept it
+                            if let Some(echo) = query.echo_timestamp {
+                                let round_trip_ms = timestamp_ms().saturating_sub(echo).saturating_sub(query.echo_delay_ms.unwrap_or(0));
+                                let mut link = link_mutex.lock().unwrap();
+                                let echoed = last_sent.as_ref().filter(|sent| sent.remote_timestamp == Some(echo));
+                                let overridden = match (&query.applied, echoed) {
+                                    (Some(applied), Some(sent)) => overridden(applied, sent),
+                                    // Older boat, or the echo of an earlier command
+                                    _ => link.map_or([None; OUTPUT_COUNT], |link| link.overridden),
+                                };
+                                *link = Some(LinkStats { round_trip_ms, overridden });
+                                metrics.observe_latency(round_trip_ms);
+                            }
+                            trace!("Boat wireless quality {:?}", query.wireless_quality);
+                            if let Some(checks) = &query.self_test {
+                                let failed: Vec<&str> = checks.iter().filter(|check| !check.passed).map(|check| check.name.as_str()).collect();
+                                event_log::record(if failed.is_empty() {
+                                    String::from("Boat self-test passed")
+                                } else {
+                                    format!("Boat self-test failed: {}", failed.join(" "))
+                                });
+                            }
+                            {
+                                let mut locked_query = query_mutex.lock().unwrap();
+                                // Sent on connection only, kept with the following queries
+                                if query.self_test.is_none() {
+                                    query.self_test = locked_query.as_ref().and_then(|last| last.query.self_test.clone());
                                 }
+                                *locked_query = Some(BoatQuery { query, received: Instant::now() });
                             }
-                            Err(e) => eprintln!("JSON parse error: {}", e),
                         }
+                        Ok(message) => error!("Unexpected message from the boat: {:?}", message),
+                        Err(e) => error!("Boat message refused, {}", e),
                     }
-                    Err(e) => {
-                        eprintln!("WebSocket error: {}", e);
-                        break;
-                    }
-                    _ => {
-                        eprintln!("Not supported !");
+                }
+            }
+            // Tools may stay idle, the boat may not
+            Ok(None) if !is_boat => {
+                if is_tool && send_to_tool(transport, None, alarms_mutex, &mut sent_alarms).is_err() {
+                    info!("Client disconnected");
+                    break;
+                }
+                continue;
+            }
+            Ok(None) => {
+                error!("No query from the boat for {}ms", CLIENT_TIMEOUT_MS);
+                break;
+            }
+            Err(e) => {
+                error!("Link error: {}", e);
+                break;
+            }
+        }
+
+        let failsafe = failsafe_mutex.lock().unwrap().clone();
+        
+        if let Some(f) = failsafe.filter(|f| sent_failsafe.as_ref() != Some(f)) {
+            match serde_json::to_string(&Message::Failsafe(f.clone())) {
+                Ok(json) => {
+                    if transport.send(&json).is_err() {
+                        info!("Client disconnected");
                         break;
                     }
+                    event_log::record("Failsafe values sent");
+                    sent_failsafe = Some(f);
                 }
+                Err(e) => error!("JSON serialization error: {}", e),
+            }
+        }
 
-                let data = {
-                    let locked_data = data_mutex.lock().unwrap();
-                    locked_data.clone()
-                };
+        let data = {
+            let locked_data = data_mutex.lock().unwrap();
+            locked_data.clone()
+        };
 
-                if let Some(mut d) = data {
-                    d.timestamp = timestamp;
-                    match serde_json::to_string(&d) {
-                        Ok(json) => {
-                            if websocket.send(Message::Text(json)).is_err() {
-                                println!("WebSocket client disconnected");
-                                break;
-                            }
+        if let Some(mut d) = data {
+            d.timestamp = timestamp;
+            d.remote_timestamp = Some(timestamp_ms());
+            match encoder.encode(&d) {
+                Ok(json) => {
+                    // Recorded whole, to be replayed
+                    if let Some(recorder) = recorder {
+                        match serde_json::to_string(&Message::Command(d.clone())) {
+                            Ok(whole) => recorder.record(Direction::Command, &whole),
+                            Err(e) => error!("JSON serialization error: {}", e),
                         }
-                        Err(e) => eprintln!("JSON serialization error: {}", e),
                     }
+                    if transport.send(&json).is_err() {
+                        info!("Client disconnected");
+                        break;
+                    }
+                    metrics.command_sent(json.len());
+                    last_sent = Some(d);
                 }
-
-                thread::sleep(Duration::from_millis(40));
+                Err(e) => error!("JSON serialization error: {}", e),
             }
-        });
+        }
+
+        thread::sleep(Duration::from_millis(40));
+    }
+    
+    if is_boat {
+        event_log::record("Boat connection closed");
+        forget_boat(query_mutex, link_mutex);
+    }
+}
+
+/// Boat gone or no longer selected, forget its last query
+fn forget_boat(query_mutex: &Mutex<Option<BoatQuery>>, link_mutex: &Mutex<Option<LinkStats>>) {
+    *query_mutex.lock().unwrap() = None;
+    *link_mutex.lock().unwrap() = None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_applied_overrides() {
+        let sent = Command {
+            rudder_star: Some(1900), rudder_port: Some(1900), motor: Some(1800), boom: Some(1200), genoa: Some(1200),
+            bow_thruster: Some(1700), gimbal_pan: Some(1500), gimbal_tilt: None,
+            ..Default::default()
+        };
+        // Motor cut over LoRa, thruster disabled by the speed, the rest within rounding or not sent
+        let applied = AppliedPulses {
+            rudder_star: 1900, rudder_port: 1901, motor: 1500, boom: 1200, genoa: 1200,
+            bow_thruster: 1500, gimbal_pan: 1500, gimbal_tilt: 1000,
+        };
+        assert_eq!(overridden(&applied, &sent), [None, None, Some(1500), None, None, None, Some(1500), None, None]);
+
+        // Sails trimmed by the boat from its wind angle
+        let trimmed = AppliedPulses { boom: 1700, genoa: 1750, ..applied };
+        let auto_trim = Command { auto_trim: Some(true), ..sent.clone() };
+        assert_eq!(overridden(&trimmed, &auto_trim), [None, None, Some(1500), None, None, None, Some(1500), None, None]);
+        assert_eq!(overridden(&trimmed, &sent)[3..5], [Some(1700), Some(1750)]);
+    }
+
+    struct Sent(Vec<String>);
+
+    impl Transport for Sent {
+        fn send(&mut self, text: &str) -> Result<(), Box<dyn std::error::Error>> {
+            self.0.push(text.to_string());
+            Ok(())
+        }
+
+        fn receive(&mut self) -> Result<Option<String>, Box<dyn std::error::Error>> {
+            Ok(None)
+        }
+    }
+
+    #[test]
+    fn test_tool_alarms() {
+        let alarms_mutex = Mutex::new(vec![Alert::Leak]);
+        let mut sent_alarms = Vec::new();
+        let mut transport = Sent(Vec::new());
+        let reply = Message::Telemetry(Telemetry { round_trip_ms: Some(20), ..Default::default() });
+        send_to_tool(&mut transport, Some(reply), &alarms_mutex, &mut sent_alarms).unwrap();
+        *alarms_mutex.lock().unwrap() = vec![Alert::Gust];
+        send_to_tool(&mut transport, None, &alarms_mutex, &mut sent_alarms).unwrap();
+        send_to_tool(&mut transport, None, &alarms_mutex, &mut sent_alarms).unwrap();
+
+        let messages: Vec<Message> = transport.0.iter().map(|text| pizboat_protocol::parse(text).unwrap()).collect();
+        assert!(matches!(messages[0], Message::Telemetry(Telemetry { round_trip_ms: Some(20), .. })));
+        let alarm = |name: &str, raised| Message::Alarm(Alarm { name: name.into(), raised });
+        assert_eq!(messages[1..], [alarm("Leak", true), alarm("Gust", true), alarm("Leak", false)]);
     }
 }
@@ -0,0 +1,176 @@
+mod physics;
+
+use physics::{BoatModel, Controls};
+
+use anyhow::Result;
+use pizboat_protocol::{self as protocol, Query};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tungstenite::{connect, Message};
+use tungstenite::stream::MaybeTlsStream;
+
+// Remote running on the same machine, typically PizRemote --sim
+const DEFAULT_WS_URL: &str = "ws://127.0.0.1:10013";
+// Same pace and link timeout as the boat
+const QUERY_PERIOD_MS: u64 = 40;
+const LINK_TIMEOUT_MS: u64 = 1000;
+const PHYSICS_PERIOD_MS: u64 = 20;
+const STATUS_PERIOD_MS: u64 = 1000;
+// Synthetic link, as reported by a boat close to the remote
+const SIM_WIRELESS_QUALITY: i16 = 60;
+const SIM_WIRELESS_SIGNAL: i16 = -45;
+
+struct SimState {
+    model: BoatModel,
+    controls: Controls,
+    failsafe: Option<Controls>,
+}
+
+fn get_timestamp_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+// Both rudders average into the single rudder of the model
+fn rudder(star: Option<u32>, port: Option<u32>) -> Option<u32> {
+    match (star, port) {
+        (Some(star), Some(port)) => Some((star + port) / 2),
+        (star, port) => star.or(port),
+    }
+}
+
+fn physics_thread(state: Arc<Mutex<SimState>>) {
+    let mut last_step = Instant::now();
+    let mut last_status = Instant::now();
+
+    loop {
+        thread::sleep(Duration::from_millis(PHYSICS_PERIOD_MS));
+        let dt_s = last_step.elapsed().as_secs_f32();
+        last_step = Instant::now();
+
+        let mut state = state.lock().unwrap();
+        let controls = state.controls;
+        state.model.step(controls, dt_s);
+
+        if last_status.elapsed() >= Duration::from_millis(STATUS_PERIOD_MS) {
+            last_status = Instant::now();
+            let model = &state.model;
+            println!("Heading {:5.1}° speed {:4.2}m/s at {:.1}m N {:.1}m E, battery {:.2}V (rudder {}us motor {}us)",
+                model.heading, model.speed, model.north_m, model.east_m, model.battery_voltage, controls.rudder_us, controls.motor_us);
+        }
+    }
+}
+
+fn read_text(socket: &mut tungstenite::WebSocket<MaybeTlsStream<std::net::TcpStream>>) -> Result<Option<String>> {
+    match socket.read()? {
+        Message::Text(text) => Ok(Some(text)),
+        _ => Ok(None),
+    }
+}
+
+fn handle_websocket(url: &str, state: &Mutex<SimState>) -> Result<()> {
+    let (mut socket, _response) = connect(url)?;
+    println!("WebSocket connected to {}", url);
+
+    if let MaybeTlsStream::Plain(stream) = socket.get_ref() {
+        stream.set_read_timeout(Some(Duration::from_millis(LINK_TIMEOUT_MS)))?;
+    }
+
+    let mut latency = 0;
+    // remote_timestamp of the last command and when it was received
+    let mut echo: Option<(u64, u64)> = None;
+    // Last rudder pulses, a delta command only carrying the ones which changed
+    let (mut rudder_star, mut rudder_port) = (None, None);
+
+    loop {
+        let timestamp = get_timestamp_ms();
+        let query = {
+            let state = state.lock().unwrap();
+            let (latitude, longitude) = state.model.position();
+            protocol::Message::Query(Query {
+                timestamp,
+                wireless_quality: Some(SIM_WIRELESS_QUALITY),
+                wireless_signal: Some(SIM_WIRELESS_SIGNAL),
+                latency: Some(latency),
+                echo_timestamp: echo.map(|(remote_timestamp, _)| remote_timestamp),
+                echo_delay_ms: echo.map(|(_, received)| timestamp.saturating_sub(received)),
+                weight: Some(0.),
+                battery_voltage: Some(state.model.battery_voltage),
+                latitude: Some(latitude),
+                longitude: Some(longitude),
+                speed: Some(state.model.speed.abs()),
+                heading: Some(state.model.heading),
+                leak: Some(false),
+                ..Default::default()
+            })
+        };
+        socket.send(Message::Text(serde_json::to_string(&query)?))?;
+
+        let Some(text) = read_text(&mut socket)? else { continue };
+        let mut message = protocol::parse(&text);
+
+        // Failsafe values come before the command answering the query
+        if let Ok(protocol::Message::Failsafe(failsafe)) = message {
+            println!("Failsafe values received: {:?}", failsafe);
+            state.lock().unwrap().failsafe = Some(Controls {
+                rudder_us: (failsafe.rudder_star as u32 + failsafe.rudder_port as u32) / 2,
+                motor_us: failsafe.motor.into(),
+            });
+            let Some(next) = read_text(&mut socket)? else { continue };
+            message = protocol::parse(&next);
+        }
+
+        match message {
+            Ok(protocol::Message::Command(command)) => {
+                let now = get_timestamp_ms();
+                latency = now.saturating_sub(command.timestamp);
+                echo = command.remote_timestamp.map(|remote_timestamp| (remote_timestamp, now));
+
+                let mut state = state.lock().unwrap();
+                rudder_star = command.rudder_star.map(u32::from).or(rudder_star);
+                rudder_port = command.rudder_port.map(u32::from).or(rudder_port);
+                if let Some(rudder_us) = rudder(rudder_star, rudder_port) {
+                    state.controls.rudder_us = rudder_us;
+                }
+                if let Some(motor_us) = command.motor {
+                    state.controls.motor_us = motor_us.into();
+                }
+            }
+            Ok(other) => eprintln!("Unexpected message from the remote: {:?}", other),
+            Err(e) => eprintln!("Remote message refused, {}", e),
+        }
+
+        thread::sleep(Duration::from_millis(QUERY_PERIOD_MS));
+    }
+}
+
+/// Stands in for the boat: answers the remote like PizBoat, moving a boat model instead of servos
+fn main() {
+    let url = std::env::args().nth(1).unwrap_or_else(|| DEFAULT_WS_URL.to_string());
+
+    let state = Arc::new(Mutex::new(SimState { model: BoatModel::new(), controls: Controls::default(), failsafe: None }));
+    let state_clone = Arc::clone(&state);
+    thread::spawn(move || physics_thread(state_clone));
+
+    loop {
+        println!("Connecting to {}", url);
+        let result = handle_websocket(&url, &state);
+
+        // Like the boat, hold the failsafe positions, neutral when none were received
+        {
+            let mut state = state.lock().unwrap();
+            let controls = state.failsafe.unwrap_or_default();
+            if state.controls != controls {
+                println!("Link lost, applying {:?}", controls);
+                state.controls = controls;
+            }
+        }
+        if let Err(e) = result {
+            eprintln!("Connection error: {}", e);
+            thread::sleep(Duration::from_secs(1));
+        }
+    }
+}
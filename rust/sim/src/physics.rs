@@ -0,0 +1,97 @@
+// Top speed ahead (m/s), reverse being slower
+const MAX_SPEED: f32 = 2.5;
+const MAX_REVERSE_SPEED: f32 = 1.0;
+// Time constant of the speed towards the throttle setting
+const SPEED_TAU_S: f32 = 2.;
+// Turn rate at full rudder and top speed, the rudder needing water flow
+const MAX_TURN_RATE_DEG_S: f32 = 40.;
+// 3S LiPo, drained at full throttle in about 20 minutes
+const BATTERY_FULL: f32 = 12.6;
+const BATTERY_EMPTY: f32 = 9.9;
+const BATTERY_DRAIN_V_S: f32 = (BATTERY_FULL - BATTERY_EMPTY) / 1200.;
+// Launch point, positions being offsets from it
+const HOME_LATITUDE: f64 = 48.8566;
+const HOME_LONGITUDE: f64 = 2.3522;
+const EARTH_RADIUS_M: f64 = 6_371_000.;
+
+/// Servo positions applied to the model, in microseconds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Controls {
+    pub rudder_us: u32,
+    pub motor_us: u32,
+}
+
+impl Default for Controls {
+    fn default() -> Self {
+        Controls { rudder_us: 1500, motor_us: 1500 }
+    }
+}
+
+/// Boat on flat water: first order speed response, turn rate proportional to rudder and speed
+#[derive(Debug, Clone)]
+pub struct BoatModel {
+    pub north_m: f64,
+    pub east_m: f64,
+    pub heading: f32,       // Degrees from north, clockwise
+    pub speed: f32,         // m/s, negative going astern
+    pub battery_voltage: f32,
+}
+
+// -1 to 1 from a servo pulse centered on 1500us
+fn deflection(pulse_us: u32) -> f32 {
+    ((pulse_us as f32 - 1500.) / 500.).clamp(-1., 1.)
+}
+
+impl BoatModel {
+    pub fn new() -> Self {
+        BoatModel { north_m: 0., east_m: 0., heading: 0., speed: 0., battery_voltage: BATTERY_FULL }
+    }
+
+    pub fn step(&mut self, controls: Controls, dt_s: f32) {
+        let throttle = if self.battery_voltage > BATTERY_EMPTY { deflection(controls.motor_us) } else { 0. };
+        let target = if throttle >= 0. { throttle * MAX_SPEED } else { throttle * MAX_REVERSE_SPEED };
+        self.speed += (target - self.speed) * (dt_s / SPEED_TAU_S).min(1.);
+
+        let turn_rate = deflection(controls.rudder_us) * MAX_TURN_RATE_DEG_S * self.speed / MAX_SPEED;
+        self.heading = (self.heading + turn_rate * dt_s).rem_euclid(360.);
+
+        let heading = (self.heading as f64).to_radians();
+        self.north_m += self.speed as f64 * heading.cos() * dt_s as f64;
+        self.east_m += self.speed as f64 * heading.sin() * dt_s as f64;
+
+        self.battery_voltage = (self.battery_voltage - BATTERY_DRAIN_V_S * throttle.abs() * dt_s).max(BATTERY_EMPTY);
+    }
+
+    pub fn position(&self) -> (f64, f64) {
+        let latitude = HOME_LATITUDE + (self.north_m / EARTH_RADIUS_M).to_degrees();
+        let longitude = HOME_LONGITUDE + (self.east_m / (EARTH_RADIUS_M * HOME_LATITUDE.to_radians().cos())).to_degrees();
+        (latitude, longitude)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_speed_and_turn() {
+        let mut boat = BoatModel::new();
+        let ahead = Controls { rudder_us: 1500, motor_us: 2000 };
+        for _ in 0..200 {
+            boat.step(ahead, 0.1);
+        }
+        assert!((boat.speed - MAX_SPEED).abs() < 0.01);
+        assert_eq!(boat.heading, 0.);
+        assert!(boat.north_m > 30. && boat.east_m.abs() < 1e-6);
+
+        // Full rudder to starboard at top speed
+        boat.step(Controls { rudder_us: 2000, motor_us: 2000 }, 1.);
+        assert!((boat.heading - MAX_TURN_RATE_DEG_S).abs() < 0.5);
+
+        for _ in 0..200 {
+            boat.step(Controls::default(), 0.1);
+        }
+        assert!(boat.speed.abs() < 0.01);
+        assert!(boat.battery_voltage < BATTERY_FULL);
+    }
+}